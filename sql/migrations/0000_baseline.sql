@@ -0,0 +1,17 @@
+-- Initial schema: speed readings and the API keys allowed to use the server
+CREATE TABLE IF NOT EXISTS speed (
+    id SERIAL PRIMARY KEY,
+    sensor_name TEXT,
+    speed REAL NOT NULL,
+    lane INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_speed_created_at ON speed (created_at);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id SERIAL PRIMARY KEY,
+    api_key TEXT NOT NULL UNIQUE,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,15 @@
+-- Optional vehicle classification reported by newer sensors
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS vehicle_class TEXT;
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS vehicle_length REAL;
+
+ALTER TABLE speed DROP CONSTRAINT IF EXISTS speed_vehicle_class_check;
+ALTER TABLE speed ADD CONSTRAINT speed_vehicle_class_check
+    CHECK (vehicle_class IS NULL OR vehicle_class IN ('car', 'truck', 'motorcycle', 'bus'));
+
+ALTER TABLE speed DROP CONSTRAINT IF EXISTS speed_vehicle_length_check;
+ALTER TABLE speed ADD CONSTRAINT speed_vehicle_length_check
+    CHECK (vehicle_length IS NULL OR vehicle_length > 0);
+
+CREATE INDEX IF NOT EXISTS idx_speed_vehicle_class_created_at
+    ON speed (vehicle_class, created_at)
+    WHERE vehicle_class IS NOT NULL;
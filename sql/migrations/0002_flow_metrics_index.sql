@@ -0,0 +1,3 @@
+-- Supports the per sensor/lane window functions used by traffic flow analytics
+CREATE INDEX IF NOT EXISTS idx_speed_sensor_lane_created_at
+    ON speed (sensor_name, lane, created_at);
@@ -0,0 +1,10 @@
+-- Quality flag set by the sensor fault detectors on suspicious readings
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS quality_flag TEXT;
+
+ALTER TABLE speed DROP CONSTRAINT IF EXISTS speed_quality_flag_check;
+ALTER TABLE speed ADD CONSTRAINT speed_quality_flag_check
+    CHECK (quality_flag IS NULL OR quality_flag IN ('stuck_value', 'sudden_jump', 'out_of_distribution'));
+
+CREATE INDEX IF NOT EXISTS idx_speed_quality_flag_created_at
+    ON speed (quality_flag, created_at)
+    WHERE quality_flag IS NOT NULL;
@@ -0,0 +1,26 @@
+-- Pre-aggregated rollups of valid readings per time bucket, lane, sensor and vehicle class
+-- A missing sensor name or vehicle class is stored as '' so it can be part of the primary key
+CREATE TABLE IF NOT EXISTS speed_rollup_minute (
+    bucket_start  TIMESTAMPTZ      NOT NULL,
+    lane          INTEGER          NOT NULL,
+    sensor_name   TEXT             NOT NULL DEFAULT '',
+    vehicle_class TEXT             NOT NULL DEFAULT '',
+    count         BIGINT           NOT NULL,
+    sum_speed     DOUBLE PRECISION NOT NULL,
+    sum_sq_speed  DOUBLE PRECISION NOT NULL,
+    min_speed     REAL             NOT NULL,
+    max_speed     REAL             NOT NULL,
+    PRIMARY KEY (bucket_start, lane, sensor_name, vehicle_class)
+);
+
+CREATE TABLE IF NOT EXISTS speed_rollup_hour (LIKE speed_rollup_minute INCLUDING ALL);
+CREATE TABLE IF NOT EXISTS speed_rollup_day (LIKE speed_rollup_minute INCLUDING ALL);
+
+-- Last speed id folded into the rollups
+CREATE TABLE IF NOT EXISTS rollup_watermark (
+    name       TEXT        PRIMARY KEY,
+    last_id    BIGINT      NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO rollup_watermark (name, last_id) VALUES ('speed', 0) ON CONFLICT (name) DO NOTHING;
@@ -0,0 +1,43 @@
+-- Turns speed into a table range-partitioned by created_at
+-- The existing rows become the speed_legacy partition, covering everything up to the end of
+-- the current month. Monthly partitions after it are created by the server's partition manager.
+DO $$
+DECLARE
+    legacy_end TIMESTAMPTZ := date_trunc('month', now(), 'UTC') + INTERVAL '1 month';
+    idx RECORD;
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'speed'::regclass) THEN
+        RETURN;
+    END IF;
+
+    ALTER TABLE speed RENAME TO speed_legacy;
+    FOR idx IN SELECT indexname FROM pg_indexes WHERE schemaname = current_schema() AND tablename = 'speed_legacy' LOOP
+        EXECUTE format('ALTER INDEX %I RENAME TO %I', idx.indexname, idx.indexname || '_legacy');
+    END LOOP;
+
+    CREATE TABLE speed (LIKE speed_legacy INCLUDING DEFAULTS INCLUDING CONSTRAINTS)
+        PARTITION BY RANGE (created_at);
+    ALTER TABLE speed ADD PRIMARY KEY (id, created_at);
+    EXECUTE format('ALTER SEQUENCE %s OWNED BY speed.id', pg_get_serial_sequence('speed_legacy', 'id'));
+
+    -- The primary key of a partitioned table must include created_at, attaching rebuilds it
+    EXECUTE (
+        SELECT format('ALTER TABLE speed_legacy DROP CONSTRAINT %I', conname)
+        FROM pg_constraint WHERE conrelid = 'speed_legacy'::regclass AND contype = 'p'
+    );
+    EXECUTE format('ALTER TABLE speed ATTACH PARTITION speed_legacy FOR VALUES FROM (MINVALUE) TO (%L)', legacy_end);
+END
+$$;
+
+-- Catches readings outside of every monthly partition, e.g. from a sensor with a wrong clock
+CREATE TABLE IF NOT EXISTS speed_default PARTITION OF speed DEFAULT;
+
+-- Indexes are created on every partition
+CREATE INDEX IF NOT EXISTS idx_speed_created_at ON speed (created_at);
+CREATE INDEX IF NOT EXISTS idx_speed_sensor_lane_created_at ON speed (sensor_name, lane, created_at);
+CREATE INDEX IF NOT EXISTS idx_speed_vehicle_class_created_at
+    ON speed (vehicle_class, created_at)
+    WHERE vehicle_class IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_speed_quality_flag_created_at
+    ON speed (quality_flag, created_at)
+    WHERE quality_flag IS NOT NULL;
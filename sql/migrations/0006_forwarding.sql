@@ -0,0 +1,17 @@
+-- Readings forwarded by edge servers keep their origin and their id there, so a batch sent
+-- twice is only stored once. A unique index of a partitioned table must include created_at.
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS origin TEXT;
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS origin_id BIGINT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_speed_origin ON speed (origin, origin_id, created_at);
+
+-- Forwarded readings carry their original timestamp, so the time of insertion is tracked
+-- separately for the jobs waiting for readings to settle
+ALTER TABLE speed ADD COLUMN IF NOT EXISTS received_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- Last local speed id acknowledged by the upstream server, on edge servers
+CREATE TABLE IF NOT EXISTS forward_watermark (
+    name       TEXT        PRIMARY KEY,
+    last_id    BIGINT      NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- API keys are stored as a peppered hash instead of plaintext. The pepper is only known by
+-- the server, which hashes the remaining plaintext keys and clears them at startup.
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS key_hash TEXT UNIQUE;
+ALTER TABLE api_keys ALTER COLUMN api_key DROP NOT NULL;
@@ -0,0 +1,10 @@
+-- API keys are managed through the admin endpoints: they get a name, an owner and scopes,
+-- and keep the first characters of their secret so they can be told apart once masked.
+-- Scopes restrict what a key can do, so a sensor key can only send readings; keys get
+-- everything but administration by default
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS name TEXT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS owner TEXT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS key_prefix TEXT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL
+    DEFAULT ARRAY['speeds:write', 'speeds:read', 'stream:read']
+    CHECK (scopes <@ ARRAY['speeds:write', 'speeds:read', 'stream:read', 'admin']);
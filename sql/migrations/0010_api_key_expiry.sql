@@ -0,0 +1,2 @@
+-- API keys can be given an expiry, after which they are refused like inactive keys
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
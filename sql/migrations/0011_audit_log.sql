@@ -0,0 +1,17 @@
+-- Audit trail of authentications, API key management and data deletions
+-- Key ids have no foreign key, events outlive the keys they mention
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    action TEXT NOT NULL,
+    key_id INTEGER,
+    subject TEXT,
+    key_prefix TEXT,
+    target_key_id INTEGER,
+    ip TEXT,
+    method TEXT,
+    route TEXT,
+    detail TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_occurred_at_idx ON audit_log (occurred_at);
@@ -0,0 +1,14 @@
+-- Local readings the upstream server refused as invalid, on edge servers. They are copied here
+-- before the forwarding moves past them, so the retention can't purge the only copy
+CREATE TABLE IF NOT EXISTS forward_quarantine (
+    speed_id       BIGINT      PRIMARY KEY,
+    sensor_name    TEXT,
+    speed          REAL        NOT NULL,
+    lane           INTEGER     NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL,
+    vehicle_class  TEXT,
+    vehicle_length REAL,
+    quality_flag   TEXT,
+    reason         TEXT        NOT NULL,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
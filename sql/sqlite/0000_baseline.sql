@@ -0,0 +1,21 @@
+-- SQLite schema for single-box edge deployments
+-- Timestamps are stored as microseconds since the Unix epoch, in UTC
+CREATE TABLE IF NOT EXISTS speed (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sensor_name TEXT,
+    speed REAL NOT NULL,
+    lane INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    vehicle_class TEXT CHECK (vehicle_class IS NULL OR vehicle_class IN ('car', 'truck', 'motorcycle', 'bus')),
+    vehicle_length REAL CHECK (vehicle_length IS NULL OR vehicle_length > 0),
+    quality_flag TEXT CHECK (quality_flag IS NULL OR quality_flag IN ('stuck_value', 'sudden_jump', 'out_of_distribution'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_speed_created_at ON speed (created_at);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    api_key TEXT NOT NULL UNIQUE,
+    is_active INTEGER NOT NULL DEFAULT 1,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER) * 1000000)
+);
@@ -0,0 +1,12 @@
+-- Readings forwarded by other edge servers keep their origin and their id there
+ALTER TABLE speed ADD COLUMN origin TEXT;
+ALTER TABLE speed ADD COLUMN origin_id INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_speed_origin ON speed (origin, origin_id, created_at);
+
+-- Last local speed id acknowledged by the upstream server
+CREATE TABLE IF NOT EXISTS forward_watermark (
+    name       TEXT    PRIMARY KEY,
+    last_id    INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,16 @@
+-- API keys are stored as a peppered hash instead of plaintext. The pepper is only known by
+-- the server, which hashes the remaining plaintext keys and clears them at startup.
+-- SQLite can't drop a NOT NULL constraint, so the table is rebuilt.
+CREATE TABLE api_keys_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    api_key TEXT UNIQUE,
+    key_hash TEXT UNIQUE,
+    is_active INTEGER NOT NULL DEFAULT 1,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER) * 1000000)
+);
+
+INSERT INTO api_keys_new (id, api_key, is_active, created_at)
+SELECT id, api_key, is_active, created_at FROM api_keys;
+
+DROP TABLE api_keys;
+ALTER TABLE api_keys_new RENAME TO api_keys;
@@ -0,0 +1,8 @@
+-- API keys are managed through the admin endpoints: they get a name, an owner and space
+-- separated scopes, and keep the first characters of their secret so they can be told apart
+-- once masked. Scopes restrict what a key can do, so a sensor key can only send readings;
+-- keys get everything but administration by default
+ALTER TABLE api_keys ADD COLUMN name TEXT;
+ALTER TABLE api_keys ADD COLUMN owner TEXT;
+ALTER TABLE api_keys ADD COLUMN key_prefix TEXT;
+ALTER TABLE api_keys ADD COLUMN scopes TEXT NOT NULL DEFAULT 'speeds:write speeds:read stream:read';
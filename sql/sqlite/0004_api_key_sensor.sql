@@ -0,0 +1,2 @@
+-- API keys can be bound to a sensor, whose name is then given to every reading sent with them
+ALTER TABLE api_keys ADD COLUMN sensor_name TEXT;
@@ -0,0 +1,3 @@
+-- API keys can be given an expiry in microseconds since the epoch, after which they are refused
+-- like inactive keys
+ALTER TABLE api_keys ADD COLUMN expires_at INTEGER;
@@ -0,0 +1,13 @@
+-- Local readings the upstream server refused as invalid, kept for inspection
+CREATE TABLE IF NOT EXISTS forward_quarantine (
+    speed_id INTEGER PRIMARY KEY,
+    sensor_name TEXT,
+    speed REAL NOT NULL,
+    lane INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    vehicle_class TEXT,
+    vehicle_length REAL,
+    quality_flag TEXT,
+    reason TEXT NOT NULL,
+    quarantined_at INTEGER NOT NULL
+);
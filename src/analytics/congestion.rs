@@ -0,0 +1,546 @@
+use crate::config::constant::{
+    CONGESTION_CONGESTED_SPEED_KMH, CONGESTION_DENSE_SPEED_KMH, CONGESTION_HYSTERESIS_KMH,
+    CONGESTION_MAX_LANES, CONGESTION_MIN_SAMPLES, CONGESTION_WINDOW_SECS,
+};
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::lane::Lane;
+use crate::core::speed_unit::SpeedUnit;
+use crate::{log_info, log_warn};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// Traffic state of a lane, ordered from best to worst
+#[derive(Debug, PartialEq, Clone, Copy, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficState {
+    #[default]
+    FreeFlow,
+    Dense,
+    Congested,
+}
+
+/// Thresholds used to classify the traffic state from the moving average speed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CongestionConfig {
+    pub window: Duration,     // Length of the rolling window
+    pub dense_speed: f64,     // Average speed (km/h) below which traffic is dense
+    pub congested_speed: f64, // Average speed (km/h) below which traffic is congested
+    pub hysteresis: f64,      // Margin (km/h) above a threshold to return to a better state
+    pub min_samples: usize,   // Minimum readings in the window before classifying
+    pub max_lanes: usize,     // Maximum sensor and lane windows tracked at once
+}
+
+impl CongestionConfig {
+    /// Builds the configuration from the `CONGESTION_*` environment variables
+    ///
+    /// Panics if the thresholds are inconsistent, like invalid values of the variables.
+    #[must_use]
+    pub fn from_env() -> Self {
+        let config = Self {
+            window: Duration::seconds(*CONGESTION_WINDOW_SECS as i64),
+            dense_speed: *CONGESTION_DENSE_SPEED_KMH,
+            congested_speed: *CONGESTION_CONGESTED_SPEED_KMH,
+            hysteresis: *CONGESTION_HYSTERESIS_KMH,
+            min_samples: *CONGESTION_MIN_SAMPLES,
+            max_lanes: *CONGESTION_MAX_LANES,
+        };
+        if let Err(e) = config.validate() {
+            panic!("{e}");
+        }
+        config
+    }
+
+    /// Checks that the congested threshold is below the dense one
+    pub fn validate(&self) -> Result<(), String> {
+        if self.congested_speed < self.dense_speed {
+            Ok(())
+        } else {
+            Err(format!(
+                "CONGESTION_CONGESTED_SPEED_KMH ({}) must be below CONGESTION_DENSE_SPEED_KMH ({})",
+                self.congested_speed, self.dense_speed
+            ))
+        }
+    }
+
+    /// Returns the state matching an average speed, without hysteresis
+    fn state_for(&self, avg_speed: f64) -> TrafficState {
+        if avg_speed < self.congested_speed {
+            TrafficState::Congested
+        } else if avg_speed < self.dense_speed {
+            TrafficState::Dense
+        } else {
+            TrafficState::FreeFlow
+        }
+    }
+
+    /// Classifies the traffic state from the current state and the moving average speed
+    ///
+    /// Traffic degrades as soon as the speed crosses a threshold, but only improves once
+    /// the speed is `hysteresis` km/h above it, so a lane hovering around a threshold doesn't flap.
+    #[must_use]
+    pub fn classify(&self, current: TrafficState, avg_speed: f64) -> TrafficState {
+        let raw = self.state_for(avg_speed);
+        if raw > current {
+            return raw;
+        }
+
+        let relaxed = self.state_for(avg_speed - self.hysteresis);
+        if relaxed < current { relaxed } else { current }
+    }
+}
+
+/// Emitted when the traffic state of a lane changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CongestionEvent {
+    pub sensor_name: Option<String>,
+    pub lane: Lane,
+    pub previous_state: TrafficState,
+    pub state: TrafficState,
+    pub avg_speed: f64, // Moving average speed in km/h
+    pub flow_rate: f64, // Vehicles per hour over the window
+    pub changed_at: DateTime<Utc>,
+}
+
+impl CongestionEvent {
+    /// Converts the moving average speed from the canonical km/h to the given unit.
+    #[inline]
+    #[must_use]
+    pub fn in_unit(mut self, unit: SpeedUnit) -> Self {
+        self.avg_speed = unit.convert_kmh(self.avg_speed);
+        self
+    }
+}
+
+/// Current traffic status of a lane, as returned by the snapshot endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CongestionStatus {
+    pub sensor_name: Option<String>,
+    pub lane: Lane,
+    pub state: TrafficState,
+    pub avg_speed: Option<f64>, // Moving average speed in km/h, if the window has readings
+    pub flow_rate: f64,         // Vehicles per hour over the window
+    pub samples: usize,         // Number of readings in the window
+    pub since: DateTime<Utc>,   // When the lane entered its current state
+}
+
+impl CongestionStatus {
+    /// Converts the moving average speed from the canonical km/h to the given unit.
+    #[inline]
+    #[must_use]
+    pub fn in_unit(mut self, unit: SpeedUnit) -> Self {
+        self.avg_speed = self.avg_speed.map(|speed| unit.convert_kmh(speed));
+        self
+    }
+}
+
+/// Rolling window of readings for one sensor and lane
+#[derive(Debug)]
+struct LaneWindow {
+    readings: VecDeque<(DateTime<Utc>, f32)>,
+    speed_sum: f64,
+    state: TrafficState,
+    since: DateTime<Utc>,
+}
+
+impl LaneWindow {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            readings: VecDeque::new(),
+            speed_sum: 0.0,
+            state: TrafficState::default(),
+            since: now,
+        }
+    }
+
+    /// Drops readings older than the window
+    fn evict(&mut self, now: DateTime<Utc>, window: Duration) {
+        while let Some(&(at, speed)) = self.readings.front() {
+            if now - at <= window {
+                break;
+            }
+            self.speed_sum -= f64::from(speed);
+            self.readings.pop_front();
+        }
+    }
+
+    fn avg_speed(&self) -> Option<f64> {
+        (!self.readings.is_empty()).then(|| self.speed_sum / self.readings.len() as f64)
+    }
+
+    fn flow_rate(&self, window: Duration) -> f64 {
+        self.readings.len() as f64 * 3600.0 / window.num_seconds().max(1) as f64
+    }
+}
+
+type LaneKey = (Option<String>, Lane);
+
+/// In-memory aggregator tracking the moving average speed and flow of every sensor and lane
+pub struct CongestionMonitor {
+    config: CongestionConfig,
+    windows: RwLock<HashMap<LaneKey, LaneWindow>>,
+    events_tx: broadcast::Sender<CongestionEvent>,
+}
+
+impl CongestionMonitor {
+    /// Creates a new monitor publishing state transitions on a channel of the given capacity
+    #[must_use]
+    pub fn new(config: CongestionConfig, capacity: usize) -> Self {
+        let (events_tx, _) = broadcast::channel(capacity);
+        Self {
+            config,
+            windows: RwLock::new(HashMap::new()),
+            events_tx,
+        }
+    }
+
+    /// Subscribes to traffic state transitions
+    pub fn subscribe(&self) -> broadcast::Receiver<CongestionEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Adds a reading to its lane window and returns the state transition it caused, if any
+    ///
+    /// Transitions are also published to subscribers. Readings tagged by the fault detectors are ignored.
+    pub fn record(&self, speed_data: &SpeedData) -> Option<CongestionEvent> {
+        if speed_data.quality_flag.is_some() {
+            return None;
+        }
+
+        let now = speed_data.created_at;
+        let key = (speed_data.sensor_name.clone(), speed_data.lane);
+
+        let mut windows = self.windows.write().unwrap();
+        if !windows.contains_key(&key) && windows.len() >= self.config.max_lanes {
+            // Sensor names come from the clients, lanes without recent readings make room
+            windows.retain(|_, window| {
+                window.evict(now, self.config.window);
+                !window.readings.is_empty()
+            });
+            if windows.len() >= self.config.max_lanes {
+                return None;
+            }
+        }
+        let window = windows
+            .entry(key.clone())
+            .or_insert_with(|| LaneWindow::new(now));
+
+        window.readings.push_back((now, speed_data.speed));
+        window.speed_sum += f64::from(speed_data.speed);
+        window.evict(now, self.config.window);
+
+        let event = self.update_state(&key, window, now);
+        drop(windows);
+
+        if let Some(event) = &event {
+            // We ignore the result because it's OK if no one is listening
+            let _ = self.events_tx.send(event.clone());
+        }
+        event
+    }
+
+    /// Classifies a lane again from the readings left in its window, returning the transition
+    ///
+    /// Lanes with fewer than `min_samples` readings keep their state, unless the window is
+    /// empty: without any vehicle in the window, traffic is back to free flow.
+    fn update_state(
+        &self,
+        (sensor_name, lane): &LaneKey,
+        window: &mut LaneWindow,
+        now: DateTime<Utc>,
+    ) -> Option<CongestionEvent> {
+        let state = match window.avg_speed() {
+            None => TrafficState::FreeFlow,
+            Some(_) if window.readings.len() < self.config.min_samples => window.state,
+            Some(avg_speed) => self.config.classify(window.state, avg_speed),
+        };
+        if state == window.state {
+            return None;
+        }
+
+        let event = CongestionEvent {
+            sensor_name: sensor_name.clone(),
+            lane: *lane,
+            previous_state: window.state,
+            state,
+            avg_speed: window.avg_speed().unwrap_or_default(),
+            flow_rate: window.flow_rate(self.config.window),
+            changed_at: now,
+        };
+        window.state = state;
+        window.since = now;
+        Some(event)
+    }
+
+    /// Returns the current status of every tracked lane, evicting readings older than the window
+    ///
+    /// Lanes are classified again once their old readings are evicted, the transitions are
+    /// published like the ones caused by readings.
+    pub fn snapshot(&self, now: DateTime<Utc>) -> Vec<CongestionStatus> {
+        let mut windows = self.windows.write().unwrap();
+
+        let mut events = Vec::new();
+        let mut statuses: Vec<CongestionStatus> = windows
+            .iter_mut()
+            .map(|(key, window)| {
+                window.evict(now, self.config.window);
+                events.extend(self.update_state(key, window, now));
+                let (sensor_name, lane) = key;
+                CongestionStatus {
+                    sensor_name: sensor_name.clone(),
+                    lane: *lane,
+                    state: window.state,
+                    avg_speed: window.avg_speed(),
+                    flow_rate: window.flow_rate(self.config.window),
+                    samples: window.readings.len(),
+                    since: window.since,
+                }
+            })
+            .collect();
+        drop(windows);
+
+        for event in events {
+            let _ = self.events_tx.send(event);
+        }
+
+        statuses
+            .sort_by(|a, b| (&a.sensor_name, a.lane as u8).cmp(&(&b.sensor_name, b.lane as u8)));
+        statuses
+    }
+}
+
+/// Spawns the task feeding the monitor from the real-time speed broadcast channel
+pub fn spawn_congestion_monitor(
+    monitor: Arc<CongestionMonitor>,
+    mut rx: broadcast::Receiver<SpeedData>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(speed_data) => {
+                    if let Some(event) = monitor.record(&speed_data) {
+                        log_info!(
+                            "Traffic state of sensor {:?} lane {:?} changed from {:?} to {:?} (avg {:.1} km/h)",
+                            event.sensor_name,
+                            event.lane,
+                            event.previous_state,
+                            event.state,
+                            event.avg_speed
+                        );
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log_warn!("Congestion monitor lagged behind, skipped {skipped} readings");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::quality_flag::QualityFlag;
+    use chrono::TimeZone as _;
+
+    fn config() -> CongestionConfig {
+        CongestionConfig {
+            window: Duration::seconds(60),
+            dense_speed: 60.0,
+            congested_speed: 30.0,
+            hysteresis: 5.0,
+            min_samples: 3,
+            max_lanes: 2,
+        }
+    }
+
+    fn reading(secs: i64, speed: f32) -> SpeedData {
+        let created_at =
+            Utc.with_ymd_and_hms(2024, 1, 15, 8, 0, 0).unwrap() + Duration::seconds(secs);
+        SpeedData::new(1, Some("A1".to_string()), speed, Lane::Left, created_at)
+    }
+
+    #[test]
+    fn test_classify_without_history() {
+        let config = config();
+        assert_eq!(
+            config.classify(TrafficState::FreeFlow, 80.0),
+            TrafficState::FreeFlow
+        );
+        assert_eq!(
+            config.classify(TrafficState::FreeFlow, 45.0),
+            TrafficState::Dense
+        );
+        assert_eq!(
+            config.classify(TrafficState::FreeFlow, 12.0),
+            TrafficState::Congested
+        );
+    }
+
+    #[test]
+    fn test_classify_hysteresis() {
+        let config = config();
+        // Just above the congested threshold is not enough to leave congestion
+        assert_eq!(
+            config.classify(TrafficState::Congested, 32.0),
+            TrafficState::Congested
+        );
+        assert_eq!(
+            config.classify(TrafficState::Congested, 36.0),
+            TrafficState::Dense
+        );
+        // Just above the dense threshold is not enough to return to free flow
+        assert_eq!(
+            config.classify(TrafficState::Dense, 62.0),
+            TrafficState::Dense
+        );
+        assert_eq!(
+            config.classify(TrafficState::Dense, 65.0),
+            TrafficState::FreeFlow
+        );
+        // A big recovery can skip the intermediate state
+        assert_eq!(
+            config.classify(TrafficState::Congested, 90.0),
+            TrafficState::FreeFlow
+        );
+        // Degradation is immediate
+        assert_eq!(
+            config.classify(TrafficState::Dense, 29.0),
+            TrafficState::Congested
+        );
+    }
+
+    #[test]
+    fn test_record_requires_min_samples() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        assert!(monitor.record(&reading(0, 10.0)).is_none());
+        assert!(monitor.record(&reading(1, 10.0)).is_none());
+
+        let event = monitor.record(&reading(2, 10.0)).unwrap();
+        assert_eq!(event.previous_state, TrafficState::FreeFlow);
+        assert_eq!(event.state, TrafficState::Congested);
+        assert_eq!(event.avg_speed, 10.0);
+    }
+
+    #[test]
+    fn test_record_publishes_transitions_once() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        let mut rx = monitor.subscribe();
+
+        for secs in 0..5 {
+            monitor.record(&reading(secs, 20.0));
+        }
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.state, TrafficState::Congested);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_record_ignores_flagged_readings() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        for secs in 0..5 {
+            let mut data = reading(secs, 5.0);
+            data.quality_flag = Some(QualityFlag::StuckValue);
+            assert!(monitor.record(&data).is_none());
+        }
+        assert!(monitor.snapshot(reading(5, 0.0).created_at).is_empty());
+    }
+
+    #[test]
+    fn test_window_eviction() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        for secs in 0..3 {
+            monitor.record(&reading(secs, 20.0));
+        }
+
+        // Old slow readings leave the window, fast ones bring the lane back to free flow
+        for secs in 120..123 {
+            monitor.record(&reading(secs, 100.0));
+        }
+
+        let snapshot = monitor.snapshot(reading(123, 0.0).created_at);
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].state, TrafficState::FreeFlow);
+        assert_eq!(snapshot[0].samples, 3);
+        assert_eq!(snapshot[0].avg_speed, Some(100.0));
+        assert_eq!(snapshot[0].flow_rate, 180.0);
+    }
+
+    #[test]
+    fn test_snapshot_tracks_lanes_separately() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        let right = SpeedData::new(
+            2,
+            Some("A1".to_string()),
+            90.0,
+            Lane::Right,
+            reading(0, 0.0).created_at,
+        );
+        monitor.record(&reading(0, 20.0));
+        monitor.record(&right);
+
+        let snapshot = monitor.snapshot(reading(1, 0.0).created_at);
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].lane, Lane::Left);
+        assert_eq!(snapshot[1].lane, Lane::Right);
+    }
+
+    #[test]
+    fn test_snapshot_reclassifies_after_eviction() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        let mut rx = monitor.subscribe();
+        for secs in 0..3 {
+            monitor.record(&reading(secs, 20.0));
+        }
+        assert_eq!(rx.try_recv().unwrap().state, TrafficState::Congested);
+
+        // The slow readings left the window and no vehicle came since
+        let snapshot = monitor.snapshot(reading(120, 0.0).created_at);
+        assert_eq!(snapshot[0].state, TrafficState::FreeFlow);
+        assert_eq!(snapshot[0].since, reading(120, 0.0).created_at);
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.previous_state, TrafficState::Congested);
+        assert_eq!(event.state, TrafficState::FreeFlow);
+    }
+
+    #[test]
+    fn test_lanes_are_bounded() {
+        let monitor = CongestionMonitor::new(config(), 16);
+        let from = |sensor: &str, secs| {
+            let mut data = reading(secs, 50.0);
+            data.sensor_name = Some(sensor.to_string());
+            data
+        };
+        monitor.record(&from("A1", 0));
+        monitor.record(&from("A2", 0));
+        monitor.record(&from("A3", 1));
+        let sensors: Vec<_> = monitor
+            .snapshot(reading(1, 0.0).created_at)
+            .into_iter()
+            .map(|status| status.sensor_name.unwrap())
+            .collect();
+        assert_eq!(sensors, ["A1", "A2"]);
+
+        // Lanes without recent readings make room for new ones
+        monitor.record(&from("A3", 120));
+        let snapshot = monitor.snapshot(reading(120, 0.0).created_at);
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].sensor_name.as_deref(), Some("A3"));
+    }
+
+    #[test]
+    fn test_validate_thresholds() {
+        assert!(config().validate().is_ok());
+        let inverted = CongestionConfig {
+            congested_speed: 70.0,
+            ..config()
+        };
+        assert!(inverted.validate().is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::core::dto::flow_metrics::FlowMetrics;
+use crate::core::lane::Lane;
+use crate::core::time_bucket::TimeBucket;
+use crate::database::pool::DbPool;
+use crate::database::types::{DbError, FromPostgresRow};
+use crate::database::util::{RANGE_QUERY_TIMEOUT, with_timeout};
+use crate::log_error;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+/// Computes the flow rate in vehicles per hour from a vehicle count over an observation window
+#[must_use]
+pub fn flow_rate(volume: i64, observed_secs: f64) -> Option<f64> {
+    (observed_secs > 0.0).then(|| volume as f64 * 3600.0 / observed_secs)
+}
+
+/// Derives the traffic density in vehicles per km from the fundamental relation `q = k * v`
+///
+/// Uses the space-mean speed (harmonic mean of spot speeds), which is the speed that relates flow and density.
+#[must_use]
+pub fn density(flow_rate: Option<f64>, space_mean_speed: Option<f64>) -> Option<f64> {
+    match (flow_rate, space_mean_speed) {
+        (Some(flow), Some(speed)) if speed > 0.0 => Some(flow / speed),
+        _ => None,
+    }
+}
+
+/// Estimates the occupancy as the percentage of the observation window during which a vehicle was over the detector
+///
+/// `occupied_secs` is the sum of `length / speed` over the vehicles that reported a length.
+#[must_use]
+pub fn occupancy(occupied_secs: Option<f64>, observed_secs: f64) -> Option<f64> {
+    match occupied_secs {
+        Some(occupied) if observed_secs > 0.0 => {
+            Some((occupied / observed_secs * 100.0).min(100.0))
+        }
+        _ => None,
+    }
+}
+
+/// Fetches traffic flow metrics per time bucket, sensor and lane within a specified date range
+///
+/// Headways are computed with a window function over consecutive readings of the same sensor and lane.
+/// The observation window of each bucket is clipped to the requested range so partial buckets don't under-report flow.
+/// Buckets are truncated in the timezone `tz`, so daily buckets follow local midnight.
+/// Readings tagged by the fault detectors are skipped unless `include_flagged` is set.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_flow_metrics(
+    pool: &DbPool,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    bucket: TimeBucket,
+    sensor_name: Option<&str>,
+    lane: Option<Lane>,
+    include_flagged: bool,
+    tz: Tz,
+) -> Result<Vec<FlowMetrics>, DbError> {
+    const QUERY: &str = "WITH readings AS (\
+            SELECT sensor_name, lane, speed, vehicle_length, created_at, \
+                   created_at - lag(created_at) OVER (PARTITION BY sensor_name, lane ORDER BY created_at, id) AS headway \
+            FROM speed \
+            WHERE created_at >= $2 AND created_at <= $3 AND ($4::text IS NULL OR sensor_name = $4) AND ($5::int IS NULL OR lane = $5) AND ($6 OR quality_flag IS NULL)\
+        ), buckets AS (\
+            SELECT date_trunc($1, created_at, $7) AS bucket_start, sensor_name, lane, count(*) AS volume, \
+                   avg(extract(epoch FROM headway))::float8 AS avg_headway_secs, \
+                   (count(*) FILTER (WHERE speed > 0) / nullif(sum(1.0 / speed) FILTER (WHERE speed > 0), 0))::float8 AS space_mean_speed, \
+                   sum(vehicle_length * 3.6 / speed) FILTER (WHERE speed > 0 AND vehicle_length IS NOT NULL)::float8 AS occupied_secs \
+            FROM readings GROUP BY 1, 2, 3\
+        ) \
+        SELECT bucket_start, sensor_name, lane, volume, avg_headway_secs, space_mean_speed, occupied_secs, \
+               extract(epoch FROM least(((bucket_start AT TIME ZONE $7) + ('1 ' || $1)::interval) AT TIME ZONE $7, $3) - greatest(bucket_start, $2))::float8 AS observed_secs \
+        FROM buckets ORDER BY 1, 2, 3";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[
+                    &bucket.as_str(),
+                    &start_date,
+                    &end_date,
+                    &sensor_name,
+                    &lane.map(|l| l as i32),
+                    &include_flagged,
+                    &tz.name(),
+                ],
+            )
+            .await
+            .map_err(DbError::from)?;
+
+        rows.iter()
+            .map(FlowMetrics::from_row)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch flow metrics: {e}");
+            e
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flow_rate() {
+        assert_eq!(flow_rate(120, 3600.0), Some(120.0));
+        assert_eq!(flow_rate(30, 900.0), Some(120.0));
+        assert_eq!(flow_rate(10, 0.0), None);
+    }
+
+    #[test]
+    fn test_density() {
+        assert_eq!(density(Some(1800.0), Some(60.0)), Some(30.0));
+        assert_eq!(density(Some(1800.0), Some(0.0)), None);
+        assert_eq!(density(None, Some(60.0)), None);
+        assert_eq!(density(Some(1800.0), None), None);
+    }
+
+    #[test]
+    fn test_occupancy() {
+        // 36 seconds occupied over one hour
+        assert_eq!(occupancy(Some(36.0), 3600.0), Some(1.0));
+        assert_eq!(occupancy(None, 3600.0), None);
+        assert_eq!(occupancy(Some(36.0), 0.0), None);
+        // Overlapping vehicles on multi-sensor setups can't exceed full occupancy
+        assert_eq!(occupancy(Some(7200.0), 3600.0), Some(100.0));
+    }
+}
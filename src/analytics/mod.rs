@@ -0,0 +1,3 @@
+pub mod congestion;
+pub mod flow;
+pub mod quality;
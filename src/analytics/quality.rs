@@ -0,0 +1,317 @@
+use crate::config::constant::{
+    QUALITY_BASELINE_SIZE, QUALITY_IDLE_SECS, QUALITY_JUMP_KMH, QUALITY_JUMP_WINDOW_SECS,
+    QUALITY_MAX_SENSORS, QUALITY_MAX_SPEED_KMH, QUALITY_MAX_ZSCORE, QUALITY_MIN_BASELINE,
+    QUALITY_STUCK_COUNT,
+};
+use crate::core::lane::Lane;
+use crate::core::quality_flag::QualityFlag;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Thresholds used by the sensor fault detectors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultDetectorConfig {
+    pub stuck_count: usize,    // Consecutive identical readings flagged as stuck
+    pub jump_kmh: f64,         // Speed change between readings flagged as a jump
+    pub jump_window: Duration, // Maximum delay between readings for the jump check
+    pub max_zscore: f64,       // Z-score flagged as out of distribution
+    pub baseline_size: usize,  // Valid readings kept as rolling baseline
+    pub min_baseline: usize,   // Baseline size required for the z-score check
+    pub max_speed: f64,        // Highest plausible speed in km/h
+    pub max_sensors: usize,    // Maximum sensor and lane histories tracked at once
+    pub idle_after: Duration,  // Delay without readings before a history can be evicted
+}
+
+impl FaultDetectorConfig {
+    /// Builds the configuration from the `QUALITY_*` environment variables
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            stuck_count: *QUALITY_STUCK_COUNT,
+            jump_kmh: *QUALITY_JUMP_KMH,
+            jump_window: Duration::seconds(*QUALITY_JUMP_WINDOW_SECS as i64),
+            max_zscore: *QUALITY_MAX_ZSCORE,
+            baseline_size: *QUALITY_BASELINE_SIZE,
+            min_baseline: *QUALITY_MIN_BASELINE,
+            max_speed: *QUALITY_MAX_SPEED_KMH,
+            max_sensors: *QUALITY_MAX_SENSORS,
+            idle_after: Duration::seconds(*QUALITY_IDLE_SECS as i64),
+        }
+    }
+}
+
+/// Detection state of one sensor and lane
+#[derive(Debug, Default)]
+struct SensorHistory {
+    last: Option<(DateTime<Utc>, f32)>, // Last unflagged reading, compared for jumps
+    last_speed: Option<f32>,            // Last reading, flagged or not, compared for repeats
+    repeats: usize,
+    baseline: VecDeque<f64>,
+    sum: f64,
+    sum_sq: f64,
+    seen: DateTime<Utc>, // Time of the last reading
+}
+
+impl SensorHistory {
+    fn push_baseline(&mut self, speed: f64, capacity: usize) {
+        self.baseline.push_back(speed);
+        self.sum += speed;
+        self.sum_sq += speed * speed;
+
+        while self.baseline.len() > capacity {
+            if let Some(old) = self.baseline.pop_front() {
+                self.sum -= old;
+                self.sum_sq -= old * old;
+            }
+        }
+    }
+
+    /// Returns the mean and standard deviation of the baseline
+    fn stats(&self) -> (f64, f64) {
+        let n = self.baseline.len() as f64;
+        let mean = self.sum / n;
+        let variance = (self.sum_sq / n - mean * mean).max(0.0);
+        (mean, variance.sqrt())
+    }
+}
+
+/// Detects stuck values, sudden jumps and out-of-distribution readings per sensor and lane
+///
+/// Flagged readings don't feed the baseline, so a faulty burst can't shift what is considered normal.
+/// Nor are they the reference of the jump check: the reading following a spike is compared with
+/// the one before it.
+pub struct FaultDetector {
+    config: FaultDetectorConfig,
+    histories: Mutex<HashMap<(Option<String>, Lane), SensorHistory>>,
+}
+
+impl FaultDetector {
+    /// Creates a new detector with the given thresholds
+    #[must_use]
+    pub fn new(config: FaultDetectorConfig) -> Self {
+        Self {
+            config,
+            histories: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs every detector on a reading and returns the first fault found, if any
+    ///
+    /// Once `max_sensors` histories are tracked, readings of other sensors and lanes only go
+    /// through the checks needing no history, until idle histories are evicted.
+    pub fn check(
+        &self,
+        sensor_name: Option<&str>,
+        lane: Lane,
+        speed: f32,
+        now: DateTime<Utc>,
+    ) -> Option<QualityFlag> {
+        let key = (sensor_name.map(str::to_string), lane);
+        let mut histories = self.histories.lock().unwrap();
+        if !histories.contains_key(&key) && histories.len() >= self.config.max_sensors {
+            // Sensor names come from the clients, sensors without recent readings make room
+            histories.retain(|_, history| now - history.seen <= self.config.idle_after);
+        }
+        let mut untracked = SensorHistory::default();
+        let history = if histories.len() < self.config.max_sensors {
+            histories.entry(key).or_default()
+        } else {
+            histories.get_mut(&key).unwrap_or(&mut untracked)
+        };
+        history.seen = now;
+
+        history.repeats = match history.last_speed.replace(speed) {
+            Some(last_speed) if last_speed == speed => history.repeats + 1,
+            _ => 1,
+        };
+
+        let flag = self.detect(history, speed, now);
+        if flag.is_none() {
+            history.last = Some((now, speed));
+            history.push_baseline(f64::from(speed), self.config.baseline_size);
+        }
+        flag
+    }
+
+    fn detect(
+        &self,
+        history: &SensorHistory,
+        speed: f32,
+        now: DateTime<Utc>,
+    ) -> Option<QualityFlag> {
+        let speed = f64::from(speed);
+
+        if history.repeats >= self.config.stuck_count {
+            return Some(QualityFlag::StuckValue);
+        }
+
+        if let Some((last_at, last_speed)) = history.last
+            && now - last_at <= self.config.jump_window
+            && (speed - f64::from(last_speed)).abs() > self.config.jump_kmh
+        {
+            return Some(QualityFlag::SuddenJump);
+        }
+
+        if !speed.is_finite() || speed < 0.0 || speed > self.config.max_speed {
+            return Some(QualityFlag::OutOfDistribution);
+        }
+
+        if history.baseline.len() >= self.config.min_baseline {
+            let (mean, std_dev) = history.stats();
+            if std_dev > 0.0 && ((speed - mean) / std_dev).abs() > self.config.max_zscore {
+                return Some(QualityFlag::OutOfDistribution);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    fn config() -> FaultDetectorConfig {
+        FaultDetectorConfig {
+            stuck_count: 3,
+            jump_kmh: 80.0,
+            jump_window: Duration::seconds(2),
+            max_zscore: 3.0,
+            baseline_size: 50,
+            min_baseline: 10,
+            max_speed: 300.0,
+            max_sensors: 2,
+            idle_after: Duration::seconds(60),
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 15, 8, 0, 0).unwrap() + Duration::seconds(secs)
+    }
+
+    #[test]
+    fn test_stuck_value() {
+        let detector = FaultDetector::new(config());
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 50.0, at(0)), None);
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 50.0, at(10)), None);
+        assert_eq!(
+            detector.check(Some("A1"), Lane::Left, 50.0, at(20)),
+            Some(QualityFlag::StuckValue)
+        );
+        // A different value resets the counter
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 51.0, at(30)), None);
+    }
+
+    #[test]
+    fn test_sudden_jump() {
+        let detector = FaultDetector::new(config());
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 40.0, at(0)), None);
+        assert_eq!(
+            detector.check(Some("A1"), Lane::Left, 140.0, at(1)),
+            Some(QualityFlag::SuddenJump)
+        );
+        // The same change over a longer delay is two different vehicles
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 40.0, at(30)), None);
+    }
+
+    #[test]
+    fn test_normal_reading_after_spike() {
+        let detector = FaultDetector::new(config());
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 40.0, at(0)), None);
+        assert_eq!(
+            detector.check(Some("A1"), Lane::Left, 180.0, at(1)),
+            Some(QualityFlag::SuddenJump)
+        );
+        // Compared with the reading before the spike, not with the spike
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 42.0, at(2)), None);
+    }
+
+    #[test]
+    fn test_impossible_speed() {
+        let detector = FaultDetector::new(config());
+        assert_eq!(
+            detector.check(None, Lane::Right, 450.0, at(0)),
+            Some(QualityFlag::OutOfDistribution)
+        );
+        assert_eq!(
+            detector.check(None, Lane::Right, -3.0, at(10)),
+            Some(QualityFlag::OutOfDistribution)
+        );
+    }
+
+    #[test]
+    fn test_out_of_distribution_against_baseline() {
+        let detector = FaultDetector::new(config());
+        for i in 0..20 {
+            let speed = 50.0 + (i % 5) as f32;
+            assert_eq!(
+                detector.check(Some("A1"), Lane::Left, speed, at(i * 10)),
+                None
+            );
+        }
+
+        assert_eq!(
+            detector.check(Some("A1"), Lane::Left, 120.0, at(300)),
+            Some(QualityFlag::OutOfDistribution)
+        );
+        assert_eq!(detector.check(Some("A1"), Lane::Left, 53.5, at(310)), None);
+    }
+
+    #[test]
+    fn test_flagged_readings_do_not_feed_baseline() {
+        let detector = FaultDetector::new(config());
+        for i in 0..10 {
+            detector.check(Some("A1"), Lane::Left, 50.0 + (i % 3) as f32, at(i * 10));
+        }
+        for i in 0..5 {
+            let flag = detector.check(Some("A1"), Lane::Left, 200.0 + i as f32, at(200 + i * 10));
+            assert_eq!(flag, Some(QualityFlag::OutOfDistribution));
+        }
+    }
+
+    #[test]
+    fn test_histories_are_bounded() {
+        let detector = FaultDetector::new(config());
+        let tracked = || detector.histories.lock().unwrap().len();
+        detector.check(Some("A1"), Lane::Left, 50.0, at(0));
+        detector.check(Some("B2"), Lane::Left, 50.0, at(1));
+
+        // A flood of sensor names doesn't grow the map, and the checks needing no history apply
+        for i in 0..100 {
+            let sensor = format!("flood-{i}");
+            assert_eq!(detector.check(Some(&sensor), Lane::Left, 50.0, at(2)), None);
+            assert_eq!(
+                detector.check(Some(&sensor), Lane::Left, 450.0, at(2)),
+                Some(QualityFlag::OutOfDistribution)
+            );
+        }
+        assert_eq!(tracked(), 2);
+
+        // Tracked sensors keep their history
+        detector.check(Some("A1"), Lane::Left, 50.0, at(30));
+        assert_eq!(
+            detector.check(Some("A1"), Lane::Left, 50.0, at(40)),
+            Some(QualityFlag::StuckValue)
+        );
+
+        // Idle sensors make room for new ones
+        detector.check(Some("C3"), Lane::Left, 50.0, at(100));
+        assert_eq!(tracked(), 2);
+        detector.check(Some("C3"), Lane::Left, 50.0, at(110));
+        assert_eq!(
+            detector.check(Some("C3"), Lane::Left, 50.0, at(120)),
+            Some(QualityFlag::StuckValue)
+        );
+    }
+
+    #[test]
+    fn test_sensors_and_lanes_are_independent() {
+        let detector = FaultDetector::new(config());
+        detector.check(Some("A1"), Lane::Left, 50.0, at(0));
+        detector.check(Some("A1"), Lane::Left, 50.0, at(1));
+        assert_eq!(detector.check(Some("A1"), Lane::Right, 50.0, at(2)), None);
+        assert_eq!(detector.check(Some("B2"), Lane::Left, 50.0, at(3)), None);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::api::handler::postgres_pool;
+use crate::api::query::audit_query::AuditQuery;
+use crate::api::query::date_range_query::DateRangeQuery;
+use crate::api::query::query_limit::QueryLimit;
+use crate::api::query::timezone_query::TimezoneQuery;
+use crate::core::app_state::AppState;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::database::retention::{PurgeReport, RetentionConfig, preview_purge};
+use crate::log_error;
+use crate::middleware::brute_force::AuthMetrics;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use chrono::Utc;
+use serde::Serialize;
+
+/// Retention settings and the rows the next purge will delete
+#[derive(Debug, Serialize)]
+pub struct RetentionStatus {
+    pub dry_run: bool,      // Purges only report, nothing is deleted
+    pub interval_secs: u64, // Delay between two purges
+    pub batch_size: i64,    // Maximum rows deleted per statement
+    pub tables: Vec<PurgeReport>,
+}
+
+/// Shows the retention policies and what will be purged next
+pub async fn get_retention_status(
+    State(state): State<AppState>,
+) -> Result<Json<RetentionStatus>, StatusCode> {
+    let pool = postgres_pool(&state)?;
+    let config = RetentionConfig::from_env();
+
+    match preview_purge(pool, &config, Utc::now()).await {
+        Ok(tables) => Ok(Json(RetentionStatus {
+            dry_run: config.dry_run,
+            interval_secs: config.interval.as_secs(),
+            batch_size: config.batch_size,
+            tables,
+        })),
+        Err(e) => {
+            log_error!("Error previewing retention purge: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Lists the audit events of a time range, most recent first
+///
+/// Without `start_date`, the events of the last 24 hours are listed.
+pub async fn get_audit_events(
+    State(state): State<AppState>,
+    Query(mut range): Query<DateRangeQuery>,
+    Query(filter): Query<AuditQuery>,
+    Query(params): Query<QueryLimit>,
+    Query(tz): Query<TimezoneQuery>,
+) -> Result<Json<Vec<AuditEvent>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).min(1000);
+
+    if range.start_date.is_none() {
+        range.start_date = Some("now-24h".to_string());
+    }
+    let (start_date, end_date) = match range.resolve(tz.timezone(), Utc::now()) {
+        Ok(range) => range,
+        Err(e) => {
+            log_error!("Invalid date range: {e:?}");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    match state
+        .repository
+        .fetch_audit_events(
+            start_date,
+            end_date,
+            filter.action,
+            filter.key_id,
+            u32::from(limit),
+        )
+        .await
+    {
+        Ok(events) => Ok(Json(events)),
+        Err(e) => {
+            log_error!("Error fetching audit events: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Shows the requests refused by the brute-force protection since this instance started
+///
+/// Returns 404 Not Found when the protection is disabled.
+pub async fn get_auth_metrics(
+    State(state): State<AppState>,
+) -> Result<Json<AuthMetrics>, StatusCode> {
+    state
+        .brute_force
+        .as_ref()
+        .map(|guard| Json(guard.metrics()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
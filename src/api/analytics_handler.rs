@@ -0,0 +1,91 @@
+use crate::analytics::congestion::CongestionStatus;
+use crate::analytics::flow::fetch_flow_metrics;
+use crate::api::handler::{postgres_pool, with_cache_headers};
+use crate::api::query::date_range_query::DateRangeQuery;
+use crate::api::query::flow_query::FlowQuery;
+use crate::api::query::timezone_query::TimezoneQuery;
+use crate::api::query::units_query::UnitsQuery;
+use crate::core::app_state::AppState;
+use crate::log_error;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, Sse};
+use axum::response::{Json, Response};
+use chrono::Utc;
+use futures_util::stream::Stream;
+use std::convert::Infallible;
+
+/// Retrieves traffic flow metrics (volume, flow rate, headway, density, occupancy) per time bucket
+pub async fn get_flow_metrics(
+    State(state): State<AppState>,
+    Query(range): Query<DateRangeQuery>,
+    Query(params): Query<FlowQuery>,
+    Query(units): Query<UnitsQuery>,
+    Query(tz): Query<TimezoneQuery>,
+) -> Result<Response, StatusCode> {
+    let pool = postgres_pool(&state)?;
+    let tz = tz.timezone();
+
+    let (start_date, end_date) = range.resolve(tz, Utc::now()).map_err(|e| {
+        log_error!("Invalid date range: {e:?}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    match fetch_flow_metrics(
+        pool,
+        start_date,
+        end_date,
+        params.bucket.unwrap_or_default(),
+        params.sensor_name.as_deref(),
+        params.lane,
+        params.include_flagged.unwrap_or(false),
+        tz,
+    )
+    .await
+    {
+        Ok(data) => {
+            let unit = units.unit();
+            let data: Vec<_> = data.into_iter().map(|m| m.in_unit(unit)).collect();
+            Ok(with_cache_headers(Json(data), 60)) // Cache for 60 seconds
+        }
+        Err(e) => {
+            log_error!("Error fetching flow metrics: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Retrieves the current traffic state of every sensor and lane seen in the rolling window
+pub async fn get_congestion_snapshot(
+    State(state): State<AppState>,
+    Query(units): Query<UnitsQuery>,
+) -> Json<Vec<CongestionStatus>> {
+    let unit = units.unit();
+    Json(
+        state
+            .congestion
+            .snapshot(Utc::now())
+            .into_iter()
+            .map(|s| s.in_unit(unit))
+            .collect(),
+    )
+}
+
+/// Server-Sent Events endpoint for traffic state transitions (free flow, dense, congested)
+pub async fn congestion_stream(
+    State(state): State<AppState>,
+    Query(units): Query<UnitsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut rx = state.congestion.subscribe();
+    let unit = units.unit();
+
+    let stream = async_stream::stream! {
+        while let Ok(event) = rx.recv().await {
+            if let Ok(json) = serde_json::to_string(&event.in_unit(unit)) {
+                yield Ok(Event::default().event("congestion").data(json));
+            }
+        }
+    };
+
+    Sse::new(stream)
+}
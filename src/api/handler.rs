@@ -1,23 +1,36 @@
 use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::AggregateQuery;
 use crate::api::query::date_range_query::DateRangeQuery;
 use crate::api::query::pagination_query::PaginationQuery;
 use crate::api::query::query_limit::QueryLimit;
+use crate::api::query::speed_filter_query::SpeedFilterQuery;
+use crate::api::query::timezone_query::TimezoneQuery;
+use crate::api::query::units_query::UnitsQuery;
+use crate::core::api_key::AuthenticatedKey;
 use crate::core::app_state::AppState;
 use crate::core::dto::speed_data::SpeedData;
+use crate::core::lane::Lane;
+use crate::core::local_time::today_bounds;
+use crate::core::speed_unit::SpeedUnit;
 use crate::database::cache::*;
-use crate::database::crud::*;
-use crate::log_error;
-use axum::extract::Query;
+use crate::database::pool::DbPool;
+use crate::database::retention::RetentionConfig;
+use crate::database::rollup::{choose_rollup, fetch_rollup_aggregates};
+use crate::{log_error, log_warn};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Extension, Query};
 use axum::http::{header, HeaderValue};
 use axum::response::sse::{Event, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::Utc;
 use futures_util::stream::Stream;
+use serde::Serialize;
 use std::convert::Infallible;
 
 /// Adds Cache-Control headers to a JSON response
 #[inline]
-fn with_cache_headers<T>(data: Json<T>, max_age: u32) -> Response
+pub(crate) fn with_cache_headers<T>(data: Json<T>, max_age: u32) -> Response
 where
     T: serde::Serialize,
 {
@@ -29,38 +42,110 @@ where
     response
 }
 
-/// Handler functions for the API
-pub async fn health_check(State(mut state): State<AppState>) -> Result<Json<String>, StatusCode> {
-    let conn = match state.db.get().await {
-        Ok(conn) => conn,
-        Err(_) => return Err(StatusCode::SERVICE_UNAVAILABLE),
-    };
+/// Converts a list of speed data entries from the canonical km/h to the requested unit
+#[inline]
+fn in_unit(data: Vec<SpeedData>, unit: SpeedUnit) -> Vec<SpeedData> {
+    if unit == SpeedUnit::Kmh {
+        return data;
+    }
+    data.into_iter().map(|d| d.in_unit(unit)).collect()
+}
 
-    // Test postgres connection
-    match conn.query("SELECT 1", &[]).await {
-        Ok(_) => {
-            // Test Redis connection
-            match redis::cmd("PING")
-                .query_async::<String>(&mut state.redis)
-                .await
-            {
-                Ok(_) => Ok(Json(String::from("true"))),
-                Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
-            }
-        }
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+/// Returns the Postgres pool behind the features that need SQL, or 501 with another backend
+pub(crate) fn postgres_pool(state: &AppState) -> Result<&DbPool, StatusCode> {
+    state.db.as_ref().ok_or_else(|| {
+        log_error!("Endpoint requires the Postgres storage backend");
+        StatusCode::NOT_IMPLEMENTED
+    })
+}
+
+/// Health report returned by `/health`
+#[derive(Serialize)]
+pub struct HealthStatus {
+    pub status: &'static str,  // "ok", or "degraded" while the cache falls back to memory
+    pub storage: &'static str, // Always "up", storage failures are reported with a 503
+    pub cache: CacheStatus,
+}
+
+/// Handler functions for the API
+///
+/// Only a storage failure makes the service unhealthy; a cache failure is reported in the body
+/// since requests are still served without it.
+pub async fn health_check(State(state): State<AppState>) -> Result<Json<HealthStatus>, StatusCode> {
+    // Test the storage backend
+    if let Err(e) = state.repository.ping().await {
+        log_error!("Health check failed, storage unreachable: {e}");
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
     }
+
+    // Probe the cache, which also detects when Redis is back
+    let _ = state.cache.ping().await;
+    let cache = state.cache.status();
+
+    Ok(Json(HealthStatus {
+        status: if cache.degraded { "degraded" } else { "ok" },
+        storage: "up",
+        cache,
+    }))
 }
 
 /// Handler to create speed data from Arduino (with cache update and real-time broadcast)
+///
+/// The speed is converted to km/h if the payload uses another unit. Each reading then goes
+/// through the sensor fault detectors; suspicious readings are still stored, but tagged with a quality flag.
+/// Readings sent with a key bound to a sensor are attributed to it, and refused with 403 Forbidden
+/// when they name another sensor. Invalid readings, e.g. of an unknown class or a non-positive
+/// length, are refused with 400 Bad Request.
 pub async fn create_speed(
-    State(mut state): State<AppState>,
-    Json(payload): Json<CreateSpeedDataRequest>,
+    State(state): State<AppState>,
+    Extension(api_key): Extension<AuthenticatedKey>,
+    payload: Result<Json<CreateSpeedDataRequest>, JsonRejection>,
 ) -> Result<StatusCode, StatusCode> {
-    match insert_speed_data(&state.db, payload).await {
+    let Json(mut payload) = payload.map_err(|rejection| match rejection {
+        JsonRejection::JsonDataError(e) => {
+            log_warn!("Rejected reading of {api_key}: {e}");
+            StatusCode::BAD_REQUEST
+        }
+        rejection => rejection.status(),
+    })?;
+    if let Some(sensor_name) = &api_key.sensor_name
+        && let Err(reason) = payload.bind_sensor(sensor_name)
+    {
+        log_warn!(
+            "Rejected reading of {api_key} for sensor {:?}: {reason}",
+            payload.sensor_name
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+    payload.normalize_speed();
+    if let Err(reason) = payload.validate() {
+        log_warn!("Rejected reading of {api_key}: {reason}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let lane = Lane::try_from(i32::from(payload.lane)).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let quality_flag = state.fault_detector.check(
+        payload.sensor_name.as_deref().filter(|s| !s.is_empty()),
+        lane,
+        payload.speed,
+        Utc::now(),
+    );
+    if let Some(flag) = quality_flag {
+        log_warn!(
+            "Reading from sensor {:?} flagged as {}",
+            payload.sensor_name,
+            flag.as_str()
+        );
+    }
+
+    match state
+        .repository
+        .insert_speed_data(payload, quality_flag)
+        .await
+    {
         Ok(speed_data) => {
             // Update cache with the newly inserted data
-            if let Err(e) = set_last_speed_in_cache(&mut state.redis, &speed_data).await {
+            if let Err(e) = set_last_speed_in_cache(state.cache.as_ref(), &speed_data).await {
                 log_error!("Failed to update cache after insert: {e:?}");
             }
 
@@ -78,11 +163,17 @@ pub async fn create_speed(
 pub async fn get_last_n_speed(
     State(state): State<AppState>,
     Query(params): Query<QueryLimit>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
 ) -> Result<Json<Vec<SpeedData>>, StatusCode> {
     let limit: u16 = params.limit.unwrap_or(100).min(1000);
 
-    match fetch_last_n_speed_data(&state.db, limit).await {
-        Ok(data) => Ok(Json(data)),
+    match state
+        .repository
+        .fetch_last_n_speed_data(limit, filter.vehicle_class)
+        .await
+    {
+        Ok(data) => Ok(Json(in_unit(data, units.unit()))),
         Err(e) => {
             log_error!("Error fetching speed data: {e:?}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -94,12 +185,18 @@ pub async fn get_last_n_speed(
 pub async fn get_speed_pagination(
     State(state): State<AppState>,
     Query(params): Query<PaginationQuery>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
 ) -> Result<Json<Vec<SpeedData>>, StatusCode> {
     let offset: u32 = params.get_offset().unwrap_or(0);
     let limit: u32 = params.limit.unwrap_or(100).min(1000);
 
-    match fetch_speed_data_with_pagination(&state.db, offset, limit).await {
-        Ok(data) => Ok(Json(data)),
+    match state
+        .repository
+        .fetch_speed_data_with_pagination(offset, limit, filter.vehicle_class)
+        .await
+    {
+        Ok(data) => Ok(Json(in_unit(data, units.unit()))),
         Err(e) => {
             log_error!("Error fetching speed data with pagination: {e:?}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -107,17 +204,25 @@ pub async fn get_speed_pagination(
     }
 }
 
-/// Retrieves all speed data entries inserted today
+/// Retrieves all speed data entries inserted today, in the requested timezone
 pub async fn get_speed_today(
     State(state): State<AppState>,
     Query(params): Query<PaginationQuery>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
+    Query(tz): Query<TimezoneQuery>,
 ) -> Result<Response, StatusCode> {
     // Get limit as u32 and clamp to valid range (0-1000)
     let limit_u32 = params.limit.unwrap_or(100).min(1000);
     // Safe conversion to u16: min(1000) ensures value fits in u16::MAX (65535)
     let limit: u16 = limit_u32 as u16;
-    match fetch_speed_data_today(&state.db, limit).await {
-        Ok(data) => Ok(with_cache_headers(Json(data), 60)), // Cache for 60 seconds
+    let (day_start, day_end) = today_bounds(tz.timezone(), chrono::Utc::now());
+    match state
+        .repository
+        .fetch_speed_data_today(day_start, day_end, limit, filter.vehicle_class)
+        .await
+    {
+        Ok(data) => Ok(with_cache_headers(Json(in_unit(data, units.unit())), 60)), // Cache for 60 seconds
         Err(e) => {
             log_error!("Error fetching today's speed data: {e:?}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -126,26 +231,42 @@ pub async fn get_speed_today(
 }
 
 /// Retrieves the last speed data entry (with Redis caching)
-pub async fn get_last_speed(State(mut state): State<AppState>) -> Result<Response, StatusCode> {
+///
+/// The cache only holds the overall latest entry, so filtered requests go straight to the database.
+/// Returns 404 if there is no matching entry yet.
+pub async fn get_last_speed(
+    State(state): State<AppState>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
+) -> Result<Response, StatusCode> {
+    let unit = units.unit();
+
+    if let Some(vehicle_class) = filter.vehicle_class {
+        return match state.repository.fetch_last_speed(Some(vehicle_class)).await {
+            Ok(Some(data)) => Ok(with_cache_headers(Json(data.in_unit(unit)), 5)), // Cache for 5 seconds
+            Ok(None) => Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                log_error!("Error fetching last speed data: {e:?}");
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+    }
+
     // Try to get from cache first
-    match get_last_speed_from_cache(&mut state.redis).await {
-        Ok(Some(cached_data)) => {
-            return Ok(with_cache_headers(Json(cached_data), 5)); // Cache for 5 seconds
-        }
-        _ => {
-            // Cache miss or error, proceed to fetch from database
-        }
+    if let Ok(Some(cached_data)) = get_last_speed_from_cache(state.cache.as_ref()).await {
+        return Ok(with_cache_headers(Json(cached_data.in_unit(unit)), 5)); // Cache for 5 seconds
     }
 
     // If not in cache or cache error, fetch from database
-    match fetch_last_speed(&state.db).await {
-        Ok(data) => {
+    match state.repository.fetch_last_speed(None).await {
+        Ok(Some(data)) => {
             // Update cache asynchronously (best effort - don't fail if cache update fails)
-            if let Err(e) = set_last_speed_in_cache(&mut state.redis, &data).await {
+            if let Err(e) = set_last_speed_in_cache(state.cache.as_ref(), &data).await {
                 log_error!("Failed to update cache: {e:?}");
             }
-            Ok(with_cache_headers(Json(data), 5)) // Cache for 5 seconds
+            Ok(with_cache_headers(Json(data.in_unit(unit)), 5)) // Cache for 5 seconds
         }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
             log_error!("Error fetching last speed data: {e:?}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -162,29 +283,121 @@ pub async fn root() -> &'static str {
 pub async fn get_speed_by_date_range(
     State(state): State<AppState>,
     Query(params): Query<DateRangeQuery>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
+    Query(tz): Query<TimezoneQuery>,
 ) -> Result<Response, StatusCode> {
+    let tz = tz.timezone();
+
+    let now = chrono::Utc::now();
+
     // Parse the start and end dates
-    let start_date = match params.parse_start_date() {
-        Ok(date) => date,
+    let (start_date, end_date) = match params.resolve(tz, now) {
+        Ok(range) => range,
         Err(e) => {
-            log_error!("Invalid start_date format: {e:?}");
+            log_error!("Invalid date range: {e:?}");
             return Err(StatusCode::BAD_REQUEST);
         }
     };
 
-    let end_date = match params.parse_end_date() {
-        Ok(date) => date,
+    // Historical data doesn't change, but a range reaching the present does
+    let max_age = if end_date < now { 3600 } else { 60 };
+
+    // Fetch data from database
+    match state
+        .repository
+        .fetch_speed_data_by_date_range(start_date, end_date, filter.vehicle_class)
+        .await
+    {
+        Ok(data) => Ok(with_cache_headers(Json(in_unit(data, units.unit())), max_age)),
         Err(e) => {
-            log_error!("Invalid end_date format: {e:?}");
-            return Err(StatusCode::BAD_REQUEST);
+            log_error!("Error fetching speed data by date range: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Retrieves speed statistics aggregated per time bucket within a specified date range
+///
+/// With Postgres, long ranges are read from the rollups; other backends aggregate the raw readings.
+pub async fn get_speed_aggregates(
+    State(state): State<AppState>,
+    Query(range): Query<DateRangeQuery>,
+    Query(params): Query<AggregateQuery>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
+    Query(tz): Query<TimezoneQuery>,
+) -> Result<Response, StatusCode> {
+    let tz = tz.timezone();
+
+    let (start_date, end_date) = range.resolve(tz, chrono::Utc::now()).map_err(|e| {
+        log_error!("Invalid date range: {e:?}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let group_by = params.parse_group_by().map_err(|e| {
+        log_error!("Invalid group_by parameter: {e:?}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let bucket = params.bucket.unwrap_or_default();
+    let include_flagged = params.include_flagged.unwrap_or(false);
+
+    // Rollups only hold valid readings, flagged ones are always read from the raw table
+    let rollup = state
+        .db
+        .as_ref()
+        .filter(|_| !include_flagged)
+        .and_then(|pool| {
+            choose_rollup(
+                bucket,
+                tz,
+                start_date,
+                end_date,
+                &RetentionConfig::from_env(),
+                chrono::Utc::now(),
+            )
+            .map(|level| (pool, level))
+        });
+
+    let result = match rollup {
+        Some((pool, level)) => {
+            fetch_rollup_aggregates(
+                pool,
+                level,
+                start_date,
+                end_date,
+                bucket,
+                group_by,
+                filter.vehicle_class,
+                tz,
+            )
+            .await
+        }
+        None => {
+            state
+                .repository
+                .fetch_speed_aggregates(
+                    start_date,
+                    end_date,
+                    bucket,
+                    group_by,
+                    filter.vehicle_class,
+                    include_flagged,
+                    tz,
+                )
+                .await
         }
     };
 
-    // Fetch data from database
-    match fetch_speed_data_by_date_range(&state.db, start_date, end_date).await {
-        Ok(data) => Ok(with_cache_headers(Json(data), 3600)), // Cache for 1 hour (historical data)
+    match result {
+        Ok(data) => {
+            let unit = units.unit();
+            let data: Vec<_> = data.into_iter().map(|a| a.in_unit(unit)).collect();
+            Ok(with_cache_headers(Json(data), 60)) // Cache for 60 seconds
+        }
         Err(e) => {
-            log_error!("Error fetching speed data by date range: {e:?}");
+            log_error!("Error fetching speed aggregates: {e:?}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
@@ -194,15 +407,23 @@ pub async fn get_speed_by_date_range(
 /// Clients can connect to this endpoint to receive speed updates as they happen
 pub async fn speed_stream(
     State(state): State<AppState>,
+    Query(filter): Query<SpeedFilterQuery>,
+    Query(units): Query<UnitsQuery>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     // Subscribe to the broadcast channel
     let mut rx = state.broadcast_tx.subscribe();
+    let unit = units.unit();
 
     // Create a stream that yields SSE events
     let stream = async_stream::stream! {
         while let Ok(speed_data) = rx.recv().await {
-            // Serialize the speed data to JSON
-            if let Ok(json) = serde_json::to_string(&speed_data) {
+            // Skip readings that don't match the requested vehicle class
+            if filter.vehicle_class.is_some() && speed_data.vehicle_class != filter.vehicle_class {
+                continue;
+            }
+
+            // Serialize the speed data to JSON, in the unit requested by this client
+            if let Ok(json) = serde_json::to_string(&speed_data.in_unit(unit)) {
                 // Yield an SSE event with the JSON data
                 yield Ok(Event::default().data(json));
             }
@@ -0,0 +1,226 @@
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::core::api_key::{AuthenticatedKey, generate_api_key, hash_api_key, key_prefix};
+use crate::core::app_state::AppState;
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use crate::database::cache::{invalidate_signing_key_cache, invalidate_token_cache};
+use crate::telemetry::audit::AuditContext;
+use crate::{log_error, log_info, log_warn};
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::Serialize;
+
+/// API key returned once on creation, with its secret
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+    pub api_key: String, // Secret to send as Bearer token, never returned again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>, // Secret signing the readings, when signing is enabled
+}
+
+/// New secret of a rotated API key, returned once
+#[derive(Debug, Serialize)]
+pub struct RotatedApiKey {
+    pub id: i32,
+    pub key_prefix: String, // First characters of the new secret
+    pub api_key: String,    // New secret, the previous one is refused from now on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>, // New signing secret, when signing is enabled
+}
+
+/// Signing secret of an API key, for the keys created before signing was enabled
+#[derive(Debug, Serialize)]
+pub struct KeySigningSecret {
+    pub id: i32,
+    pub signing_secret: String, // Secret signing the readings
+}
+
+/// Creates an API key and returns its secret, which can't be retrieved later
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    context: AuditContext,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), StatusCode> {
+    if let Err(reason) = payload.validate() {
+        log_warn!("Rejected API key creation: {reason}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let api_key = generate_api_key();
+    let key_hash = hash_api_key(&api_key);
+    match state
+        .repository
+        .create_api_key(payload, &key_hash, &key_prefix(&api_key))
+        .await
+    {
+        Ok(info) => {
+            log_info!("Created API key {} ({:?})", info.id, info.name);
+            state.record_audit(context.event(AuditAction::KeyCreated).on_key(info.id));
+            Ok((
+                StatusCode::CREATED,
+                Json(CreatedApiKey {
+                    info,
+                    api_key,
+                    signing_secret: signing_secret(&state, &key_hash),
+                }),
+            ))
+        }
+        Err(e) => {
+            log_error!("Error creating API key: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Lists the API keys, masked to the first characters of their secret
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiKeyInfo>>, StatusCode> {
+    match state.repository.list_api_keys().await {
+        Ok(keys) => Ok(Json(keys)),
+        Err(e) => {
+            log_error!("Error listing API keys: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Replaces the secret of an API key, the previous one is refused immediately
+pub async fn rotate_api_key(
+    State(state): State<AppState>,
+    context: AuditContext,
+    Path(id): Path<i32>,
+) -> Result<Json<RotatedApiKey>, StatusCode> {
+    let api_key = generate_api_key();
+    let key_prefix = key_prefix(&api_key);
+    let key_hash = hash_api_key(&api_key);
+
+    match state
+        .repository
+        .rotate_api_key(id, &key_hash, &key_prefix)
+        .await
+    {
+        Ok(Some(previous_hash)) => {
+            forget_api_key(&state, id, &previous_hash).await;
+            log_info!("Rotated API key {id}");
+            state.record_audit(context.event(AuditAction::KeyRotated).on_key(id));
+            Ok(Json(RotatedApiKey {
+                id,
+                key_prefix,
+                api_key,
+                signing_secret: signing_secret(&state, &key_hash),
+            }))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log_error!("Error rotating API key {id}: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Deactivates an API key, which is refused from the next request on
+///
+/// Admins can't deactivate the key they are using, so they can't lock themselves out.
+pub async fn deactivate_api_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AuthenticatedKey>,
+    context: AuditContext,
+    Path(id): Path<i32>,
+) -> StatusCode {
+    if caller.id == Some(id) {
+        return StatusCode::CONFLICT;
+    }
+
+    match state.repository.deactivate_api_key(id).await {
+        Ok(Some(key_hash)) => {
+            forget_api_key(&state, id, &key_hash).await;
+            log_info!("Deactivated API key {id}");
+            state.record_audit(context.event(AuditAction::KeyDeactivated).on_key(id));
+            StatusCode::NO_CONTENT
+        }
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            log_error!("Error deactivating API key {id}: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Deletes an API key, which is refused from the next request on
+pub async fn delete_api_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AuthenticatedKey>,
+    context: AuditContext,
+    Path(id): Path<i32>,
+) -> StatusCode {
+    if caller.id == Some(id) {
+        return StatusCode::CONFLICT;
+    }
+
+    match state.repository.delete_api_key(id).await {
+        Ok(Some(key_hash)) => {
+            forget_api_key(&state, id, &key_hash).await;
+            log_info!("Deleted API key {id}");
+            state.record_audit(context.event(AuditAction::KeyDeleted).on_key(id));
+            StatusCode::NO_CONTENT
+        }
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            log_error!("Error deleting API key {id}: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Returns the signing secret of an active API key
+///
+/// Keys created or rotated while `SIGNING_SECRET` was not set never got theirs. Returns 404
+/// when signing is disabled, like for unknown and inactive keys.
+pub async fn get_signing_secret(
+    State(state): State<AppState>,
+    context: AuditContext,
+    Path(id): Path<i32>,
+) -> Result<Json<KeySigningSecret>, StatusCode> {
+    if state.signing.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match state.repository.validate_key_id(id).await {
+        Ok(Some((_, key_hash))) => {
+            log_info!("Retrieved the signing secret of API key {id}");
+            state.record_audit(context.event(AuditAction::SigningSecretRead).on_key(id));
+            Ok(Json(KeySigningSecret {
+                id,
+                signing_secret: signing_secret(&state, &key_hash).unwrap_or_default(),
+            }))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log_error!("Error retrieving the signing secret of API key {id}: {e:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Drops a key from the token and signing key caches, so it isn't accepted until the entries
+/// expire
+async fn forget_api_key(state: &AppState, id: i32, key_hash: &str) {
+    if let Err(e) = invalidate_token_cache(state.cache.as_ref(), key_hash).await {
+        log_error!("Failed to invalidate token cache after a key change: {e:?}");
+    }
+    if let Err(e) = invalidate_signing_key_cache(state.cache.as_ref(), id).await {
+        log_error!("Failed to invalidate signing key cache after a key change: {e:?}");
+    }
+}
+
+/// Returns the signing secret of the key with this hash, when signed readings are accepted
+fn signing_secret(state: &AppState, key_hash: &str) -> Option<String> {
+    state
+        .signing
+        .as_ref()
+        .map(|signing| signing.signing_secret(key_hash))
+}
@@ -1,4 +1,9 @@
+pub mod admin_handler;
+pub mod analytics_handler;
 pub mod handler;
+pub mod key_handler;
 pub mod payload;
 
 pub mod query;
+pub mod router;
+pub mod sync_handler;
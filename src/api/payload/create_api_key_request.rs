@@ -0,0 +1,86 @@
+use crate::core::api_key_scope::ApiKeyScope;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Maximum length of the name and owner of a key
+const MAX_LABEL_LENGTH: usize = 128;
+
+/// Represents a request to create an API key, whose secret is generated by the server
+#[non_exhaustive]
+#[must_use]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String, // Human readable name, e.g. the site or the device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>, // Optional person or team responsible for the key
+    #[serde(default = "ApiKeyScope::defaults")]
+    pub scopes: Vec<ApiKeyScope>, // Scopes of the key, all but `admin` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_name: Option<String>, // Optional sensor the readings sent with the key come from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>, // Optional expiry, the key never expires by default
+}
+
+impl CreateApiKeyRequest {
+    /// Checks the request can be stored, returning the reason otherwise
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.name.trim().is_empty() || self.name.len() > MAX_LABEL_LENGTH {
+            return Err("name must be between 1 and 128 characters");
+        }
+        if self
+            .owner
+            .as_ref()
+            .is_some_and(|owner| owner.len() > MAX_LABEL_LENGTH)
+        {
+            return Err("owner must be at most 128 characters");
+        }
+        if self
+            .sensor_name
+            .as_ref()
+            .is_some_and(|sensor| sensor.trim().is_empty() || sensor.len() > MAX_LABEL_LENGTH)
+        {
+            return Err("sensor_name must be between 1 and 128 characters");
+        }
+        if self.scopes.is_empty() {
+            return Err("scopes must not be empty");
+        }
+        if self
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err("expires_at must be in the future");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_api_key_request() {
+        let request: CreateApiKeyRequest = serde_json::from_str(r#"{"name":"Site A"}"#).unwrap();
+        assert_eq!(request.scopes, ApiKeyScope::defaults());
+        assert_eq!(request.owner, None);
+        assert!(request.validate().is_ok());
+
+        let request: CreateApiKeyRequest =
+            serde_json::from_str(r#"{"name":" ","owner":"ops","scopes":["admin"]}"#).unwrap();
+        assert_eq!(request.scopes, [ApiKeyScope::Admin]);
+        assert!(request.validate().is_err());
+
+        let request: CreateApiKeyRequest =
+            serde_json::from_str(r#"{"name":"Site A","scopes":[]}"#).unwrap();
+        assert!(request.validate().is_err());
+
+        let request: CreateApiKeyRequest =
+            serde_json::from_str(r#"{"name":"Site A","sensor_name":""}"#).unwrap();
+        assert!(request.validate().is_err());
+
+        let request: CreateApiKeyRequest =
+            serde_json::from_str(r#"{"name":"Site A","expires_at":"2020-01-01T00:00:00Z"}"#)
+                .unwrap();
+        assert!(request.validate().is_err());
+    }
+}
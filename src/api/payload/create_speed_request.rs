@@ -1,14 +1,61 @@
+use crate::core::speed_unit::SpeedUnit;
+use crate::core::vehicle_class::VehicleClass;
 use serde::{Deserialize, Serialize};
 
 /// Represents a request to create speed data
+///
+/// There is no `created_at` field here, as it is handled by the database since Arduino don't have correct time
 #[non_exhaustive]
 #[must_use]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateSpeedDataRequest {
     pub sensor_name: Option<String>, // Optional sensor name
-    pub speed: f32,                  // Speed in km/h
+    pub speed: f32,                  // Speed in `unit`, km/h by default
     pub lane: u8, // Lane represented as an unsigned 8-bit integer, see `Lane` enum for details
-                  // no created_at field here, as it will be handled by the database since Arduino don't have correct time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_class: Option<VehicleClass>, // Optional class reported by classifying sensors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_length: Option<f32>, // Optional vehicle length in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<SpeedUnit>, // Optional unit of `speed`, defaults to km/h
+}
+
+impl CreateSpeedDataRequest {
+    /// Attributes the reading to the sensor an API key is bound to
+    ///
+    /// The reading may omit its sensor name, but not give another one.
+    pub fn bind_sensor(&mut self, sensor_name: &str) -> Result<(), &'static str> {
+        match self.sensor_name.as_deref() {
+            Some(name) if !name.is_empty() && name != sensor_name => {
+                Err("sensor_name doesn't match the API key")
+            }
+            _ => {
+                self.sensor_name = Some(sensor_name.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks the reading can be stored, returning the reason otherwise
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !self.speed.is_finite() {
+            return Err("speed must be finite");
+        }
+        if self
+            .vehicle_length
+            .is_some_and(|length| !length.is_finite() || length <= 0.0)
+        {
+            return Err("vehicle_length must be positive");
+        }
+        Ok(())
+    }
+
+    /// Converts `speed` to the canonical km/h used for storage
+    pub fn normalize_speed(&mut self) {
+        if let Some(unit) = self.unit.take() {
+            self.speed = unit.to_kmh(f64::from(self.speed)) as f32;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -21,6 +68,9 @@ mod tests {
             sensor_name: Some("Sensor A".to_string()),
             speed: 60.0,
             lane: 2,
+            vehicle_class: None,
+            vehicle_length: None,
+            unit: None,
         };
 
         let serialized = serde_json::to_string(&request).unwrap();
@@ -33,4 +83,67 @@ mod tests {
         assert_eq!(deserialized.speed, 60.0);
         assert_eq!(deserialized.lane, 2);
     }
+
+    #[tokio::test]
+    async fn test_create_speed_data_request_with_classification() {
+        let json = r#"{"sensor_name":"Sensor B","speed":82.5,"lane":1,"vehicle_class":"truck","vehicle_length":12.4}"#;
+        let request: CreateSpeedDataRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.vehicle_class, Some(VehicleClass::Truck));
+        assert_eq!(request.vehicle_length, Some(12.4));
+
+        let serialized = serde_json::to_string(&request).unwrap();
+        assert_eq!(serialized, json);
+    }
+
+    #[tokio::test]
+    async fn test_create_speed_data_request_normalize_speed() {
+        let json = r#"{"sensor_name":"UK-01","speed":50.0,"lane":0,"unit":"mph"}"#;
+        let mut request: CreateSpeedDataRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.unit, Some(SpeedUnit::Mph));
+
+        request.normalize_speed();
+        assert_eq!(request.unit, None);
+        assert!((request.speed - 80.4672).abs() < 1e-4);
+
+        // Requests without a unit are already in km/h
+        let json = r#"{"speed":50.0,"lane":0}"#;
+        let mut request: CreateSpeedDataRequest = serde_json::from_str(json).unwrap();
+        request.normalize_speed();
+        assert_eq!(request.speed, 50.0);
+    }
+
+    #[tokio::test]
+    async fn test_create_speed_data_request_invalid_class() {
+        let json = r#"{"speed":82.5,"lane":1,"vehicle_class":"spaceship"}"#;
+        let result: Result<CreateSpeedDataRequest, _> = serde_json::from_str(json);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_create_speed_data_request_validate() {
+        let request = |json: &str| serde_json::from_str::<CreateSpeedDataRequest>(json).unwrap();
+
+        assert!(request(r#"{"speed":50.0,"lane":0}"#).validate().is_ok());
+        assert!(
+            request(r#"{"speed":50.0,"lane":0,"vehicle_length":4.2}"#)
+                .validate()
+                .is_ok()
+        );
+        for length in ["0", "-4.2", "1e39"] {
+            let json = format!(r#"{{"speed":50.0,"lane":0,"vehicle_length":{length}}}"#);
+            assert!(request(&json).validate().is_err(), "{length}");
+        }
+        assert!(request(r#"{"speed":1e39,"lane":0}"#).validate().is_err());
+    }
+
+    #[test]
+    fn test_create_speed_data_request_bind_sensor() {
+        let mut request: CreateSpeedDataRequest =
+            serde_json::from_str(r#"{"speed":50.0,"lane":0}"#).unwrap();
+        assert!(request.bind_sensor("S1").is_ok());
+        assert_eq!(request.sensor_name.as_deref(), Some("S1"));
+        assert!(request.bind_sensor("S1").is_ok());
+        assert!(request.bind_sensor("S2").is_err());
+        assert_eq!(request.sensor_name.as_deref(), Some("S1"));
+    }
 }
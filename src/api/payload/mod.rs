@@ -1 +1,3 @@
+pub mod create_api_key_request;
 pub mod create_speed_request;
+pub mod speed_batch_request;
@@ -0,0 +1,112 @@
+use crate::core::dto::speed_data::SpeedData;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of readings accepted in one batch
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Maximum length of the origin naming the edge server
+const MAX_ORIGIN_LENGTH: usize = 64;
+
+/// Represents a batch of readings forwarded by an edge server
+///
+/// Readings keep the id, sensor and timestamp they were given on the edge server.
+#[non_exhaustive]
+#[must_use]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpeedBatchRequest {
+    pub origin: String,           // Name of the edge server, unique among the edges
+    pub readings: Vec<SpeedData>, // Readings ordered by their id on the edge server
+}
+
+impl SpeedBatchRequest {
+    /// Creates a batch of readings forwarded from `origin`
+    pub fn new(origin: impl Into<String>, readings: Vec<SpeedData>) -> Self {
+        Self {
+            origin: origin.into(),
+            readings,
+        }
+    }
+
+    /// Checks the batch can be stored, returning the reason otherwise
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.origin.trim().is_empty() || self.origin.len() > MAX_ORIGIN_LENGTH {
+            return Err("origin must be between 1 and 64 characters");
+        }
+        if self.readings.len() > MAX_BATCH_SIZE {
+            return Err("too many readings in the batch");
+        }
+        if self.readings.iter().any(|r| !r.speed.is_finite()) {
+            return Err("speeds must be finite");
+        }
+        if self.readings.iter().any(|r| {
+            r.vehicle_length
+                .is_some_and(|length| !length.is_finite() || length <= 0.0)
+        }) {
+            return Err("vehicle lengths must be positive");
+        }
+        Ok(())
+    }
+
+    /// Attributes every reading to the sensor an API key is bound to
+    ///
+    /// Readings may omit their sensor name, but not give another one.
+    pub fn bind_sensor(&mut self, sensor_name: &str) -> Result<(), &'static str> {
+        if self.readings.iter().any(|r| {
+            r.sensor_name
+                .as_deref()
+                .is_some_and(|name| !name.is_empty() && name != sensor_name)
+        }) {
+            return Err("sensor_name doesn't match the API key");
+        }
+        for reading in &mut self.readings {
+            reading.sensor_name = Some(sensor_name.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Result of a forwarded batch
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SpeedBatchResponse {
+    pub received: usize, // Readings in the batch
+    pub inserted: u64,   // Readings not already stored by a previous delivery
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lane::Lane;
+    use chrono::Utc;
+
+    #[test]
+    fn test_speed_batch_request_validate() {
+        let reading = SpeedData::new(1, None, 50.0, Lane::Left, Utc::now());
+        assert!(
+            SpeedBatchRequest::new("site-a", vec![reading.clone()])
+                .validate()
+                .is_ok()
+        );
+        assert!(SpeedBatchRequest::new(" ", vec![]).validate().is_err());
+        assert!(
+            SpeedBatchRequest::new("site-a", vec![reading.clone(); MAX_BATCH_SIZE + 1])
+                .validate()
+                .is_err()
+        );
+
+        let mut invalid = reading.clone();
+        invalid.speed = f32::NAN;
+        assert!(
+            SpeedBatchRequest::new("site-a", vec![invalid])
+                .validate()
+                .is_err()
+        );
+
+        let mut invalid = reading;
+        invalid.vehicle_length = Some(0.0);
+        assert!(
+            SpeedBatchRequest::new("site-a", vec![invalid])
+                .validate()
+                .is_err()
+        );
+    }
+}
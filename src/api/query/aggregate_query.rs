@@ -0,0 +1,84 @@
+use crate::core::time_bucket::TimeBucket;
+use serde::Deserialize;
+
+/// Query parameters for the aggregate endpoint
+#[derive(Debug, Deserialize)]
+pub struct AggregateQuery {
+    pub bucket: Option<TimeBucket>, // Width of the time buckets, defaults to `hour`
+    pub group_by: Option<String>,   // Comma separated list of `lane`, `sensor` and `class`
+    pub include_flagged: Option<bool>, // Include readings tagged by fault detectors
+}
+
+/// Dimensions the aggregates are grouped by, in addition to the time bucket
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct GroupBy {
+    pub lane: bool,
+    pub sensor: bool,
+    pub class: bool,
+}
+
+impl AggregateQuery {
+    /// Parses the `group_by` field into the grouping dimensions
+    pub fn parse_group_by(&self) -> Result<GroupBy, String> {
+        let mut group_by = GroupBy::default();
+
+        let Some(raw) = self.group_by.as_deref() else {
+            return Ok(group_by);
+        };
+
+        for dimension in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match dimension {
+                "lane" => group_by.lane = true,
+                "sensor" => group_by.sensor = true,
+                "class" => group_by.class = true,
+                _ => {
+                    return Err(format!(
+                        "Invalid group_by dimension: '{}'. Expected 'lane', 'sensor' or 'class'",
+                        dimension
+                    ));
+                }
+            }
+        }
+
+        Ok(group_by)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_group_by_default() {
+        let query = AggregateQuery {
+            bucket: None,
+            group_by: None,
+            include_flagged: None,
+        };
+        assert_eq!(query.parse_group_by().unwrap(), GroupBy::default());
+    }
+
+    #[test]
+    fn test_parse_group_by_multiple() {
+        let query = AggregateQuery {
+            bucket: Some(TimeBucket::Day),
+            group_by: Some("class, lane".to_string()),
+            include_flagged: None,
+        };
+        let group_by = query.parse_group_by().unwrap();
+        assert!(group_by.class);
+        assert!(group_by.lane);
+        assert!(!group_by.sensor);
+    }
+
+    #[test]
+    fn test_parse_group_by_invalid() {
+        let query = AggregateQuery {
+            bucket: None,
+            group_by: Some("color".to_string()),
+            include_flagged: Some(true),
+        };
+        let err = query.parse_group_by().unwrap_err();
+        assert!(err.contains("Invalid group_by dimension"));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::core::audit_action::AuditAction;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for filtering the audit trail by action and API key
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<AuditAction>,
+    pub key_id: Option<i32>, // Requests made with the key and admin actions on it
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_query_deserialization() {
+        let query: AuditQuery =
+            serde_json::from_str(r#"{"action":"auth_failure","key_id":7}"#).unwrap();
+        assert_eq!(query.action, Some(AuditAction::AuthFailure));
+        assert_eq!(query.key_id, Some(7));
+
+        let query: AuditQuery = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(query.action, None);
+        assert_eq!(query.key_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_audit_query_invalid_action() {
+        let result: Result<AuditQuery, _> = serde_json::from_str(r#"{"action":"login"}"#);
+        assert!(result.is_err());
+    }
+}
@@ -1,55 +1,131 @@
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use crate::config::constant::MAX_QUERY_RANGE_DAYS;
+use crate::core::local_time::local_to_utc;
+use crate::core::relative_time::{NamedPeriod, parse_relative};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
 
 /// Query parameters for filtering speed data by date range
-#[derive(Debug, Deserialize)]
+///
+/// Besides absolute dates, both bounds accept relative expressions (`now`, `now-1h`, `-15m`,
+/// ISO 8601 durations such as `PT15M`) and named periods (`today`, `yesterday`, `this_week`,
+/// `last_week`, `this_month`, `last_month`).
+#[derive(Debug, Default, Deserialize)]
 pub struct DateRangeQuery {
-    pub start_date: String, // Expected format: "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD"
-    pub end_date: String,   // Expected format: "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD"
+    pub start_date: Option<String>, // Required, see above for the accepted formats
+    pub end_date: Option<String>,   // Defaults to now, or to the end of a named period
 }
 
 impl DateRangeQuery {
     /// Internal helper to parse date with optional time component
     ///
-    /// If the date string includes time, uses that time exactly.
-    /// If only a date is provided, uses the default_time tuple (hour, minute, second).
+    /// An RFC 3339 string carries its own offset and is used as is.
+    /// Otherwise the date is a local time of `tz`: if the string includes time, uses that time
+    /// exactly, if only a date is provided, uses the default_time tuple (hour, minute, second).
     fn parse_date_internal(
         date_str: &str,
         default_time: (u32, u32, u32),
+        tz: Tz,
     ) -> Result<DateTime<Utc>, String> {
-        // Try parsing as full datetime first (YYYY-MM-DD HH:MM:SS)
-        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(date_str, "%Y-%m-%d %H:%M:%S") {
-            return Ok(DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc));
+        // Try parsing as RFC 3339 with an explicit offset first
+        if let Ok(dt) = DateTime::parse_from_rfc3339(date_str) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+
+        // Try parsing as full datetime (YYYY-MM-DD HH:MM:SS or YYYY-MM-DDTHH:MM:SS)
+        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(date_str, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S"))
+        {
+            return Ok(local_to_utc(tz, naive_dt));
         }
 
         // Try parsing as date only (YYYY-MM-DD)
         if let Ok(naive_date) = NaiveDate::parse_from_str(date_str, "%Y-%m-%d") {
             let (h, m, s) = default_time;
             let naive_dt = naive_date.and_hms_opt(h, m, s).unwrap();
-            return Ok(DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc));
+            return Ok(local_to_utc(tz, naive_dt));
         }
 
-        // If neither format works, return an error
+        // If no format works, return an error
         Err(format!(
-            "Invalid date format: '{}'. Expected 'YYYY-MM-DD' or 'YYYY-MM-DD HH:MM:SS'",
+            "Invalid date format: '{}'. Expected 'YYYY-MM-DD', 'YYYY-MM-DD HH:MM:SS', RFC 3339, a relative expression or a named period",
             date_str
         ))
     }
 
-    /// Parse a date string that can be either "YYYY-MM-DD" or "YYYY-MM-DD HH:MM:SS"
-    /// Returns a DateTime<Utc>, defaulting to 00:00:00 if only date is provided
+    /// Internal helper to parse one bound of the range, relative to `now` if needed
+    ///
+    /// A named period resolves to its first instant for a start and to its last one for an end.
+    fn parse_bound(
+        value: &str,
+        default_time: (u32, u32, u32),
+        is_end: bool,
+        tz: Tz,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, String> {
+        if let Ok(period) = NamedPeriod::try_from(value) {
+            let (start, end) = period.bounds(tz, now);
+            return Ok(if is_end {
+                end - Duration::microseconds(1)
+            } else {
+                start
+            });
+        }
+
+        match parse_relative(value, now) {
+            Some(result) => result,
+            None => Self::parse_date_internal(value, default_time, tz),
+        }
+    }
+
+    /// Parse a date string that can be either "YYYY-MM-DD", "YYYY-MM-DD HH:MM:SS" or RFC 3339
+    /// Returns a DateTime<Utc>, defaulting to 00:00:00 UTC if only date is provided
     pub fn parse_date(date_str: &str) -> Result<DateTime<Utc>, String> {
-        Self::parse_date_internal(date_str, (0, 0, 0))
+        Self::parse_date_internal(date_str, (0, 0, 0), Tz::UTC)
+    }
+
+    /// Parse the start_date field as a local time of `tz` (defaults to 00:00:00)
+    pub fn parse_start_date(&self, tz: Tz, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        let start_date = self
+            .start_date
+            .as_deref()
+            .ok_or_else(|| "Missing start_date".to_string())?;
+        Self::parse_bound(start_date, (0, 0, 0), false, tz, now)
     }
 
-    /// Parse the start_date field (defaults to 00:00:00)
-    pub fn parse_start_date(&self) -> Result<DateTime<Utc>, String> {
-        Self::parse_date_internal(&self.start_date, (0, 0, 0))
+    /// Parse the end_date field as a local time of `tz` (defaults to 23:59:59 to include entire day)
+    ///
+    /// Without end_date, the range ends with the period named by start_date, or now.
+    pub fn parse_end_date(&self, tz: Tz, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        match (self.end_date.as_deref(), self.start_date.as_deref()) {
+            (Some(end_date), _) => Self::parse_bound(end_date, (23, 59, 59), true, tz, now),
+            (None, Some(start_date)) if NamedPeriod::try_from(start_date).is_ok() => {
+                Self::parse_bound(start_date, (23, 59, 59), true, tz, now)
+            }
+            (None, _) => Ok(now),
+        }
     }
 
-    /// Parse the end_date field (defaults to 23:59:59 to include entire day)
-    pub fn parse_end_date(&self) -> Result<DateTime<Utc>, String> {
-        Self::parse_date_internal(&self.end_date, (23, 59, 59))
+    /// Parse both bounds, rejecting inverted ranges and ranges longer than `MAX_QUERY_RANGE_DAYS`
+    pub fn resolve(
+        &self,
+        tz: Tz,
+        now: DateTime<Utc>,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+        let start = self.parse_start_date(tz, now)?;
+        let end = self.parse_end_date(tz, now)?;
+
+        if start > end {
+            return Err(format!("Inverted range: {start} is after {end}"));
+        }
+        if end - start > Duration::days(*MAX_QUERY_RANGE_DAYS) {
+            return Err(format!(
+                "Range too long: at most {} days are allowed",
+                *MAX_QUERY_RANGE_DAYS
+            ));
+        }
+
+        Ok((start, end))
     }
 }
 
@@ -57,6 +133,17 @@ impl DateRangeQuery {
 mod tests {
     use super::*;
 
+    fn now() -> DateTime<Utc> {
+        DateRangeQuery::parse_date("2024-03-13 10:30:00").unwrap()
+    }
+
+    fn query(start_date: Option<&str>, end_date: Option<&str>) -> DateRangeQuery {
+        DateRangeQuery {
+            start_date: start_date.map(str::to_string),
+            end_date: end_date.map(str::to_string),
+        }
+    }
+
     #[test]
     fn test_parse_date() {
         let dt1 = DateRangeQuery::parse_date("2024-01-01 12:30:45").unwrap();
@@ -71,21 +158,85 @@ mod tests {
 
     #[test]
     fn test_parse_start_date() {
-        let query = DateRangeQuery {
-            start_date: "2024-01-01 10:00:00".to_string(),
-            end_date: "2024-01-02".to_string(),
-        };
-        let start_dt = query.parse_start_date().unwrap();
+        let query = query(Some("2024-01-01 10:00:00"), Some("2024-01-02"));
+        let start_dt = query.parse_start_date(Tz::UTC, now()).unwrap();
         assert_eq!(start_dt.to_rfc3339(), "2024-01-01T10:00:00+00:00");
     }
 
     #[test]
     fn test_parse_end_date() {
-        let query = DateRangeQuery {
-            start_date: "2024-01-01".to_string(),
-            end_date: "2024-01-02".to_string(),
-        };
-        let end_dt = query.parse_end_date().unwrap();
+        let query = query(Some("2024-01-01"), Some("2024-01-02"));
+        let end_dt = query.parse_end_date(Tz::UTC, now()).unwrap();
         assert_eq!(end_dt.to_rfc3339(), "2024-01-02T23:59:59+00:00");
     }
+
+    #[test]
+    fn test_parse_rfc3339_with_offset() {
+        let dt = DateRangeQuery::parse_date("2024-01-01T12:30:45+02:00").unwrap();
+        assert_eq!(dt.to_rfc3339(), "2024-01-01T10:30:45+00:00");
+
+        let dt = DateRangeQuery::parse_date("2024-01-01T12:30:45Z").unwrap();
+        assert_eq!(dt.to_rfc3339(), "2024-01-01T12:30:45+00:00");
+    }
+
+    #[test]
+    fn test_parse_dates_in_timezone() {
+        let query = query(Some("2024-07-01"), Some("2024-07-01T18:00:00+00:00"));
+        let paris = chrono_tz::Europe::Paris;
+
+        let start_dt = query.parse_start_date(paris, now()).unwrap();
+        assert_eq!(start_dt.to_rfc3339(), "2024-06-30T22:00:00+00:00");
+
+        // An explicit offset wins over the requested timezone
+        let end_dt = query.parse_end_date(paris, now()).unwrap();
+        assert_eq!(end_dt.to_rfc3339(), "2024-07-01T18:00:00+00:00");
+    }
+
+    #[test]
+    fn test_relative_start_defaults_end_to_now() {
+        let (start, end) = query(Some("-15m"), None).resolve(Tz::UTC, now()).unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-03-13T10:15:00+00:00");
+        assert_eq!(end, now());
+
+        let (start, end) = query(Some("now-1h"), Some("now"))
+            .resolve(Tz::UTC, now())
+            .unwrap();
+        assert_eq!(end - start, Duration::hours(1));
+
+        let (start, _) = query(Some("PT30M"), None).resolve(Tz::UTC, now()).unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-03-13T10:00:00+00:00");
+    }
+
+    #[test]
+    fn test_named_period_without_end() {
+        let (start, end) = query(Some("yesterday"), None)
+            .resolve(Tz::UTC, now())
+            .unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-03-12T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-12T23:59:59.999999+00:00");
+
+        let (start, end) = query(Some("this_week"), Some("now"))
+            .resolve(Tz::UTC, now())
+            .unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-03-11T00:00:00+00:00");
+        assert_eq!(end, now());
+    }
+
+    #[test]
+    fn test_resolve_rejects_invalid_ranges() {
+        let err = query(None, Some("now")).resolve(Tz::UTC, now()).unwrap_err();
+        assert!(err.contains("Missing start_date"));
+
+        let err = query(Some("2024-03-10"), Some("2024-03-01"))
+            .resolve(Tz::UTC, now())
+            .unwrap_err();
+        assert!(err.contains("Inverted range"));
+
+        let err = query(Some("2020-01-01"), Some("2024-01-01"))
+            .resolve(Tz::UTC, now())
+            .unwrap_err();
+        assert!(err.contains("Range too long"));
+
+        assert!(query(Some("-15x"), None).resolve(Tz::UTC, now()).is_err());
+    }
 }
@@ -0,0 +1,37 @@
+use crate::core::lane::Lane;
+use crate::core::time_bucket::TimeBucket;
+use serde::Deserialize;
+
+/// Query parameters for the traffic flow analytics endpoint
+#[derive(Debug, Deserialize)]
+pub struct FlowQuery {
+    pub bucket: Option<TimeBucket>, // Width of the time buckets, defaults to `hour`
+    pub sensor_name: Option<String>, // Only compute metrics for this sensor
+    pub lane: Option<Lane>,         // Only compute metrics for this lane
+    pub include_flagged: Option<bool>, // Include readings tagged by fault detectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flow_query_deserialization() {
+        let query: FlowQuery =
+            serde_json::from_str(r#"{"bucket":"minute","sensor_name":"A1","lane":1}"#).unwrap();
+        assert_eq!(query.bucket, Some(TimeBucket::Minute));
+        assert_eq!(query.sensor_name.as_deref(), Some("A1"));
+        assert_eq!(query.lane, Some(Lane::Right));
+
+        let query: FlowQuery = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(query.bucket, None);
+        assert_eq!(query.lane, None);
+        assert_eq!(query.include_flagged, None);
+    }
+
+    #[tokio::test]
+    async fn test_flow_query_invalid_lane() {
+        let result: Result<FlowQuery, _> = serde_json::from_str(r#"{"lane":3}"#);
+        assert!(result.is_err());
+    }
+}
@@ -1,3 +1,9 @@
+pub mod aggregate_query;
+pub mod audit_query;
 pub mod date_range_query;
+pub mod flow_query;
 pub mod pagination_query;
 pub mod query_limit;
+pub mod speed_filter_query;
+pub mod timezone_query;
+pub mod units_query;
@@ -0,0 +1,29 @@
+use crate::core::vehicle_class::VehicleClass;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for filtering speed data by vehicle classification
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SpeedFilterQuery {
+    pub vehicle_class: Option<VehicleClass>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_speed_filter_query_deserialization() {
+        let query: SpeedFilterQuery = serde_json::from_str(r#"{"vehicle_class":"truck"}"#).unwrap();
+        assert_eq!(query.vehicle_class, Some(VehicleClass::Truck));
+
+        let query: SpeedFilterQuery = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(query.vehicle_class, None);
+    }
+
+    #[tokio::test]
+    async fn test_speed_filter_query_invalid_class() {
+        let result: Result<SpeedFilterQuery, _> =
+            serde_json::from_str(r#"{"vehicle_class":"boat"}"#);
+        assert!(result.is_err());
+    }
+}
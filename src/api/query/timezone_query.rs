@@ -0,0 +1,33 @@
+use crate::config::constant::DEFAULT_TIMEZONE;
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Query parameter selecting the timezone used for day boundaries and dates without offset
+#[derive(Debug, Default, Deserialize)]
+pub struct TimezoneQuery {
+    pub tz: Option<Tz>, // IANA name, e.g. "Europe/Paris"
+}
+
+impl TimezoneQuery {
+    /// Gets the requested timezone, defaulting to `DEFAULT_TIMEZONE`
+    pub fn timezone(&self) -> Tz {
+        self.tz.unwrap_or(*DEFAULT_TIMEZONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timezone_query_deserialization() {
+        let query: TimezoneQuery = serde_json::from_str(r#"{"tz":"Europe/Paris"}"#).unwrap();
+        assert_eq!(query.timezone(), chrono_tz::Europe::Paris);
+    }
+
+    #[tokio::test]
+    async fn test_timezone_query_invalid_name() {
+        let result: Result<TimezoneQuery, _> = serde_json::from_str(r#"{"tz":"Mars/Olympus"}"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use crate::core::speed_unit::SpeedUnit;
+use serde::{Deserialize, Serialize};
+
+/// Query parameter selecting the unit of the speeds in the response
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UnitsQuery {
+    pub units: Option<SpeedUnit>, // Defaults to km/h
+}
+
+impl UnitsQuery {
+    /// Gets the requested unit, defaulting to km/h
+    pub fn unit(&self) -> SpeedUnit {
+        self.units.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_units_query_deserialization() {
+        let query: UnitsQuery = serde_json::from_str(r#"{"units":"mph"}"#).unwrap();
+        assert_eq!(query.unit(), SpeedUnit::Mph);
+
+        let query: UnitsQuery = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(query.unit(), SpeedUnit::Kmh);
+    }
+
+    #[tokio::test]
+    async fn test_units_query_invalid_unit() {
+        let result: Result<UnitsQuery, _> = serde_json::from_str(r#"{"units":"furlongs"}"#);
+        assert!(result.is_err());
+    }
+}
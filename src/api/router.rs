@@ -0,0 +1,154 @@
+use crate::api::admin_handler::{get_audit_events, get_auth_metrics, get_retention_status};
+use crate::api::analytics_handler::{congestion_stream, get_congestion_snapshot, get_flow_metrics};
+use crate::api::handler::{
+    create_speed, get_last_n_speed, get_last_speed, get_speed_aggregates, get_speed_by_date_range,
+    get_speed_pagination, get_speed_today, health_check, root, speed_stream,
+};
+use crate::api::key_handler::{
+    create_api_key, deactivate_api_key, delete_api_key, get_signing_secret, list_api_keys,
+    rotate_api_key,
+};
+use crate::api::sync_handler::create_speed_batch;
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::core::app_state::AppState;
+use crate::middleware::auth::{auth_middleware, require_scope};
+use crate::middleware::brute_force::guard_authentication;
+use crate::middleware::client_ip::resolve_client_ip;
+use crate::middleware::rate_limit::{limit_ip_rate, limit_key_rate};
+use crate::middleware::signature::signature_middleware;
+use axum::{
+    Router, middleware,
+    routing::{delete, get, post},
+};
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
+
+/// Builds the application router with every route, whatever the storage backend
+pub fn create_router(app_state: AppState) -> Router {
+    // Readings of sensors, which may sign them rather than send a Bearer token
+    let ingest_routes = scoped(
+        Router::new().route("/api/speeds", post(create_speed)),
+        &app_state,
+        ApiKeyScope::SpeedsWrite,
+    )
+    .route_layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        auth_middleware,
+    ))
+    .route_layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        signature_middleware,
+    ))
+    .route_layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        guard_authentication,
+    ))
+    .route_layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        limit_ip_rate,
+    ));
+
+    // Readings forwarded by edge servers
+    let write_routes = scoped(
+        Router::new().route("/api/speeds/batch", post(create_speed_batch)),
+        &app_state,
+        ApiKeyScope::SpeedsWrite,
+    );
+
+    // Stored readings and analytics
+    let read_routes = scoped(
+        Router::new()
+            // RESTful endpoints for speed measurements
+            .route("/api/speeds", get(get_last_n_speed))
+            .route("/api/speeds/latest", get(get_last_speed))
+            .route("/api/speeds/today", get(get_speed_today))
+            .route("/api/speeds/paginated", get(get_speed_pagination))
+            .route("/api/speeds/range", get(get_speed_by_date_range))
+            .route("/api/speeds/aggregate", get(get_speed_aggregates))
+            // Traffic flow analytics
+            .route("/api/analytics/flow", get(get_flow_metrics))
+            // Congestion detection snapshot
+            .route("/api/analytics/congestion", get(get_congestion_snapshot)),
+        &app_state,
+        ApiKeyScope::SpeedsRead,
+    );
+
+    // Real-time SSE endpoints for speed notifications and congestion state transitions
+    let stream_routes = scoped(
+        Router::new()
+            .route("/api/speeds/stream", get(speed_stream))
+            .route("/api/analytics/congestion/stream", get(congestion_stream)),
+        &app_state,
+        ApiKeyScope::StreamRead,
+    );
+
+    // Administration
+    let admin_routes = scoped(
+        Router::new()
+            .route("/api/admin/retention", get(get_retention_status))
+            .route("/api/admin/audit", get(get_audit_events))
+            .route("/api/admin/auth/metrics", get(get_auth_metrics))
+            .route("/api/admin/keys", post(create_api_key))
+            .route("/api/admin/keys", get(list_api_keys))
+            .route("/api/admin/keys/{id}", delete(delete_api_key))
+            .route("/api/admin/keys/{id}/rotate", post(rotate_api_key))
+            .route(
+                "/api/admin/keys/{id}/signing-secret",
+                get(get_signing_secret),
+            )
+            .route("/api/admin/keys/{id}/deactivate", post(deactivate_api_key)),
+        &app_state,
+        ApiKeyScope::Admin,
+    );
+
+    // Protected routes that require Bearer token authentication, then the scope of their group,
+    // each client IP being limited and checked for a lockout before authentication
+    let protected_routes = Router::new()
+        .merge(write_routes)
+        .merge(read_routes)
+        .merge(stream_routes)
+        .merge(admin_routes)
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            guard_authentication,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            limit_ip_rate,
+        ));
+
+    // Public routes that don't require authentication
+    let public_routes = Router::new()
+        .route("/", get(root))
+        .route("/health", get(health_check));
+
+    // Combine all routes
+    Router::new()
+        .merge(public_routes)
+        .merge(ingest_routes)
+        .merge(protected_routes)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            resolve_client_ip,
+        ))
+        .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http())
+        .with_state(app_state)
+}
+
+/// Restricts a group of routes to the keys granted `scope`, then limits the rate of each key
+/// to the limit of the scope
+fn scoped(routes: Router<AppState>, app_state: &AppState, scope: ApiKeyScope) -> Router<AppState> {
+    routes
+        .route_layer(middleware::from_fn_with_state(
+            (app_state.clone(), scope),
+            limit_key_rate,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            (app_state.clone(), scope),
+            require_scope,
+        ))
+}
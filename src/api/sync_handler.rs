@@ -0,0 +1,56 @@
+use crate::api::payload::speed_batch_request::{SpeedBatchRequest, SpeedBatchResponse};
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::app_state::AppState;
+use crate::database::cache::invalidate_last_speed_cache;
+use crate::{log_error, log_info, log_warn};
+use axum::extract::{Extension, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+
+/// Stores a batch of readings forwarded by an edge server
+///
+/// Delivery is at least once, so readings already received from the same origin are skipped
+/// and the batch can safely be sent again. Forwarded readings are not broadcast to the stream
+/// subscribers nor checked by the fault detectors, the edge server already did it. As with
+/// single readings, a key bound to a sensor can only send readings of that sensor.
+pub async fn create_speed_batch(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<AuthenticatedKey>,
+    Json(mut payload): Json<SpeedBatchRequest>,
+) -> Result<Json<SpeedBatchResponse>, StatusCode> {
+    if let Err(reason) = payload.validate() {
+        log_warn!("Rejected batch from {:?}: {reason}", payload.origin);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(sensor_name) = &api_key.sensor_name
+        && let Err(reason) = payload.bind_sensor(sensor_name)
+    {
+        log_warn!("Rejected batch of {api_key}: {reason}");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let received = payload.readings.len();
+    match state
+        .repository
+        .insert_forwarded_speed_data(&payload.origin, payload.readings)
+        .await
+    {
+        Ok(inserted) => {
+            if inserted > 0 {
+                log_info!(
+                    "Stored {inserted}/{received} readings forwarded by {}",
+                    payload.origin
+                );
+                // The latest reading may come from the batch
+                if let Err(e) = invalidate_last_speed_cache(state.cache.as_ref()).await {
+                    log_error!("Failed to invalidate cache after batch insert: {e:?}");
+                }
+            }
+            Ok(Json(SpeedBatchResponse { received, inserted }))
+        }
+        Err(e) => {
+            log_error!("Error storing batch from {}: {e:?}", payload.origin);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Demo server running without Postgres nor Redis
+//!
+//! Readings are kept in memory, seeded with the last hour of simulated traffic and fed by a
+//! simulated sensor. SQL analytics (aggregates, flow metrics, retention) answer 501.
+
+use speed_stream::analytics::congestion::{
+    CongestionConfig, CongestionMonitor, spawn_congestion_monitor,
+};
+use speed_stream::analytics::quality::{FaultDetector, FaultDetectorConfig};
+use speed_stream::api::router::create_router;
+use speed_stream::config::constant::{
+    CACHE_MEMORY_CAPACITY, DEMO_API_KEY, DEMO_INTERVAL_MS, HOST, PORT,
+};
+use speed_stream::core::app_state::AppState;
+use speed_stream::core::dto::speed_data::SpeedData;
+use speed_stream::core::lane::Lane;
+use speed_stream::database::cache::memory::MemoryCache;
+use speed_stream::database::repository::memory::InMemorySpeedRepository;
+use speed_stream::telemetry::tracing::log_level::LogLevel;
+use speed_stream::telemetry::tracing::logger::Logger;
+use speed_stream::{log_error, log_info};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Simulated speed in km/h of the n-th reading: free flow with a slowdown every few minutes
+fn simulated_speed(n: u64) -> f32 {
+    let wave = ((n % 300) as f32 / 300.0 * std::f32::consts::TAU).sin();
+    let jitter = ((n * 7919) % 13) as f32 - 6.0;
+    (85.0 + 30.0 * wave + jitter).max(5.0)
+}
+
+/// Simulated lane of the n-th reading
+fn simulated_lane(n: u64) -> Lane {
+    if n.is_multiple_of(3) {
+        Lane::Left
+    } else {
+        Lane::Right
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dotenv_path = std::env::current_dir()?.join(".env");
+    dotenvy::from_path(dotenv_path).ok();
+
+    Logger::init("demo.log", LogLevel::Trace)?;
+
+    log_info!("Starting Sensor API Server in demo mode...");
+
+    // The demo key is an admin key, so the administration endpoints can be tried too
+    let repository = Arc::new(InMemorySpeedRepository::new().with_admin_key(&DEMO_API_KEY));
+
+    // Seed the last hour of traffic
+    let interval = std::time::Duration::from_millis((*DEMO_INTERVAL_MS).max(1));
+    let now = chrono::Utc::now();
+    let seeded = (3_600_000 / (*DEMO_INTERVAL_MS).max(1)).min(10_000);
+    for n in 0..seeded {
+        let created_at = now - chrono::Duration::from_std(interval * (seeded - n) as u32)?;
+        let data = SpeedData::new(
+            0,
+            Some("demo".to_string()),
+            simulated_speed(n),
+            simulated_lane(n),
+            created_at,
+        );
+        let _ = repository.insert_at(data, created_at);
+    }
+    log_info!("Seeded {seeded} simulated readings");
+
+    let (broadcast_tx, _) = tokio::sync::broadcast::channel(1000);
+
+    let congestion = Arc::new(CongestionMonitor::new(CongestionConfig::from_env(), 100));
+    spawn_congestion_monitor(congestion.clone(), broadcast_tx.subscribe());
+
+    let fault_detector = Arc::new(FaultDetector::new(FaultDetectorConfig::from_env()));
+
+    // Simulated sensor, published like the readings received by create_speed
+    let sensor_repository = repository.clone();
+    let sensor_tx = broadcast_tx.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        for n in seeded.. {
+            ticker.tick().await;
+            let data = SpeedData::new(
+                0,
+                Some("demo".to_string()),
+                simulated_speed(n),
+                simulated_lane(n),
+                chrono::Utc::now(),
+            );
+            let data = sensor_repository.insert_at(data, chrono::Utc::now());
+            let _ = sensor_tx.send(data);
+        }
+    });
+
+    let app_state = AppState::new(
+        repository,
+        None,
+        Arc::new(MemoryCache::new(*CACHE_MEMORY_CAPACITY)),
+        broadcast_tx,
+        congestion,
+        fault_detector,
+    );
+    let app = create_router(app_state);
+
+    let addr = format!("{}:{}", *HOST, *PORT);
+    let listener: TcpListener = TcpListener::bind(&addr).await.map_err(|e| {
+        log_error!("Failed to bind to {addr}: {e}");
+        e
+    })?;
+
+    log_info!(
+        "Demo listening on http://{} (API key: {})",
+        listener.local_addr()?,
+        DEMO_API_KEY.as_str()
+    );
+
+    axum::serve(listener, app).await.map_err(|e| {
+        log_error!("Server error: {e}");
+        e
+    })?;
+
+    Ok(())
+}
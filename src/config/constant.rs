@@ -3,10 +3,10 @@ use std::sync::LazyLock;
 /// Database connection URL
 /// Priority: POSTGRES_URL > individual POSTGRES_* variables
 pub static DATABASE_URL: LazyLock<String> = LazyLock::new(|| {
-    if let Ok(url) = std::env::var("POSTGRES_URL") {
-        if !url.is_empty() {
-            return url;
-        }
+    if let Ok(url) = std::env::var("POSTGRES_URL")
+        && !url.is_empty()
+    {
+        return url;
     }
 
     let user = std::env::var("POSTGRES_USER").unwrap_or_else(|_| "speedstream".to_string());
@@ -22,10 +22,10 @@ pub static DATABASE_URL: LazyLock<String> = LazyLock::new(|| {
 /// Redis connection URL with authentication
 /// Priority: REDIS_URL > individual REDIS_* variables
 pub static REDIS_URL: LazyLock<String> = LazyLock::new(|| {
-    if let Ok(url) = std::env::var("REDIS_URL") {
-        if !url.is_empty() {
-            return url;
-        }
+    if let Ok(url) = std::env::var("REDIS_URL")
+        && !url.is_empty()
+    {
+        return url;
     }
 
     let host = std::env::var("REDIS_HOST").unwrap_or_else(|_| "redis".to_string());
@@ -42,6 +42,42 @@ pub static REDIS_URL: LazyLock<String> = LazyLock::new(|| {
     }
 });
 
+/// Secret mixed into the hashes of the API keys, kept out of the database
+///
+/// Changing it invalidates every stored key.
+pub static API_KEY_PEPPER: LazyLock<String> =
+    LazyLock::new(|| std::env::var("API_KEY_PEPPER").unwrap_or_default());
+
+/// Storage backend: "postgres", or "sqlite" for builds with the `sqlite` feature
+pub static STORAGE_BACKEND: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "postgres".to_string())
+});
+
+/// Path of the SQLite database file, created if missing
+pub static SQLITE_PATH: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("SQLITE_PATH").unwrap_or_else(|_| "speedstream.db".to_string())
+});
+
+/// Cache backend: "redis" (with an in-process fallback while Redis is down) or "memory"
+pub static CACHE_BACKEND: LazyLock<String> =
+    LazyLock::new(|| std::env::var("CACHE_BACKEND").unwrap_or_else(|_| "redis".to_string()));
+
+/// Maximum number of entries of the in-process cache
+pub static CACHE_MEMORY_CAPACITY: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("CACHE_MEMORY_CAPACITY")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .expect("CACHE_MEMORY_CAPACITY must be a number")
+});
+
+/// Delay before retrying Redis once it failed, in seconds
+pub static CACHE_RETRY_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("CACHE_RETRY_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("CACHE_RETRY_SECS must be a number")
+});
+
 /// Allowed CORS origins
 pub static ALLOWED_ORIGINS: LazyLock<Vec<String>> = LazyLock::new(|| {
     std::env::var("ALLOWED_ORIGINS")
@@ -65,3 +101,506 @@ pub static PORT: LazyLock<u16> = LazyLock::new(|| {
         .parse()
         .expect("SERVER_PORT must be a number")
 });
+
+/// Length of the rolling window used for congestion detection, in seconds
+pub static CONGESTION_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("CONGESTION_WINDOW_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("CONGESTION_WINDOW_SECS must be a number")
+});
+
+/// Average speed (km/h) below which traffic is considered dense
+pub static CONGESTION_DENSE_SPEED_KMH: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("CONGESTION_DENSE_SPEED_KMH")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("CONGESTION_DENSE_SPEED_KMH must be a number")
+});
+
+/// Average speed (km/h) below which traffic is considered congested
+pub static CONGESTION_CONGESTED_SPEED_KMH: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("CONGESTION_CONGESTED_SPEED_KMH")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("CONGESTION_CONGESTED_SPEED_KMH must be a number")
+});
+
+/// Speed margin (km/h) above a threshold required before returning to a better traffic state
+pub static CONGESTION_HYSTERESIS_KMH: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("CONGESTION_HYSTERESIS_KMH")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .expect("CONGESTION_HYSTERESIS_KMH must be a number")
+});
+
+/// Minimum number of readings in the window before a lane is classified
+pub static CONGESTION_MIN_SAMPLES: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("CONGESTION_MIN_SAMPLES")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .expect("CONGESTION_MIN_SAMPLES must be a number")
+});
+
+/// Maximum sensor and lane windows tracked by the congestion monitor
+pub static CONGESTION_MAX_LANES: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("CONGESTION_MAX_LANES")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .expect("CONGESTION_MAX_LANES must be a number")
+});
+
+/// Number of consecutive identical readings after which a sensor is considered stuck
+pub static QUALITY_STUCK_COUNT: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("QUALITY_STUCK_COUNT")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("QUALITY_STUCK_COUNT must be a number")
+});
+
+/// Speed change (km/h) between consecutive readings considered a sudden jump
+pub static QUALITY_JUMP_KMH: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("QUALITY_JUMP_KMH")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .expect("QUALITY_JUMP_KMH must be a number")
+});
+
+/// Maximum delay (seconds) between two readings for a speed change to count as a sudden jump
+pub static QUALITY_JUMP_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("QUALITY_JUMP_WINDOW_SECS")
+        .unwrap_or_else(|_| "2".to_string())
+        .parse()
+        .expect("QUALITY_JUMP_WINDOW_SECS must be a number")
+});
+
+/// Z-score above which a reading is out of distribution compared to the rolling baseline
+pub static QUALITY_MAX_ZSCORE: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("QUALITY_MAX_ZSCORE")
+        .unwrap_or_else(|_| "4".to_string())
+        .parse()
+        .expect("QUALITY_MAX_ZSCORE must be a number")
+});
+
+/// Number of recent valid readings kept per sensor and lane as the rolling baseline
+pub static QUALITY_BASELINE_SIZE: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("QUALITY_BASELINE_SIZE")
+        .unwrap_or_else(|_| "200".to_string())
+        .parse()
+        .expect("QUALITY_BASELINE_SIZE must be a number")
+});
+
+/// Minimum baseline size before the out-of-distribution check applies
+pub static QUALITY_MIN_BASELINE: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("QUALITY_MIN_BASELINE")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("QUALITY_MIN_BASELINE must be a number")
+});
+
+/// Highest physically plausible speed (km/h), anything above is always flagged
+pub static QUALITY_MAX_SPEED_KMH: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("QUALITY_MAX_SPEED_KMH")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("QUALITY_MAX_SPEED_KMH must be a number")
+});
+
+/// Maximum number of sensor and lane histories kept by the fault detectors
+pub static QUALITY_MAX_SENSORS: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("QUALITY_MAX_SENSORS")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .expect("QUALITY_MAX_SENSORS must be a number")
+});
+
+/// Delay (seconds) without readings after which a sensor history can make room for another
+pub static QUALITY_IDLE_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("QUALITY_IDLE_SECS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .expect("QUALITY_IDLE_SECS must be a number")
+});
+
+/// Timezone used for "today" and for date-only inputs when a request doesn't specify `tz`
+pub static DEFAULT_TIMEZONE: LazyLock<chrono_tz::Tz> = LazyLock::new(|| {
+    std::env::var("DEFAULT_TIMEZONE")
+        .unwrap_or_else(|_| "UTC".to_string())
+        .parse()
+        .expect("DEFAULT_TIMEZONE must be a valid IANA timezone name")
+});
+
+/// Longest range accepted by the date range endpoints, in days
+pub static MAX_QUERY_RANGE_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("MAX_QUERY_RANGE_DAYS")
+        .unwrap_or_else(|_| "366".to_string())
+        .parse()
+        .expect("MAX_QUERY_RANGE_DAYS must be a number")
+});
+
+/// Delay between two refreshes of the rollup tables, in seconds
+pub static ROLLUP_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("ROLLUP_INTERVAL_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("ROLLUP_INTERVAL_SECS must be a number")
+});
+
+/// Maximum number of readings folded into the rollups per transaction
+pub static ROLLUP_BATCH_SIZE: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("ROLLUP_BATCH_SIZE")
+        .unwrap_or_else(|_| "50000".to_string())
+        .parse()
+        .expect("ROLLUP_BATCH_SIZE must be a number")
+});
+
+/// Age in seconds before a reading is folded into the rollups, so in-flight inserts are never skipped
+pub static ROLLUP_SETTLE_SECS: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("ROLLUP_SETTLE_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("ROLLUP_SETTLE_SECS must be a number")
+});
+
+/// Days raw readings are kept (0 keeps them forever)
+pub static RETENTION_RAW_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_RAW_DAYS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("RETENTION_RAW_DAYS must be a number")
+});
+
+/// Days minute rollups are kept (0 keeps them forever)
+pub static RETENTION_MINUTE_ROLLUP_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_MINUTE_ROLLUP_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("RETENTION_MINUTE_ROLLUP_DAYS must be a number")
+});
+
+/// Days hour rollups are kept (0 keeps them forever)
+pub static RETENTION_HOUR_ROLLUP_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_HOUR_ROLLUP_DAYS")
+        .unwrap_or_else(|_| "1825".to_string())
+        .parse()
+        .expect("RETENTION_HOUR_ROLLUP_DAYS must be a number")
+});
+
+/// Days day rollups are kept (0 keeps them forever)
+pub static RETENTION_DAY_ROLLUP_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_DAY_ROLLUP_DAYS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("RETENTION_DAY_ROLLUP_DAYS must be a number")
+});
+
+/// Days audit events are kept (0 keeps them forever)
+pub static RETENTION_AUDIT_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_AUDIT_DAYS")
+        .unwrap_or_else(|_| "365".to_string())
+        .parse()
+        .expect("RETENTION_AUDIT_DAYS must be a number")
+});
+
+/// Delay between two retention purges, in seconds
+pub static RETENTION_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("RETENTION_INTERVAL_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .expect("RETENTION_INTERVAL_SECS must be a number")
+});
+
+/// Maximum number of rows deleted per statement by the retention purge
+pub static RETENTION_BATCH_SIZE: LazyLock<i64> = LazyLock::new(|| {
+    std::env::var("RETENTION_BATCH_SIZE")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .expect("RETENTION_BATCH_SIZE must be a number")
+});
+
+/// Only report what the retention purge would delete
+pub static RETENTION_DRY_RUN: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("RETENTION_DRY_RUN")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .expect("RETENTION_DRY_RUN must be true or false")
+});
+
+/// Delay between two runs of the partition manager, in seconds
+pub static PARTITION_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("PARTITION_INTERVAL_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .expect("PARTITION_INTERVAL_SECS must be a number")
+});
+
+/// Number of future monthly partitions created ahead of time
+pub static PARTITION_MONTHS_AHEAD: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("PARTITION_MONTHS_AHEAD")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse()
+        .expect("PARTITION_MONTHS_AHEAD must be a number")
+});
+
+/// Drop partitions detached by the raw retention instead of keeping them for archiving
+pub static PARTITION_DROP_DETACHED: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("PARTITION_DROP_DETACHED")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .expect("PARTITION_DROP_DETACHED must be true or false")
+});
+
+/// Apply pending schema migrations at startup, otherwise only check the schema is up to date
+pub static RUN_MIGRATIONS: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("RUN_MIGRATIONS")
+        .unwrap_or_else(|_| "true".to_string())
+        .parse()
+        .expect("RUN_MIGRATIONS must be true or false")
+});
+
+/// Base URL of the central server readings are forwarded to, empty to disable forwarding
+pub static FORWARD_URL: LazyLock<String> =
+    LazyLock::new(|| std::env::var("FORWARD_URL").unwrap_or_default());
+
+/// API key sent to the central server
+pub static FORWARD_API_KEY: LazyLock<String> =
+    LazyLock::new(|| std::env::var("FORWARD_API_KEY").unwrap_or_default());
+
+/// Name identifying this edge server on the central server
+pub static FORWARD_ORIGIN: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("FORWARD_ORIGIN").unwrap_or_else(|_| "edge".to_string())
+});
+
+/// Maximum readings forwarded per request
+pub static FORWARD_BATCH_SIZE: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("FORWARD_BATCH_SIZE")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse()
+        .expect("FORWARD_BATCH_SIZE must be a number")
+});
+
+/// Delay between two forwarding rounds once caught up, in seconds
+pub static FORWARD_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("FORWARD_INTERVAL_SECS")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .expect("FORWARD_INTERVAL_SECS must be a number")
+});
+
+/// Maximum delay between two attempts while the central server is unreachable, in seconds
+pub static FORWARD_MAX_BACKOFF_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("FORWARD_MAX_BACKOFF_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("FORWARD_MAX_BACKOFF_SECS must be a number")
+});
+
+/// Age before a local reading is forwarded, in seconds (must exceed the insert timeout)
+pub static FORWARD_SETTLE_SECS: LazyLock<f64> = LazyLock::new(|| {
+    std::env::var("FORWARD_SETTLE_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("FORWARD_SETTLE_SECS must be a number")
+});
+
+/// API key accepted by the demo server
+pub static DEMO_API_KEY: LazyLock<String> =
+    LazyLock::new(|| std::env::var("DEMO_API_KEY").unwrap_or_else(|_| "demo".to_string()));
+
+/// Delay between two simulated readings of the demo server, in milliseconds
+pub static DEMO_INTERVAL_MS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("DEMO_INTERVAL_MS")
+        .unwrap_or_else(|_| "1000".to_string())
+        .parse()
+        .expect("DEMO_INTERVAL_MS must be a number")
+});
+
+/// JSON Web Key Set verifying the JWT bearer tokens, a file path or an http(s) URL, empty to
+/// accept API keys only
+pub static JWT_JWKS: LazyLock<String> =
+    LazyLock::new(|| std::env::var("JWT_JWKS").unwrap_or_default());
+
+/// Issuer the JWT bearer tokens must be issued by, the `iss` claim
+pub static JWT_ISSUER: LazyLock<String> =
+    LazyLock::new(|| std::env::var("JWT_ISSUER").unwrap_or_default());
+
+/// Audience the JWT bearer tokens must be issued for, the `aud` claim
+pub static JWT_AUDIENCE: LazyLock<String> =
+    LazyLock::new(|| std::env::var("JWT_AUDIENCE").unwrap_or_default());
+
+/// Scope of the JWT bearer tokens granting the `admin` scope, e.g. `speedstream:admin`, empty
+/// to refuse administration to JWTs
+pub static JWT_ADMIN_SCOPE: LazyLock<String> =
+    LazyLock::new(|| std::env::var("JWT_ADMIN_SCOPE").unwrap_or_default());
+
+/// Clock skew tolerated on the expiry and activation of JWT bearer tokens, in seconds
+pub static JWT_LEEWAY_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("JWT_LEEWAY_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("JWT_LEEWAY_SECS must be a number")
+});
+
+/// Delay after which the JSON Web Key Set is loaded again, in seconds
+pub static JWT_JWKS_REFRESH_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("JWT_JWKS_REFRESH_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .expect("JWT_JWKS_REFRESH_SECS must be a number")
+});
+
+/// Master secret the signing secrets of the API keys are derived from, empty to refuse signed
+/// requests
+pub static SIGNING_SECRET: LazyLock<String> =
+    LazyLock::new(|| std::env::var("SIGNING_SECRET").unwrap_or_default());
+
+/// Maximum difference between the timestamp of a signed request and the server clock, in seconds
+pub static SIGNATURE_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("SIGNATURE_WINDOW_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("SIGNATURE_WINDOW_SECS must be a number")
+});
+
+/// Time for an exhausted rate limit to be fully restored, in seconds; the limits below are
+/// numbers of requests per window, 0 for unlimited
+pub static RATE_LIMIT_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_WINDOW_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("RATE_LIMIT_WINDOW_SECS must be a number")
+});
+
+/// Addresses and networks of the reverse proxies, whose `X-Forwarded-For` header is trusted
+pub static TRUSTED_PROXIES: LazyLock<Vec<String>> = LazyLock::new(|| {
+    std::env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+});
+
+/// Requests of a client IP per window, whatever the token, checked before authentication
+pub static RATE_LIMIT_PER_IP: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_PER_IP")
+        .unwrap_or_else(|_| "1200".to_string())
+        .parse()
+        .expect("RATE_LIMIT_PER_IP must be a number")
+});
+
+/// Requests of an API key per window on the `speeds:write` routes
+pub static RATE_LIMIT_SPEEDS_WRITE: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_SPEEDS_WRITE")
+        .unwrap_or_else(|_| "120".to_string())
+        .parse()
+        .expect("RATE_LIMIT_SPEEDS_WRITE must be a number")
+});
+
+/// Requests of an API key per window on the `speeds:read` routes
+pub static RATE_LIMIT_SPEEDS_READ: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_SPEEDS_READ")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse()
+        .expect("RATE_LIMIT_SPEEDS_READ must be a number")
+});
+
+/// Requests of an API key per window on the `stream:read` routes, i.e. stream connections
+pub static RATE_LIMIT_STREAM_READ: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_STREAM_READ")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("RATE_LIMIT_STREAM_READ must be a number")
+});
+
+/// Requests of an API key per window on the `admin` routes
+pub static RATE_LIMIT_ADMIN: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("RATE_LIMIT_ADMIN")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("RATE_LIMIT_ADMIN must be a number")
+});
+
+/// Record every authenticated request in the audit trail, not only the refused ones
+///
+/// Off by default: each reading would be recorded, doubling the writes of the ingestion.
+pub static AUDIT_AUTH_SUCCESS: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("AUDIT_AUTH_SUCCESS")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .expect("AUDIT_AUTH_SUCCESS must be true or false")
+});
+
+/// Audit events waiting to be written before new ones are dropped
+pub static AUDIT_QUEUE_CAPACITY: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("AUDIT_QUEUE_CAPACITY")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .expect("AUDIT_QUEUE_CAPACITY must be a number")
+});
+
+/// Window over which the failed authentications of a client IP are counted, in seconds
+pub static AUTH_FAILURE_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("AUTH_FAILURE_WINDOW_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("AUTH_FAILURE_WINDOW_SECS must be a number")
+});
+
+/// Failed authentications of a client IP per window before it is locked out, 0 to never lock out
+pub static AUTH_MAX_FAILURES: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("AUTH_MAX_FAILURES")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("AUTH_MAX_FAILURES must be a number")
+});
+
+/// Duration of the first lockout of a client IP, doubled on each following one, in seconds
+pub static AUTH_LOCKOUT_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("AUTH_LOCKOUT_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("AUTH_LOCKOUT_SECS must be a number")
+});
+
+/// Longest lockout of a client IP, in seconds
+pub static AUTH_MAX_LOCKOUT_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("AUTH_MAX_LOCKOUT_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .expect("AUTH_MAX_LOCKOUT_SECS must be a number")
+});
+
+/// Unknown tokens looked up per breaker window before lookups are limited, 0 to never limit
+///
+/// Unset, the requests a client IP may send within the breaker window, see `BruteForceConfig`.
+pub static AUTH_BREAKER_FAILURES: LazyLock<Option<u32>> = LazyLock::new(|| {
+    std::env::var("AUTH_BREAKER_FAILURES")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().expect("AUTH_BREAKER_FAILURES must be a number"))
+});
+
+/// Window over which the unknown tokens are counted by the circuit breaker, in seconds
+pub static AUTH_BREAKER_WINDOW_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("AUTH_BREAKER_WINDOW_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("AUTH_BREAKER_WINDOW_SECS must be a number")
+});
+
+/// Lookups of uncached tokens running at once while the circuit breaker is open, 0 for none
+pub static AUTH_BREAKER_LOOKUPS: LazyLock<u32> = LazyLock::new(|| {
+    std::env::var("AUTH_BREAKER_LOOKUPS")
+        .unwrap_or_else(|_| "4".to_string())
+        .parse()
+        .expect("AUTH_BREAKER_LOOKUPS must be a number")
+});
+
+/// Time the token lookups stay limited once the circuit breaker opens, in seconds
+pub static AUTH_BREAKER_COOLDOWN_SECS: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("AUTH_BREAKER_COOLDOWN_SECS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("AUTH_BREAKER_COOLDOWN_SECS must be a number")
+});
@@ -0,0 +1,158 @@
+use crate::config::constant::API_KEY_PEPPER;
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+
+/// Number of leading characters of a key kept in clear to tell keys apart
+const KEY_PREFIX_LENGTH: usize = 8;
+
+/// API key or JWT that authenticated the current request
+///
+/// Set as a request extension by the auth middleware, and cached along with the key hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticatedKey {
+    pub id: Option<i32>,             // Id of the key in `api_keys`, None for a JWT
+    pub subject: Option<String>,     // Subject of the JWT, the `sub` claim
+    pub scopes: Vec<ApiKeyScope>,    // Scopes deciding the routes the key may call
+    pub sensor_name: Option<String>, // Sensor the readings sent with the key come from
+    pub expires_at: Option<DateTime<Utc>>, // The key is refused from then on, never if None
+}
+
+impl AuthenticatedKey {
+    /// Checks whether the key was granted `scope`
+    #[must_use]
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Returns the key as authenticated, once checked active and not expired
+    #[must_use]
+    pub fn from_info(info: &ApiKeyInfo) -> Self {
+        Self {
+            id: Some(info.id),
+            subject: None,
+            scopes: info.scopes.clone(),
+            sensor_name: info.sensor_name.clone(),
+            expires_at: info.expires_at,
+        }
+    }
+
+    /// Checks whether the key has expired at `now`
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Names the key in log messages
+impl fmt::Display for AuthenticatedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.id, &self.subject) {
+            (Some(id), _) => write!(f, "API key {id}"),
+            (None, Some(subject)) => write!(f, "JWT of {subject:?}"),
+            (None, None) => write!(f, "JWT"),
+        }
+    }
+}
+
+/// Generates a new random API key of 64 hexadecimal characters
+#[must_use]
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("the OS random generator is available");
+    hex::encode(bytes)
+}
+
+/// Returns the leading characters of a key, stored to show a masked version of it
+#[must_use]
+pub fn key_prefix(token: &str) -> String {
+    token.chars().take(KEY_PREFIX_LENGTH).collect()
+}
+
+/// Hashes an API key into the identifier stored in the database and used in cache keys
+///
+/// The hash is an HMAC-SHA256 keyed with `API_KEY_PEPPER`, so a leaked `api_keys` table or
+/// cache can't be turned back into usable keys without the pepper. API keys are random and
+/// long, so a fast hash is enough and lets every request be checked with an index lookup.
+#[must_use]
+pub fn hash_api_key(token: &str) -> String {
+    hash_with_pepper(API_KEY_PEPPER.as_bytes(), token)
+}
+
+/// Hashes an API key with an explicit pepper, as a lowercase hex string
+#[must_use]
+pub fn hash_with_pepper(pepper: &[u8], token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(pepper).expect("HMAC accepts keys of any size");
+    mac.update(token.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_with_pepper() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hash_with_pepper(b"Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(
+            hash_with_pepper(b"pepper-a", "token"),
+            hash_with_pepper(b"pepper-b", "token")
+        );
+    }
+
+    #[test]
+    fn test_generate_api_key() {
+        let key = generate_api_key();
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(key, generate_api_key());
+        assert_eq!(key_prefix(&key), key[..8]);
+    }
+
+    #[test]
+    fn test_hash_api_key() {
+        let hash = hash_api_key("my-secret-token");
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains("my-secret-token"));
+        assert_eq!(hash, hash_api_key("my-secret-token"));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+        let mut key = AuthenticatedKey {
+            id: Some(1),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        assert!(!key.is_expired(now));
+        key.expires_at = Some(now + chrono::Duration::seconds(1));
+        assert!(!key.is_expired(now));
+        assert!(key.is_expired(now + chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut key = AuthenticatedKey {
+            id: Some(3),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        assert_eq!(key.to_string(), "API key 3");
+        key.id = None;
+        key.subject = Some("sensor-42".to_string());
+        assert_eq!(key.to_string(), "JWT of \"sensor-42\"");
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+/// Permission granted to an API key, each route requiring one of them.
+///
+/// Variants are serialized as `resource:action` strings, which is also how they are stored
+/// in the `scopes` column of `api_keys`.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiKeyScope {
+    #[serde(rename = "speeds:write")]
+    SpeedsWrite, // Sends readings, e.g. a sensor or an edge server
+    #[serde(rename = "speeds:read")]
+    SpeedsRead, // Reads the stored readings and the analytics
+    #[serde(rename = "stream:read")]
+    StreamRead, // Follows the real-time SSE streams
+    #[serde(rename = "admin")]
+    Admin, // Manages API keys and reads the administration endpoints
+}
+
+/// Scopes of the keys created without explicit scopes, everything but administration
+pub const DEFAULT_SCOPES: [ApiKeyScope; 3] = [
+    ApiKeyScope::SpeedsWrite,
+    ApiKeyScope::SpeedsRead,
+    ApiKeyScope::StreamRead,
+];
+
+impl ApiKeyScope {
+    /// Returns the string representation used in the API and the database.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SpeedsWrite => "speeds:write",
+            Self::SpeedsRead => "speeds:read",
+            Self::StreamRead => "stream:read",
+            Self::Admin => "admin",
+        }
+    }
+
+    /// Returns the scopes of the keys created without explicit scopes
+    #[must_use]
+    pub fn defaults() -> Vec<Self> {
+        DEFAULT_SCOPES.to_vec()
+    }
+
+    /// Parses a list of scopes as stored in the database
+    pub fn parse_all<'a>(
+        values: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Self>, &'static str> {
+        values.into_iter().map(Self::try_from).collect()
+    }
+}
+
+/// Converts a database string to `ApiKeyScope`
+impl TryFrom<&str> for ApiKeyScope {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "speeds:write" => Ok(Self::SpeedsWrite),
+            "speeds:read" => Ok(Self::SpeedsRead),
+            "stream:read" => Ok(Self::StreamRead),
+            "admin" => Ok(Self::Admin),
+            _ => Err("Invalid value for ApiKeyScope"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_scope_as_str_round_trip() {
+        for scope in [
+            ApiKeyScope::SpeedsWrite,
+            ApiKeyScope::SpeedsRead,
+            ApiKeyScope::StreamRead,
+            ApiKeyScope::Admin,
+        ] {
+            assert_eq!(ApiKeyScope::try_from(scope.as_str()), Ok(scope));
+            assert_eq!(
+                serde_json::to_string(&scope).unwrap(),
+                format!("\"{}\"", scope.as_str())
+            );
+        }
+        assert!(ApiKeyScope::try_from("speeds:delete").is_err());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        assert_eq!(
+            ApiKeyScope::parse_all("speeds:read stream:read".split_whitespace()),
+            Ok(vec![ApiKeyScope::SpeedsRead, ApiKeyScope::StreamRead])
+        );
+        assert_eq!(ApiKeyScope::parse_all([]), Ok(vec![]));
+        assert!(ApiKeyScope::parse_all(["speeds:read", "root"]).is_err());
+    }
+}
@@ -1,29 +1,111 @@
+use crate::analytics::congestion::CongestionMonitor;
+use crate::analytics::quality::FaultDetector;
+use crate::core::dto::audit_event::AuditEvent;
 use crate::core::dto::speed_data::SpeedData;
+use crate::database::cache::Cache;
 use crate::database::pool::DbPool;
-use redis::aio::ConnectionManager;
+use crate::database::repository::SpeedRepository;
+use crate::middleware::brute_force::BruteForceGuard;
+use crate::middleware::client_ip::TrustedProxies;
+use crate::middleware::jwt::JwtVerifier;
+use crate::middleware::rate_limit::RateLimitConfig;
+use crate::middleware::signature::SigningConfig;
+use crate::telemetry::audit::AuditLog;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 
 #[derive(Clone)]
 #[non_exhaustive]
 pub struct AppState {
-    pub db: DbPool,
-    pub redis: ConnectionManager,
+    pub repository: Arc<dyn SpeedRepository>, // Storage of the readings and API keys
+    pub db: Option<DbPool>, // Postgres pool for SQL only features, None with another backend
+    pub cache: Arc<dyn Cache>, // Redis or in-process cache
     pub broadcast_tx: broadcast::Sender<SpeedData>,
+    pub congestion: Arc<CongestionMonitor>,
+    pub fault_detector: Arc<FaultDetector>,
+    pub jwt: Option<Arc<JwtVerifier>>, // Verifier of JWT bearer tokens, None to accept API keys only
+    pub signing: Option<Arc<SigningConfig>>, // Settings of signed requests, None to refuse them
+    pub rate_limit: Option<Arc<RateLimitConfig>>, // Limits of the requests, None for unlimited
+    pub audit: Option<AuditLog>,       // Queue of the audit trail, None to record nothing
+    pub brute_force: Option<Arc<BruteForceGuard>>, // Lockouts and circuit breaker, None to disable
+    pub trusted_proxies: Option<Arc<TrustedProxies>>, // Proxies telling the client IP, None to use the peer
 }
 
 impl AppState {
-    /// Creates a new instance of `AppState` with the provided database connection pool, Redis client, and broadcast channel.
+    /// Creates a new instance of `AppState` with the provided repository, optional Postgres pool, cache, broadcast channel, congestion monitor and fault detector.
     #[inline]
     #[must_use]
     pub fn new(
-        db: DbPool,
-        redis: ConnectionManager,
+        repository: Arc<dyn SpeedRepository>,
+        db: Option<DbPool>,
+        cache: Arc<dyn Cache>,
         broadcast_tx: broadcast::Sender<SpeedData>,
+        congestion: Arc<CongestionMonitor>,
+        fault_detector: Arc<FaultDetector>,
     ) -> Self {
         Self {
+            repository,
             db,
-            redis,
+            cache,
             broadcast_tx,
+            congestion,
+            fault_detector,
+            jwt: None,
+            signing: None,
+            rate_limit: None,
+            audit: None,
+            brute_force: None,
+            trusted_proxies: None,
+        }
+    }
+
+    /// Accepts JWT bearer tokens verified by `jwt` besides API keys
+    #[must_use]
+    pub fn with_jwt(mut self, jwt: Arc<JwtVerifier>) -> Self {
+        self.jwt = Some(jwt);
+        self
+    }
+
+    /// Accepts readings signed with the signing secret of an API key besides Bearer tokens
+    #[must_use]
+    pub fn with_signing(mut self, signing: Arc<SigningConfig>) -> Self {
+        self.signing = Some(signing);
+        self
+    }
+
+    /// Limits the requests of each client IP and each key
+    #[must_use]
+    pub fn with_rate_limit(mut self, rate_limit: Arc<RateLimitConfig>) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Records authentications, key management and deletions in the audit trail
+    #[must_use]
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Locks out the client IPs failing to authenticate and limits the token lookups when
+    /// too many tokens are unknown
+    #[must_use]
+    pub fn with_brute_force(mut self, brute_force: Arc<BruteForceGuard>) -> Self {
+        self.brute_force = Some(brute_force);
+        self
+    }
+
+    /// Reads the client IP from the `X-Forwarded-For` header of the requests sent by `proxies`
+    #[must_use]
+    pub fn with_trusted_proxies(mut self, proxies: Arc<TrustedProxies>) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    /// Queues an event of the audit trail, if recorded
+    pub fn record_audit(&self, event: AuditEvent) {
+        if let Some(audit) = &self.audit {
+            audit.record(event);
         }
     }
 }
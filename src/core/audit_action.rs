@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+/// Kind of event recorded in the audit trail.
+///
+/// Variants are serialized as snake case strings, which is also how they are stored
+/// in the `action` column of `audit_log`.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    AuthSuccess,       // A request was authenticated by an API key, a JWT or a signature
+    AuthFailure,       // A request was refused for its credentials or their scopes
+    KeyCreated,        // An admin created an API key
+    KeyRotated,        // An admin replaced the secret of an API key
+    KeyDeactivated,    // An admin deactivated an API key
+    KeyDeleted,        // An admin deleted an API key
+    SigningSecretRead, // An admin retrieved the signing secret of an API key
+    DataPurged,        // Readings, rollups or audit events were deleted past their retention
+}
+
+impl AuditAction {
+    /// Returns the string representation used in the API and the database.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AuthSuccess => "auth_success",
+            Self::AuthFailure => "auth_failure",
+            Self::KeyCreated => "key_created",
+            Self::KeyRotated => "key_rotated",
+            Self::KeyDeactivated => "key_deactivated",
+            Self::KeyDeleted => "key_deleted",
+            Self::SigningSecretRead => "signing_secret_read",
+            Self::DataPurged => "data_purged",
+        }
+    }
+}
+
+/// Converts a database string to `AuditAction`
+impl TryFrom<&str> for AuditAction {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "auth_success" => Ok(Self::AuthSuccess),
+            "auth_failure" => Ok(Self::AuthFailure),
+            "key_created" => Ok(Self::KeyCreated),
+            "key_rotated" => Ok(Self::KeyRotated),
+            "key_deactivated" => Ok(Self::KeyDeactivated),
+            "key_deleted" => Ok(Self::KeyDeleted),
+            "signing_secret_read" => Ok(Self::SigningSecretRead),
+            "data_purged" => Ok(Self::DataPurged),
+            _ => Err("Invalid value for AuditAction"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_action_serialization() {
+        assert_eq!(
+            serde_json::to_string(&AuditAction::KeyDeactivated).unwrap(),
+            r#""key_deactivated""#
+        );
+        let action: AuditAction = serde_json::from_str(r#""auth_failure""#).unwrap();
+        assert_eq!(action, AuditAction::AuthFailure);
+    }
+
+    #[tokio::test]
+    async fn test_audit_action_as_str_round_trip() {
+        for action in [
+            AuditAction::AuthSuccess,
+            AuditAction::AuthFailure,
+            AuditAction::KeyCreated,
+            AuditAction::KeyRotated,
+            AuditAction::KeyDeactivated,
+            AuditAction::KeyDeleted,
+            AuditAction::SigningSecretRead,
+            AuditAction::DataPurged,
+        ] {
+            assert_eq!(AuditAction::try_from(action.as_str()), Ok(action));
+        }
+        assert!(AuditAction::try_from("logged_in").is_err());
+    }
+}
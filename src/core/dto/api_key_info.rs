@@ -0,0 +1,45 @@
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::database::types::FromPostgresRow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Represents an API key as listed to administrators, without its secret
+///
+/// Only the first characters of the key are kept, as `key_prefix`, to tell keys apart. Keys
+/// created before hashing have no prefix.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
+#[must_use]
+pub struct ApiKeyInfo {
+    pub id: i32,
+    pub name: Option<String>, // Human readable name, e.g. the site or the device
+    pub owner: Option<String>, // Person or team responsible for the key
+    pub scopes: Vec<ApiKeyScope>, // Scopes deciding the routes the key may call
+    pub sensor_name: Option<String>, // Sensor the key is bound to, if any
+    pub expires_at: Option<DateTime<Utc>>, // Timestamp from which the key is refused, if any
+    pub key_prefix: Option<String>, // First characters of the key
+    pub is_active: bool,      // Inactive keys are refused
+    pub created_at: DateTime<Utc>, // Timestamp when the key was created
+}
+
+impl FromPostgresRow for ApiKeyInfo {
+    fn from_row(row: &tokio_postgres::Row) -> Result<Self, crate::database::types::DbError> {
+        use crate::database::types::DbError;
+
+        Ok(ApiKeyInfo {
+            id: row.try_get("id").map_err(DbError::from)?,
+            name: row.try_get("name").map_err(DbError::from)?,
+            owner: row.try_get("owner").map_err(DbError::from)?,
+            scopes: ApiKeyScope::parse_all(
+                row.try_get::<_, Vec<&str>>("scopes")
+                    .map_err(DbError::from)?,
+            )
+            .map_err(|e| DbError::RowParsing(format!("Invalid scopes value: {}", e)))?,
+            sensor_name: row.try_get("sensor_name").map_err(DbError::from)?,
+            expires_at: row.try_get("expires_at").map_err(DbError::from)?,
+            key_prefix: row.try_get("key_prefix").map_err(DbError::from)?,
+            is_active: row.try_get("is_active").map_err(DbError::from)?,
+            created_at: row.try_get("created_at").map_err(DbError::from)?,
+        })
+    }
+}
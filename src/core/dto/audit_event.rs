@@ -0,0 +1,89 @@
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::audit_action::AuditAction;
+use crate::database::types::FromPostgresRow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Represents an entry of the audit trail
+///
+/// `key_id` and `subject` identify who made the request, `target_key_id` the API key an admin
+/// acted on. Events outlive the keys they mention, deleted keys included.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+#[must_use]
+pub struct AuditEvent {
+    pub id: i64,                    // Set once stored, 0 until then
+    pub occurred_at: DateTime<Utc>, // Timestamp when the event happened
+    pub action: AuditAction,        // What happened
+    pub key_id: Option<i32>,        // API key making the request, or claimed by it on failures
+    pub subject: Option<String>,    // Subject of the JWT making the request
+    pub key_prefix: Option<String>, // First characters of a refused Bearer token
+    pub target_key_id: Option<i32>, // API key created, rotated, deactivated or deleted
+    pub ip: Option<String>,         // Address of the client, when known
+    pub method: Option<String>,     // HTTP method of the request
+    pub route: Option<String>,      // Path of the request
+    pub detail: Option<String>,     // Reason of a failure, or what was deleted
+}
+
+impl AuditEvent {
+    /// Creates an event happening now, not tied to a request
+    #[inline]
+    pub fn new(action: AuditAction) -> Self {
+        AuditEvent {
+            id: 0,
+            occurred_at: Utc::now(),
+            action,
+            key_id: None,
+            subject: None,
+            key_prefix: None,
+            target_key_id: None,
+            ip: None,
+            method: None,
+            route: None,
+            detail: None,
+        }
+    }
+
+    /// Attributes the event to the API key or JWT making the request.
+    #[inline]
+    pub fn by(mut self, api_key: &AuthenticatedKey) -> Self {
+        self.key_id = api_key.id;
+        self.subject.clone_from(&api_key.subject);
+        self
+    }
+
+    /// Sets the API key an admin acted on.
+    #[inline]
+    pub fn on_key(mut self, id: i32) -> Self {
+        self.target_key_id = Some(id);
+        self
+    }
+
+    /// Sets the reason of a failure, or what was deleted.
+    #[inline]
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl FromPostgresRow for AuditEvent {
+    fn from_row(row: &tokio_postgres::Row) -> Result<Self, crate::database::types::DbError> {
+        use crate::database::types::DbError;
+
+        Ok(AuditEvent {
+            id: row.try_get("id").map_err(DbError::from)?,
+            occurred_at: row.try_get("occurred_at").map_err(DbError::from)?,
+            action: AuditAction::try_from(row.try_get::<_, &str>("action").map_err(DbError::from)?)
+                .map_err(|e| DbError::RowParsing(format!("Invalid action value: {}", e)))?,
+            key_id: row.try_get("key_id").map_err(DbError::from)?,
+            subject: row.try_get("subject").map_err(DbError::from)?,
+            key_prefix: row.try_get("key_prefix").map_err(DbError::from)?,
+            target_key_id: row.try_get("target_key_id").map_err(DbError::from)?,
+            ip: row.try_get("ip").map_err(DbError::from)?,
+            method: row.try_get("method").map_err(DbError::from)?,
+            route: row.try_get("route").map_err(DbError::from)?,
+            detail: row.try_get("detail").map_err(DbError::from)?,
+        })
+    }
+}
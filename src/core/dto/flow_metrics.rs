@@ -0,0 +1,59 @@
+use crate::analytics::flow::{density, flow_rate, occupancy};
+use crate::core::lane::Lane;
+use crate::core::speed_unit::SpeedUnit;
+use crate::database::types::FromPostgresRow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Represents traffic flow metrics of one sensor and lane over one time bucket
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
+#[must_use]
+pub struct FlowMetrics {
+    pub bucket_start: DateTime<Utc>,   // Start of the time bucket
+    pub sensor_name: Option<String>,   // Sensor that reported the readings
+    pub lane: Lane,                    // Lane of the readings
+    pub volume: i64,                   // Number of vehicles in the bucket
+    pub flow_rate: Option<f64>,        // Vehicles per hour
+    pub avg_headway_secs: Option<f64>, // Mean time between consecutive vehicles in seconds
+    pub space_mean_speed: Option<f64>, // Harmonic mean of the speeds in km/h
+    pub density: Option<f64>,          // Vehicles per km
+    pub occupancy: Option<f64>,        // Estimated detector occupancy in percent
+}
+
+impl FlowMetrics {
+    /// Converts the space-mean speed from the canonical km/h to the given unit.
+    ///
+    /// Density stays expressed in vehicles per km.
+    #[inline]
+    pub fn in_unit(mut self, unit: SpeedUnit) -> Self {
+        self.space_mean_speed = self.space_mean_speed.map(|speed| unit.convert_kmh(speed));
+        self
+    }
+}
+
+impl FromPostgresRow for FlowMetrics {
+    fn from_row(row: &tokio_postgres::Row) -> Result<Self, crate::database::types::DbError> {
+        use crate::database::types::DbError;
+
+        let volume: i64 = row.try_get("volume").map_err(DbError::from)?;
+        let observed_secs: f64 = row.try_get("observed_secs").map_err(DbError::from)?;
+        let space_mean_speed: Option<f64> =
+            row.try_get("space_mean_speed").map_err(DbError::from)?;
+        let occupied_secs: Option<f64> = row.try_get("occupied_secs").map_err(DbError::from)?;
+        let flow_rate = flow_rate(volume, observed_secs);
+
+        Ok(FlowMetrics {
+            bucket_start: row.try_get("bucket_start").map_err(DbError::from)?,
+            sensor_name: row.try_get("sensor_name").map_err(DbError::from)?,
+            lane: Lane::try_from(row.try_get::<_, i32>("lane").map_err(DbError::from)?)
+                .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?,
+            volume,
+            flow_rate,
+            avg_headway_secs: row.try_get("avg_headway_secs").map_err(DbError::from)?,
+            space_mean_speed,
+            density: density(flow_rate, space_mean_speed),
+            occupancy: occupancy(occupied_secs, observed_secs),
+        })
+    }
+}
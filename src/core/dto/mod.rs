@@ -1 +1,5 @@
+pub mod api_key_info;
+pub mod audit_event;
+pub mod flow_metrics;
+pub mod speed_aggregate;
 pub mod speed_data;
@@ -0,0 +1,93 @@
+use crate::core::lane::Lane;
+use crate::core::speed_unit::SpeedUnit;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::types::FromPostgresRow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Represents aggregated speed statistics over one time bucket
+///
+/// The `lane`, `sensor_name` and `vehicle_class` fields are only set when the
+/// aggregate was grouped by the corresponding dimension.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
+#[must_use]
+pub struct SpeedAggregate {
+    pub bucket_start: DateTime<Utc>,         // Start of the time bucket
+    pub lane: Option<Lane>,                  // Lane of the group, if grouped by lane
+    pub sensor_name: Option<String>,         // Sensor of the group, if grouped by sensor
+    pub vehicle_class: Option<VehicleClass>, // Vehicle class of the group, if grouped by class
+    pub count: i64,                          // Number of readings in the bucket
+    pub avg_speed: f64,                      // Average speed in km/h
+    pub min_speed: f32,                      // Minimum speed in km/h
+    pub max_speed: f32,                      // Maximum speed in km/h
+}
+
+impl SpeedAggregate {
+    /// Converts the speed statistics from the canonical km/h to the given unit.
+    #[inline]
+    pub fn in_unit(mut self, unit: SpeedUnit) -> Self {
+        self.avg_speed = unit.convert_kmh(self.avg_speed);
+        self.min_speed = unit.convert_kmh_f32(self.min_speed);
+        self.max_speed = unit.convert_kmh_f32(self.max_speed);
+        self
+    }
+}
+
+impl FromPostgresRow for SpeedAggregate {
+    fn from_row(row: &tokio_postgres::Row) -> Result<Self, crate::database::types::DbError> {
+        use crate::database::types::DbError;
+
+        Ok(SpeedAggregate {
+            bucket_start: row.try_get("bucket_start").map_err(DbError::from)?,
+            lane: row
+                .try_get::<_, Option<i32>>("lane")
+                .map_err(DbError::from)?
+                .map(Lane::try_from)
+                .transpose()
+                .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?,
+            sensor_name: row.try_get("sensor_name").map_err(DbError::from)?,
+            vehicle_class: row
+                .try_get::<_, Option<&str>>("vehicle_class")
+                .map_err(DbError::from)?
+                .map(VehicleClass::try_from)
+                .transpose()
+                .map_err(|e| DbError::RowParsing(format!("Invalid vehicle class value: {}", e)))?,
+            count: row.try_get("count").map_err(DbError::from)?,
+            avg_speed: row.try_get("avg_speed").map_err(DbError::from)?,
+            min_speed: row.try_get("min_speed").map_err(DbError::from)?,
+            max_speed: row.try_get("max_speed").map_err(DbError::from)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    #[tokio::test]
+    async fn test_speed_aggregate_serialization() {
+        let aggregate = SpeedAggregate {
+            bucket_start: Utc.with_ymd_and_hms(2024, 1, 15, 8, 0, 0).unwrap(),
+            lane: None,
+            sensor_name: None,
+            vehicle_class: Some(VehicleClass::Bus),
+            count: 12,
+            avg_speed: 54.25,
+            min_speed: 31.0,
+            max_speed: 70.5,
+        };
+
+        let json = serde_json::to_value(&aggregate).unwrap();
+        assert_eq!(json["bucket_start"], "2024-01-15T08:00:00Z");
+        assert_eq!(json["lane"], serde_json::Value::Null);
+        assert_eq!(json["vehicle_class"], "bus");
+        assert_eq!(json["count"], 12);
+        assert_eq!(json["avg_speed"], 54.25);
+
+        let converted = aggregate.in_unit(SpeedUnit::Ms);
+        assert_eq!(converted.min_speed, (31.0 / 3.6) as f32);
+        assert_eq!(converted.max_speed, (70.5 / 3.6) as f32);
+    }
+}
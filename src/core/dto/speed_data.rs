@@ -1,4 +1,7 @@
 use crate::core::lane::Lane;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::speed_unit::SpeedUnit;
+use crate::core::vehicle_class::VehicleClass;
 use crate::database::types::FromPostgresRow;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -13,6 +16,12 @@ pub struct SpeedData {
     pub speed: f32,                  // Represents the speed of the vehicle in km/h
     pub lane: Lane,                  // Represents the lane of the vehicle (Left or Right)
     pub created_at: DateTime<Utc>,   // Timestamp when the speed data was created
+    #[serde(default)]
+    pub vehicle_class: Option<VehicleClass>, // Optional class of the vehicle (car, truck, ...)
+    #[serde(default)]
+    pub vehicle_length: Option<f32>, // Optional length of the vehicle in meters
+    #[serde(default)]
+    pub quality_flag: Option<QualityFlag>, // Set when a fault detector considered the reading suspicious
 }
 
 impl SpeedData {
@@ -31,8 +40,30 @@ impl SpeedData {
             speed,
             lane,
             created_at,
+            vehicle_class: None,
+            vehicle_length: None,
+            quality_flag: None,
         }
     }
+
+    /// Sets the vehicle classification fields reported by the sensor.
+    #[inline]
+    pub fn with_vehicle(
+        mut self,
+        vehicle_class: Option<VehicleClass>,
+        vehicle_length: Option<f32>,
+    ) -> Self {
+        self.vehicle_class = vehicle_class;
+        self.vehicle_length = vehicle_length;
+        self
+    }
+
+    /// Converts the speed from the canonical km/h to the given unit.
+    #[inline]
+    pub fn in_unit(mut self, unit: SpeedUnit) -> Self {
+        self.speed = unit.convert_kmh_f32(self.speed);
+        self
+    }
 }
 
 impl FromPostgresRow for SpeedData {
@@ -46,6 +77,19 @@ impl FromPostgresRow for SpeedData {
             lane: Lane::try_from(row.try_get::<_, i32>("lane").map_err(DbError::from)?)
                 .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?,
             created_at: row.try_get("created_at").map_err(DbError::from)?,
+            vehicle_class: row
+                .try_get::<_, Option<&str>>("vehicle_class")
+                .map_err(DbError::from)?
+                .map(VehicleClass::try_from)
+                .transpose()
+                .map_err(|e| DbError::RowParsing(format!("Invalid vehicle class value: {}", e)))?,
+            vehicle_length: row.try_get("vehicle_length").map_err(DbError::from)?,
+            quality_flag: row
+                .try_get::<_, Option<&str>>("quality_flag")
+                .map_err(DbError::from)?
+                .map(QualityFlag::try_from)
+                .transpose()
+                .map_err(|e| DbError::RowParsing(format!("Invalid quality flag value: {}", e)))?,
         })
     }
 }
@@ -73,6 +117,40 @@ mod tests {
         assert_eq!(sensor_data.speed, SPEED);
         assert_eq!(sensor_data.lane, Lane::Left);
         assert_eq!(sensor_data.created_at, created_at);
+        assert_eq!(sensor_data.vehicle_class, None);
+        assert_eq!(sensor_data.vehicle_length, None);
+    }
+
+    #[tokio::test]
+    async fn test_sensor_data_with_vehicle() {
+        let created_at = Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap();
+        let sensor_data = SpeedData::new(1, None, 88.0, Lane::Right, created_at)
+            .with_vehicle(Some(VehicleClass::Truck), Some(16.5));
+
+        assert_eq!(sensor_data.vehicle_class, Some(VehicleClass::Truck));
+        assert_eq!(sensor_data.vehicle_length, Some(16.5));
+
+        let json = serde_json::to_value(&sensor_data).unwrap();
+        assert_eq!(json["vehicle_class"], "truck");
+        assert_eq!(json["vehicle_length"], 16.5);
+    }
+
+    #[tokio::test]
+    async fn test_sensor_data_in_unit() {
+        let created_at = Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap();
+        let sensor_data = SpeedData::new(1, None, 36.0, Lane::Left, created_at);
+
+        assert_eq!(sensor_data.clone().in_unit(SpeedUnit::Kmh).speed, 36.0);
+        assert_eq!(sensor_data.in_unit(SpeedUnit::Ms).speed, 10.0);
     }
 
+    #[tokio::test]
+    async fn test_sensor_data_deserialization_without_vehicle_fields() {
+        // Entries cached before classification support must still deserialize
+        let json = r#"{"id":7,"sensor_name":null,"speed":50.0,"lane":0,"created_at":"2023-10-01T12:00:00Z"}"#;
+        let sensor_data: SpeedData = serde_json::from_str(json).unwrap();
+        assert_eq!(sensor_data.id, 7);
+        assert_eq!(sensor_data.vehicle_class, None);
+        assert_eq!(sensor_data.vehicle_length, None);
+    }
 }
@@ -5,7 +5,7 @@ use serde::{Deserialize, Deserializer, Serialize};
 /// Variants:
 /// - `Left`: Represents the left lane (value: 0)
 /// - `Right`: Represents the right lane (value: 1)
-#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash)]
 #[repr(u8)]
 pub enum Lane {
     Left = 0,
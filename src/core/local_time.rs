@@ -0,0 +1,91 @@
+use chrono::offset::LocalResult;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// Converts a local wall-clock time of the given timezone to UTC
+///
+/// Ambiguous times (when clocks go back) resolve to the earliest instant. Times that don't
+/// exist (when clocks go forward) resolve to the first valid instant after the gap.
+#[must_use]
+pub fn local_to_utc(tz: Tz, naive: NaiveDateTime) -> DateTime<Utc> {
+    let mut candidate = naive;
+    loop {
+        match tz.from_local_datetime(&candidate) {
+            LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => {
+                return dt.with_timezone(&Utc);
+            }
+            LocalResult::None => {
+                candidate = (candidate + Duration::minutes(1))
+                    .with_second(0)
+                    .unwrap_or(candidate);
+            }
+        }
+    }
+}
+
+/// Returns the UTC bounds `[start, end)` of a calendar day in the given timezone
+///
+/// A day lasts 23 or 25 hours when it contains a DST transition.
+#[must_use]
+pub fn day_bounds(tz: Tz, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = local_to_utc(tz, date.and_time(NaiveTime::MIN));
+    let next = date.succ_opt().unwrap_or(date);
+    let end = local_to_utc(tz, next.and_time(NaiveTime::MIN));
+    (start, end)
+}
+
+/// Returns the UTC bounds `[start, end)` of the current day in the given timezone
+#[must_use]
+pub fn today_bounds(tz: Tz, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    day_bounds(tz, now.with_timezone(&tz).date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_day_bounds_utc() {
+        let (start, end) = day_bounds(Tz::UTC, date(2024, 1, 15));
+        assert_eq!(start.to_rfc3339(), "2024-01-15T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-01-16T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_day_bounds_paris_winter_and_summer() {
+        let (start, _) = day_bounds(chrono_tz::Europe::Paris, date(2024, 1, 15));
+        assert_eq!(start.to_rfc3339(), "2024-01-14T23:00:00+00:00");
+
+        let (start, _) = day_bounds(chrono_tz::Europe::Paris, date(2024, 7, 15));
+        assert_eq!(start.to_rfc3339(), "2024-07-14T22:00:00+00:00");
+    }
+
+    #[test]
+    fn test_day_bounds_across_dst_transitions() {
+        let (start, end) = day_bounds(chrono_tz::Europe::Paris, date(2024, 3, 31));
+        assert_eq!(end - start, Duration::hours(23));
+
+        let (start, end) = day_bounds(chrono_tz::Europe::Paris, date(2024, 10, 27));
+        assert_eq!(end - start, Duration::hours(25));
+    }
+
+    #[test]
+    fn test_day_starting_in_dst_gap() {
+        // Clocks in Havana jump from 00:00 to 01:00, so the day starts at 01:00 local time
+        let (start, _) = day_bounds(chrono_tz::America::Havana, date(2024, 3, 10));
+        assert_eq!(start.to_rfc3339(), "2024-03-10T05:00:00+00:00");
+    }
+
+    #[test]
+    fn test_today_bounds_uses_local_date() {
+        // 23:30 UTC is already the next day in Paris
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 23, 30, 0).unwrap();
+        let (start, end) = today_bounds(chrono_tz::Europe::Paris, now);
+        assert_eq!(start.to_rfc3339(), "2024-01-15T23:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-01-16T23:00:00+00:00");
+    }
+}
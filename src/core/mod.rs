@@ -1,3 +1,12 @@
+pub mod api_key;
+pub mod api_key_scope;
 pub mod app_state;
+pub mod audit_action;
 pub mod dto;
-mod lane;
+pub mod lane;
+pub mod local_time;
+pub mod quality_flag;
+pub mod relative_time;
+pub mod speed_unit;
+pub mod time_bucket;
+pub mod vehicle_class;
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// Reason why a reading was tagged as suspicious by the sensor fault detectors.
+///
+/// Variants are serialized as snake case strings, which is also how they are stored
+/// in the `quality_flag` column.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityFlag {
+    StuckValue,        // The sensor repeated the exact same value too many times
+    SuddenJump,        // The speed changed implausibly fast between consecutive readings
+    OutOfDistribution, // The speed is impossible or far outside the sensor's rolling baseline
+}
+
+impl QualityFlag {
+    /// Returns the string representation used in the API and the database.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StuckValue => "stuck_value",
+            Self::SuddenJump => "sudden_jump",
+            Self::OutOfDistribution => "out_of_distribution",
+        }
+    }
+}
+
+/// Converts a database string to `QualityFlag`
+impl TryFrom<&str> for QualityFlag {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "stuck_value" => Ok(Self::StuckValue),
+            "sudden_jump" => Ok(Self::SuddenJump),
+            "out_of_distribution" => Ok(Self::OutOfDistribution),
+            _ => Err("Invalid value for QualityFlag"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_quality_flag_serialization() {
+        assert_eq!(
+            serde_json::to_string(&QualityFlag::OutOfDistribution).unwrap(),
+            r#""out_of_distribution""#
+        );
+        let flag: QualityFlag = serde_json::from_str(r#""stuck_value""#).unwrap();
+        assert_eq!(flag, QualityFlag::StuckValue);
+    }
+
+    #[tokio::test]
+    async fn test_quality_flag_as_str_round_trip() {
+        for flag in [
+            QualityFlag::StuckValue,
+            QualityFlag::SuddenJump,
+            QualityFlag::OutOfDistribution,
+        ] {
+            assert_eq!(QualityFlag::try_from(flag.as_str()), Ok(flag));
+        }
+        assert!(QualityFlag::try_from("broken").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::core::local_time::day_bounds;
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// Calendar periods that can be used instead of a date, resolved in the requested timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamedPeriod {
+    Today,
+    Yesterday,
+    ThisWeek, // Weeks start on Monday
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+}
+
+impl NamedPeriod {
+    /// Returns the UTC bounds `[start, end)` of the period containing `now`
+    #[must_use]
+    pub fn bounds(self, tz: Tz, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = now.with_timezone(&tz).date_naive();
+        let (first, last) = match self {
+            Self::Today => (today, today),
+            Self::Yesterday => {
+                let yesterday = today - Days::new(1);
+                (yesterday, yesterday)
+            }
+            Self::ThisWeek => week_of(today),
+            Self::LastWeek => week_of(today - Days::new(7)),
+            Self::ThisMonth => month_of(today),
+            Self::LastMonth => month_of(today - Months::new(1)),
+        };
+        (day_bounds(tz, first).0, day_bounds(tz, last).1)
+    }
+}
+
+impl TryFrom<&str> for NamedPeriod {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "today" => Ok(Self::Today),
+            "yesterday" => Ok(Self::Yesterday),
+            "this_week" => Ok(Self::ThisWeek),
+            "last_week" => Ok(Self::LastWeek),
+            "this_month" => Ok(Self::ThisMonth),
+            "last_month" => Ok(Self::LastMonth),
+            _ => Err(format!("Unknown period: '{value}'")),
+        }
+    }
+}
+
+/// Returns the first and last day of the week containing `date`
+fn week_of(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let week = date.week(Weekday::Mon);
+    (week.first_day(), week.last_day())
+}
+
+/// Returns the first and last day of the month containing `date`
+fn month_of(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let first = date.with_day(1).unwrap_or(date);
+    let last = first + Months::new(1) - Days::new(1);
+    (first, last)
+}
+
+/// Parses a duration, either compact (`15m`, `1h30m`, `2d`, `1w`) or ISO 8601 (`PT15M`, `P1DT12H`, `P2W`)
+///
+/// Years and months are rejected since their length varies.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration: '{value}'");
+
+    let iso = value.strip_prefix('P');
+    let body = iso.unwrap_or(value);
+
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    let mut parts = 0;
+
+    for c in body.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if iso.is_some() && c == 'T' && !in_time && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+
+        let multiplier: i64 = match (iso.is_some(), in_time, c) {
+            (true, false, 'W') | (false, _, 'w') => 604_800,
+            (true, false, 'D') | (false, _, 'd') => 86_400,
+            (true, true, 'H') | (false, _, 'h') => 3600,
+            (true, true, 'M') | (false, _, 'm') => 60,
+            (true, true, 'S') | (false, _, 's') => 1,
+            _ => return Err(invalid()),
+        };
+
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        seconds = n
+            .checked_mul(multiplier)
+            .and_then(|s| seconds.checked_add(s))
+            .ok_or_else(invalid)?;
+        number.clear();
+        parts += 1;
+    }
+
+    if parts == 0 || !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Duration::try_seconds(seconds).ok_or_else(invalid)
+}
+
+/// Parses an instant relative to `now`: `now`, `now-1h`, `now+30m`, `-15m` or an ISO 8601
+/// duration such as `PT15M` (meaning that long ago)
+///
+/// Returns `None` if the value isn't a relative expression.
+pub fn parse_relative(value: &str, now: DateTime<Utc>) -> Option<Result<DateTime<Utc>, String>> {
+    let shift = |duration: Result<Duration, String>, sign: i32| {
+        duration.and_then(|d| {
+            let shifted = if sign < 0 {
+                now.checked_sub_signed(d)
+            } else {
+                now.checked_add_signed(d)
+            };
+            shifted.ok_or_else(|| format!("Out of range: '{value}'"))
+        })
+    };
+
+    if value == "now" {
+        return Some(Ok(now));
+    }
+    if let Some(rest) = value.strip_prefix("now-") {
+        return Some(shift(parse_duration(rest), -1));
+    }
+    if let Some(rest) = value.strip_prefix("now+") {
+        return Some(shift(parse_duration(rest), 1));
+    }
+    if let Some(rest) = value.strip_prefix('-') {
+        return Some(shift(parse_duration(rest), -1));
+    }
+    if value.starts_with('P') {
+        return Some(shift(parse_duration(value), -1));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    fn now() -> DateTime<Utc> {
+        // Wednesday
+        Utc.with_ymd_and_hms(2024, 3, 13, 10, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_compact_duration() {
+        assert_eq!(parse_duration("15m"), Ok(Duration::minutes(15)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::days(2)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::weeks(1)));
+        assert!(parse_duration("15").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("15x").is_err());
+    }
+
+    #[test]
+    fn test_parse_iso_duration() {
+        assert_eq!(parse_duration("PT15M"), Ok(Duration::minutes(15)));
+        assert_eq!(parse_duration("P1DT12H"), Ok(Duration::hours(36)));
+        assert_eq!(parse_duration("P2W"), Ok(Duration::weeks(2)));
+        assert_eq!(parse_duration("PT30S"), Ok(Duration::seconds(30)));
+        assert!(parse_duration("P1M").is_err());
+        assert!(parse_duration("P1Y").is_err());
+        assert!(parse_duration("PT").is_err());
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        assert_eq!(parse_relative("now", now()), Some(Ok(now())));
+        assert_eq!(
+            parse_relative("now-1h", now()),
+            Some(Ok(now() - Duration::hours(1)))
+        );
+        assert_eq!(
+            parse_relative("-15m", now()),
+            Some(Ok(now() - Duration::minutes(15)))
+        );
+        assert_eq!(
+            parse_relative("PT15M", now()),
+            Some(Ok(now() - Duration::minutes(15)))
+        );
+        assert_eq!(parse_relative("2024-01-01", now()), None);
+        assert!(matches!(parse_relative("now-xyz", now()), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_named_periods() {
+        let (start, end) = NamedPeriod::Yesterday.bounds(Tz::UTC, now());
+        assert_eq!(start.to_rfc3339(), "2024-03-12T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-13T00:00:00+00:00");
+
+        let (start, end) = NamedPeriod::ThisWeek.bounds(Tz::UTC, now());
+        assert_eq!(start.to_rfc3339(), "2024-03-11T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-18T00:00:00+00:00");
+
+        let (start, end) = NamedPeriod::LastMonth.bounds(Tz::UTC, now());
+        assert_eq!(start.to_rfc3339(), "2024-02-01T00:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_named_period_in_timezone() {
+        // Last week in Paris contains the switch to summer time
+        let now = Utc.with_ymd_and_hms(2024, 4, 3, 12, 0, 0).unwrap();
+        let (start, end) = NamedPeriod::LastWeek.bounds(chrono_tz::Europe::Paris, now);
+        assert_eq!(start.to_rfc3339(), "2024-03-24T23:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-31T22:00:00+00:00");
+    }
+
+    #[test]
+    fn test_named_period_from_str() {
+        assert_eq!(
+            NamedPeriod::try_from("this_week"),
+            Ok(NamedPeriod::ThisWeek)
+        );
+        assert!(NamedPeriod::try_from("next_week").is_err());
+    }
+}
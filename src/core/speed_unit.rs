@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+/// Unit of a speed value.
+///
+/// Speeds are always stored in km/h; other units are converted at the API boundary.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Default, Serialize, Deserialize)]
+pub enum SpeedUnit {
+    #[default]
+    #[serde(rename = "kmh", alias = "km/h", alias = "kph")]
+    Kmh,
+    #[serde(rename = "mph")]
+    Mph,
+    #[serde(rename = "ms", alias = "m/s")]
+    Ms,
+}
+
+/// Number of kilometers in one international mile
+const KM_PER_MILE: f64 = 1.609_344;
+
+impl SpeedUnit {
+    /// Returns the string representation used in the API.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Kmh => "kmh",
+            Self::Mph => "mph",
+            Self::Ms => "ms",
+        }
+    }
+
+    /// Converts a speed in km/h to this unit
+    #[must_use]
+    pub fn convert_kmh(self, kmh: f64) -> f64 {
+        match self {
+            Self::Kmh => kmh,
+            Self::Mph => kmh / KM_PER_MILE,
+            Self::Ms => kmh / 3.6,
+        }
+    }
+
+    /// Converts a speed in this unit to km/h
+    #[must_use]
+    pub fn to_kmh(self, value: f64) -> f64 {
+        match self {
+            Self::Kmh => value,
+            Self::Mph => value * KM_PER_MILE,
+            Self::Ms => value * 3.6,
+        }
+    }
+
+    /// Converts a single precision speed in km/h to this unit
+    #[must_use]
+    pub fn convert_kmh_f32(self, kmh: f32) -> f32 {
+        self.convert_kmh(f64::from(kmh)) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_speed_unit_deserialization() {
+        let unit: SpeedUnit = serde_json::from_str(r#""mph""#).unwrap();
+        assert_eq!(unit, SpeedUnit::Mph);
+
+        let unit: SpeedUnit = serde_json::from_str(r#""m/s""#).unwrap();
+        assert_eq!(unit, SpeedUnit::Ms);
+
+        let unit: SpeedUnit = serde_json::from_str(r#""km/h""#).unwrap();
+        assert_eq!(unit, SpeedUnit::Kmh);
+
+        let invalid: Result<SpeedUnit, _> = serde_json::from_str(r#""knots""#);
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_speed_unit_conversions() {
+        assert_eq!(SpeedUnit::Kmh.convert_kmh(100.0), 100.0);
+        assert!((SpeedUnit::Mph.convert_kmh(100.0) - 62.137_119).abs() < 1e-6);
+        assert_eq!(SpeedUnit::Ms.convert_kmh(36.0), 10.0);
+
+        assert!((SpeedUnit::Mph.to_kmh(60.0) - 96.560_64).abs() < 1e-6);
+        assert_eq!(SpeedUnit::Ms.to_kmh(10.0), 36.0);
+    }
+
+    #[tokio::test]
+    async fn test_speed_unit_round_trip() {
+        for unit in [SpeedUnit::Kmh, SpeedUnit::Mph, SpeedUnit::Ms] {
+            let value = unit.convert_kmh(unit.to_kmh(42.5));
+            assert!((value - 42.5).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use chrono::{DateTime, Datelike, Days, Duration, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Width of the time buckets used by aggregate endpoints.
+///
+/// Each variant maps to a PostgreSQL `date_trunc` field.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Minute,
+    #[default]
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeBucket {
+    /// Returns the `date_trunc` field name for this bucket.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// Returns the start of the bucket containing `at`, in the local time of `tz`
+    ///
+    /// Mirrors `date_trunc(field, at, tz)` for the backends without time zone support in SQL;
+    /// weeks start on Monday. A local start that is ambiguous resolves to its first occurrence,
+    /// and one skipped by a daylight saving change to the first local time after the gap.
+    #[must_use]
+    pub fn truncate(&self, at: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+        let local = at.with_timezone(&tz).naive_local();
+        let date = local.date();
+        let start = match self {
+            Self::Minute => date.and_hms_opt(local.hour(), local.minute(), 0),
+            Self::Hour => date.and_hms_opt(local.hour(), 0, 0),
+            Self::Day => date.and_hms_opt(0, 0, 0),
+            Self::Week => (date - Days::new(u64::from(date.weekday().num_days_from_monday())))
+                .and_hms_opt(0, 0, 0),
+            Self::Month => date.with_day(1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+        };
+
+        // Gaps last at most a few hours and start on a whole minute
+        start
+            .and_then(|start| {
+                (0..=24 * 60).find_map(|minutes| {
+                    tz.from_local_datetime(&(start + Duration::minutes(minutes)))
+                        .earliest()
+                })
+            })
+            .map_or(at, |start| start.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_time_bucket_deserialization() {
+        let bucket: TimeBucket = serde_json::from_str(r#""minute""#).unwrap();
+        assert_eq!(bucket, TimeBucket::Minute);
+
+        let invalid: Result<TimeBucket, _> = serde_json::from_str(r#""decade""#);
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_time_bucket_default_and_as_str() {
+        assert_eq!(TimeBucket::default(), TimeBucket::Hour);
+        assert_eq!(TimeBucket::Week.as_str(), "week");
+        assert_eq!(TimeBucket::Month.as_str(), "month");
+    }
+
+    #[tokio::test]
+    async fn test_truncate_in_time_zone() {
+        use chrono::TimeZone as _;
+
+        // Wednesday 2024-03-13 23:45:30 UTC is Thursday 00:45:30 in Paris
+        let at = Utc.with_ymd_and_hms(2024, 3, 13, 23, 45, 30).unwrap();
+        let paris = chrono_tz::Europe::Paris;
+        let utc = |d, h, m| Utc.with_ymd_and_hms(2024, 3, d, h, m, 0).unwrap();
+
+        assert_eq!(TimeBucket::Minute.truncate(at, paris), utc(13, 23, 45));
+        assert_eq!(TimeBucket::Hour.truncate(at, paris), utc(13, 23, 0));
+        assert_eq!(TimeBucket::Day.truncate(at, paris), utc(13, 23, 0));
+        assert_eq!(TimeBucket::Day.truncate(at, Tz::UTC), utc(13, 0, 0));
+        assert_eq!(TimeBucket::Week.truncate(at, paris), utc(10, 23, 0));
+        assert_eq!(
+            TimeBucket::Month.truncate(at, paris),
+            Utc.with_ymd_and_hms(2024, 2, 29, 23, 0, 0).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_truncate_in_daylight_saving_gap() {
+        use chrono::TimeZone as _;
+
+        // Havana skips from 00:00 to 01:00 on 2024-03-10, the day starts at 01:00 local time
+        let havana = chrono_tz::America::Havana;
+        let at = Utc.with_ymd_and_hms(2024, 3, 10, 16, 0, 0).unwrap();
+        assert_eq!(
+            TimeBucket::Day.truncate(at, havana),
+            Utc.with_ymd_and_hms(2024, 3, 10, 5, 0, 0).unwrap()
+        );
+    }
+}
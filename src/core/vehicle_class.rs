@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Represents the class of a vehicle as reported by classifying sensors.
+///
+/// Variants are serialized as lowercase strings (`"car"`, `"truck"`, `"motorcycle"`, `"bus"`),
+/// which is also how they are stored in the `vehicle_class` column.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VehicleClass {
+    Car,
+    Truck,
+    Motorcycle,
+    Bus,
+}
+
+impl VehicleClass {
+    /// Returns the string representation used in the API and the database.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Car => "car",
+            Self::Truck => "truck",
+            Self::Motorcycle => "motorcycle",
+            Self::Bus => "bus",
+        }
+    }
+}
+
+/// Converts a database string to `VehicleClass`
+impl TryFrom<&str> for VehicleClass {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "car" => Ok(Self::Car),
+            "truck" => Ok(Self::Truck),
+            "motorcycle" => Ok(Self::Motorcycle),
+            "bus" => Ok(Self::Bus),
+            _ => Err("Invalid value for VehicleClass"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_vehicle_class_serialization() {
+        assert_eq!(
+            serde_json::to_string(&VehicleClass::Car).unwrap(),
+            r#""car""#
+        );
+        assert_eq!(
+            serde_json::to_string(&VehicleClass::Motorcycle).unwrap(),
+            r#""motorcycle""#
+        );
+
+        let truck: VehicleClass = serde_json::from_str(r#""truck""#).unwrap();
+        assert_eq!(truck, VehicleClass::Truck);
+
+        let invalid: Result<VehicleClass, _> = serde_json::from_str(r#""tractor""#);
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vehicle_class_try_from() {
+        assert_eq!(VehicleClass::try_from("bus"), Ok(VehicleClass::Bus));
+        assert_eq!(VehicleClass::try_from("car"), Ok(VehicleClass::Car));
+        assert_eq!(
+            VehicleClass::try_from("Car"),
+            Err("Invalid value for VehicleClass")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vehicle_class_as_str_round_trip() {
+        for class in [
+            VehicleClass::Car,
+            VehicleClass::Truck,
+            VehicleClass::Motorcycle,
+            VehicleClass::Bus,
+        ] {
+            assert_eq!(VehicleClass::try_from(class.as_str()), Ok(class));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::database::pool::DbPool;
+use crate::database::types::{DbError, FromPostgresRow};
+use crate::database::util::{INSERT_TIMEOUT, RANGE_QUERY_TIMEOUT, with_timeout};
+use crate::log_error;
+use chrono::{DateTime, Utc};
+
+/// Stores a batch of audit events in one statement
+pub async fn insert_audit_events(pool: &DbPool, events: &[AuditEvent]) -> Result<(), DbError> {
+    const QUERY: &str = "INSERT INTO audit_log (occurred_at,action,key_id,subject,key_prefix,target_key_id,ip,method,route,detail) SELECT * FROM UNNEST($1::timestamptz[], $2::text[], $3::int4[], $4::text[], $5::text[], $6::int4[], $7::text[], $8::text[], $9::text[], $10::text[])";
+
+    let occurred_ats: Vec<DateTime<Utc>> = events.iter().map(|e| e.occurred_at).collect();
+    let actions: Vec<&str> = events.iter().map(|e| e.action.as_str()).collect();
+    let key_ids: Vec<Option<i32>> = events.iter().map(|e| e.key_id).collect();
+    let subjects: Vec<Option<&str>> = events.iter().map(|e| e.subject.as_deref()).collect();
+    let key_prefixes: Vec<Option<&str>> = events.iter().map(|e| e.key_prefix.as_deref()).collect();
+    let target_key_ids: Vec<Option<i32>> = events.iter().map(|e| e.target_key_id).collect();
+    let ips: Vec<Option<&str>> = events.iter().map(|e| e.ip.as_deref()).collect();
+    let methods: Vec<Option<&str>> = events.iter().map(|e| e.method.as_deref()).collect();
+    let routes: Vec<Option<&str>> = events.iter().map(|e| e.route.as_deref()).collect();
+    let details: Vec<Option<&str>> = events.iter().map(|e| e.detail.as_deref()).collect();
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        conn.execute(
+            QUERY,
+            &[
+                &occurred_ats,
+                &actions,
+                &key_ids,
+                &subjects,
+                &key_prefixes,
+                &target_key_ids,
+                &ips,
+                &methods,
+                &routes,
+                &details,
+            ],
+        )
+        .await
+        .map_err(DbError::from)
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            log_error!("Failed to insert {} audit events: {e}", events.len());
+            e
+        })
+}
+
+/// Fetches at most `limit` audit events of `[start_date, end_date]`, most recent first
+///
+/// `key_id` keeps the events of requests made with the key and of admin actions on it.
+pub async fn fetch_audit_events(
+    pool: &DbPool,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    action: Option<AuditAction>,
+    key_id: Option<i32>,
+    limit: u32,
+) -> Result<Vec<AuditEvent>, DbError> {
+    const QUERY: &str = "SELECT id,occurred_at,action,key_id,subject,key_prefix,target_key_id,ip,method,route,detail FROM audit_log WHERE occurred_at BETWEEN $1 AND $2 AND ($3::text IS NULL OR action = $3) AND ($4::int4 IS NULL OR key_id = $4 OR target_key_id = $4) ORDER BY occurred_at DESC, id DESC LIMIT $5";
+
+    let action = action.map(|a| a.as_str());
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[&start_date, &end_date, &action, &key_id, &i64::from(limit)],
+            )
+            .await
+            .map_err(DbError::from)?;
+        rows.iter().map(AuditEvent::from_row).collect()
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch audit events: {e}");
+            e
+        })
+}
@@ -1,21 +1,36 @@
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::core::api_key::{AuthenticatedKey, hash_api_key};
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::core::dto::api_key_info::ApiKeyInfo;
 use crate::database::pool::DbPool;
-use crate::database::types::DbError;
-use crate::database::util::{with_timeout, AUTH_QUERY_TIMEOUT};
+use crate::database::types::{DbError, FromPostgresRow};
+use crate::database::util::{
+    AUTH_QUERY_TIMEOUT, INSERT_TIMEOUT, SIMPLE_SELECT_TIMEOUT, with_timeout,
+};
 use crate::log_error;
+use tokio_postgres::types::ToSql;
 
-/// Validates if a token exists and is active in the database
-pub async fn validate_token(pool: &DbPool, token: &str) -> Result<bool, DbError> {
-    const QUERY: &str = "SELECT is_active FROM api_keys WHERE api_key = $1";
+/// Returns the active API key with this hash, if any
+pub async fn validate_token(
+    pool: &DbPool,
+    key_hash: &str,
+) -> Result<Option<AuthenticatedKey>, DbError> {
+    const QUERY: &str = "SELECT id, name, owner, scopes, sensor_name, expires_at, key_prefix, is_active, created_at FROM api_keys WHERE key_hash = $1 AND is_active AND (expires_at IS NULL OR expires_at > now())";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
-        let rows = conn.query(&stmt, &[&token]).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(&stmt, &[&key_hash])
+            .await
+            .map_err(DbError::from)?;
 
         match rows.first() {
-            Some(row) => Ok(row.try_get::<_, bool>("is_active").unwrap_or(false)),
-            None => Ok(false),
+            Some(row) => Ok(Some(AuthenticatedKey::from_info(&ApiKeyInfo::from_row(
+                row,
+            )?))),
+            None => Ok(None),
         }
     };
 
@@ -26,3 +41,201 @@ pub async fn validate_token(pool: &DbPool, token: &str) -> Result<bool, DbError>
             e
         })
 }
+
+/// Returns the active API key with this id along with its hash, if any
+pub async fn validate_key_id(
+    pool: &DbPool,
+    id: i32,
+) -> Result<Option<(AuthenticatedKey, String)>, DbError> {
+    const QUERY: &str = "SELECT id, name, owner, scopes, sensor_name, expires_at, key_prefix, is_active, created_at, key_hash FROM api_keys WHERE id = $1 AND key_hash IS NOT NULL AND is_active AND (expires_at IS NULL OR expires_at > now())";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let row = conn.query_opt(QUERY, &[&id]).await.map_err(DbError::from)?;
+        match row {
+            Some(row) => {
+                let key_hash: String = row.try_get("key_hash").map_err(DbError::from)?;
+                let api_key = AuthenticatedKey::from_info(&ApiKeyInfo::from_row(&row)?);
+                Ok(Some((api_key, key_hash)))
+            }
+            None => Ok(None),
+        }
+    };
+
+    with_timeout(query_future, AUTH_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to validate API key {id} in database: {e}");
+            e
+        })
+}
+
+/// Replaces the API keys still stored in plaintext by their hash
+///
+/// Run at startup, so keys created before hashing or inserted by hand keep working.
+/// Returns the number of keys hashed.
+pub async fn hash_plaintext_api_keys(pool: &DbPool) -> Result<u64, DbError> {
+    const SELECT: &str = "SELECT id, api_key FROM api_keys WHERE api_key IS NOT NULL FOR UPDATE";
+    const UPDATE: &str = "UPDATE api_keys SET key_hash = $2, api_key = NULL WHERE id = $1";
+
+    let mut conn = pool.get().await?;
+
+    let query_future = async {
+        let tx = conn.transaction().await.map_err(DbError::from)?;
+        let rows = tx.query(SELECT, &[]).await.map_err(DbError::from)?;
+        for row in &rows {
+            let id: i32 = row.try_get("id").map_err(DbError::from)?;
+            let token: String = row.try_get("api_key").map_err(DbError::from)?;
+            tx.execute(UPDATE, &[&id, &hash_api_key(&token)])
+                .await
+                .map_err(DbError::from)?;
+        }
+        tx.commit().await.map_err(DbError::from)?;
+        Ok(rows.len() as u64)
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to hash plaintext API keys: {e}");
+            e
+        })
+}
+
+/// Counts the API keys still stored in plaintext
+pub async fn count_plaintext_api_keys(pool: &DbPool) -> Result<u64, DbError> {
+    const QUERY: &str = "SELECT count(*) AS plaintext FROM api_keys WHERE api_key IS NOT NULL";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let plaintext: i64 = conn
+            .query_one(QUERY, &[])
+            .await
+            .map_err(DbError::from)?
+            .try_get("plaintext")
+            .map_err(DbError::from)?;
+        Ok(plaintext as u64)
+    };
+
+    with_timeout(query_future, SIMPLE_SELECT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to count plaintext API keys: {e}");
+            e
+        })
+}
+
+/// Stores a new API key, given the hash and prefix of its generated secret
+pub async fn create_api_key(
+    pool: &DbPool,
+    request: &CreateApiKeyRequest,
+    key_hash: &str,
+    key_prefix: &str,
+) -> Result<ApiKeyInfo, DbError> {
+    const QUERY: &str = "INSERT INTO api_keys (key_hash, key_prefix, name, owner, scopes, sensor_name, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, name, owner, scopes, sensor_name, expires_at, key_prefix, is_active, created_at";
+
+    let scopes: Vec<&str> = request.scopes.iter().map(ApiKeyScope::as_str).collect();
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let row = conn
+            .query_one(
+                QUERY,
+                &[
+                    &key_hash,
+                    &key_prefix,
+                    &request.name,
+                    &request.owner,
+                    &scopes,
+                    &request.sensor_name,
+                    &request.expires_at,
+                ],
+            )
+            .await
+            .map_err(DbError::from)?;
+        ApiKeyInfo::from_row(&row)
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to create API key: {e}");
+            e
+        })
+}
+
+/// Lists every API key, oldest first
+pub async fn fetch_api_keys(pool: &DbPool) -> Result<Vec<ApiKeyInfo>, DbError> {
+    const QUERY: &str = "SELECT id, name, owner, scopes, sensor_name, expires_at, key_prefix, is_active, created_at FROM api_keys ORDER BY id";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let rows = conn.query(QUERY, &[]).await.map_err(DbError::from)?;
+        rows.iter()
+            .map(ApiKeyInfo::from_row)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, SIMPLE_SELECT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch API keys: {e}");
+            e
+        })
+}
+
+/// Replaces the secret of an API key and returns the hash of the previous one
+///
+/// Returns `None` if the key doesn't exist.
+pub async fn rotate_api_key(
+    pool: &DbPool,
+    id: i32,
+    key_hash: &str,
+    key_prefix: &str,
+) -> Result<Option<String>, DbError> {
+    const QUERY: &str = "UPDATE api_keys k SET key_hash = $2, key_prefix = $3 FROM (SELECT id, key_hash FROM api_keys WHERE id = $1 FOR UPDATE) old WHERE k.id = old.id RETURNING COALESCE(old.key_hash, '') AS key_hash";
+
+    update_api_key(pool, QUERY, &[&id, &key_hash, &key_prefix], "rotate").await
+}
+
+/// Deactivates an API key and returns its hash, `None` if the key doesn't exist
+pub async fn deactivate_api_key(pool: &DbPool, id: i32) -> Result<Option<String>, DbError> {
+    const QUERY: &str = "UPDATE api_keys SET is_active = FALSE WHERE id = $1 RETURNING COALESCE(key_hash, '') AS key_hash";
+
+    update_api_key(pool, QUERY, &[&id], "deactivate").await
+}
+
+/// Deletes an API key and returns its hash, `None` if the key doesn't exist
+pub async fn delete_api_key(pool: &DbPool, id: i32) -> Result<Option<String>, DbError> {
+    const QUERY: &str =
+        "DELETE FROM api_keys WHERE id = $1 RETURNING COALESCE(key_hash, '') AS key_hash";
+
+    update_api_key(pool, QUERY, &[&id], "delete").await
+}
+
+/// Runs a statement changing one API key and returning its `key_hash`
+async fn update_api_key(
+    pool: &DbPool,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+    action: &str,
+) -> Result<Option<String>, DbError> {
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let row = conn.query_opt(query, params).await.map_err(DbError::from)?;
+        row.map(|row| row.try_get("key_hash").map_err(DbError::from))
+            .transpose()
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to {action} API key: {e}");
+            e
+        })
+}
@@ -0,0 +1,339 @@
+use crate::database::cache::memory::MemoryCache;
+use crate::database::cache::rate_limit::{BucketState, TokenBucket};
+use crate::database::cache::redis::RedisCache;
+use crate::database::cache::{Cache, CacheError, CacheStatus};
+use crate::{log_info, log_warn};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Redis cache that falls back to an in-process cache while Redis is unreachable
+///
+/// In degraded mode, Redis is retried at most once every `retry` interval so requests don't
+/// wait on it. Keys deleted meanwhile are deleted from Redis on recovery, so entries
+/// invalidated during an outage (e.g. revoked tokens) don't come back, and messages published
+/// meanwhile are published then so other instances still evict them.
+pub struct FallbackCache {
+    primary: RedisCache,
+    fallback: MemoryCache,
+    retry: Duration,
+    last_failure: Mutex<Option<Instant>>, // Set while degraded
+    pending_deletes: Mutex<HashSet<String>>,
+    pending_publishes: Mutex<Vec<(String, String)>>, // Channel and message, in order
+}
+
+impl FallbackCache {
+    /// Creates a cache using `primary` while it answers, and `fallback` otherwise
+    #[must_use]
+    pub fn new(primary: RedisCache, fallback: MemoryCache, retry: Duration) -> Self {
+        Self {
+            primary,
+            fallback,
+            retry,
+            last_failure: Mutex::new(None),
+            pending_deletes: Mutex::new(HashSet::new()),
+            pending_publishes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Checks whether Redis is currently bypassed
+    #[must_use]
+    pub fn is_degraded(&self) -> bool {
+        self.last_failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Checks whether the next command should be sent to Redis
+    fn use_primary(&self) -> bool {
+        self.last_failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none_or(|at| at.elapsed() >= self.retry)
+    }
+
+    /// Switches to degraded mode, or postpones the next retry
+    fn degrade(&self, error: &CacheError) {
+        let mut last_failure = self.last_failure.lock().unwrap_or_else(|e| e.into_inner());
+        if last_failure.is_none() {
+            log_warn!("Redis unreachable, using the in-process cache: {error}");
+        }
+        *last_failure = Some(Instant::now());
+    }
+
+    /// Leaves degraded mode after a successful Redis command
+    async fn recover(&self) {
+        if self
+            .last_failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .is_none()
+        {
+            return;
+        }
+
+        let pending: Vec<String> = self
+            .pending_deletes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .collect();
+        for (index, key) in pending.iter().enumerate() {
+            if let Err(e) = self.primary.del(key).await {
+                // Keys not deleted yet are replayed on the next recovery
+                self.pending_deletes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .extend(pending[index..].iter().cloned());
+                self.degrade(&e);
+                return;
+            }
+        }
+
+        let publishes: Vec<(String, String)> = self
+            .pending_publishes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain(..)
+            .collect();
+        for (index, (channel, message)) in publishes.iter().enumerate() {
+            if let Err(e) = self.primary.publish(channel, message).await {
+                // Messages not published yet are replayed first on the next recovery
+                self.pending_publishes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .splice(0..0, publishes[index..].iter().cloned());
+                self.degrade(&e);
+                return;
+            }
+        }
+
+        self.fallback.clear();
+        log_info!(
+            "Redis reachable again, {} keys invalidated and {} messages published during the outage",
+            pending.len(),
+            publishes.len()
+        );
+    }
+}
+
+#[async_trait]
+impl Cache for FallbackCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        if self.use_primary() {
+            match self.primary.get(key).await {
+                Ok(value) => {
+                    self.recover().await;
+                    return Ok(value);
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.get(key).await
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        if self.use_primary() {
+            match self.primary.set_ex(key, value, ttl).await {
+                Ok(()) => {
+                    self.recover().await;
+                    return Ok(());
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.set_ex(key, value, ttl).await
+    }
+
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool, CacheError> {
+        if self.use_primary() {
+            match self.primary.set_nx(key, value, ttl).await {
+                Ok(set) => {
+                    self.recover().await;
+                    return Ok(set);
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.set_nx(key, value, ttl).await
+    }
+
+    async fn take_token(&self, key: &str, bucket: TokenBucket) -> Result<BucketState, CacheError> {
+        if self.use_primary() {
+            match self.primary.take_token(key, bucket).await {
+                Ok(state) => {
+                    self.recover().await;
+                    return Ok(state);
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.take_token(key, bucket).await
+    }
+
+    async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, CacheError> {
+        if self.use_primary() {
+            match self.primary.incr(key, ttl).await {
+                Ok(count) => {
+                    self.recover().await;
+                    return Ok(count);
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.incr(key, ttl).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, CacheError> {
+        if self.use_primary() {
+            match self.primary.exists(key).await {
+                Ok(exists) => {
+                    self.recover().await;
+                    return Ok(exists);
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.fallback.exists(key).await
+    }
+
+    async fn del(&self, key: &str) -> Result<(), CacheError> {
+        if self.use_primary() {
+            match self.primary.del(key).await {
+                Ok(()) => {
+                    self.recover().await;
+                    return Ok(());
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.pending_deletes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string());
+        self.fallback.del(key).await
+    }
+
+    async fn ping(&self) -> Result<(), CacheError> {
+        match self.primary.ping().await {
+            Ok(()) => {
+                self.recover().await;
+                Ok(())
+            }
+            Err(e) => {
+                self.degrade(&e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn publish(&self, channel: &str, message: &str) -> Result<(), CacheError> {
+        if self.use_primary() {
+            match self.primary.publish(channel, message).await {
+                Ok(()) => {
+                    self.recover().await;
+                    return Ok(());
+                }
+                Err(e) => self.degrade(&e),
+            }
+        }
+        self.pending_publishes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((channel.to_string(), message.to_string()));
+        self.fallback.publish(channel, message).await
+    }
+
+    async fn evict_local(&self, key: &str) -> Result<(), CacheError> {
+        self.fallback.evict_local(key).await
+    }
+
+    fn status(&self) -> CacheStatus {
+        CacheStatus {
+            backend: "redis",
+            degraded: self.is_degraded(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::tracing::log_level::LogLevel;
+    use crate::telemetry::tracing::logger::Logger;
+
+    fn unreachable() -> FallbackCache {
+        // Switching to degraded mode logs a warning
+        let _ = Logger::init(
+            std::env::temp_dir().join("speedstream-cache-tests.log"),
+            LogLevel::Error,
+        );
+
+        // Nothing listens on port 1
+        let primary = RedisCache::new("redis://127.0.0.1:1").unwrap();
+        FallbackCache::new(primary, MemoryCache::new(10), Duration::from_secs(60))
+    }
+
+    #[tokio::test]
+    async fn test_serves_from_memory_when_redis_is_down() {
+        let cache = unreachable();
+        assert!(!cache.status().degraded);
+
+        cache
+            .set_ex("a", "1", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(cache.status().degraded);
+        assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("1"));
+
+        cache.del("a").await.unwrap();
+        assert!(!cache.exists("a").await.unwrap());
+        assert!(cache.pending_deletes.lock().unwrap().contains("a"));
+    }
+
+    #[tokio::test]
+    async fn test_keeps_pending_deletes_when_recovery_fails() {
+        let cache = unreachable();
+        cache.del("a").await.unwrap();
+        cache.del("b").await.unwrap();
+
+        // Redis is still down when the replay starts
+        cache.recover().await;
+        assert!(cache.status().degraded);
+        let pending = cache.pending_deletes.lock().unwrap().clone();
+        assert_eq!(pending, HashSet::from(["a".to_string(), "b".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_keeps_pending_publishes_when_recovery_fails() {
+        let cache = unreachable();
+        cache.publish("revoked", "h1").await.unwrap();
+        cache.publish("revoked", "h2").await.unwrap();
+
+        cache.recover().await;
+        assert!(cache.status().degraded);
+        assert_eq!(
+            *cache.pending_publishes.lock().unwrap(),
+            [
+                ("revoked".to_string(), "h1".to_string()),
+                ("revoked".to_string(), "h2".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ping_reports_failure() {
+        let cache = unreachable();
+        assert!(cache.ping().await.is_err());
+        assert_eq!(
+            cache.status(),
+            CacheStatus {
+                backend: "redis",
+                degraded: true
+            }
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::database::cache::rate_limit::{
+    BucketState, RATE_LIMIT_PREFIX, TokenBucket, decode_bucket, encode_bucket,
+};
+use crate::database::cache::{
+    AUTH_FAILURES_PREFIX, Cache, CacheError, CacheStatus, LOCKOUT_COUNT_PREFIX, LOCKOUT_PREFIX,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    value: String,
+    expires_at: Instant,
+    last_used: u64, // Position in `Entries::recency`
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    recency: BTreeMap<u64, String>, // Least recently used first
+    clock: u64,
+}
+
+impl Entries {
+    /// Marks `key` as just used and returns its entry, dropping it if expired
+    fn touch(&mut self, key: &str, now: Instant) -> Option<&Entry> {
+        let entry = self.map.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+
+        if entry.expires_at <= now {
+            self.map.remove(key);
+            return None;
+        }
+
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.recency.insert(self.clock, key.to_string());
+        self.map.get(key)
+    }
+
+    /// Inserts or replaces `key`, evicting the least recently used entries beyond `capacity`
+    fn insert(&mut self, key: &str, value: &str, expires_at: Instant, capacity: usize) {
+        self.remove(key);
+
+        while self.map.len() >= capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.map.remove(&oldest);
+        }
+
+        self.clock += 1;
+        let last_used = self.clock;
+        self.recency.insert(last_used, key.to_string());
+        self.map.insert(
+            key.to_string(),
+            Entry {
+                value: value.to_string(),
+                expires_at,
+                last_used,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.map.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+}
+
+/// In-process cache with a TTL per entry, evicting the least recently used entries when full
+///
+/// Used on its own when Redis isn't configured, and as the fallback when Redis is down.
+/// Entries are not shared between replicas. Rate limit buckets and the lockout state of the
+/// clients are kept apart from the cached data, each with its own capacity, so a flood of one
+/// can't evict the others.
+pub struct MemoryCache {
+    entries: Mutex<Entries>,     // Cached data
+    rate_limits: Mutex<Entries>, // Rate limit buckets
+    lockouts: Mutex<Entries>,    // Failed authentications and lockouts
+    capacity: usize,             // Maximum entries of each store
+}
+
+impl MemoryCache {
+    /// Creates a cache holding at most `capacity` entries of cached data, and as many buckets
+    /// and lockout entries
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            rate_limits: Mutex::new(Entries::default()),
+            lockouts: Mutex::new(Entries::default()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Returns the number of entries, expired ones included until they are evicted
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.stores()
+            .iter()
+            .map(|store| store.lock().unwrap_or_else(|e| e.into_inner()).map.len())
+            .sum()
+    }
+
+    /// Checks whether the cache holds no entry
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every entry
+    pub fn clear(&self) {
+        for store in self.stores() {
+            *store.lock().unwrap_or_else(|e| e.into_inner()) = Entries::default();
+        }
+    }
+
+    fn stores(&self) -> [&Mutex<Entries>; 3] {
+        [&self.entries, &self.rate_limits, &self.lockouts]
+    }
+
+    /// Returns the store holding `key`
+    fn store(&self, key: &str) -> &Mutex<Entries> {
+        if key.starts_with(RATE_LIMIT_PREFIX) {
+            &self.rate_limits
+        } else if [AUTH_FAILURES_PREFIX, LOCKOUT_PREFIX, LOCKOUT_COUNT_PREFIX]
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+        {
+            &self.lockouts
+        } else {
+            &self.entries
+        }
+    }
+
+    fn read(&self, key: &str) -> Option<String> {
+        let mut entries = self.store(key).lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .touch(key, Instant::now())
+            .map(|entry| entry.value.clone())
+    }
+
+    fn write(&self, key: &str, value: &str, ttl: Duration) {
+        let mut entries = self.store(key).lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key, value, Instant::now() + ttl, self.capacity);
+    }
+
+    fn write_new(&self, key: &str, value: &str, ttl: Duration) -> bool {
+        let mut entries = self.store(key).lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if entries.touch(key, now).is_some() {
+            return false;
+        }
+        entries.insert(key, value, now + ttl, self.capacity);
+        true
+    }
+
+    fn take(&self, key: &str, bucket: TokenBucket) -> BucketState {
+        let mut entries = self.store(key).lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let previous = entries
+            .touch(key, now)
+            .and_then(|entry| decode_bucket(&entry.value));
+
+        let updated_at = Utc::now().timestamp_millis();
+        let (tokens, state) = bucket.take(previous, updated_at);
+        let value = encode_bucket(tokens, updated_at);
+        entries.insert(key, &value, now + state.reset, self.capacity);
+        state
+    }
+
+    fn increment(&self, key: &str, ttl: Duration) -> u64 {
+        let mut entries = self.store(key).lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let (count, expires_at) = match entries.touch(key, now) {
+            Some(entry) => (
+                entry.value.parse::<u64>().unwrap_or(0) + 1,
+                entry.expires_at,
+            ),
+            None => (1, now + ttl),
+        };
+        entries.insert(key, &count.to_string(), expires_at, self.capacity);
+        count
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        Ok(self.read(key))
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        self.write(key, value, ttl);
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool, CacheError> {
+        Ok(self.write_new(key, value, ttl))
+    }
+
+    async fn take_token(&self, key: &str, bucket: TokenBucket) -> Result<BucketState, CacheError> {
+        Ok(self.take(key, bucket))
+    }
+
+    async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, CacheError> {
+        Ok(self.increment(key, ttl))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, CacheError> {
+        Ok(self.read(key).is_some())
+    }
+
+    async fn del(&self, key: &str) -> Result<(), CacheError> {
+        self.store(key)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), CacheError> {
+        Ok(())
+    }
+
+    async fn publish(&self, _channel: &str, _message: &str) -> Result<(), CacheError> {
+        Ok(()) // Not shared with other instances
+    }
+
+    async fn evict_local(&self, key: &str) -> Result<(), CacheError> {
+        self.del(key).await
+    }
+
+    fn status(&self) -> CacheStatus {
+        CacheStatus {
+            backend: "memory",
+            degraded: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn test_set_get_del() {
+        let cache = MemoryCache::new(10);
+        cache.set_ex("a", "1", TTL).await.unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("1"));
+        assert!(cache.exists("a").await.unwrap());
+
+        cache.del("a").await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire() {
+        let cache = MemoryCache::new(10);
+        cache.set_ex("a", "1", Duration::ZERO).await.unwrap();
+        assert!(!cache.exists("a").await.unwrap());
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        cache.set_ex("a", "1", TTL).await.unwrap();
+        cache.set_ex("b", "2", TTL).await.unwrap();
+
+        // Reading "a" makes "b" the least recently used entry
+        assert!(cache.exists("a").await.unwrap());
+        cache.set_ex("c", "3", TTL).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.exists("a").await.unwrap());
+        assert!(!cache.exists("b").await.unwrap());
+        assert!(cache.exists("c").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_keeps_capacity() {
+        let cache = MemoryCache::new(2);
+        cache.set_ex("a", "1", TTL).await.unwrap();
+        cache.set_ex("a", "2", TTL).await.unwrap();
+        cache.set_ex("b", "3", TTL).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_set_nx_keeps_present_entry() {
+        let cache = MemoryCache::new(10);
+        assert!(cache.set_nx("a", "1", TTL).await.unwrap());
+        assert!(!cache.set_nx("a", "2", TTL).await.unwrap());
+        assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("1"));
+
+        // Expired entries are replaced
+        assert!(cache.set_nx("b", "1", Duration::ZERO).await.unwrap());
+        assert!(cache.set_nx("b", "2", TTL).await.unwrap());
+        assert_eq!(cache.get("b").await.unwrap().as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_take_token() {
+        let cache = MemoryCache::new(10);
+        let bucket = TokenBucket {
+            capacity: 2,
+            period: Duration::from_secs(3600),
+        };
+        for allowed in [true, true, false] {
+            assert_eq!(
+                cache.take_token("a", bucket).await.unwrap().allowed,
+                allowed
+            );
+        }
+        // Buckets are independent
+        assert!(cache.take_token("b", bucket).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_survive_cached_data() {
+        let cache = MemoryCache::new(2);
+        let bucket = TokenBucket {
+            capacity: 1,
+            period: Duration::from_secs(3600),
+        };
+        let key = format!("{RATE_LIMIT_PREFIX}ip:203.0.113.9");
+        assert!(cache.take_token(&key, bucket).await.unwrap().allowed);
+
+        // A flood of cached data only evicts cached data
+        for i in 0..10 {
+            cache.set_ex(&i.to_string(), "1", TTL).await.unwrap();
+        }
+        assert!(!cache.take_token(&key, bucket).await.unwrap().allowed);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_incr_keeps_first_expiration() {
+        let cache = MemoryCache::new(10);
+        assert_eq!(cache.incr("a", TTL).await.unwrap(), 1);
+        assert_eq!(cache.incr("a", TTL).await.unwrap(), 2);
+        assert_eq!(cache.get("a").await.unwrap().as_deref(), Some("2"));
+
+        // The counter starts over once expired
+        assert_eq!(cache.incr("b", Duration::ZERO).await.unwrap(), 1);
+        assert_eq!(cache.incr("b", TTL).await.unwrap(), 1);
+    }
+}
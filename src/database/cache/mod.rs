@@ -0,0 +1,457 @@
+pub mod fallback;
+pub mod memory;
+pub mod rate_limit;
+pub mod redis;
+pub mod revocation;
+
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::dto::speed_data::SpeedData;
+use crate::database::cache::rate_limit::{BucketState, TokenBucket};
+use crate::database::cache::revocation::REVOCATION_CHANNEL;
+use crate::log_error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+const LAST_SPEED_KEY: &str = "speedstream:last_speed";
+const CACHE_TTL: i64 = 3600; // 1 hour TTL
+const TOKEN_CACHE_PREFIX: &str = "speedstream:token:";
+const TOKEN_CACHE_TTL: u32 = 86400; // 24 hours TTL for valid tokens
+const NEGATIVE_TOKEN_CACHE_PREFIX: &str = "speedstream:invalid_token:";
+const NEGATIVE_TOKEN_CACHE_TTL: u32 = 60; // 1 minute TTL for invalid tokens
+pub(crate) const SIGNING_KEY_CACHE_PREFIX: &str = "speedstream:signing_key:";
+pub(crate) const AUTH_FAILURES_PREFIX: &str = "speedstream:authfail:"; // Failed authentications per client
+pub(crate) const LOCKOUT_PREFIX: &str = "speedstream:lockout:"; // End of the lockout of a client
+pub(crate) const LOCKOUT_COUNT_PREFIX: &str = "speedstream:lockouts:"; // Lockouts per client
+
+/// Error raised by a cache backend
+#[derive(Debug)]
+pub struct CacheError(pub String);
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cache error: {}", self.0)
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<::redis::RedisError> for CacheError {
+    fn from(e: ::redis::RedisError) -> Self {
+        CacheError(e.to_string())
+    }
+}
+
+/// State of the cache, as reported by `/health`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CacheStatus {
+    pub backend: &'static str, // "redis" or "memory"
+    pub degraded: bool,        // Redis is unreachable and the in-process cache serves instead
+}
+
+/// Key-value store with expiration, used to spare database round trips
+///
+/// The cache is only an optimization: callers log its errors and carry on.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Returns the value of `key`, if present and not expired
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError>;
+
+    /// Sets `key` to `value` for `ttl`
+    async fn set_ex(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError>;
+
+    /// Sets `key` to `value` for `ttl` unless it is present, returning whether it was set
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool, CacheError>;
+
+    /// Takes a token from the bucket stored at `key`, refilled according to `bucket`
+    async fn take_token(&self, key: &str, bucket: TokenBucket) -> Result<BucketState, CacheError>;
+
+    /// Increments the counter at `key`, expiring `ttl` after its first increment, and returns it
+    async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, CacheError>;
+
+    /// Checks whether `key` is present and not expired
+    async fn exists(&self, key: &str) -> Result<bool, CacheError>;
+
+    /// Removes `key`
+    async fn del(&self, key: &str) -> Result<(), CacheError>;
+
+    /// Checks that the backend answers
+    async fn ping(&self) -> Result<(), CacheError>;
+
+    /// Sends `message` to the instances subscribed to `channel`, when the backend is shared
+    async fn publish(&self, channel: &str, message: &str) -> Result<(), CacheError>;
+
+    /// Removes `key` from the entries held by this process only
+    ///
+    /// Used once another instance removed it from the shared backend.
+    async fn evict_local(&self, key: &str) -> Result<(), CacheError>;
+
+    /// Reports the backend in use and whether it is degraded
+    fn status(&self) -> CacheStatus;
+}
+
+/// Generates a cache key for a token, from its hash so the token never reaches the cache
+///
+/// This function is public for testing purposes
+#[inline]
+pub fn generate_token_cache_key(key_hash: &str) -> String {
+    format!("{TOKEN_CACHE_PREFIX}{key_hash}")
+}
+
+/// Generates a cache key for an invalid token, from its hash
+///
+/// This function is public for testing purposes
+#[inline]
+pub fn generate_invalid_token_cache_key(key_hash: &str) -> String {
+    format!("{NEGATIVE_TOKEN_CACHE_PREFIX}{key_hash}")
+}
+
+/// Generates the cache key of the API key signing requests with this id
+///
+/// This function is public for testing purposes
+#[inline]
+pub fn generate_signing_key_cache_key(id: i32) -> String {
+    format!("{SIGNING_KEY_CACHE_PREFIX}{id}")
+}
+
+/// Retrieves the last speed data from the cache
+pub async fn get_last_speed_from_cache(cache: &dyn Cache) -> Result<Option<SpeedData>, CacheError> {
+    let cached = cache.get(LAST_SPEED_KEY).await.map_err(|e| {
+        log_error!("Failed to get last speed from cache: {e}");
+        e
+    })?;
+
+    match cached {
+        Some(json_str) => match serde_json::from_str::<SpeedData>(&json_str) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) => {
+                log_error!("Failed to deserialize speed data from cache: {e}");
+                Ok(None)
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+/// Sets the last speed data in the cache
+pub async fn set_last_speed_in_cache(
+    cache: &dyn Cache,
+    speed_data: &SpeedData,
+) -> Result<(), CacheError> {
+    let json_str = serde_json::to_string(speed_data).map_err(|e| {
+        log_error!("Failed to serialize speed data for cache: {e}");
+        CacheError(format!("Serialization error: {e}"))
+    })?;
+
+    cache
+        .set_ex(
+            LAST_SPEED_KEY,
+            &json_str,
+            Duration::from_secs(CACHE_TTL as u64),
+        )
+        .await
+        .map_err(|e| {
+            log_error!("Failed to set last speed in cache: {e}");
+            e
+        })?;
+
+    Ok(())
+}
+
+/// Invalidates the last speed cache entry
+pub async fn invalidate_last_speed_cache(cache: &dyn Cache) -> Result<(), CacheError> {
+    cache.del(LAST_SPEED_KEY).await.map_err(|e| {
+        log_error!("Failed to invalidate last speed cache: {e}");
+        e
+    })?;
+
+    Ok(())
+}
+
+/// Returns the API key cached as valid for this hash, if any
+pub async fn get_cached_token(
+    cache: &dyn Cache,
+    key_hash: &str,
+) -> Result<Option<AuthenticatedKey>, CacheError> {
+    let key = generate_token_cache_key(key_hash);
+    let cached = cache.get(&key).await.map_err(|e| {
+        log_error!("Failed to check token in cache: {e}");
+        e
+    })?;
+
+    // Entries cached by older versions hold no key or no scopes, and are looked up again
+    Ok(cached.and_then(|json_str| serde_json::from_str(&json_str).ok()))
+}
+
+/// Returns how long a valid key may stay cached: `TOKEN_CACHE_TTL`, but at most until it expires
+///
+/// Keys expiring within a second are not cached, as the cache can't hold them for less.
+fn token_cache_ttl(api_key: &AuthenticatedKey, now: DateTime<Utc>) -> Option<Duration> {
+    let ttl = Duration::from_secs(u64::from(TOKEN_CACHE_TTL));
+    match api_key.expires_at {
+        Some(expires_at) => (expires_at - now)
+            .to_std()
+            .ok()
+            .filter(|remaining| remaining.as_secs() >= 1)
+            .map(|remaining| remaining.min(ttl)),
+        None => Some(ttl),
+    }
+}
+
+/// Caches a valid token, along with the key it authenticates, until the key expires at most
+pub async fn cache_valid_token(
+    cache: &dyn Cache,
+    key_hash: &str,
+    api_key: &AuthenticatedKey,
+) -> Result<(), CacheError> {
+    let Some(ttl) = token_cache_ttl(api_key, Utc::now()) else {
+        return Ok(());
+    };
+    let key = generate_token_cache_key(key_hash);
+    let json_str = serde_json::to_string(api_key).map_err(|e| {
+        log_error!("Failed to serialize API key for cache: {e}");
+        CacheError(format!("Serialization error: {e}"))
+    })?;
+    cache.set_ex(&key, &json_str, ttl).await.map_err(|e| {
+        log_error!("Failed to cache valid token: {e}");
+        e
+    })?;
+    Ok(())
+}
+
+/// Invalidates a cached token, on this instance and the ones sharing the cache
+///
+/// The revocation is published so the instances serving from their in-process cache evict the
+/// token too, instead of accepting it until its entry expires.
+pub async fn invalidate_token_cache(cache: &dyn Cache, key_hash: &str) -> Result<(), CacheError> {
+    let key = generate_token_cache_key(key_hash);
+    cache.del(&key).await.map_err(|e| {
+        log_error!("Failed to invalidate token cache: {e}");
+        e
+    })?;
+    cache
+        .publish(REVOCATION_CHANNEL, key_hash)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to publish token revocation: {e}");
+            e
+        })?;
+    Ok(())
+}
+
+/// API key cached for the signed requests, with the hash its signing secret is derived from
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSigningKey {
+    key: AuthenticatedKey,
+    key_hash: String,
+}
+
+/// Returns the API key cached for the requests signed with this id, along with its hash
+pub async fn get_cached_signing_key(
+    cache: &dyn Cache,
+    id: i32,
+) -> Result<Option<(AuthenticatedKey, String)>, CacheError> {
+    let key = generate_signing_key_cache_key(id);
+    let cached = cache.get(&key).await.map_err(|e| {
+        log_error!("Failed to check signing key in cache: {e}");
+        e
+    })?;
+
+    Ok(cached
+        .and_then(|json_str| serde_json::from_str::<CachedSigningKey>(&json_str).ok())
+        .map(|cached| (cached.key, cached.key_hash)))
+}
+
+/// Caches the API key signing requests with its id, until the key expires at most
+pub async fn cache_signing_key(
+    cache: &dyn Cache,
+    api_key: &AuthenticatedKey,
+    key_hash: &str,
+) -> Result<(), CacheError> {
+    let (Some(id), Some(ttl)) = (api_key.id, token_cache_ttl(api_key, Utc::now())) else {
+        return Ok(());
+    };
+    let key = generate_signing_key_cache_key(id);
+    let json_str = serde_json::to_string(&CachedSigningKey {
+        key: api_key.clone(),
+        key_hash: key_hash.to_string(),
+    })
+    .map_err(|e| {
+        log_error!("Failed to serialize signing key for cache: {e}");
+        CacheError(format!("Serialization error: {e}"))
+    })?;
+    cache.set_ex(&key, &json_str, ttl).await.map_err(|e| {
+        log_error!("Failed to cache signing key: {e}");
+        e
+    })?;
+    Ok(())
+}
+
+/// Invalidates the cached signing key of an id, on this instance and the ones sharing the cache
+///
+/// Published on the revocation channel like the token hashes, as its cache key.
+pub async fn invalidate_signing_key_cache(cache: &dyn Cache, id: i32) -> Result<(), CacheError> {
+    let key = generate_signing_key_cache_key(id);
+    cache.del(&key).await.map_err(|e| {
+        log_error!("Failed to invalidate signing key cache: {e}");
+        e
+    })?;
+    cache.publish(REVOCATION_CHANNEL, &key).await.map_err(|e| {
+        log_error!("Failed to publish signing key revocation: {e}");
+        e
+    })?;
+    Ok(())
+}
+
+/// Checks if a token is cached as invalid
+pub async fn is_token_cached_invalid(
+    cache: &dyn Cache,
+    key_hash: &str,
+) -> Result<bool, CacheError> {
+    let key = generate_invalid_token_cache_key(key_hash);
+    let exists = cache.exists(&key).await.map_err(|e| {
+        log_error!("Failed to check invalid token cache: {e}");
+        e
+    })?;
+    Ok(exists)
+}
+
+/// Caches an invalid token with short TTL
+pub async fn cache_invalid_token(cache: &dyn Cache, key_hash: &str) -> Result<(), CacheError> {
+    let key = generate_invalid_token_cache_key(key_hash);
+    cache
+        .set_ex(
+            &key,
+            "1",
+            Duration::from_secs(u64::from(NEGATIVE_TOKEN_CACHE_TTL)),
+        )
+        .await
+        .map_err(|e| {
+            log_error!("Failed to cache invalid token: {e}");
+            e
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api_key::hash_api_key;
+    use crate::core::api_key_scope::ApiKeyScope;
+
+    #[test]
+    fn test_generate_token_cache_key() {
+        let key_hash = hash_api_key("abc123def456");
+        let key = generate_token_cache_key(&key_hash);
+        assert_eq!(key, format!("speedstream:token:{key_hash}"));
+        assert!(!key.contains("abc123def456"));
+    }
+
+    #[test]
+    fn test_generate_token_cache_key_empty() {
+        let key = generate_token_cache_key(&hash_api_key(""));
+        assert_eq!(key.len(), "speedstream:token:".len() + 64);
+    }
+
+    #[test]
+    fn test_generate_token_cache_key_special_chars() {
+        let token = "abc-123_DEF.456~xyz";
+        let key = generate_token_cache_key(&hash_api_key(token));
+        assert!(!key.contains(token));
+        assert!(
+            key["speedstream:token:".len()..]
+                .chars()
+                .all(|c| c.is_ascii_hexdigit())
+        );
+    }
+
+    #[test]
+    fn test_generate_token_cache_key_long_token() {
+        // Keys have the same length whatever the token
+        let token = "a".repeat(256);
+        let key = generate_token_cache_key(&hash_api_key(&token));
+        assert_eq!(key.len(), "speedstream:token:".len() + 64);
+    }
+
+    #[test]
+    fn test_generate_invalid_token_cache_key() {
+        let key_hash = hash_api_key("abc 123 def");
+        assert_eq!(
+            generate_invalid_token_cache_key(&key_hash),
+            format!("speedstream:invalid_token:{key_hash}")
+        );
+        assert_ne!(
+            generate_invalid_token_cache_key(&key_hash),
+            generate_token_cache_key(&key_hash)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_valid_token() {
+        let cache = memory::MemoryCache::new(10);
+        let key_hash = hash_api_key("abc123def456");
+        let api_key = AuthenticatedKey {
+            id: Some(3),
+            subject: None,
+            scopes: vec![ApiKeyScope::SpeedsRead, ApiKeyScope::Admin],
+            sensor_name: None,
+            expires_at: None,
+        };
+
+        assert_eq!(get_cached_token(&cache, &key_hash).await.unwrap(), None);
+        cache_valid_token(&cache, &key_hash, &api_key)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_cached_token(&cache, &key_hash).await.unwrap(),
+            Some(api_key)
+        );
+
+        invalidate_token_cache(&cache, &key_hash).await.unwrap();
+        assert_eq!(get_cached_token(&cache, &key_hash).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_token_cache_ttl() {
+        let now = Utc::now();
+        let mut api_key = AuthenticatedKey {
+            id: Some(3),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        let ttl = Duration::from_secs(u64::from(TOKEN_CACHE_TTL));
+        assert_eq!(token_cache_ttl(&api_key, now), Some(ttl));
+
+        // Keys expiring before the TTL are only cached until they expire
+        api_key.expires_at = Some(now + chrono::Duration::minutes(5));
+        assert_eq!(
+            token_cache_ttl(&api_key, now),
+            Some(Duration::from_secs(300))
+        );
+        api_key.expires_at = Some(now + chrono::Duration::days(30));
+        assert_eq!(token_cache_ttl(&api_key, now), Some(ttl));
+        api_key.expires_at = Some(now + chrono::Duration::milliseconds(500));
+        assert_eq!(token_cache_ttl(&api_key, now), None);
+        api_key.expires_at = Some(now - chrono::Duration::minutes(5));
+        assert_eq!(token_cache_ttl(&api_key, now), None);
+    }
+
+    #[test]
+    fn test_cache_ttl_constants() {
+        // Verify TTL constants are reasonable
+        assert_eq!(CACHE_TTL, 3600); // 1 hour
+        assert_eq!(TOKEN_CACHE_TTL, 86400); // 24 hours
+        assert!(TOKEN_CACHE_TTL as i64 > CACHE_TTL); // Token cache should be longer
+    }
+
+    #[test]
+    fn test_cache_key_prefix() {
+        // Verify the prefix is what we expect
+        assert_eq!(TOKEN_CACHE_PREFIX, "speedstream:token:");
+        assert_eq!(LAST_SPEED_KEY, "speedstream:last_speed");
+    }
+}
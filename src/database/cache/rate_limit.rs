@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+/// Prefix of the keys of the rate limit buckets
+pub(crate) const RATE_LIMIT_PREFIX: &str = "speedstream:ratelimit:";
+
+/// Updates a token bucket stored in a Redis hash, the same way as `take_token` in memory
+///
+/// Takes the capacity, the refill rate in tokens per millisecond and the current time in
+/// milliseconds. Returns whether a token was taken and the tokens left, as a string since Redis
+/// truncates numbers returned by scripts. The key expires once the bucket is full again.
+pub(crate) const TAKE_TOKEN_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(state[1]) or capacity
+local updated_at = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * rate)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) / rate) + 1000)
+return {allowed, tostring(tokens)}
+";
+
+/// Limit of a token bucket: `capacity` requests at once, refilled evenly over `period`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBucket {
+    pub capacity: u32,    // Requests allowed in a burst, and per period on average
+    pub period: Duration, // Time for an empty bucket to fill up again
+}
+
+impl TokenBucket {
+    /// Returns the refill rate, in tokens per millisecond
+    #[must_use]
+    pub fn rate(&self) -> f64 {
+        f64::from(self.capacity) / (self.period.as_millis().max(1) as f64)
+    }
+
+    /// Refills a bucket holding `state`, its tokens and update time, up to `now`, then takes a token
+    ///
+    /// A bucket seen for the first time is full. Times are in milliseconds. Returns the
+    /// tokens left along with the outcome.
+    #[must_use]
+    pub fn take(&self, state: Option<(f64, i64)>, now: i64) -> (f64, BucketState) {
+        let capacity = f64::from(self.capacity);
+        let (tokens, updated_at) = state.unwrap_or((capacity, now));
+        let tokens = (tokens + (now - updated_at).max(0) as f64 * self.rate()).min(capacity);
+
+        let allowed = tokens >= 1.0;
+        let tokens = if allowed { tokens - 1.0 } else { tokens };
+        (tokens, self.state(allowed, tokens))
+    }
+
+    /// Describes a bucket left with `tokens` once a token was taken, or refused
+    #[must_use]
+    pub fn state(&self, allowed: bool, tokens: f64) -> BucketState {
+        let period = self.period.as_millis() as f64;
+        let capacity = f64::from(self.capacity);
+        let millis = |tokens: f64| {
+            Duration::from_millis((tokens.max(0.0) * period / capacity).ceil() as u64)
+        };
+        BucketState {
+            allowed,
+            limit: self.capacity,
+            remaining: tokens.max(0.0).floor() as u32,
+            reset: millis(capacity - tokens),
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                millis(1.0 - tokens)
+            },
+        }
+    }
+}
+
+/// Outcome of taking a token from a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketState {
+    pub allowed: bool,         // A token was taken, the request may proceed
+    pub limit: u32,            // Capacity of the bucket
+    pub remaining: u32,        // Whole tokens left
+    pub reset: Duration,       // Until the bucket is full again
+    pub retry_after: Duration, // Until a token is available, zero when allowed
+}
+
+/// Encodes the state of a bucket as stored by the in-process cache
+pub(crate) fn encode_bucket(tokens: f64, updated_at: i64) -> String {
+    format!("{tokens} {updated_at}")
+}
+
+/// Decodes the state of a bucket stored by `encode_bucket`
+pub(crate) fn decode_bucket(value: &str) -> Option<(f64, i64)> {
+    let (tokens, updated_at) = value.split_once(' ')?;
+    Some((tokens.parse().ok()?, updated_at.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET: TokenBucket = TokenBucket {
+        capacity: 2,
+        period: Duration::from_secs(10),
+    };
+
+    #[test]
+    fn test_take_until_empty_then_refill() {
+        let (tokens, state) = BUCKET.take(None, 0);
+        assert!(state.allowed);
+        assert_eq!(state.remaining, 1);
+        assert_eq!(state.reset, Duration::from_secs(5));
+
+        let (tokens, state) = BUCKET.take(Some((tokens, 0)), 0);
+        assert!(state.allowed);
+        assert_eq!(state.remaining, 0);
+
+        let (tokens, state) = BUCKET.take(Some((tokens, 0)), 1_000);
+        assert!(!state.allowed);
+        assert_eq!(state.retry_after, Duration::from_secs(4));
+        assert_eq!(state.reset, Duration::from_secs(9));
+
+        // One token every 5 seconds
+        let (_, state) = BUCKET.take(Some((tokens, 1_000)), 5_000);
+        assert!(state.allowed);
+        assert_eq!(state.retry_after, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_refill_caps_at_capacity() {
+        let (tokens, state) = BUCKET.take(Some((0.0, 0)), 3_600_000);
+        assert!(state.allowed);
+        assert_eq!(tokens, 1.0);
+    }
+
+    #[test]
+    fn test_encode_decode_bucket() {
+        let value = encode_bucket(1.25, 1_700_000_000_000);
+        assert_eq!(decode_bucket(&value), Some((1.25, 1_700_000_000_000)));
+        assert_eq!(decode_bucket("garbage"), None);
+    }
+}
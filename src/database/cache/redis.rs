@@ -0,0 +1,176 @@
+use crate::database::cache::rate_limit::{BucketState, TAKE_TOKEN_SCRIPT, TokenBucket};
+use crate::database::cache::{Cache, CacheError, CacheStatus};
+use async_trait::async_trait;
+use chrono::Utc;
+use redis::AsyncCommands;
+use redis::aio::{ConnectionManager, PubSubStream};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Maximum time a Redis command may take before the cache is considered unreachable
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Increments a counter, setting its expiration in milliseconds on the first increment only
+const INCR_SCRIPT: &str = r"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+end
+return count
+";
+
+/// Minimum delay between two connection attempts while Redis has never been reached
+const CONNECT_RETRY: Duration = Duration::from_secs(10);
+
+/// Cache backed by Redis
+///
+/// The connection is established lazily, so the server starts even if Redis is down.
+/// Once connected, the `ConnectionManager` reconnects by itself.
+pub struct RedisCache {
+    client: redis::Client,
+    manager: OnceCell<ConnectionManager>,
+    last_attempt: Mutex<Option<Instant>>,
+}
+
+impl RedisCache {
+    /// Creates a cache for the Redis server at `url`, without connecting yet
+    pub fn new(url: &str) -> Result<Self, CacheError> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            manager: OnceCell::new(),
+            last_attempt: Mutex::new(None),
+        })
+    }
+
+    /// Returns a handle on the connection, connecting first if needed
+    async fn connection(&self) -> Result<ConnectionManager, CacheError> {
+        if let Some(manager) = self.manager.get() {
+            return Ok(manager.clone());
+        }
+
+        {
+            let mut last_attempt = self.last_attempt.lock().unwrap_or_else(|e| e.into_inner());
+            if last_attempt.is_some_and(|at| at.elapsed() < CONNECT_RETRY) {
+                return Err(CacheError("Redis is not connected".to_string()));
+            }
+            *last_attempt = Some(Instant::now());
+        }
+
+        let manager = self
+            .manager
+            .get_or_try_init(|| async {
+                with_timeout(ConnectionManager::new(self.client.clone())).await
+            })
+            .await?;
+        Ok(manager.clone())
+    }
+
+    /// Subscribes to `channel` on a dedicated connection and returns its messages
+    ///
+    /// The stream ends when the connection is lost.
+    pub async fn subscribe(&self, channel: &str) -> Result<PubSubStream, CacheError> {
+        let mut pubsub = with_timeout(self.client.get_async_pubsub()).await?;
+        with_timeout(pubsub.subscribe(channel)).await?;
+        Ok(pubsub.into_on_message())
+    }
+}
+
+/// Wraps a Redis command with `REDIS_TIMEOUT`
+async fn with_timeout<T>(
+    fut: impl Future<Output = Result<T, redis::RedisError>>,
+) -> Result<T, CacheError> {
+    tokio::time::timeout(REDIS_TIMEOUT, fut)
+        .await
+        .map_err(|_| CacheError("Redis command timed out".to_string()))?
+        .map_err(CacheError::from)
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(conn.get(key)).await
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl: Duration) -> Result<(), CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(conn.set_ex(key, value, ttl.as_secs().max(1))).await
+    }
+
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool, CacheError> {
+        let mut conn = self.connection().await?;
+        let set: Option<String> = with_timeout(
+            redis::cmd("SET")
+                .arg(key)
+                .arg(value)
+                .arg("NX")
+                .arg("EX")
+                .arg(ttl.as_secs().max(1))
+                .query_async(&mut conn),
+        )
+        .await?;
+        Ok(set.is_some()) // Nil when the key is already present
+    }
+
+    async fn take_token(&self, key: &str, bucket: TokenBucket) -> Result<BucketState, CacheError> {
+        let mut conn = self.connection().await?;
+        let (allowed, tokens): (i32, String) = with_timeout(
+            redis::Script::new(TAKE_TOKEN_SCRIPT)
+                .key(key)
+                .arg(bucket.capacity)
+                .arg(bucket.rate())
+                .arg(Utc::now().timestamp_millis())
+                .invoke_async(&mut conn),
+        )
+        .await?;
+        let tokens = tokens
+            .parse()
+            .map_err(|_| CacheError(format!("Invalid token count: {tokens}")))?;
+        Ok(bucket.state(allowed == 1, tokens))
+    }
+
+    async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(
+            redis::Script::new(INCR_SCRIPT)
+                .key(key)
+                .arg(ttl.as_millis().max(1) as u64)
+                .invoke_async(&mut conn),
+        )
+        .await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(conn.exists(key)).await
+    }
+
+    async fn del(&self, key: &str) -> Result<(), CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(conn.del(key)).await
+    }
+
+    async fn ping(&self) -> Result<(), CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(redis::cmd("PING").query_async::<String>(&mut conn)).await?;
+        Ok(())
+    }
+
+    async fn publish(&self, channel: &str, message: &str) -> Result<(), CacheError> {
+        let mut conn = self.connection().await?;
+        with_timeout(conn.publish::<_, _, ()>(channel, message)).await
+    }
+
+    async fn evict_local(&self, _key: &str) -> Result<(), CacheError> {
+        Ok(()) // Entries are only held by Redis
+    }
+
+    fn status(&self) -> CacheStatus {
+        CacheStatus {
+            backend: "redis",
+            degraded: false,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::database::cache::redis::RedisCache;
+use crate::database::cache::{
+    Cache, CacheError, SIGNING_KEY_CACHE_PREFIX, generate_token_cache_key,
+};
+use crate::{log_info, log_warn};
+use futures_util::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Redis channel on which the hashes of revoked tokens, and the cache keys of revoked signing
+/// keys, are published
+pub const REVOCATION_CHANNEL: &str = "speedstream:revoked_tokens";
+
+/// Evicts a token or signing key revoked by another instance from the in-process cache
+pub async fn evict_revoked_token(cache: &dyn Cache, message: &str) -> Result<(), CacheError> {
+    if message.starts_with(SIGNING_KEY_CACHE_PREFIX) {
+        return cache.evict_local(message).await;
+    }
+    cache.evict_local(&generate_token_cache_key(message)).await
+}
+
+/// Spawns the background task evicting the tokens revoked by any instance
+///
+/// The instance revoking a token deletes it from Redis, but the other instances may hold it in
+/// their in-process cache, filled while Redis was unreachable. The subscription is restored
+/// every `retry` while Redis is unreachable.
+pub fn spawn_revocation_listener(
+    subscriber: RedisCache,
+    cache: Arc<dyn Cache>,
+    retry: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut connected = true;
+        loop {
+            match subscriber.subscribe(REVOCATION_CHANNEL).await {
+                Ok(mut messages) => {
+                    if !connected {
+                        log_info!("Listening to token revocations again");
+                        connected = true;
+                    }
+                    while let Some(message) = messages.next().await {
+                        match message.get_payload::<String>() {
+                            Ok(revoked) => {
+                                if let Err(e) = evict_revoked_token(cache.as_ref(), &revoked).await
+                                {
+                                    log_warn!("Failed to evict a revoked token: {e}");
+                                }
+                            }
+                            Err(e) => {
+                                log_warn!("Ignoring an invalid token revocation: {e}");
+                            }
+                        }
+                    }
+                    log_warn!("Lost the subscription to token revocations");
+                }
+                Err(e) => {
+                    if connected {
+                        log_warn!("Failed to subscribe to token revocations: {e}");
+                        connected = false;
+                    }
+                }
+            }
+            tokio::time::sleep(retry).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api_key::{AuthenticatedKey, hash_api_key};
+    use crate::database::cache::memory::MemoryCache;
+    use crate::database::cache::{
+        cache_signing_key, cache_valid_token, generate_signing_key_cache_key,
+        get_cached_signing_key, get_cached_token,
+    };
+
+    #[tokio::test]
+    async fn test_evict_revoked_token() {
+        let cache = MemoryCache::new(10);
+        let key_hash = hash_api_key("abc123def456");
+        let api_key = AuthenticatedKey {
+            id: Some(1),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        cache_valid_token(&cache, &key_hash, &api_key)
+            .await
+            .unwrap();
+
+        evict_revoked_token(&cache, &key_hash).await.unwrap();
+        assert_eq!(get_cached_token(&cache, &key_hash).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_evict_revoked_signing_key() {
+        let cache = MemoryCache::new(10);
+        let key_hash = hash_api_key("abc123def456");
+        let api_key = AuthenticatedKey {
+            id: Some(7),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        cache_signing_key(&cache, &api_key, &key_hash)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_cached_signing_key(&cache, 7).await.unwrap(),
+            Some((api_key, key_hash))
+        );
+
+        evict_revoked_token(&cache, &generate_signing_key_cache_key(7))
+            .await
+            .unwrap();
+        assert_eq!(get_cached_signing_key(&cache, 7).await.unwrap(), None);
+    }
+}
@@ -1,16 +1,25 @@
 use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
 use crate::core::dto::speed_data::SpeedData;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
 use crate::database::pool::DbPool;
 use crate::database::types::{DbError, FromPostgresRow};
 use crate::database::util::{with_timeout, INSERT_TIMEOUT, SIMPLE_SELECT_TIMEOUT, RANGE_QUERY_TIMEOUT};
 use crate::log_error;
+use chrono_tz::Tz;
 
 /// Inserts speed data into the database and returns the inserted record.
+///
+/// `quality_flag` is the result of the fault detectors run on the reading, if any.
 pub async fn insert_speed_data(
     pool: &DbPool,
     payload: CreateSpeedDataRequest,
+    quality_flag: Option<QualityFlag>,
 ) -> Result<SpeedData, DbError> {
-    const QUERY: &str = "INSERT INTO speed (sensor_name,speed,lane) VALUES (NULLIF($1, ''), $2, $3) RETURNING id, sensor_name, speed, lane, created_at";
+    const QUERY: &str = "INSERT INTO speed (sensor_name,speed,lane,vehicle_class,vehicle_length,quality_flag) VALUES (NULLIF($1, ''), $2, $3, $4, $5, $6) RETURNING id, sensor_name, speed, lane, created_at, vehicle_class, vehicle_length, quality_flag";
 
     let conn = pool.get().await?;
 
@@ -23,6 +32,9 @@ pub async fn insert_speed_data(
                     &payload.sensor_name.unwrap_or_default(),
                     &payload.speed,
                     &i32::from(payload.lane),
+                    &payload.vehicle_class.map(|c| c.as_str()),
+                    &payload.vehicle_length,
+                    &quality_flag.map(|f| f.as_str()),
                 ],
             )
             .await
@@ -43,15 +55,21 @@ pub async fn insert_speed_data(
 pub async fn fetch_last_n_speed_data(
     pool: &DbPool,
     number: u16,
+    vehicle_class: Option<VehicleClass>,
 ) -> Result<Vec<SpeedData>, DbError> {
-    const QUERY: &str =
-        "SELECT id,sensor_name,speed,lane,created_at FROM speed ORDER BY id DESC LIMIT $1";
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE ($2::text IS NULL OR vehicle_class = $2) ORDER BY created_at DESC, id DESC LIMIT $1";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
-        let rows = conn.query(&stmt, &[&(i64::from(number))]).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[&(i64::from(number)), &vehicle_class.map(|c| c.as_str())],
+            )
+            .await
+            .map_err(DbError::from)?;
 
         rows.iter()
             .map(SpeedData::from_row)
@@ -71,16 +89,23 @@ pub async fn fetch_speed_data_with_pagination(
     pool: &DbPool,
     offset: u32,
     limit: u32,
+    vehicle_class: Option<VehicleClass>,
 ) -> Result<Vec<SpeedData>, DbError> {
-    const QUERY: &str =
-        "SELECT id,sensor_name,speed,lane,created_at FROM speed OFFSET $1 LIMIT $2";
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE ($3::text IS NULL OR vehicle_class = $3) ORDER BY created_at, id OFFSET $1 LIMIT $2";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
         let rows = conn
-            .query(&stmt, &[&(i64::from(offset)), &(i64::from(limit))])
+            .query(
+                &stmt,
+                &[
+                    &(i64::from(offset)),
+                    &(i64::from(limit)),
+                    &vehicle_class.map(|c| c.as_str()),
+                ],
+            )
             .await
             .map_err(DbError::from)?;
 
@@ -97,18 +122,34 @@ pub async fn fetch_speed_data_with_pagination(
         })
 }
 
-/// Fetches all rows from inserted in the current day
+/// Fetches all rows inserted in the current day
+///
+/// The day boundaries are computed by the caller, in the requested timezone.
 pub async fn fetch_speed_data_today(
     pool: &DbPool,
+    day_start: chrono::DateTime<chrono::Utc>,
+    day_end: chrono::DateTime<chrono::Utc>,
     limit: u16,
+    vehicle_class: Option<VehicleClass>,
 ) -> Result<Vec<SpeedData>, DbError> {
-    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at FROM speed WHERE created_at >= CURRENT_DATE LIMIT $1";
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE created_at >= $3 AND created_at < $4 AND ($2::text IS NULL OR vehicle_class = $2) LIMIT $1";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
-        let rows = conn.query(&stmt, &[&(i64::from(limit))]).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[
+                    &(i64::from(limit)),
+                    &vehicle_class.map(|c| c.as_str()),
+                    &day_start,
+                    &day_end,
+                ],
+            )
+            .await
+            .map_err(DbError::from)?;
 
         rows.iter()
             .map(SpeedData::from_row)
@@ -123,18 +164,23 @@ pub async fn fetch_speed_data_today(
         })
 }
 
-/// Fetches the last speed data entry from the database
-pub async fn fetch_last_speed(pool: &DbPool) -> Result<SpeedData, DbError> {
-    const QUERY: &str =
-        "SELECT id,sensor_name,speed,lane,created_at FROM speed ORDER BY id DESC LIMIT 1";
+/// Fetches the last speed data entry from the database, if any
+pub async fn fetch_last_speed(
+    pool: &DbPool,
+    vehicle_class: Option<VehicleClass>,
+) -> Result<Option<SpeedData>, DbError> {
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE ($1::text IS NULL OR vehicle_class = $1) ORDER BY created_at DESC, id DESC LIMIT 1";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
-        let row = conn.query_one(&stmt, &[]).await.map_err(DbError::from)?;
+        let row = conn
+            .query_opt(&stmt, &[&vehicle_class.map(|c| c.as_str())])
+            .await
+            .map_err(DbError::from)?;
 
-        SpeedData::from_row(&row)
+        row.as_ref().map(SpeedData::from_row).transpose()
     };
 
     with_timeout(query_future, SIMPLE_SELECT_TIMEOUT)
@@ -150,14 +196,21 @@ pub async fn fetch_speed_data_by_date_range(
     pool: &DbPool,
     start_date: chrono::DateTime<chrono::Utc>,
     end_date: chrono::DateTime<chrono::Utc>,
+    vehicle_class: Option<VehicleClass>,
 ) -> Result<Vec<SpeedData>, DbError> {
-    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at FROM speed WHERE created_at >= $1 AND created_at <= $2 ORDER BY created_at ASC";
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE created_at >= $1 AND created_at <= $2 AND ($3::text IS NULL OR vehicle_class = $3) ORDER BY created_at ASC";
 
     let conn = pool.get().await?;
 
     let query_future = async {
         let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
-        let rows = conn.query(&stmt, &[&start_date, &end_date]).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[&start_date, &end_date, &vehicle_class.map(|c| c.as_str())],
+            )
+            .await
+            .map_err(DbError::from)?;
 
         rows.iter()
             .map(SpeedData::from_row)
@@ -171,3 +224,56 @@ pub async fn fetch_speed_data_by_date_range(
             e
         })
 }
+
+/// Fetches speed statistics aggregated per time bucket within a specified date range
+///
+/// Rows are additionally grouped by lane, sensor and/or vehicle class depending on `group_by`.
+/// Buckets are truncated in the timezone `tz`, so daily buckets follow local midnight.
+/// Readings tagged by the fault detectors are skipped unless `include_flagged` is set.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_speed_aggregates(
+    pool: &DbPool,
+    start_date: chrono::DateTime<chrono::Utc>,
+    end_date: chrono::DateTime<chrono::Utc>,
+    bucket: TimeBucket,
+    group_by: GroupBy,
+    vehicle_class: Option<VehicleClass>,
+    include_flagged: bool,
+    tz: Tz,
+) -> Result<Vec<SpeedAggregate>, DbError> {
+    const QUERY: &str = "SELECT date_trunc($1, created_at, $9) AS bucket_start, CASE WHEN $4 THEN lane END AS lane, CASE WHEN $5 THEN sensor_name END AS sensor_name, CASE WHEN $6 THEN vehicle_class END AS vehicle_class, count(*) AS count, avg(speed)::float8 AS avg_speed, min(speed) AS min_speed, max(speed) AS max_speed FROM speed WHERE created_at >= $2 AND created_at <= $3 AND ($7::text IS NULL OR vehicle_class = $7) AND ($8 OR quality_flag IS NULL) GROUP BY 1, 2, 3, 4 ORDER BY 1, 2, 3, 4";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[
+                    &bucket.as_str(),
+                    &start_date,
+                    &end_date,
+                    &group_by.lane,
+                    &group_by.sensor,
+                    &group_by.class,
+                    &vehicle_class.map(|c| c.as_str()),
+                    &include_flagged,
+                    &tz.name(),
+                ],
+            )
+            .await
+            .map_err(DbError::from)?;
+
+        rows.iter()
+            .map(SpeedAggregate::from_row)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch speed aggregates: {e}");
+            e
+        })
+}
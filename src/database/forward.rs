@@ -0,0 +1,194 @@
+use crate::core::dto::speed_data::SpeedData;
+use crate::database::pool::DbPool;
+use crate::database::types::{DbError, FromPostgresRow};
+use crate::database::util::{INSERT_TIMEOUT, SIMPLE_SELECT_TIMEOUT, with_timeout};
+use crate::log_error;
+use chrono::{DateTime, Utc};
+
+/// Stores readings forwarded by an edge server and returns how many were new
+///
+/// Readings are identified by `origin` and their id on the edge server, so the ones of a batch
+/// sent again are ignored. They keep their sensor, timestamp and quality flag.
+pub async fn insert_forwarded_speed_data(
+    pool: &DbPool,
+    origin: &str,
+    readings: &[SpeedData],
+) -> Result<u64, DbError> {
+    const QUERY: &str = "INSERT INTO speed (origin,origin_id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag) SELECT $1::text, * FROM UNNEST($2::int8[], $3::text[], $4::real[], $5::int4[], $6::timestamptz[], $7::text[], $8::real[], $9::text[]) ON CONFLICT (origin, origin_id, created_at) DO NOTHING";
+
+    let origin_ids: Vec<i64> = readings.iter().map(|r| i64::from(r.id)).collect();
+    let sensor_names: Vec<Option<&str>> =
+        readings.iter().map(|r| r.sensor_name.as_deref()).collect();
+    let speeds: Vec<f32> = readings.iter().map(|r| r.speed).collect();
+    let lanes: Vec<i32> = readings.iter().map(|r| r.lane as i32).collect();
+    let created_ats: Vec<DateTime<Utc>> = readings.iter().map(|r| r.created_at).collect();
+    let vehicle_classes: Vec<Option<&str>> = readings
+        .iter()
+        .map(|r| r.vehicle_class.map(|c| c.as_str()))
+        .collect();
+    let vehicle_lengths: Vec<Option<f32>> = readings.iter().map(|r| r.vehicle_length).collect();
+    let quality_flags: Vec<Option<&str>> = readings
+        .iter()
+        .map(|r| r.quality_flag.map(|f| f.as_str()))
+        .collect();
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
+        conn.execute(
+            &stmt,
+            &[
+                &origin,
+                &origin_ids,
+                &sensor_names,
+                &speeds,
+                &lanes,
+                &created_ats,
+                &vehicle_classes,
+                &vehicle_lengths,
+                &quality_flags,
+            ],
+        )
+        .await
+        .map_err(DbError::from)
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to insert forwarded speed data: {e}");
+            e
+        })
+}
+
+/// Copies local readings refused by the upstream server into the quarantine
+///
+/// Readings keep their local id, so a batch quarantined twice is only stored once.
+pub async fn quarantine_speed_data(
+    pool: &DbPool,
+    readings: &[SpeedData],
+    reason: &str,
+) -> Result<(), DbError> {
+    const QUERY: &str = "INSERT INTO forward_quarantine (speed_id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag,reason) SELECT *, $9::text FROM UNNEST($1::int8[], $2::text[], $3::real[], $4::int4[], $5::timestamptz[], $6::text[], $7::real[], $8::text[]) ON CONFLICT (speed_id) DO NOTHING";
+
+    let speed_ids: Vec<i64> = readings.iter().map(|r| i64::from(r.id)).collect();
+    let sensor_names: Vec<Option<&str>> =
+        readings.iter().map(|r| r.sensor_name.as_deref()).collect();
+    let speeds: Vec<f32> = readings.iter().map(|r| r.speed).collect();
+    let lanes: Vec<i32> = readings.iter().map(|r| r.lane as i32).collect();
+    let created_ats: Vec<DateTime<Utc>> = readings.iter().map(|r| r.created_at).collect();
+    let vehicle_classes: Vec<Option<&str>> = readings
+        .iter()
+        .map(|r| r.vehicle_class.map(|c| c.as_str()))
+        .collect();
+    let vehicle_lengths: Vec<Option<f32>> = readings.iter().map(|r| r.vehicle_length).collect();
+    let quality_flags: Vec<Option<&str>> = readings
+        .iter()
+        .map(|r| r.quality_flag.map(|f| f.as_str()))
+        .collect();
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        conn.execute(
+            QUERY,
+            &[
+                &speed_ids,
+                &sensor_names,
+                &speeds,
+                &lanes,
+                &created_ats,
+                &vehicle_classes,
+                &vehicle_lengths,
+                &quality_flags,
+                &reason,
+            ],
+        )
+        .await
+        .map_err(DbError::from)?;
+        Ok(())
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to quarantine speed data: {e}");
+            e
+        })
+}
+
+/// Fetches up to `limit` local readings with an id above `after_id`, by increasing id
+///
+/// Readings are only returned once stored for `settle_secs`, which must exceed the insert
+/// timeout, so rows still being inserted with a lower id are never skipped. Readings forwarded
+/// by other servers are left out.
+pub async fn fetch_unforwarded_speed_data(
+    pool: &DbPool,
+    after_id: i64,
+    limit: u32,
+    settle_secs: f64,
+) -> Result<Vec<SpeedData>, DbError> {
+    const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE id > $1::int8 AND origin IS NULL AND received_at < now() - make_interval(secs => $3) ORDER BY id LIMIT $2";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(QUERY).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(&stmt, &[&after_id, &i64::from(limit), &settle_secs])
+            .await
+            .map_err(DbError::from)?;
+
+        rows.iter()
+            .map(SpeedData::from_row)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, SIMPLE_SELECT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch unforwarded speed data: {e}");
+            e
+        })
+}
+
+/// Fetches the last local speed id acknowledged by the upstream server, 0 if none
+pub async fn fetch_forward_watermark(pool: &DbPool) -> Result<i64, DbError> {
+    const QUERY: &str = "SELECT last_id FROM forward_watermark WHERE name = 'upstream'";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let row = conn.query_opt(QUERY, &[]).await.map_err(DbError::from)?;
+        row.map_or(Ok(0), |row| row.try_get("last_id").map_err(DbError::from))
+    };
+
+    with_timeout(query_future, SIMPLE_SELECT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch forward watermark: {e}");
+            e
+        })
+}
+
+/// Records the last local speed id acknowledged by the upstream server
+pub async fn store_forward_watermark(pool: &DbPool, last_id: i64) -> Result<(), DbError> {
+    const QUERY: &str = "INSERT INTO forward_watermark (name, last_id) VALUES ('upstream', $1) ON CONFLICT (name) DO UPDATE SET last_id = EXCLUDED.last_id, updated_at = now()";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        conn.execute(QUERY, &[&last_id])
+            .await
+            .map_err(DbError::from)?;
+        Ok(())
+    };
+
+    with_timeout(query_future, INSERT_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to store forward watermark: {e}");
+            e
+        })
+}
@@ -0,0 +1,277 @@
+use crate::database::pool::DbPool;
+use crate::database::types::DbError;
+use crate::{log_error, log_info};
+use std::fmt;
+
+/// A versioned schema change embedded in the binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Migrations known to this build, in order of version
+///
+/// Each one is the script of `sql/migrations` with the same numeric prefix.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 0,
+        name: "baseline",
+        sql: include_str!("../../sql/migrations/0000_baseline.sql"),
+    },
+    Migration {
+        version: 1,
+        name: "vehicle_classification",
+        sql: include_str!("../../sql/migrations/0001_vehicle_classification.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "flow_metrics_index",
+        sql: include_str!("../../sql/migrations/0002_flow_metrics_index.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "quality_flag",
+        sql: include_str!("../../sql/migrations/0003_quality_flag.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "rollups",
+        sql: include_str!("../../sql/migrations/0004_rollups.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "partition_speed",
+        sql: include_str!("../../sql/migrations/0005_partition_speed.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "forwarding",
+        sql: include_str!("../../sql/migrations/0006_forwarding.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "api_key_hash",
+        sql: include_str!("../../sql/migrations/0007_api_key_hash.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "api_key_management",
+        sql: include_str!("../../sql/migrations/0008_api_key_management.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "api_key_sensor",
+        sql: include_str!("../../sql/migrations/0009_api_key_sensor.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "api_key_expiry",
+        sql: include_str!("../../sql/migrations/0010_api_key_expiry.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "audit_log",
+        sql: include_str!("../../sql/migrations/0011_audit_log.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "forward_quarantine",
+        sql: include_str!("../../sql/migrations/0012_forward_quarantine.sql"),
+    },
+];
+
+/// Key of the advisory lock serializing migrations across replicas
+const MIGRATION_LOCK_KEY: i64 = 0x5350_4545_4453_5452; // "SPEEDSTR"
+
+/// Errors raised while checking or applying migrations
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database could not be queried or a migration failed
+    Database(DbError),
+
+    /// The database was migrated by a newer build, with versions this one doesn't know
+    UnknownVersion { applied: i64, latest: i64 },
+
+    /// Migrations starting at this version are not applied yet
+    Pending(i64),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "Migration failed: {}", e),
+            MigrationError::UnknownVersion { applied, latest } => write!(
+                f,
+                "Database schema is at version {} but this build only knows up to version {}",
+                applied, latest
+            ),
+            MigrationError::Pending(version) => write!(
+                f,
+                "Database schema is missing migration {:04} and later, run `SpeedStream migrate`",
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<DbError> for MigrationError {
+    fn from(e: DbError) -> Self {
+        MigrationError::Database(e)
+    }
+}
+
+impl From<tokio_postgres::Error> for MigrationError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        MigrationError::Database(DbError::from(e))
+    }
+}
+
+/// Returns the latest version known to this build
+#[must_use]
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(-1, |m| m.version)
+}
+
+/// Returns the migrations not applied yet, or an error if the database has a version this
+/// build doesn't know
+pub fn pending_migrations(applied: &[i64]) -> Result<Vec<&'static Migration>, MigrationError> {
+    let latest = latest_version();
+    if let Some(&newer) = applied.iter().filter(|&&v| v > latest).max() {
+        return Err(MigrationError::UnknownVersion {
+            applied: newer,
+            latest,
+        });
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect())
+}
+
+/// Applies the pending migrations, each in its own transaction
+///
+/// Each transaction takes an advisory lock and skips migrations applied meanwhile, so replicas
+/// starting together apply them only once. The lock is released on commit or rollback, never
+/// left on the pooled connection. Returns the versions applied.
+pub async fn run_migrations(pool: &DbPool) -> Result<Vec<i64>, MigrationError> {
+    const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY, name TEXT NOT NULL, applied_at TIMESTAMPTZ NOT NULL DEFAULT now())";
+    const LOCK: &str = "SELECT pg_advisory_xact_lock($1)";
+    const IS_APPLIED: &str =
+        "SELECT EXISTS (SELECT 1 FROM schema_migrations WHERE version = $1) AS applied";
+    const INSERT_APPLIED: &str = "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)";
+
+    let mut conn = pool.get().await.map_err(DbError::from)?;
+
+    let tx = conn.transaction().await?;
+    tx.execute(LOCK, &[&MIGRATION_LOCK_KEY]).await?;
+    tx.batch_execute(CREATE_TABLE).await?;
+    tx.commit().await?;
+
+    let pending = pending_migrations(&applied_versions(&conn).await?)?;
+
+    let mut versions = Vec::with_capacity(pending.len());
+    for migration in pending {
+        let tx = conn.transaction().await?;
+        tx.execute(LOCK, &[&MIGRATION_LOCK_KEY]).await?;
+        let applied: bool = tx
+            .query_one(IS_APPLIED, &[&migration.version])
+            .await?
+            .try_get("applied")?;
+        if applied {
+            continue;
+        }
+
+        tx.batch_execute(migration.sql).await.map_err(|e| {
+            log_error!(
+                "Migration {:04}_{} failed: {e}",
+                migration.version,
+                migration.name
+            );
+            e
+        })?;
+        tx.execute(INSERT_APPLIED, &[&migration.version, &migration.name])
+            .await?;
+        tx.commit().await?;
+
+        log_info!(
+            "Applied migration {:04}_{}",
+            migration.version,
+            migration.name
+        );
+        versions.push(migration.version);
+    }
+
+    Ok(versions)
+}
+
+/// Checks that the schema is up to date without applying any migration
+///
+/// Only reads the database, a missing `schema_migrations` table meaning no migration applied.
+/// Returns the versions still to apply.
+pub async fn check_migrations(pool: &DbPool) -> Result<Vec<i64>, MigrationError> {
+    const TABLE_EXISTS: &str =
+        "SELECT to_regclass('schema_migrations') IS NOT NULL AS table_exists";
+
+    let conn = pool.get().await.map_err(DbError::from)?;
+
+    let table_exists: bool = conn
+        .query_one(TABLE_EXISTS, &[])
+        .await?
+        .try_get("table_exists")?;
+    let applied = if table_exists {
+        applied_versions(&conn).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(pending_migrations(&applied)?
+        .iter()
+        .map(|m| m.version)
+        .collect())
+}
+
+/// Returns the versions recorded in `schema_migrations`
+async fn applied_versions(conn: &tokio_postgres::Client) -> Result<Vec<i64>, MigrationError> {
+    const SELECT_APPLIED: &str = "SELECT version FROM schema_migrations ORDER BY version";
+
+    Ok(conn
+        .query(SELECT_APPLIED, &[])
+        .await?
+        .iter()
+        .map(|row| row.try_get("version"))
+        .collect::<Result<Vec<i64>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64);
+            assert!(!migration.sql.trim().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let pending = pending_migrations(&[0, 1, 2]).unwrap();
+        let versions: Vec<_> = pending.iter().map(|m| m.version).collect();
+        assert_eq!(versions, (3..=latest_version()).collect::<Vec<_>>());
+
+        let all: Vec<_> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert!(pending_migrations(&all).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let err = pending_migrations(&[0, latest_version() + 1]).unwrap_err();
+        assert!(matches!(err, MigrationError::UnknownVersion { .. }));
+    }
+}
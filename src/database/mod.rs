@@ -1,6 +1,13 @@
+pub mod audit;
 pub mod auth;
 pub mod cache;
 pub mod crud;
+pub mod forward;
+pub mod migration;
+pub mod partition;
 pub mod pool;
+pub mod repository;
+pub mod retention;
+pub mod rollup;
 pub mod types;
 pub mod util;
@@ -0,0 +1,393 @@
+use crate::config::constant::{
+    FORWARD_URL, PARTITION_DROP_DETACHED, PARTITION_INTERVAL_SECS, PARTITION_MONTHS_AHEAD,
+    RETENTION_RAW_DAYS,
+};
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::database::pool::DbPool;
+use crate::database::types::DbError;
+use crate::database::util::{RANGE_QUERY_TIMEOUT, with_timeout};
+use crate::telemetry::audit::AuditLog;
+use crate::{log_error, log_info};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc};
+
+/// A partition of the `speed` table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub name: String,
+    pub from: Option<DateTime<Utc>>, // None for MINVALUE
+    pub to: Option<DateTime<Utc>>,   // None for MAXVALUE
+    pub is_default: bool,
+}
+
+impl Partition {
+    /// Checks whether the partition holds any instant of `[from, to)`
+    fn overlaps(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> bool {
+        !self.is_default
+            && self.from.is_none_or(|start| start < to)
+            && self.to.is_none_or(|end| end > from)
+    }
+}
+
+/// Lower and upper bounds of a partition, `None` standing for MINVALUE or MAXVALUE
+pub type PartitionBounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Settings of the background task managing the partitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionConfig {
+    pub interval: std::time::Duration, // Delay between two checks
+    pub months_ahead: u32,             // Future monthly partitions kept ready
+    pub keep_days: Option<i64>,        // Raw retention, None keeps partitions forever
+    pub drop_detached: bool,           // Drop partitions once detached, except the legacy one
+    pub keep_unforwarded: bool,        // Edge servers keep readings until forwarded
+}
+
+impl PartitionConfig {
+    /// Builds the configuration from the `PARTITION_*`, `RETENTION_RAW_DAYS` and `FORWARD_URL`
+    /// environment variables
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(*PARTITION_INTERVAL_SECS),
+            months_ahead: *PARTITION_MONTHS_AHEAD,
+            keep_days: (*RETENTION_RAW_DAYS > 0).then_some(*RETENTION_RAW_DAYS),
+            drop_detached: *PARTITION_DROP_DETACHED,
+            keep_unforwarded: !FORWARD_URL.trim().is_empty(),
+        }
+    }
+
+    /// Checks whether an expired partition is dropped once detached
+    ///
+    /// The partition starting at MINVALUE holds every reading stored before partitioning, e.g.
+    /// `speed_legacy`, and is only ever detached, so the history from before the upgrade can't be
+    /// lost to a misconfigured retention.
+    #[must_use]
+    pub fn drops(&self, partition: &Partition) -> bool {
+        self.drop_detached && partition.from.is_some()
+    }
+}
+
+/// Returns the name of the monthly partition starting on `month`
+#[must_use]
+pub fn partition_name(month: NaiveDate) -> String {
+    format!("speed_p{:04}_{:02}", month.year(), month.month())
+}
+
+/// Parses a partition bound as printed by `pg_get_expr`, e.g.
+/// `FOR VALUES FROM ('2024-03-01 00:00:00+00') TO ('2024-04-01 00:00:00+00')`
+///
+/// Returns `None` for the default partition or an unexpected expression.
+pub fn parse_bounds(expr: &str) -> Option<PartitionBounds> {
+    let parse = |bound: &str| -> Option<Option<DateTime<Utc>>> {
+        let bound = bound.trim().trim_start_matches('(').trim_end_matches(')');
+        match bound {
+            "MINVALUE" | "MAXVALUE" => Some(None),
+            quoted => {
+                let value = quoted.trim_matches('\'');
+                DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%#z")
+                    .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%#z"))
+                    .ok()
+                    .map(|dt| Some(dt.with_timezone(&Utc)))
+            }
+        }
+    };
+
+    let rest = expr.strip_prefix("FOR VALUES FROM ")?;
+    let (from, to) = rest.split_once(" TO ")?;
+    Some((parse(from)?, parse(to)?))
+}
+
+/// Returns the monthly partitions to create, from the current month to `months_ahead` months
+/// later, skipping months already covered by an existing partition
+#[must_use]
+pub fn plan_partitions(
+    existing: &[Partition],
+    now: DateTime<Utc>,
+    months_ahead: u32,
+) -> Vec<(String, DateTime<Utc>, DateTime<Utc>)> {
+    let current = now.date_naive().with_day(1).unwrap_or(now.date_naive());
+    let start_of = |month: NaiveDate| month.and_time(NaiveTime::MIN).and_utc();
+
+    (0..=months_ahead)
+        .filter_map(|offset| {
+            let month = current.checked_add_months(Months::new(offset))?;
+            let from = start_of(month);
+            let to = start_of(month.checked_add_months(Months::new(1))?);
+            let taken = existing.iter().any(|p| p.overlaps(from, to));
+            (!taken).then(|| (partition_name(month), from, to))
+        })
+        .collect()
+}
+
+/// Lists the partitions of the `speed` table
+pub async fn list_partitions(pool: &DbPool) -> Result<Vec<Partition>, DbError> {
+    const QUERY: &str = "SELECT c.relname::text AS name, pg_get_expr(c.relpartbound, c.oid) AS bound FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid WHERE i.inhparent = 'speed'::regclass ORDER BY 1";
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let rows = conn.query(QUERY, &[]).await.map_err(DbError::from)?;
+        rows.iter()
+            .map(|row| {
+                let name: String = row.try_get("name").map_err(DbError::from)?;
+                let bound: String = row.try_get("bound").map_err(DbError::from)?;
+                if bound == "DEFAULT" {
+                    return Ok(Partition {
+                        name,
+                        from: None,
+                        to: None,
+                        is_default: true,
+                    });
+                }
+                let (from, to) = parse_bounds(&bound).ok_or_else(|| {
+                    DbError::RowParsing(format!("Invalid bound of partition {name}: {bound}"))
+                })?;
+                Ok(Partition {
+                    name,
+                    from,
+                    to,
+                    is_default: false,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT).await
+}
+
+/// Creates the missing monthly partitions up to `months_ahead` months in the future
+///
+/// Readings of a new month already caught by the default partition are moved into the new
+/// partition in the same transaction. A month failing to be created is logged and retried on
+/// the next run without blocking the following ones. Returns the names of the partitions created.
+pub async fn ensure_partitions(
+    pool: &DbPool,
+    config: &PartitionConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, DbError> {
+    let existing = list_partitions(pool).await?;
+    let default = existing
+        .iter()
+        .find(|p| p.is_default)
+        .map(|p| p.name.clone());
+    let mut conn = pool.get().await?;
+
+    let mut created = Vec::new();
+    for (name, from, to) in plan_partitions(&existing, now, config.months_ahead) {
+        let (from, to) = (from.to_rfc3339(), to.to_rfc3339());
+        let mut statement = String::new();
+        if let Some(default) = &default {
+            statement.push_str(&format!(
+                "LOCK TABLE {default} IN ACCESS EXCLUSIVE MODE; \
+                 CREATE TEMP TABLE speed_moving ON COMMIT DROP AS SELECT * FROM {default} WHERE created_at >= '{from}' AND created_at < '{to}'; \
+                 DELETE FROM {default} WHERE created_at >= '{from}' AND created_at < '{to}'; "
+            ));
+        }
+        statement.push_str(&format!(
+            "CREATE TABLE IF NOT EXISTS {name} PARTITION OF speed FOR VALUES FROM ('{from}') TO ('{to}');"
+        ));
+        if default.is_some() {
+            statement.push_str(" INSERT INTO speed SELECT * FROM speed_moving;");
+        }
+
+        let create_future = async {
+            let tx = conn.transaction().await.map_err(DbError::from)?;
+            tx.batch_execute(&statement).await.map_err(DbError::from)?;
+            tx.commit().await.map_err(DbError::from)
+        };
+        match with_timeout(create_future, RANGE_QUERY_TIMEOUT).await {
+            Ok(()) => created.push(name),
+            Err(e) => {
+                log_error!("Failed to create partition {name}: {e}");
+            }
+        }
+    }
+
+    Ok(created)
+}
+
+/// Detaches the partitions entirely older than the raw retention
+///
+/// A partition is only detached once all its readings are folded into the rollups, and on edge
+/// servers forwarded upstream, then dropped if `drop_detached` is set, except the legacy one.
+/// Returns the names of the partitions detached, along with whether they were dropped.
+pub async fn detach_expired_partitions(
+    pool: &DbPool,
+    config: &PartitionConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<(String, bool)>, DbError> {
+    const UNFOLDED: &str = "SELECT EXISTS (SELECT 1 FROM speed WHERE ($1::timestamptz IS NULL OR created_at >= $1) AND created_at < $2 AND (id > coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0) OR ($3::bool AND origin IS NULL AND id > coalesce((SELECT last_id FROM forward_watermark WHERE name = 'upstream'), 0)))) AS unfolded";
+
+    let Some(keep_days) = config.keep_days else {
+        return Ok(Vec::new());
+    };
+    let cutoff = now - Duration::days(keep_days);
+
+    let existing = list_partitions(pool).await?;
+    let conn = pool.get().await?;
+
+    let mut detached = Vec::new();
+    for partition in existing {
+        let Some(to) = partition.to.filter(|to| *to <= cutoff) else {
+            continue;
+        };
+        let unfolded: bool = conn
+            .query_one(UNFOLDED, &[&partition.from, &to, &config.keep_unforwarded])
+            .await
+            .map_err(DbError::from)?
+            .try_get("unfolded")
+            .map_err(DbError::from)?;
+        if unfolded {
+            continue;
+        }
+
+        let dropped = config.drops(&partition);
+        let mut statement = format!("ALTER TABLE speed DETACH PARTITION {};", partition.name);
+        if dropped {
+            statement.push_str(&format!(" DROP TABLE {};", partition.name));
+        }
+        with_timeout(
+            async { conn.batch_execute(&statement).await.map_err(DbError::from) },
+            RANGE_QUERY_TIMEOUT,
+        )
+        .await
+        .map_err(|e| {
+            log_error!("Failed to detach partition {}: {e}", partition.name);
+            e
+        })?;
+        detached.push((partition.name, dropped));
+    }
+
+    Ok(detached)
+}
+
+/// Spawns the background task creating future partitions and detaching expired ones
+///
+/// Detached partitions are recorded in the audit trail.
+pub fn spawn_partition_manager(
+    pool: DbPool,
+    config: PartitionConfig,
+    audit: AuditLog,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+
+            if let Ok(created) = ensure_partitions(&pool, &config, Utc::now()).await
+                && !created.is_empty()
+            {
+                log_info!("Created partitions: {}", created.join(", "));
+            }
+
+            if let Ok(detached) = detach_expired_partitions(&pool, &config, Utc::now()).await
+                && !detached.is_empty()
+            {
+                for (name, dropped) in detached {
+                    let verb = if dropped { "Dropped" } else { "Detached" };
+                    log_info!("{verb} expired partition {name}");
+                    audit.record(
+                        AuditEvent::new(AuditAction::DataPurged)
+                            .with_detail(format!("{verb} partition {name}")),
+                    );
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_partition_name() {
+        let month = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(partition_name(month), "speed_p2024_03");
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        assert_eq!(
+            parse_bounds(
+                "FOR VALUES FROM ('2024-03-01 00:00:00+00') TO ('2024-04-01 00:00:00+00')"
+            ),
+            Some((
+                Some(at("2024-03-01T00:00:00Z")),
+                Some(at("2024-04-01T00:00:00Z"))
+            ))
+        );
+        assert_eq!(
+            parse_bounds("FOR VALUES FROM (MINVALUE) TO ('2024-03-01 01:00:00+01')"),
+            Some((None, Some(at("2024-03-01T00:00:00Z"))))
+        );
+        assert_eq!(parse_bounds("DEFAULT"), None);
+    }
+
+    #[test]
+    fn test_plan_partitions_skips_covered_months() {
+        let legacy = Partition {
+            name: "speed_legacy".to_string(),
+            from: None,
+            to: Some(at("2024-04-01T00:00:00Z")),
+            is_default: false,
+        };
+        let default = Partition {
+            name: "speed_default".to_string(),
+            from: None,
+            to: None,
+            is_default: true,
+        };
+
+        let planned = plan_partitions(&[legacy, default], at("2024-03-20T10:00:00Z"), 2);
+        let names: Vec<_> = planned.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, ["speed_p2024_04", "speed_p2024_05"]);
+        assert_eq!(planned[0].1, at("2024-04-01T00:00:00Z"));
+        assert_eq!(planned[0].2, at("2024-05-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_legacy_partition_is_never_dropped() {
+        let config = PartitionConfig {
+            interval: std::time::Duration::from_secs(3600),
+            months_ahead: 3,
+            keep_days: Some(90),
+            drop_detached: true,
+            keep_unforwarded: false,
+        };
+        let legacy = Partition {
+            name: "speed_legacy".to_string(),
+            from: None,
+            to: Some(at("2024-04-01T00:00:00Z")),
+            is_default: false,
+        };
+        let monthly = Partition {
+            name: "speed_p2024_04".to_string(),
+            from: Some(at("2024-04-01T00:00:00Z")),
+            to: Some(at("2024-05-01T00:00:00Z")),
+            is_default: false,
+        };
+
+        assert!(!config.drops(&legacy));
+        assert!(config.drops(&monthly));
+
+        let archiving = PartitionConfig {
+            drop_detached: false,
+            ..config
+        };
+        assert!(!archiving.drops(&monthly));
+    }
+
+    #[test]
+    fn test_plan_partitions_across_year() {
+        let planned = plan_partitions(&[], at("2024-12-05T00:00:00Z"), 1);
+        let names: Vec<_> = planned.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, ["speed_p2024_12", "speed_p2025_01"]);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::time_bucket::TimeBucket;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// Group of an aggregate: bucket start, lane, sensor and vehicle class
+type GroupKey = (
+    DateTime<Utc>,
+    Option<u8>,
+    Option<String>,
+    Option<&'static str>,
+);
+
+/// Aggregates readings per time bucket and group, for the backends that can't do it in SQL
+///
+/// Readings must already be filtered on the date range, vehicle class and quality flag.
+/// Results are ordered by bucket, then lane, sensor and class.
+#[must_use]
+pub fn aggregate_readings<'a>(
+    readings: impl IntoIterator<Item = &'a SpeedData>,
+    bucket: TimeBucket,
+    group_by: GroupBy,
+    tz: Tz,
+) -> Vec<SpeedAggregate> {
+    let mut groups: BTreeMap<GroupKey, SpeedAggregate> = BTreeMap::new();
+
+    for reading in readings {
+        let bucket_start = bucket.truncate(reading.created_at, tz);
+        let lane = group_by.lane.then_some(reading.lane);
+        let sensor_name = reading.sensor_name.clone().filter(|_| group_by.sensor);
+        let vehicle_class = reading.vehicle_class.filter(|_| group_by.class);
+        let key = (
+            bucket_start,
+            lane.map(|l| l as u8),
+            sensor_name.clone(),
+            vehicle_class.map(|c| c.as_str()),
+        );
+
+        let aggregate = groups.entry(key).or_insert_with(|| SpeedAggregate {
+            bucket_start,
+            lane,
+            sensor_name,
+            vehicle_class,
+            count: 0,
+            avg_speed: 0.0,
+            min_speed: reading.speed,
+            max_speed: reading.speed,
+        });
+        aggregate.count += 1;
+        aggregate.avg_speed += f64::from(reading.speed); // Sum until every reading is counted
+        aggregate.min_speed = aggregate.min_speed.min(reading.speed);
+        aggregate.max_speed = aggregate.max_speed.max(reading.speed);
+    }
+
+    groups
+        .into_values()
+        .map(|mut aggregate| {
+            aggregate.avg_speed /= aggregate.count as f64;
+            aggregate
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lane::Lane;
+    use crate::core::vehicle_class::VehicleClass;
+    use chrono::{Duration, TimeZone as _};
+
+    #[test]
+    fn test_aggregate_readings() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap();
+        let reading = |minutes, speed, lane, class| {
+            SpeedData::new(0, None, speed, lane, start + Duration::minutes(minutes))
+                .with_vehicle(class, None)
+        };
+        let readings = [
+            reading(5, 40.0, Lane::Left, Some(VehicleClass::Car)),
+            reading(50, 60.0, Lane::Right, Some(VehicleClass::Car)),
+            reading(70, 90.0, Lane::Left, Some(VehicleClass::Truck)),
+        ];
+
+        let hourly = aggregate_readings(&readings, TimeBucket::Hour, GroupBy::default(), Tz::UTC);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].bucket_start, start);
+        assert_eq!(hourly[0].count, 2);
+        assert_eq!(hourly[0].avg_speed, 50.0);
+        assert_eq!((hourly[0].min_speed, hourly[0].max_speed), (40.0, 60.0));
+        assert_eq!(hourly[0].lane, None);
+
+        let group_by = GroupBy {
+            lane: true,
+            sensor: false,
+            class: true,
+        };
+        let daily = aggregate_readings(&readings, TimeBucket::Day, group_by, Tz::UTC);
+        let groups: Vec<_> = daily
+            .iter()
+            .map(|a| (a.lane, a.vehicle_class, a.count))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (Some(Lane::Left), Some(VehicleClass::Car), 1),
+                (Some(Lane::Left), Some(VehicleClass::Truck), 1),
+                (Some(Lane::Right), Some(VehicleClass::Car), 1),
+            ]
+        );
+    }
+}
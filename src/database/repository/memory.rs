@@ -0,0 +1,707 @@
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::api_key::{AuthenticatedKey, hash_api_key, key_prefix};
+use crate::core::api_key_scope::{ApiKeyScope, DEFAULT_SCOPES};
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::lane::Lane;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::repository::SpeedRepository;
+use crate::database::repository::aggregate::aggregate_readings;
+use crate::database::types::DbError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+
+/// Repository keeping everything in process memory
+///
+/// Nothing survives a restart; meant for integration tests and the demo binary.
+#[derive(Default)]
+pub struct InMemorySpeedRepository {
+    readings: RwLock<Vec<SpeedData>>,            // Ordered by created_at
+    api_keys: RwLock<Vec<(ApiKeyInfo, String)>>, // API keys with their hash, ordered by id
+    last_id: AtomicI32,
+    last_key_id: AtomicI32,
+    forwarded: RwLock<HashMap<ForwardedKey, i32>>, // Readings received from edge servers -> local id
+    forward_watermark: AtomicI64,
+    quarantine: RwLock<Vec<(SpeedData, String)>>, // Readings refused upstream with the reason
+    audit_events: RwLock<Vec<AuditEvent>>,        // Ordered by id
+    last_audit_id: AtomicI64,
+}
+
+/// Origin, id on the origin and timestamp of a forwarded reading
+type ForwardedKey = (String, i32, DateTime<Utc>);
+
+impl InMemorySpeedRepository {
+    /// Creates an empty repository
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an API key with the default scopes, active or not
+    #[must_use]
+    pub fn with_api_key(self, token: &str, is_active: bool) -> Self {
+        self.register_api_key(token, DEFAULT_SCOPES.to_vec(), None, is_active);
+        self
+    }
+
+    /// Registers an active API key with the default scopes and the admin scope
+    #[must_use]
+    pub fn with_admin_key(self, token: &str) -> Self {
+        let mut scopes = DEFAULT_SCOPES.to_vec();
+        scopes.push(ApiKeyScope::Admin);
+        self.register_api_key(token, scopes, None, true);
+        self
+    }
+
+    /// Registers an active API key with only the given scopes
+    #[must_use]
+    pub fn with_scoped_key(self, token: &str, scopes: &[ApiKeyScope]) -> Self {
+        self.register_api_key(token, scopes.to_vec(), None, true);
+        self
+    }
+
+    /// Registers an active API key sending the readings of `sensor_name`
+    #[must_use]
+    pub fn with_sensor_key(self, token: &str, sensor_name: &str) -> Self {
+        self.register_api_key(
+            token,
+            vec![ApiKeyScope::SpeedsWrite],
+            Some(sensor_name.to_string()),
+            true,
+        );
+        self
+    }
+
+    /// Stores an API key given its secret
+    fn register_api_key(
+        &self,
+        token: &str,
+        scopes: Vec<ApiKeyScope>,
+        sensor_name: Option<String>,
+        is_active: bool,
+    ) {
+        let info = ApiKeyInfo {
+            id: self.last_key_id.fetch_add(1, Ordering::Relaxed) + 1,
+            name: None,
+            owner: None,
+            scopes,
+            sensor_name,
+            expires_at: None,
+            key_prefix: Some(key_prefix(token)),
+            is_active,
+            created_at: Utc::now(),
+        };
+        self.api_keys
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((info, hash_api_key(token)));
+    }
+
+    /// Applies `update` to the API key `id` and returns its hash before the update
+    fn update_api_key(
+        &self,
+        id: i32,
+        update: impl FnOnce(&mut ApiKeyInfo, &mut String),
+    ) -> Option<String> {
+        let mut api_keys = self.api_keys.write().unwrap_or_else(|e| e.into_inner());
+        let (info, key_hash) = api_keys.iter_mut().find(|(info, _)| info.id == id)?;
+        let previous = key_hash.clone();
+        update(info, key_hash);
+        Some(previous)
+    }
+
+    /// Stores a reading with the given timestamp, e.g. to seed historical data
+    pub fn insert_at(&self, mut data: SpeedData, created_at: DateTime<Utc>) -> SpeedData {
+        let mut readings = self.readings.write().unwrap_or_else(|e| e.into_inner());
+        data.id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        data.created_at = created_at;
+        let position = readings.partition_point(|r| r.created_at <= created_at);
+        readings.insert(position, data.clone());
+        data
+    }
+
+    /// Returns the readings refused by the upstream server along with the reason
+    pub fn quarantined(&self) -> Vec<(SpeedData, String)> {
+        self.quarantine
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Returns the readings matching `filter`, oldest first
+    fn select(&self, filter: impl Fn(&SpeedData) -> bool) -> Vec<SpeedData> {
+        self.readings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|r| filter(r))
+            .cloned()
+            .collect()
+    }
+}
+
+#[inline]
+fn matches_class(data: &SpeedData, vehicle_class: Option<VehicleClass>) -> bool {
+    vehicle_class.is_none() || data.vehicle_class == vehicle_class
+}
+
+#[async_trait]
+impl SpeedRepository for InMemorySpeedRepository {
+    async fn ping(&self) -> Result<(), DbError> {
+        Ok(())
+    }
+
+    async fn insert_speed_data(
+        &self,
+        payload: CreateSpeedDataRequest,
+        quality_flag: Option<QualityFlag>,
+    ) -> Result<SpeedData, DbError> {
+        let lane = Lane::try_from(i32::from(payload.lane))
+            .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?;
+        let sensor_name = payload.sensor_name.filter(|s| !s.is_empty());
+
+        let mut data = SpeedData::new(0, sensor_name, payload.speed, lane, Utc::now())
+            .with_vehicle(payload.vehicle_class, payload.vehicle_length);
+        data.quality_flag = quality_flag;
+
+        Ok(self.insert_at(data, Utc::now()))
+    }
+
+    async fn fetch_last_n_speed_data(
+        &self,
+        number: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        let mut data = self.select(|r| matches_class(r, vehicle_class));
+        data.reverse();
+        data.truncate(usize::from(number));
+        Ok(data)
+    }
+
+    async fn fetch_speed_data_with_pagination(
+        &self,
+        offset: u32,
+        limit: u32,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        Ok(self
+            .select(|r| matches_class(r, vehicle_class))
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn fetch_speed_data_today(
+        &self,
+        day_start: DateTime<Utc>,
+        day_end: DateTime<Utc>,
+        limit: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        let mut data = self.select(|r| {
+            r.created_at >= day_start && r.created_at < day_end && matches_class(r, vehicle_class)
+        });
+        data.truncate(usize::from(limit));
+        Ok(data)
+    }
+
+    async fn fetch_last_speed(
+        &self,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Option<SpeedData>, DbError> {
+        Ok(self.select(|r| matches_class(r, vehicle_class)).pop())
+    }
+
+    async fn fetch_speed_data_by_date_range(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        Ok(self.select(|r| {
+            r.created_at >= start_date
+                && r.created_at <= end_date
+                && matches_class(r, vehicle_class)
+        }))
+    }
+
+    async fn fetch_speed_aggregates(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket: TimeBucket,
+        group_by: GroupBy,
+        vehicle_class: Option<VehicleClass>,
+        include_flagged: bool,
+        tz: Tz,
+    ) -> Result<Vec<SpeedAggregate>, DbError> {
+        let readings = self.select(|r| {
+            r.created_at >= start_date
+                && r.created_at <= end_date
+                && matches_class(r, vehicle_class)
+                && (include_flagged || r.quality_flag.is_none())
+        });
+        Ok(aggregate_readings(&readings, bucket, group_by, tz))
+    }
+
+    async fn insert_forwarded_speed_data(
+        &self,
+        origin: &str,
+        readings: Vec<SpeedData>,
+    ) -> Result<u64, DbError> {
+        let mut forwarded = self.forwarded.write().unwrap_or_else(|e| e.into_inner());
+        let mut inserted = 0;
+        for reading in readings {
+            let key = (origin.to_string(), reading.id, reading.created_at);
+            if forwarded.contains_key(&key) {
+                continue;
+            }
+            let created_at = reading.created_at;
+            forwarded.insert(key, self.insert_at(reading, created_at).id);
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+
+    async fn fetch_unforwarded_speed_data(
+        &self,
+        after_id: i64,
+        limit: u32,
+        _settle_secs: f64, // Readings are visible as soon as their id is assigned
+    ) -> Result<Vec<SpeedData>, DbError> {
+        let forwarded: HashSet<i32> = self
+            .forwarded
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .copied()
+            .collect();
+
+        let mut data = self.select(|r| i64::from(r.id) > after_id && !forwarded.contains(&r.id));
+        data.sort_by_key(|r| r.id);
+        data.truncate(limit as usize);
+        Ok(data)
+    }
+
+    async fn fetch_forward_watermark(&self) -> Result<i64, DbError> {
+        Ok(self.forward_watermark.load(Ordering::Relaxed))
+    }
+
+    async fn store_forward_watermark(&self, last_id: i64) -> Result<(), DbError> {
+        self.forward_watermark.store(last_id, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn quarantine_speed_data(
+        &self,
+        readings: &[SpeedData],
+        reason: &str,
+    ) -> Result<(), DbError> {
+        let mut quarantine = self.quarantine.write().unwrap_or_else(|e| e.into_inner());
+        for reading in readings {
+            if !quarantine.iter().any(|(r, _)| r.id == reading.id) {
+                quarantine.push((reading.clone(), reason.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    async fn validate_token(&self, key_hash: &str) -> Result<Option<AuthenticatedKey>, DbError> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(info, hash)| info.is_active && hash == key_hash)
+            .map(|(info, _)| AuthenticatedKey::from_info(info))
+            .filter(|key| !key.is_expired(Utc::now())))
+    }
+
+    async fn validate_key_id(
+        &self,
+        id: i32,
+    ) -> Result<Option<(AuthenticatedKey, String)>, DbError> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(info, _)| info.is_active && info.id == id)
+            .map(|(info, hash)| (AuthenticatedKey::from_info(info), hash.clone()))
+            .filter(|(key, _)| !key.is_expired(Utc::now())))
+    }
+
+    async fn hash_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        Ok(0) // Keys are hashed when registered
+    }
+
+    async fn count_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        Ok(0)
+    }
+
+    async fn create_api_key(
+        &self,
+        request: CreateApiKeyRequest,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<ApiKeyInfo, DbError> {
+        let info = ApiKeyInfo {
+            id: self.last_key_id.fetch_add(1, Ordering::Relaxed) + 1,
+            name: Some(request.name),
+            owner: request.owner,
+            scopes: request.scopes,
+            sensor_name: request.sensor_name,
+            expires_at: request.expires_at,
+            key_prefix: Some(key_prefix.to_string()),
+            is_active: true,
+            created_at: Utc::now(),
+        };
+        self.api_keys
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((info.clone(), key_hash.to_string()));
+        Ok(info)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, DbError> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(info, _)| info.clone())
+            .collect())
+    }
+
+    async fn rotate_api_key(
+        &self,
+        id: i32,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<Option<String>, DbError> {
+        Ok(self.update_api_key(id, |info, hash| {
+            info.key_prefix = Some(key_prefix.to_string());
+            *hash = key_hash.to_string();
+        }))
+    }
+
+    async fn deactivate_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        Ok(self.update_api_key(id, |info, _| info.is_active = false))
+    }
+
+    async fn delete_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        let mut api_keys = self.api_keys.write().unwrap_or_else(|e| e.into_inner());
+        let position = api_keys.iter().position(|(info, _)| info.id == id);
+        Ok(position.map(|position| api_keys.remove(position).1))
+    }
+
+    async fn insert_audit_events(&self, events: Vec<AuditEvent>) -> Result<(), DbError> {
+        let mut audit_events = self.audit_events.write().unwrap_or_else(|e| e.into_inner());
+        for mut event in events {
+            event.id = self.last_audit_id.fetch_add(1, Ordering::Relaxed) + 1;
+            audit_events.push(event);
+        }
+        Ok(())
+    }
+
+    async fn fetch_audit_events(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        action: Option<AuditAction>,
+        key_id: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, DbError> {
+        let mut events: Vec<AuditEvent> = self
+            .audit_events
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|e| e.occurred_at >= start_date && e.occurred_at <= end_date)
+            .filter(|e| action.is_none_or(|action| e.action == action))
+            .filter(|e| key_id.is_none_or(|id| e.key_id == Some(id) || e.target_key_id == Some(id)))
+            .cloned()
+            .collect();
+        events.sort_by_key(|e| std::cmp::Reverse((e.occurred_at, e.id)));
+        events.truncate(limit as usize);
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone as _};
+
+    fn request(speed: f32, vehicle_class: Option<VehicleClass>) -> CreateSpeedDataRequest {
+        serde_json::from_value(serde_json::json!({
+            "sensor_name": "",
+            "speed": speed,
+            "lane": 1,
+            "vehicle_class": vehicle_class,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_fetch() {
+        let repository = InMemorySpeedRepository::new();
+        let first = repository
+            .insert_speed_data(request(50.0, None), None)
+            .await
+            .unwrap();
+        let second = repository
+            .insert_speed_data(request(80.0, Some(VehicleClass::Truck)), None)
+            .await
+            .unwrap();
+
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(first.sensor_name, None);
+
+        let last = repository.fetch_last_speed(None).await.unwrap().unwrap();
+        assert_eq!(last.id, 2);
+
+        let last_n = repository.fetch_last_n_speed_data(10, None).await.unwrap();
+        let ids: Vec<_> = last_n.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [2, 1]);
+
+        let page = repository
+            .fetch_speed_data_with_pagination(1, 10, None)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, 2);
+
+        let trucks = repository
+            .fetch_last_n_speed_data(10, Some(VehicleClass::Truck))
+            .await
+            .unwrap();
+        assert_eq!(trucks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_by_date_range() {
+        let repository = InMemorySpeedRepository::new();
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        for hours in [0, 2, 1] {
+            let data = SpeedData::new(0, None, 50.0, Lane::Left, start);
+            let _ = repository.insert_at(data, start + Duration::hours(hours));
+        }
+
+        let data = repository
+            .fetch_speed_data_by_date_range(start, start + Duration::hours(1), None)
+            .await
+            .unwrap();
+        let times: Vec<_> = data.iter().map(|d| d.created_at - start).collect();
+        assert_eq!(times, [Duration::zero(), Duration::hours(1)]);
+        assert!(
+            repository
+                .fetch_last_speed(Some(VehicleClass::Bus))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_readings_are_stored_once() {
+        let repository = InMemorySpeedRepository::new();
+        let local = repository
+            .insert_speed_data(request(50.0, None), None)
+            .await
+            .unwrap();
+
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap();
+        let batch = vec![
+            SpeedData::new(1, Some("edge".to_string()), 60.0, Lane::Right, at),
+            SpeedData::new(2, None, 70.0, Lane::Left, at + Duration::seconds(1)),
+        ];
+        let inserted = repository
+            .insert_forwarded_speed_data("site-a", batch.clone())
+            .await
+            .unwrap();
+        assert_eq!(inserted, 2);
+
+        // Sent again, e.g. after a lost acknowledgement
+        let inserted = repository
+            .insert_forwarded_speed_data("site-a", batch)
+            .await
+            .unwrap();
+        assert_eq!(inserted, 0);
+
+        let range = repository
+            .fetch_speed_data_by_date_range(at, at + Duration::seconds(1), None)
+            .await
+            .unwrap();
+        assert_eq!(range.len(), 2);
+        assert_eq!(range[0].sensor_name.as_deref(), Some("edge"));
+
+        // Only local readings are forwarded upstream
+        let unforwarded = repository
+            .fetch_unforwarded_speed_data(0, 10, 0.0)
+            .await
+            .unwrap();
+        let ids: Vec<_> = unforwarded.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [local.id]);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token() {
+        let repository = InMemorySpeedRepository::new()
+            .with_api_key("active", true)
+            .with_api_key("revoked", false);
+
+        for (token, valid) in [("active", true), ("revoked", false), ("unknown", false)] {
+            let key = repository
+                .validate_token(&hash_api_key(token))
+                .await
+                .unwrap();
+            assert_eq!(key.is_some(), valid);
+        }
+        assert!(repository.validate_token("active").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_manage_api_keys() {
+        let repository = InMemorySpeedRepository::new().with_admin_key("admin");
+        let request: CreateApiKeyRequest =
+            serde_json::from_value(serde_json::json!({"name": "Site A", "owner": "ops"})).unwrap();
+        let created = repository
+            .create_api_key(request, &hash_api_key("first"), "first")
+            .await
+            .unwrap();
+        assert_eq!(created.id, 2);
+        assert_eq!(created.scopes, DEFAULT_SCOPES);
+
+        // Rotation returns the previous hash and only accepts the new secret
+        let previous = repository
+            .rotate_api_key(created.id, &hash_api_key("second"), "second")
+            .await
+            .unwrap();
+        assert_eq!(previous, Some(hash_api_key("first")));
+        assert!(
+            repository
+                .validate_token(&hash_api_key("first"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let key = repository
+            .validate_token(&hash_api_key("second"))
+            .await
+            .unwrap();
+        assert_eq!(key.and_then(|k| k.id), Some(created.id));
+
+        assert!(
+            repository
+                .deactivate_api_key(created.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repository
+                .validate_token(&hash_api_key("second"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(!repository.list_api_keys().await.unwrap()[1].is_active);
+
+        assert!(
+            repository
+                .delete_api_key(created.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repository
+                .delete_api_key(created.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(repository.list_api_keys().await.unwrap().len(), 1);
+
+        // Expired keys are refused like inactive ones
+        let request: CreateApiKeyRequest = serde_json::from_value(
+            serde_json::json!({"name": "Old", "expires_at": "2020-01-01T00:00:00Z"}),
+        )
+        .unwrap();
+        let _ = repository
+            .create_api_key(request, &hash_api_key("expired"), "expired")
+            .await
+            .unwrap();
+        assert!(
+            repository
+                .validate_token(&hash_api_key("expired"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_events() {
+        let repository = InMemorySpeedRepository::new();
+        let at = Utc.with_ymd_and_hms(2024, 3, 13, 10, 0, 0).unwrap();
+        let event = |action, minutes| {
+            let mut event = AuditEvent::new(action);
+            event.occurred_at = at + Duration::minutes(minutes);
+            event
+        };
+        let mut failure = event(AuditAction::AuthFailure, 1).with_detail("Invalid token");
+        failure.key_prefix = Some("deadbeef".to_string());
+        let mut rotation = event(AuditAction::KeyRotated, 2).on_key(7);
+        rotation.key_id = Some(1);
+        repository
+            .insert_audit_events(vec![event(AuditAction::DataPurged, 0), failure, rotation])
+            .await
+            .unwrap();
+
+        // Most recent first, within the range
+        let events = repository
+            .fetch_audit_events(at, at + Duration::minutes(1), None, None, 10)
+            .await
+            .unwrap();
+        let ids: Vec<_> = events.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(events[0].key_prefix.as_deref(), Some("deadbeef"));
+
+        let end = at + Duration::hours(1);
+        let events = repository
+            .fetch_audit_events(at, end, Some(AuditAction::KeyRotated), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        // Events of requests made with the key and of actions on it
+        for (key_id, expected) in [(1, 1), (7, 1), (8, 0)] {
+            let events = repository
+                .fetch_audit_events(at, end, None, Some(key_id), 10)
+                .await
+                .unwrap();
+            assert_eq!(events.len(), expected, "key {key_id}");
+        }
+
+        let events = repository
+            .fetch_audit_events(at, end, None, None, 1)
+            .await
+            .unwrap();
+        assert_eq!(events[0].action, AuditAction::KeyRotated);
+    }
+}
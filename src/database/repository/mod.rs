@@ -0,0 +1,187 @@
+pub mod aggregate;
+pub mod memory;
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::types::DbError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+/// Storage of the speed readings and API keys used by the request handlers
+///
+/// Postgres is the production backend, SQLite (`sqlite` feature) serves single-box edge
+/// deployments, and the in-memory one integration tests and the demo binary. Analytics relying
+/// on Postgres (rollups, flow metrics, retention) are reached through `AppState::db`.
+#[async_trait]
+pub trait SpeedRepository: Send + Sync {
+    /// Checks that the storage is reachable
+    async fn ping(&self) -> Result<(), DbError>;
+
+    /// Stores a reading and returns it with its id and timestamp
+    ///
+    /// `quality_flag` is the result of the fault detectors run on the reading, if any.
+    async fn insert_speed_data(
+        &self,
+        payload: CreateSpeedDataRequest,
+        quality_flag: Option<QualityFlag>,
+    ) -> Result<SpeedData, DbError>;
+
+    /// Returns the last `number` readings, most recent first
+    async fn fetch_last_n_speed_data(
+        &self,
+        number: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError>;
+
+    /// Returns `limit` readings after the first `offset` ones, oldest first
+    async fn fetch_speed_data_with_pagination(
+        &self,
+        offset: u32,
+        limit: u32,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError>;
+
+    /// Returns at most `limit` readings of `[day_start, day_end)`
+    async fn fetch_speed_data_today(
+        &self,
+        day_start: DateTime<Utc>,
+        day_end: DateTime<Utc>,
+        limit: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError>;
+
+    /// Returns the most recent reading, if any
+    async fn fetch_last_speed(
+        &self,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Option<SpeedData>, DbError>;
+
+    /// Returns the readings of `[start_date, end_date]`, oldest first
+    async fn fetch_speed_data_by_date_range(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError>;
+
+    /// Returns the statistics of the readings of `[start_date, end_date]` per time bucket and group
+    ///
+    /// Buckets are computed in the local time of `tz`. Flagged readings are left out unless
+    /// `include_flagged` is set.
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_speed_aggregates(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket: TimeBucket,
+        group_by: GroupBy,
+        vehicle_class: Option<VehicleClass>,
+        include_flagged: bool,
+        tz: Tz,
+    ) -> Result<Vec<SpeedAggregate>, DbError>;
+
+    /// Stores readings forwarded by the edge server `origin` and returns how many were new
+    ///
+    /// Readings keep their sensor, timestamp and quality flag; their id is the one on the edge
+    /// server, and a reading already received from `origin` is ignored.
+    async fn insert_forwarded_speed_data(
+        &self,
+        origin: &str,
+        readings: Vec<SpeedData>,
+    ) -> Result<u64, DbError>;
+
+    /// Returns up to `limit` local readings with an id above `after_id`, by increasing id
+    ///
+    /// Used by edge servers to forward their readings; forwarded ones are left out. Backends
+    /// whose inserts can commit out of id order only return readings stored for `settle_secs`.
+    async fn fetch_unforwarded_speed_data(
+        &self,
+        after_id: i64,
+        limit: u32,
+        settle_secs: f64,
+    ) -> Result<Vec<SpeedData>, DbError>;
+
+    /// Returns the last local speed id acknowledged by the upstream server, 0 if none
+    async fn fetch_forward_watermark(&self) -> Result<i64, DbError>;
+
+    /// Records the last local speed id acknowledged by the upstream server
+    async fn store_forward_watermark(&self, last_id: i64) -> Result<(), DbError>;
+
+    /// Keeps a copy of local readings the upstream server refused, along with the reason
+    ///
+    /// A reading already quarantined is left as is.
+    async fn quarantine_speed_data(
+        &self,
+        readings: &[SpeedData],
+        reason: &str,
+    ) -> Result<(), DbError>;
+
+    /// Returns the active API key with this hash, from `hash_api_key`
+    async fn validate_token(&self, key_hash: &str) -> Result<Option<AuthenticatedKey>, DbError>;
+
+    /// Returns the active API key with this id along with its hash, for signed requests
+    async fn validate_key_id(&self, id: i32)
+    -> Result<Option<(AuthenticatedKey, String)>, DbError>;
+
+    /// Replaces the API keys still stored in plaintext by their hash, returns how many were
+    async fn hash_plaintext_api_keys(&self) -> Result<u64, DbError>;
+
+    /// Returns the number of API keys still stored in plaintext
+    async fn count_plaintext_api_keys(&self) -> Result<u64, DbError>;
+
+    /// Stores a new API key, given the hash and prefix of its generated secret
+    async fn create_api_key(
+        &self,
+        request: CreateApiKeyRequest,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<ApiKeyInfo, DbError>;
+
+    /// Lists every API key, oldest first
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, DbError>;
+
+    /// Replaces the secret of an API key, returns the hash of the previous one
+    ///
+    /// Like the other key updates, returns `None` if the key doesn't exist.
+    async fn rotate_api_key(
+        &self,
+        id: i32,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<Option<String>, DbError>;
+
+    /// Deactivates an API key, returns its hash
+    async fn deactivate_api_key(&self, id: i32) -> Result<Option<String>, DbError>;
+
+    /// Deletes an API key, returns its hash
+    async fn delete_api_key(&self, id: i32) -> Result<Option<String>, DbError>;
+
+    /// Stores audit events, keeping the time they occurred at and ignoring their id
+    async fn insert_audit_events(&self, events: Vec<AuditEvent>) -> Result<(), DbError>;
+
+    /// Returns at most `limit` audit events of `[start_date, end_date]`, most recent first
+    ///
+    /// `key_id` keeps the events of requests made with the key and of admin actions on it.
+    async fn fetch_audit_events(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        action: Option<AuditAction>,
+        key_id: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, DbError>;
+}
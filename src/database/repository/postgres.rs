@@ -0,0 +1,222 @@
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::audit;
+use crate::database::auth;
+use crate::database::crud::*;
+use crate::database::forward;
+use crate::database::pool::DbPool;
+use crate::database::repository::SpeedRepository;
+use crate::database::types::DbError;
+use crate::database::util::{SIMPLE_SELECT_TIMEOUT, with_timeout};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+/// Repository backed by the Postgres connection pool
+#[derive(Clone)]
+pub struct PostgresSpeedRepository {
+    pool: DbPool,
+}
+
+impl PostgresSpeedRepository {
+    /// Creates a new instance of `PostgresSpeedRepository` using the provided pool.
+    #[inline]
+    #[must_use]
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SpeedRepository for PostgresSpeedRepository {
+    async fn ping(&self) -> Result<(), DbError> {
+        let conn = self.pool.get().await?;
+        with_timeout(
+            async { conn.query("SELECT 1", &[]).await.map_err(DbError::from) },
+            SIMPLE_SELECT_TIMEOUT,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_speed_data(
+        &self,
+        payload: CreateSpeedDataRequest,
+        quality_flag: Option<QualityFlag>,
+    ) -> Result<SpeedData, DbError> {
+        insert_speed_data(&self.pool, payload, quality_flag).await
+    }
+
+    async fn fetch_last_n_speed_data(
+        &self,
+        number: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        fetch_last_n_speed_data(&self.pool, number, vehicle_class).await
+    }
+
+    async fn fetch_speed_data_with_pagination(
+        &self,
+        offset: u32,
+        limit: u32,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        fetch_speed_data_with_pagination(&self.pool, offset, limit, vehicle_class).await
+    }
+
+    async fn fetch_speed_data_today(
+        &self,
+        day_start: DateTime<Utc>,
+        day_end: DateTime<Utc>,
+        limit: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        fetch_speed_data_today(&self.pool, day_start, day_end, limit, vehicle_class).await
+    }
+
+    async fn fetch_last_speed(
+        &self,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Option<SpeedData>, DbError> {
+        fetch_last_speed(&self.pool, vehicle_class).await
+    }
+
+    async fn fetch_speed_data_by_date_range(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        fetch_speed_data_by_date_range(&self.pool, start_date, end_date, vehicle_class).await
+    }
+
+    async fn fetch_speed_aggregates(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket: TimeBucket,
+        group_by: GroupBy,
+        vehicle_class: Option<VehicleClass>,
+        include_flagged: bool,
+        tz: Tz,
+    ) -> Result<Vec<SpeedAggregate>, DbError> {
+        fetch_speed_aggregates(
+            &self.pool,
+            start_date,
+            end_date,
+            bucket,
+            group_by,
+            vehicle_class,
+            include_flagged,
+            tz,
+        )
+        .await
+    }
+
+    async fn insert_forwarded_speed_data(
+        &self,
+        origin: &str,
+        readings: Vec<SpeedData>,
+    ) -> Result<u64, DbError> {
+        forward::insert_forwarded_speed_data(&self.pool, origin, &readings).await
+    }
+
+    async fn fetch_unforwarded_speed_data(
+        &self,
+        after_id: i64,
+        limit: u32,
+        settle_secs: f64,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        forward::fetch_unforwarded_speed_data(&self.pool, after_id, limit, settle_secs).await
+    }
+
+    async fn fetch_forward_watermark(&self) -> Result<i64, DbError> {
+        forward::fetch_forward_watermark(&self.pool).await
+    }
+
+    async fn store_forward_watermark(&self, last_id: i64) -> Result<(), DbError> {
+        forward::store_forward_watermark(&self.pool, last_id).await
+    }
+
+    async fn quarantine_speed_data(
+        &self,
+        readings: &[SpeedData],
+        reason: &str,
+    ) -> Result<(), DbError> {
+        forward::quarantine_speed_data(&self.pool, readings, reason).await
+    }
+
+    async fn validate_token(&self, key_hash: &str) -> Result<Option<AuthenticatedKey>, DbError> {
+        auth::validate_token(&self.pool, key_hash).await
+    }
+
+    async fn validate_key_id(
+        &self,
+        id: i32,
+    ) -> Result<Option<(AuthenticatedKey, String)>, DbError> {
+        auth::validate_key_id(&self.pool, id).await
+    }
+
+    async fn hash_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        auth::hash_plaintext_api_keys(&self.pool).await
+    }
+
+    async fn count_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        auth::count_plaintext_api_keys(&self.pool).await
+    }
+
+    async fn create_api_key(
+        &self,
+        request: CreateApiKeyRequest,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<ApiKeyInfo, DbError> {
+        auth::create_api_key(&self.pool, &request, key_hash, key_prefix).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, DbError> {
+        auth::fetch_api_keys(&self.pool).await
+    }
+
+    async fn rotate_api_key(
+        &self,
+        id: i32,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<Option<String>, DbError> {
+        auth::rotate_api_key(&self.pool, id, key_hash, key_prefix).await
+    }
+
+    async fn deactivate_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        auth::deactivate_api_key(&self.pool, id).await
+    }
+
+    async fn delete_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        auth::delete_api_key(&self.pool, id).await
+    }
+
+    async fn insert_audit_events(&self, events: Vec<AuditEvent>) -> Result<(), DbError> {
+        audit::insert_audit_events(&self.pool, &events).await
+    }
+
+    async fn fetch_audit_events(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        action: Option<AuditAction>,
+        key_id: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, DbError> {
+        audit::fetch_audit_events(&self.pool, start_date, end_date, action, key_id, limit).await
+    }
+}
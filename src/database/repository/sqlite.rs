@@ -0,0 +1,1301 @@
+use crate::api::payload::create_api_key_request::CreateApiKeyRequest;
+use crate::api::payload::create_speed_request::CreateSpeedDataRequest;
+use crate::api::query::aggregate_query::GroupBy;
+use crate::core::api_key::{AuthenticatedKey, hash_api_key};
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::api_key_info::ApiKeyInfo;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::dto::speed_data::SpeedData;
+use crate::core::lane::Lane;
+use crate::core::quality_flag::QualityFlag;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::migration::{Migration, MigrationError};
+use crate::database::repository::SpeedRepository;
+use crate::database::types::{DbError, FromSqliteRow};
+use crate::{log_error, log_info};
+use async_trait::async_trait;
+use chrono::{DateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use rusqlite::{Connection, OptionalExtension, Params, Row, params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// SQLite migrations known to this build, in order of version
+///
+/// Each one is the script of `sql/sqlite` with the same numeric prefix. The SQLite schema is
+/// versioned separately from the Postgres one since it has no rollups nor partitions.
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 0,
+        name: "baseline",
+        sql: include_str!("../../../sql/sqlite/0000_baseline.sql"),
+    },
+    Migration {
+        version: 1,
+        name: "forwarding",
+        sql: include_str!("../../../sql/sqlite/0001_forwarding.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "api_key_hash",
+        sql: include_str!("../../../sql/sqlite/0002_api_key_hash.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "api_key_management",
+        sql: include_str!("../../../sql/sqlite/0003_api_key_management.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "api_key_sensor",
+        sql: include_str!("../../../sql/sqlite/0004_api_key_sensor.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "api_key_expiry",
+        sql: include_str!("../../../sql/sqlite/0005_api_key_expiry.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "audit_log",
+        sql: include_str!("../../../sql/sqlite/0006_audit_log.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "forward_quarantine",
+        sql: include_str!("../../../sql/sqlite/0007_forward_quarantine.sql"),
+    },
+];
+
+/// Maximum time to wait for a lock held by another connection to the same file
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Repository backed by a SQLite file, for single-box edge deployments
+///
+/// SQLite calls are blocking, so they run on the blocking thread pool, one at a time.
+/// Timestamps are stored as microseconds since the Unix epoch.
+#[derive(Clone)]
+pub struct SqliteSpeedRepository {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteSpeedRepository {
+    /// Opens or creates the database at `path`, and applies the pending migrations
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MigrationError> {
+        Self::init(Connection::open(path).map_err(DbError::from)?)
+    }
+
+    /// Opens a private in-memory database, e.g. for tests
+    pub fn open_in_memory() -> Result<Self, MigrationError> {
+        Self::init(Connection::open_in_memory().map_err(DbError::from)?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, MigrationError> {
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(DbError::from)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .map_err(DbError::from)?;
+        conn.pragma_update(None, "synchronous", "NORMAL")
+            .map_err(DbError::from)?;
+
+        run_sqlite_migrations(&mut conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool
+    async fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, DbError> + Send + 'static,
+    ) -> Result<T, DbError> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&conn)
+        })
+        .await
+        .map_err(|e| DbError::PoolError(format!("SQLite task failed: {e}")))?
+        .map_err(|e| {
+            log_error!("SQLite query failed: {e}");
+            e
+        })
+    }
+}
+
+/// Applies the SQLite migrations not applied yet, and returns their versions
+///
+/// `PRAGMA user_version` holds the number of applied migrations.
+fn run_sqlite_migrations(conn: &mut Connection) -> Result<Vec<i64>, MigrationError> {
+    let applied: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(DbError::from)?;
+    let known = SQLITE_MIGRATIONS.len() as i64;
+    if applied > known {
+        return Err(MigrationError::UnknownVersion {
+            applied: applied - 1,
+            latest: known - 1,
+        });
+    }
+
+    let mut versions = Vec::new();
+    for migration in &SQLITE_MIGRATIONS[applied as usize..] {
+        let tx = conn.transaction().map_err(DbError::from)?;
+        tx.execute_batch(migration.sql).map_err(DbError::from)?;
+        tx.pragma_update(None, "user_version", migration.version + 1)
+            .map_err(DbError::from)?;
+        tx.commit().map_err(DbError::from)?;
+
+        log_info!(
+            "Applied SQLite migration {:04}_{}",
+            migration.version,
+            migration.name
+        );
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+/// Converts a stored timestamp back to a date
+fn from_micros(micros: i64) -> Result<DateTime<Utc>, DbError> {
+    DateTime::from_timestamp_micros(micros)
+        .ok_or_else(|| DbError::RowParsing(format!("Invalid timestamp value: {micros}")))
+}
+
+impl FromSqliteRow for SpeedData {
+    fn from_sqlite_row(row: &Row<'_>) -> Result<Self, DbError> {
+        let lane = Lane::try_from(row.get::<_, i32>("lane")?)
+            .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?;
+        let vehicle_class = row
+            .get::<_, Option<String>>("vehicle_class")?
+            .as_deref()
+            .map(VehicleClass::try_from)
+            .transpose()
+            .map_err(|e| DbError::RowParsing(format!("Invalid vehicle class value: {}", e)))?;
+
+        let mut data = SpeedData::new(
+            row.get("id")?,
+            row.get("sensor_name")?,
+            row.get("speed")?,
+            lane,
+            from_micros(row.get("created_at")?)?,
+        )
+        .with_vehicle(vehicle_class, row.get("vehicle_length")?);
+        data.quality_flag = row
+            .get::<_, Option<String>>("quality_flag")?
+            .as_deref()
+            .map(QualityFlag::try_from)
+            .transpose()
+            .map_err(|e| DbError::RowParsing(format!("Invalid quality flag value: {}", e)))?;
+        Ok(data)
+    }
+}
+
+impl FromSqliteRow for SpeedAggregate {
+    fn from_sqlite_row(row: &Row<'_>) -> Result<Self, DbError> {
+        let lane = row
+            .get::<_, Option<i32>>("lane")?
+            .map(Lane::try_from)
+            .transpose()
+            .map_err(|e| DbError::RowParsing(format!("Invalid lane value: {}", e)))?;
+        let vehicle_class = row
+            .get::<_, Option<String>>("vehicle_class")?
+            .as_deref()
+            .map(VehicleClass::try_from)
+            .transpose()
+            .map_err(|e| DbError::RowParsing(format!("Invalid vehicle class value: {}", e)))?;
+
+        Ok(SpeedAggregate {
+            bucket_start: from_micros(row.get("bucket_start")?)?,
+            lane,
+            sensor_name: row.get("sensor_name")?,
+            vehicle_class,
+            count: row.get("count")?,
+            avg_speed: row.get("avg_speed")?,
+            min_speed: row.get::<_, f64>("min_speed")? as f32,
+            max_speed: row.get::<_, f64>("max_speed")? as f32,
+        })
+    }
+}
+
+impl FromSqliteRow for ApiKeyInfo {
+    fn from_sqlite_row(row: &Row<'_>) -> Result<Self, DbError> {
+        Ok(ApiKeyInfo {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            owner: row.get("owner")?,
+            scopes: parse_scopes(&row.get::<_, String>("scopes")?)?,
+            sensor_name: row.get("sensor_name")?,
+            expires_at: row
+                .get::<_, Option<i64>>("expires_at")?
+                .map(from_micros)
+                .transpose()?,
+            key_prefix: row.get("key_prefix")?,
+            is_active: row.get("is_active")?,
+            created_at: from_micros(row.get("created_at")?)?,
+        })
+    }
+}
+
+impl FromSqliteRow for AuditEvent {
+    fn from_sqlite_row(row: &Row<'_>) -> Result<Self, DbError> {
+        let action = AuditAction::try_from(row.get::<_, String>("action")?.as_str())
+            .map_err(|e| DbError::RowParsing(format!("Invalid action value: {}", e)))?;
+
+        let mut event = AuditEvent::new(action);
+        event.id = row.get("id")?;
+        event.occurred_at = from_micros(row.get("occurred_at")?)?;
+        event.key_id = row.get("key_id")?;
+        event.subject = row.get("subject")?;
+        event.key_prefix = row.get("key_prefix")?;
+        event.target_key_id = row.get("target_key_id")?;
+        event.ip = row.get("ip")?;
+        event.method = row.get("method")?;
+        event.route = row.get("route")?;
+        event.detail = row.get("detail")?;
+        Ok(event)
+    }
+}
+
+/// Columns of `api_keys` read into an `ApiKeyInfo`
+const API_KEY_COLUMNS: &str =
+    "id, name, owner, scopes, sensor_name, expires_at, key_prefix, is_active, created_at";
+
+/// Parses the space separated scopes of an API key
+fn parse_scopes(scopes: &str) -> Result<Vec<ApiKeyScope>, DbError> {
+    ApiKeyScope::parse_all(scopes.split_whitespace())
+        .map_err(|e| DbError::RowParsing(format!("Invalid scopes value: {}", e)))
+}
+
+/// Runs a query returning readings
+/// Returns an SQL expression giving the UTC offset of `tz` in seconds at `created_at`
+///
+/// The offset is looked up on every day of the range, and each change located to the second,
+/// so the expression only lists the transitions between `start` and `end`.
+fn utc_offset_expr(tz: Tz, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    const DAY: i64 = 86_400;
+    let offset = |secs: i64| {
+        let at = DateTime::from_timestamp(secs, 0).unwrap_or_default();
+        tz.offset_from_utc_datetime(&at.naive_utc())
+            .fix()
+            .local_minus_utc()
+    };
+
+    let (start, end) = (start.timestamp(), end.timestamp());
+    let mut current = offset(start);
+    let mut whens = String::new();
+    let mut before = start;
+    while before < end {
+        let after = (before + DAY).min(end);
+        if offset(after) != current {
+            // The offset changes in (low, high]
+            let (mut low, mut high) = (before, after);
+            while high - low > 1 {
+                let middle = low + (high - low) / 2;
+                if offset(middle) == current {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            whens.push_str(&format!(
+                " WHEN created_at < {} THEN {current}",
+                high * 1_000_000
+            ));
+            current = offset(high);
+        }
+        before = after;
+    }
+    if whens.is_empty() {
+        current.to_string()
+    } else {
+        format!("CASE{whens} ELSE {current} END")
+    }
+}
+
+fn query_speed_data(
+    conn: &Connection,
+    query: &str,
+    params: impl Params,
+) -> Result<Vec<SpeedData>, DbError> {
+    let mut stmt = conn.prepare_cached(query)?;
+    let mut rows = stmt.query(params)?;
+    let mut data = Vec::new();
+    while let Some(row) = rows.next()? {
+        data.push(SpeedData::from_sqlite_row(row)?);
+    }
+    Ok(data)
+}
+
+#[async_trait]
+impl SpeedRepository for SqliteSpeedRepository {
+    async fn ping(&self) -> Result<(), DbError> {
+        self.call(|conn| {
+            conn.query_row("SELECT 1", [], |_| Ok(()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_speed_data(
+        &self,
+        payload: CreateSpeedDataRequest,
+        quality_flag: Option<QualityFlag>,
+    ) -> Result<SpeedData, DbError> {
+        const QUERY: &str = "INSERT INTO speed (sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag) VALUES (NULLIF(?1, ''), ?2, ?3, ?4, ?5, ?6, ?7) RETURNING id, sensor_name, speed, lane, created_at, vehicle_class, vehicle_length, quality_flag";
+
+        self.call(move |conn| {
+            let mut data = query_speed_data(
+                conn,
+                QUERY,
+                params![
+                    payload.sensor_name.unwrap_or_default(),
+                    payload.speed,
+                    i32::from(payload.lane),
+                    Utc::now().timestamp_micros(),
+                    payload.vehicle_class.map(|c| c.as_str()),
+                    payload.vehicle_length,
+                    quality_flag.map(|f| f.as_str()),
+                ],
+            )?;
+            data.pop()
+                .ok_or_else(|| DbError::RowParsing("Insert returned no row".to_string()))
+        })
+        .await
+    }
+
+    async fn fetch_last_n_speed_data(
+        &self,
+        number: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE (?2 IS NULL OR vehicle_class = ?2) ORDER BY created_at DESC, id DESC LIMIT ?1";
+
+        self.call(move |conn| {
+            query_speed_data(
+                conn,
+                QUERY,
+                params![number, vehicle_class.map(|c| c.as_str())],
+            )
+        })
+        .await
+    }
+
+    async fn fetch_speed_data_with_pagination(
+        &self,
+        offset: u32,
+        limit: u32,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE (?3 IS NULL OR vehicle_class = ?3) ORDER BY created_at, id LIMIT ?2 OFFSET ?1";
+
+        self.call(move |conn| {
+            query_speed_data(
+                conn,
+                QUERY,
+                params![offset, limit, vehicle_class.map(|c| c.as_str())],
+            )
+        })
+        .await
+    }
+
+    async fn fetch_speed_data_today(
+        &self,
+        day_start: DateTime<Utc>,
+        day_end: DateTime<Utc>,
+        limit: u16,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE created_at >= ?3 AND created_at < ?4 AND (?2 IS NULL OR vehicle_class = ?2) ORDER BY created_at, id LIMIT ?1";
+
+        self.call(move |conn| {
+            query_speed_data(
+                conn,
+                QUERY,
+                params![
+                    limit,
+                    vehicle_class.map(|c| c.as_str()),
+                    day_start.timestamp_micros(),
+                    day_end.timestamp_micros(),
+                ],
+            )
+        })
+        .await
+    }
+
+    async fn fetch_last_speed(
+        &self,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Option<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE (?1 IS NULL OR vehicle_class = ?1) ORDER BY created_at DESC, id DESC LIMIT 1";
+
+        self.call(move |conn| {
+            Ok(query_speed_data(conn, QUERY, params![vehicle_class.map(|c| c.as_str())])?.pop())
+        })
+        .await
+    }
+
+    async fn fetch_speed_data_by_date_range(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        vehicle_class: Option<VehicleClass>,
+    ) -> Result<Vec<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE created_at >= ?1 AND created_at <= ?2 AND (?3 IS NULL OR vehicle_class = ?3) ORDER BY created_at ASC";
+
+        self.call(move |conn| {
+            query_speed_data(
+                conn,
+                QUERY,
+                params![
+                    start_date.timestamp_micros(),
+                    end_date.timestamp_micros(),
+                    vehicle_class.map(|c| c.as_str()),
+                ],
+            )
+        })
+        .await
+    }
+
+    /// SQLite has no time zone support, so readings are grouped on their local time computed
+    /// with the UTC offsets in force over the range, and buckets start at the truncated
+    /// earliest reading of each group
+    async fn fetch_speed_aggregates(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket: TimeBucket,
+        group_by: GroupBy,
+        vehicle_class: Option<VehicleClass>,
+        include_flagged: bool,
+        tz: Tz,
+    ) -> Result<Vec<SpeedAggregate>, DbError> {
+        let local = format!(
+            "(created_at / 1000000 + {})",
+            utc_offset_expr(tz, start_date, end_date)
+        );
+        let bucket_key = match bucket {
+            TimeBucket::Minute => format!("{local} / 60"),
+            TimeBucket::Hour => format!("{local} / 3600"),
+            TimeBucket::Day => format!("{local} / 86400"),
+            // 1970-01-01 is a Thursday, weeks start on Monday
+            TimeBucket::Week => format!("({local} / 86400 + 3) / 7"),
+            TimeBucket::Month => format!("strftime('%Y-%m', {local}, 'unixepoch')"),
+        };
+        let query = format!(
+            "SELECT {bucket_key} AS bucket, min(created_at) AS bucket_start, \
+                    CASE WHEN ?5 THEN lane END AS lane, CASE WHEN ?6 THEN sensor_name END AS sensor_name, \
+                    CASE WHEN ?7 THEN vehicle_class END AS vehicle_class, count(*) AS count, \
+                    avg(speed) AS avg_speed, min(speed) AS min_speed, max(speed) AS max_speed \
+             FROM speed WHERE created_at >= ?1 AND created_at <= ?2 AND (?3 IS NULL OR vehicle_class = ?3) AND (?4 OR quality_flag IS NULL) \
+             GROUP BY 1, 3, 4, 5 ORDER BY 1, 3, 4, 5"
+        );
+
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(params![
+                start_date.timestamp_micros(),
+                end_date.timestamp_micros(),
+                vehicle_class.map(|c| c.as_str()),
+                include_flagged,
+                group_by.lane,
+                group_by.sensor,
+                group_by.class,
+            ])?;
+            let mut aggregates = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut aggregate = SpeedAggregate::from_sqlite_row(row)?;
+                aggregate.bucket_start = bucket.truncate(aggregate.bucket_start, tz);
+                aggregates.push(aggregate);
+            }
+            Ok(aggregates)
+        })
+        .await
+    }
+
+    async fn insert_forwarded_speed_data(
+        &self,
+        origin: &str,
+        readings: Vec<SpeedData>,
+    ) -> Result<u64, DbError> {
+        const QUERY: &str = "INSERT INTO speed (origin,origin_id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9) ON CONFLICT (origin, origin_id, created_at) DO NOTHING";
+
+        let origin = origin.to_string();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let mut inserted = 0;
+            {
+                let mut stmt = tx.prepare_cached(QUERY)?;
+                for r in &readings {
+                    inserted += stmt.execute(params![
+                        origin,
+                        r.id,
+                        r.sensor_name,
+                        r.speed,
+                        r.lane as i32,
+                        r.created_at.timestamp_micros(),
+                        r.vehicle_class.map(|c| c.as_str()),
+                        r.vehicle_length,
+                        r.quality_flag.map(|f| f.as_str()),
+                    ])? as u64;
+                }
+            }
+            tx.commit()?;
+            Ok(inserted)
+        })
+        .await
+    }
+
+    async fn fetch_unforwarded_speed_data(
+        &self,
+        after_id: i64,
+        limit: u32,
+        _settle_secs: f64, // Inserts are serialized, ids are committed in order
+    ) -> Result<Vec<SpeedData>, DbError> {
+        const QUERY: &str = "SELECT id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag FROM speed WHERE id > ?1 AND origin IS NULL ORDER BY id LIMIT ?2";
+
+        self.call(move |conn| query_speed_data(conn, QUERY, params![after_id, limit]))
+            .await
+    }
+
+    async fn fetch_forward_watermark(&self) -> Result<i64, DbError> {
+        const QUERY: &str = "SELECT last_id FROM forward_watermark WHERE name = 'upstream'";
+
+        self.call(|conn| {
+            let last_id = conn.query_row(QUERY, [], |row| row.get(0)).optional()?;
+            Ok(last_id.unwrap_or(0))
+        })
+        .await
+    }
+
+    async fn store_forward_watermark(&self, last_id: i64) -> Result<(), DbError> {
+        const QUERY: &str = "INSERT INTO forward_watermark (name, last_id, updated_at) VALUES ('upstream', ?1, ?2) ON CONFLICT (name) DO UPDATE SET last_id = excluded.last_id, updated_at = excluded.updated_at";
+
+        self.call(move |conn| {
+            conn.execute(QUERY, params![last_id, Utc::now().timestamp_micros()])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn quarantine_speed_data(
+        &self,
+        readings: &[SpeedData],
+        reason: &str,
+    ) -> Result<(), DbError> {
+        const QUERY: &str = "INSERT INTO forward_quarantine (speed_id,sensor_name,speed,lane,created_at,vehicle_class,vehicle_length,quality_flag,reason,quarantined_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) ON CONFLICT (speed_id) DO NOTHING";
+
+        let readings = readings.to_vec();
+        let reason = reason.to_string();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(QUERY)?;
+                let now = Utc::now().timestamp_micros();
+                for r in &readings {
+                    stmt.execute(params![
+                        r.id,
+                        r.sensor_name,
+                        r.speed,
+                        r.lane as i32,
+                        r.created_at.timestamp_micros(),
+                        r.vehicle_class.map(|c| c.as_str()),
+                        r.vehicle_length,
+                        r.quality_flag.map(|f| f.as_str()),
+                        reason,
+                        now,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn validate_token(&self, key_hash: &str) -> Result<Option<AuthenticatedKey>, DbError> {
+        let key_hash = key_hash.to_string();
+        self.call(move |conn| {
+            let query = format!(
+                "SELECT {API_KEY_COLUMNS} FROM api_keys WHERE key_hash = ?1 AND is_active AND (expires_at IS NULL OR expires_at > ?2)"
+            );
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(params![key_hash, Utc::now().timestamp_micros()])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(AuthenticatedKey::from_info(
+                    &ApiKeyInfo::from_sqlite_row(row)?,
+                ))),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn validate_key_id(
+        &self,
+        id: i32,
+    ) -> Result<Option<(AuthenticatedKey, String)>, DbError> {
+        self.call(move |conn| {
+            let query = format!(
+                "SELECT {API_KEY_COLUMNS}, key_hash FROM api_keys WHERE id = ?1 AND key_hash IS NOT NULL AND is_active AND (expires_at IS NULL OR expires_at > ?2)"
+            );
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(params![id, Utc::now().timestamp_micros()])?;
+            match rows.next()? {
+                Some(row) => {
+                    let key_hash: String = row.get("key_hash")?;
+                    let api_key = AuthenticatedKey::from_info(&ApiKeyInfo::from_sqlite_row(row)?);
+                    Ok(Some((api_key, key_hash)))
+                }
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn hash_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        const SELECT: &str = "SELECT id, api_key FROM api_keys WHERE api_key IS NOT NULL";
+        const UPDATE: &str = "UPDATE api_keys SET key_hash = ?2, api_key = NULL WHERE id = ?1";
+
+        self.call(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            let keys = tx
+                .prepare(SELECT)?
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (id, token) in &keys {
+                tx.execute(UPDATE, params![id, hash_api_key(token)])?;
+            }
+            tx.commit()?;
+            Ok(keys.len() as u64)
+        })
+        .await
+    }
+
+    async fn count_plaintext_api_keys(&self) -> Result<u64, DbError> {
+        const QUERY: &str = "SELECT count(*) FROM api_keys WHERE api_key IS NOT NULL";
+
+        self.call(|conn| Ok(conn.query_row(QUERY, [], |row| row.get(0))?))
+            .await
+    }
+
+    async fn create_api_key(
+        &self,
+        request: CreateApiKeyRequest,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<ApiKeyInfo, DbError> {
+        const INSERT: &str = "INSERT INTO api_keys (key_hash, key_prefix, name, owner, scopes, sensor_name, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+        let key_hash = key_hash.to_string();
+        let key_prefix = key_prefix.to_string();
+        let scopes: Vec<&str> = request.scopes.iter().map(ApiKeyScope::as_str).collect();
+        let scopes = scopes.join(" ");
+        self.call(move |conn| {
+            conn.execute(
+                INSERT,
+                params![
+                    key_hash,
+                    key_prefix,
+                    request.name,
+                    request.owner,
+                    scopes,
+                    request.sensor_name,
+                    request.expires_at.map(|at| at.timestamp_micros())
+                ],
+            )?;
+            let query = format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE id = ?1");
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query([conn.last_insert_rowid()])?;
+            match rows.next()? {
+                Some(row) => ApiKeyInfo::from_sqlite_row(row),
+                None => Err(DbError::RowParsing(
+                    "Inserted API key not found".to_string(),
+                )),
+            }
+        })
+        .await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyInfo>, DbError> {
+        self.call(|conn| {
+            let query = format!("SELECT {API_KEY_COLUMNS} FROM api_keys ORDER BY id");
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query([])?;
+            let mut keys = Vec::new();
+            while let Some(row) = rows.next()? {
+                keys.push(ApiKeyInfo::from_sqlite_row(row)?);
+            }
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn rotate_api_key(
+        &self,
+        id: i32,
+        key_hash: &str,
+        key_prefix: &str,
+    ) -> Result<Option<String>, DbError> {
+        const UPDATE: &str = "UPDATE api_keys SET key_hash = ?2, key_prefix = ?3 WHERE id = ?1";
+
+        let key_hash = key_hash.to_string();
+        let key_prefix = key_prefix.to_string();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let previous = select_key_hash(&tx, id)?;
+            if previous.is_some() {
+                tx.execute(UPDATE, params![id, key_hash, key_prefix])?;
+            }
+            tx.commit()?;
+            Ok(previous)
+        })
+        .await
+    }
+
+    async fn deactivate_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        const UPDATE: &str = "UPDATE api_keys SET is_active = 0 WHERE id = ?1";
+
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let key_hash = select_key_hash(&tx, id)?;
+            tx.execute(UPDATE, [id])?;
+            tx.commit()?;
+            Ok(key_hash)
+        })
+        .await
+    }
+
+    async fn delete_api_key(&self, id: i32) -> Result<Option<String>, DbError> {
+        const DELETE: &str = "DELETE FROM api_keys WHERE id = ?1";
+
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let key_hash = select_key_hash(&tx, id)?;
+            tx.execute(DELETE, [id])?;
+            tx.commit()?;
+            Ok(key_hash)
+        })
+        .await
+    }
+
+    async fn insert_audit_events(&self, events: Vec<AuditEvent>) -> Result<(), DbError> {
+        const QUERY: &str = "INSERT INTO audit_log (occurred_at,action,key_id,subject,key_prefix,target_key_id,ip,method,route,detail) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(QUERY)?;
+                for e in &events {
+                    stmt.execute(params![
+                        e.occurred_at.timestamp_micros(),
+                        e.action.as_str(),
+                        e.key_id,
+                        e.subject,
+                        e.key_prefix,
+                        e.target_key_id,
+                        e.ip,
+                        e.method,
+                        e.route,
+                        e.detail,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn fetch_audit_events(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        action: Option<AuditAction>,
+        key_id: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, DbError> {
+        const QUERY: &str = "SELECT id,occurred_at,action,key_id,subject,key_prefix,target_key_id,ip,method,route,detail FROM audit_log WHERE occurred_at >= ?1 AND occurred_at <= ?2 AND (?3 IS NULL OR action = ?3) AND (?4 IS NULL OR key_id = ?4 OR target_key_id = ?4) ORDER BY occurred_at DESC, id DESC LIMIT ?5";
+
+        self.call(move |conn| {
+            let mut stmt = conn.prepare_cached(QUERY)?;
+            let mut rows = stmt.query(params![
+                start_date.timestamp_micros(),
+                end_date.timestamp_micros(),
+                action.map(|a| a.as_str()),
+                key_id,
+                limit,
+            ])?;
+            let mut events = Vec::new();
+            while let Some(row) = rows.next()? {
+                events.push(AuditEvent::from_sqlite_row(row)?);
+            }
+            Ok(events)
+        })
+        .await
+    }
+}
+
+/// Returns the hash of the API key `id`, empty for keys not hashed yet, `None` if missing
+fn select_key_hash(conn: &Connection, id: i32) -> Result<Option<String>, DbError> {
+    const QUERY: &str = "SELECT COALESCE(key_hash, '') FROM api_keys WHERE id = ?1";
+
+    Ok(conn.query_row(QUERY, [id], |row| row.get(0)).optional()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::tracing::log_level::LogLevel;
+    use crate::telemetry::tracing::logger::Logger;
+
+    fn repository() -> SqliteSpeedRepository {
+        // Migrations log the versions they apply
+        let _ = Logger::init(
+            std::env::temp_dir().join("speedstream-sqlite-tests.log"),
+            LogLevel::Error,
+        );
+        SqliteSpeedRepository::open_in_memory().unwrap()
+    }
+
+    fn request(speed: f32, vehicle_class: Option<VehicleClass>) -> CreateSpeedDataRequest {
+        serde_json::from_value(serde_json::json!({
+            "sensor_name": "A",
+            "speed": speed,
+            "lane": 1,
+            "vehicle_class": vehicle_class,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_fetch() {
+        let repository = repository();
+        repository.ping().await.unwrap();
+        assert!(repository.fetch_last_speed(None).await.unwrap().is_none());
+
+        let first = repository
+            .insert_speed_data(request(50.0, None), None)
+            .await
+            .unwrap();
+        let second = repository
+            .insert_speed_data(
+                request(80.0, Some(VehicleClass::Truck)),
+                Some(QualityFlag::SuddenJump),
+            )
+            .await
+            .unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(first.sensor_name.as_deref(), Some("A"));
+        assert_eq!(second.quality_flag, Some(QualityFlag::SuddenJump));
+
+        let last_n = repository.fetch_last_n_speed_data(10, None).await.unwrap();
+        let ids: Vec<_> = last_n.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [2, 1]);
+
+        let page = repository
+            .fetch_speed_data_with_pagination(1, 10, None)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].speed, 80.0);
+
+        let trucks = repository
+            .fetch_last_speed(Some(VehicleClass::Truck))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(trucks.id, 2);
+
+        let range = repository
+            .fetch_speed_data_by_date_range(first.created_at, second.created_at, None)
+            .await
+            .unwrap();
+        assert_eq!(range.len(), 2);
+        assert_eq!(range[0].created_at, first.created_at);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_speed_aggregates() {
+        let repository = repository();
+        for (speed, flag) in [
+            (40.0, None),
+            (60.0, None),
+            (250.0, Some(QualityFlag::SuddenJump)),
+        ] {
+            let _ = repository
+                .insert_speed_data(request(speed, None), flag)
+                .await
+                .unwrap();
+        }
+
+        let end = Utc::now();
+        let start = end - chrono::Duration::hours(1);
+        let aggregates = repository
+            .fetch_speed_aggregates(
+                start,
+                end,
+                TimeBucket::Day,
+                GroupBy::default(),
+                None,
+                false,
+                Tz::UTC,
+            )
+            .await
+            .unwrap();
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].count, 2);
+        assert_eq!(aggregates[0].avg_speed, 50.0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_speed_aggregates_across_daylight_saving() {
+        use crate::database::repository::aggregate::aggregate_readings;
+        use chrono::TimeZone as _;
+
+        // Paris moves from UTC+1 to UTC+2 on 2024-03-31 at 01:00 UTC
+        let repository = repository();
+        let at = |d, h, m| Utc.with_ymd_and_hms(2024, 3, d, h, m, 0).unwrap();
+        let readings: Vec<_> = [
+            (at(25, 8, 0), 40.0, Lane::Left, Some(VehicleClass::Car)),
+            (at(30, 22, 59), 50.0, Lane::Left, None),
+            (at(30, 23, 0), 60.0, Lane::Right, Some(VehicleClass::Truck)),
+            (at(31, 0, 30), 70.0, Lane::Left, Some(VehicleClass::Car)),
+            (at(31, 1, 30), 80.0, Lane::Left, Some(VehicleClass::Car)),
+            (at(31, 22, 30), 90.0, Lane::Right, None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, (created_at, speed, lane, class))| {
+            SpeedData::new(
+                id as i32 + 1,
+                Some("A".to_string()),
+                speed,
+                lane,
+                created_at,
+            )
+            .with_vehicle(class, None)
+        })
+        .collect();
+        repository
+            .insert_forwarded_speed_data("edge", readings.clone())
+            .await
+            .unwrap();
+
+        let paris = chrono_tz::Europe::Paris;
+        let summary = |aggregates: &[SpeedAggregate]| {
+            aggregates
+                .iter()
+                .map(|a| {
+                    (
+                        a.bucket_start,
+                        a.lane,
+                        a.vehicle_class,
+                        a.count,
+                        a.avg_speed,
+                        a.min_speed,
+                        a.max_speed,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        for bucket in [
+            TimeBucket::Minute,
+            TimeBucket::Hour,
+            TimeBucket::Day,
+            TimeBucket::Week,
+            TimeBucket::Month,
+        ] {
+            for group_by in [
+                GroupBy::default(),
+                GroupBy {
+                    lane: true,
+                    sensor: true,
+                    class: true,
+                },
+            ] {
+                let aggregates = repository
+                    .fetch_speed_aggregates(
+                        at(1, 0, 0),
+                        at(31, 23, 59),
+                        bucket,
+                        group_by,
+                        None,
+                        false,
+                        paris,
+                    )
+                    .await
+                    .unwrap();
+                let expected = aggregate_readings(&readings, bucket, group_by, paris);
+                assert_eq!(summary(&aggregates), summary(&expected), "{bucket:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_token() {
+        let repository = repository();
+        repository
+            .call(|conn| {
+                conn.execute_batch(
+                    "INSERT INTO api_keys (api_key, is_active) VALUES ('active', 1), ('revoked', 0)",
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        // Keys inserted in plaintext are only accepted once hashed
+        let key_hash = hash_api_key("active");
+        assert!(
+            repository
+                .validate_token(&key_hash)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(repository.count_plaintext_api_keys().await.unwrap(), 2);
+        assert_eq!(repository.hash_plaintext_api_keys().await.unwrap(), 2);
+        assert_eq!(repository.hash_plaintext_api_keys().await.unwrap(), 0);
+
+        for (token, valid) in [("active", true), ("revoked", false), ("unknown", false)] {
+            let key = repository
+                .validate_token(&hash_api_key(token))
+                .await
+                .unwrap();
+            assert_eq!(key.is_some(), valid);
+        }
+
+        assert_eq!(repository.count_plaintext_api_keys().await.unwrap(), 0);
+
+        // Expired keys are refused like inactive ones
+        let request: CreateApiKeyRequest = serde_json::from_value(
+            serde_json::json!({"name": "Old", "expires_at": "2020-01-01T00:00:00Z"}),
+        )
+        .unwrap();
+        let expired = repository
+            .create_api_key(request, &hash_api_key("expired"), "expired")
+            .await
+            .unwrap();
+        assert!(expired.expires_at.is_some());
+        assert!(
+            repository
+                .validate_token(&hash_api_key("expired"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Signed requests name their key by id
+        let (key, key_hash) = repository.validate_key_id(1).await.unwrap().unwrap();
+        assert_eq!(key.id, Some(1));
+        assert_eq!(key_hash, hash_api_key("active"));
+        for id in [2, expired.id, 99] {
+            assert!(repository.validate_key_id(id).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwarding() {
+        let repository = repository();
+        let local = repository
+            .insert_speed_data(request(50.0, None), None)
+            .await
+            .unwrap();
+
+        let mut forwarded = local.clone();
+        forwarded.sensor_name = Some("edge".to_string());
+        for expected in [1, 0] {
+            let inserted = repository
+                .insert_forwarded_speed_data("site-a", vec![forwarded.clone()])
+                .await
+                .unwrap();
+            assert_eq!(inserted, expected);
+        }
+
+        let unforwarded = repository
+            .fetch_unforwarded_speed_data(0, 10, 0.0)
+            .await
+            .unwrap();
+        let ids: Vec<_> = unforwarded.iter().map(|d| d.id).collect();
+        assert_eq!(ids, [local.id]);
+
+        assert_eq!(repository.fetch_forward_watermark().await.unwrap(), 0);
+        repository.store_forward_watermark(1).await.unwrap();
+        repository.store_forward_watermark(7).await.unwrap();
+        assert_eq!(repository.fetch_forward_watermark().await.unwrap(), 7);
+
+        for _ in 0..2 {
+            repository
+                .quarantine_speed_data(&unforwarded, "422 Unprocessable Entity")
+                .await
+                .unwrap();
+        }
+        let quarantined: Vec<(i32, String)> = repository
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT speed_id, reason FROM forward_quarantine")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                Ok(rows.collect::<Result<_, _>>()?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            quarantined,
+            [(local.id, "422 Unprocessable Entity".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schema_checks_readings() {
+        let repository = repository();
+        let mut reading = repository
+            .insert_speed_data(request(50.0, None), None)
+            .await
+            .unwrap();
+
+        // Refused like by Postgres, so an edge never stores what the central server refuses
+        reading.vehicle_length = Some(0.0);
+        assert!(
+            repository
+                .insert_forwarded_speed_data("site-a", vec![reading])
+                .await
+                .is_err()
+        );
+        let result = repository
+            .call(|conn| {
+                Ok(conn.execute(
+                    "INSERT INTO speed (speed, lane, created_at, vehicle_class) VALUES (50, 1, 0, 'spaceship')",
+                    [],
+                )?)
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_manage_api_keys() {
+        let repository = repository();
+        let request: CreateApiKeyRequest = serde_json::from_value(
+            serde_json::json!({"name": "Site A", "scopes": ["speeds:write", "admin"]}),
+        )
+        .unwrap();
+        let created = repository
+            .create_api_key(request, &hash_api_key("first"), "first")
+            .await
+            .unwrap();
+        assert_eq!(created.name.as_deref(), Some("Site A"));
+        assert_eq!(
+            created.scopes,
+            [ApiKeyScope::SpeedsWrite, ApiKeyScope::Admin]
+        );
+        assert!(created.is_active);
+
+        let previous = repository
+            .rotate_api_key(created.id, &hash_api_key("second"), "second")
+            .await
+            .unwrap();
+        assert_eq!(previous, Some(hash_api_key("first")));
+        let key = repository
+            .validate_token(&hash_api_key("second"))
+            .await
+            .unwrap();
+        assert!(key.is_some_and(|k| k.has_scope(ApiKeyScope::Admin)));
+
+        assert!(
+            repository
+                .deactivate_api_key(created.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        let keys = repository.list_api_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].key_prefix.as_deref(), Some("second"));
+        assert!(!keys[0].is_active);
+
+        assert!(
+            repository
+                .delete_api_key(created.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repository
+                .delete_api_key(created.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repository
+                .rotate_api_key(created.id, "x", "x")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_events() {
+        let repository = repository();
+        let start = Utc::now();
+        let mut failure = AuditEvent::new(AuditAction::AuthFailure).with_detail("Invalid token");
+        failure.ip = Some("192.0.2.7".to_string());
+        failure.route = Some("/api/speeds".to_string());
+        repository
+            .insert_audit_events(vec![
+                failure,
+                AuditEvent::new(AuditAction::KeyDeleted).on_key(3),
+            ])
+            .await
+            .unwrap();
+
+        let end = Utc::now();
+        let events = repository
+            .fetch_audit_events(start, end, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, AuditAction::KeyDeleted);
+        assert_eq!(events[1].ip.as_deref(), Some("192.0.2.7"));
+        assert_eq!(events[1].detail.as_deref(), Some("Invalid token"));
+
+        let events = repository
+            .fetch_audit_events(start, end, Some(AuditAction::AuthFailure), Some(3), 10)
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+        let events = repository
+            .fetch_audit_events(start, end, None, Some(3), 10)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_migrations_are_applied_once() {
+        let _ = Logger::init(
+            std::env::temp_dir().join("speedstream-sqlite-tests.log"),
+            LogLevel::Error,
+        );
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(
+            run_sqlite_migrations(&mut conn).unwrap(),
+            [0, 1, 2, 3, 4, 5, 6, 7]
+        );
+        assert!(run_sqlite_migrations(&mut conn).unwrap().is_empty());
+
+        conn.pragma_update(None, "user_version", 99).unwrap();
+        assert!(matches!(
+            run_sqlite_migrations(&mut conn),
+            Err(MigrationError::UnknownVersion { applied: 98, .. })
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+use crate::config::constant::{
+    FORWARD_URL, RETENTION_AUDIT_DAYS, RETENTION_BATCH_SIZE, RETENTION_DAY_ROLLUP_DAYS,
+    RETENTION_DRY_RUN, RETENTION_HOUR_ROLLUP_DAYS, RETENTION_INTERVAL_SECS,
+    RETENTION_MINUTE_ROLLUP_DAYS, RETENTION_RAW_DAYS,
+};
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::database::pool::DbPool;
+use crate::database::types::DbError;
+use crate::database::util::{RANGE_QUERY_TIMEOUT, with_timeout};
+use crate::telemetry::audit::AuditLog;
+use crate::{log_error, log_info};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// Tables subject to a retention policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionTarget {
+    Raw,
+    MinuteRollup,
+    HourRollup,
+    DayRollup,
+    Audit,
+}
+
+impl RetentionTarget {
+    /// Returns the name of the table
+    #[must_use]
+    pub fn table(&self) -> &'static str {
+        match self {
+            Self::Raw => "speed",
+            Self::MinuteRollup => "speed_rollup_minute",
+            Self::HourRollup => "speed_rollup_hour",
+            Self::DayRollup => "speed_rollup_day",
+            Self::Audit => "audit_log",
+        }
+    }
+
+    /// Counts the rows older than the cutoff `$1`
+    ///
+    /// Raw readings are only purged once folded into the rollups, and with `keep_unforwarded`
+    /// once forwarded to the central server.
+    fn preview_query(&self, keep_unforwarded: bool) -> &'static str {
+        match self {
+            Self::Raw if keep_unforwarded => {
+                "SELECT count(*) AS rows, min(created_at) AS oldest FROM speed WHERE created_at < $1 AND id <= coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0) AND (origin IS NOT NULL OR id <= coalesce((SELECT last_id FROM forward_watermark WHERE name = 'upstream'), 0))"
+            }
+            Self::Raw => {
+                "SELECT count(*) AS rows, min(created_at) AS oldest FROM speed WHERE created_at < $1 AND id <= coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0)"
+            }
+            Self::MinuteRollup => {
+                "SELECT count(*) AS rows, min(bucket_start) AS oldest FROM speed_rollup_minute WHERE bucket_start < $1"
+            }
+            Self::HourRollup => {
+                "SELECT count(*) AS rows, min(bucket_start) AS oldest FROM speed_rollup_hour WHERE bucket_start < $1"
+            }
+            Self::DayRollup => {
+                "SELECT count(*) AS rows, min(bucket_start) AS oldest FROM speed_rollup_day WHERE bucket_start < $1"
+            }
+            Self::Audit => {
+                "SELECT count(*) AS rows, min(occurred_at) AS oldest FROM audit_log WHERE occurred_at < $1"
+            }
+        }
+    }
+
+    /// Deletes at most `$2` rows older than the cutoff `$1`
+    fn delete_query(&self, keep_unforwarded: bool) -> &'static str {
+        match self {
+            Self::Raw if keep_unforwarded => {
+                "DELETE FROM speed WHERE id IN (SELECT id FROM speed WHERE created_at < $1 AND id <= coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0) AND (origin IS NOT NULL OR id <= coalesce((SELECT last_id FROM forward_watermark WHERE name = 'upstream'), 0)) ORDER BY id LIMIT $2)"
+            }
+            Self::Raw => {
+                "DELETE FROM speed WHERE id IN (SELECT id FROM speed WHERE created_at < $1 AND id <= coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0) ORDER BY id LIMIT $2)"
+            }
+            Self::MinuteRollup => {
+                "DELETE FROM speed_rollup_minute WHERE ctid IN (SELECT ctid FROM speed_rollup_minute WHERE bucket_start < $1 LIMIT $2)"
+            }
+            Self::HourRollup => {
+                "DELETE FROM speed_rollup_hour WHERE ctid IN (SELECT ctid FROM speed_rollup_hour WHERE bucket_start < $1 LIMIT $2)"
+            }
+            Self::DayRollup => {
+                "DELETE FROM speed_rollup_day WHERE ctid IN (SELECT ctid FROM speed_rollup_day WHERE bucket_start < $1 LIMIT $2)"
+            }
+            Self::Audit => {
+                "DELETE FROM audit_log WHERE id IN (SELECT id FROM audit_log WHERE occurred_at < $1 ORDER BY id LIMIT $2)"
+            }
+        }
+    }
+}
+
+/// How long the rows of one table are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub target: RetentionTarget,
+    pub keep_days: Option<i64>, // None keeps rows forever
+}
+
+impl RetentionPolicy {
+    /// Returns the instant before which rows are purged, if any
+    #[must_use]
+    pub fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.keep_days.map(|days| now - Duration::days(days))
+    }
+}
+
+/// Settings of the background purge task
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionConfig {
+    pub policies: Vec<RetentionPolicy>,
+    pub interval: std::time::Duration, // Delay between two purges
+    pub batch_size: i64,               // Maximum rows deleted per statement
+    pub dry_run: bool,                 // Only report what would be purged
+    pub keep_unforwarded: bool,        // Edge servers keep readings until forwarded
+}
+
+impl RetentionConfig {
+    /// Builds the configuration from the `RETENTION_*` environment variables
+    ///
+    /// A retention of 0 days keeps the rows forever.
+    #[must_use]
+    pub fn from_env() -> Self {
+        let keep = |days: i64| (days > 0).then_some(days);
+        Self {
+            policies: vec![
+                RetentionPolicy {
+                    target: RetentionTarget::Raw,
+                    keep_days: keep(*RETENTION_RAW_DAYS),
+                },
+                RetentionPolicy {
+                    target: RetentionTarget::MinuteRollup,
+                    keep_days: keep(*RETENTION_MINUTE_ROLLUP_DAYS),
+                },
+                RetentionPolicy {
+                    target: RetentionTarget::HourRollup,
+                    keep_days: keep(*RETENTION_HOUR_ROLLUP_DAYS),
+                },
+                RetentionPolicy {
+                    target: RetentionTarget::DayRollup,
+                    keep_days: keep(*RETENTION_DAY_ROLLUP_DAYS),
+                },
+                RetentionPolicy {
+                    target: RetentionTarget::Audit,
+                    keep_days: keep(*RETENTION_AUDIT_DAYS),
+                },
+            ],
+            interval: std::time::Duration::from_secs(*RETENTION_INTERVAL_SECS),
+            batch_size: *RETENTION_BATCH_SIZE,
+            dry_run: *RETENTION_DRY_RUN,
+            keep_unforwarded: !FORWARD_URL.trim().is_empty(),
+        }
+    }
+
+    /// Returns the instant before which the rows of a table are purged, if any
+    #[must_use]
+    pub fn cutoff(&self, target: RetentionTarget, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.policies
+            .iter()
+            .find(|policy| policy.target == target)
+            .and_then(|policy| policy.cutoff(now))
+    }
+}
+
+/// Rows of one table that are, or would be, purged
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeReport {
+    pub target: RetentionTarget,
+    pub table: &'static str,
+    pub keep_days: Option<i64>,        // None keeps rows forever
+    pub cutoff: Option<DateTime<Utc>>, // Rows older than this are purged
+    pub rows: i64,                     // Rows older than the cutoff, or deleted
+    pub oldest: Option<DateTime<Utc>>, // Oldest purgeable row, in previews
+}
+
+impl PurgeReport {
+    fn new(policy: &RetentionPolicy, cutoff: Option<DateTime<Utc>>) -> Self {
+        Self {
+            target: policy.target,
+            table: policy.target.table(),
+            keep_days: policy.keep_days,
+            cutoff,
+            rows: 0,
+            oldest: None,
+        }
+    }
+}
+
+/// Reports how many rows of each table are older than their retention
+pub async fn preview_purge(
+    pool: &DbPool,
+    config: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<PurgeReport>, DbError> {
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let mut reports = Vec::with_capacity(config.policies.len());
+        for policy in &config.policies {
+            let cutoff = policy.cutoff(now);
+            let mut report = PurgeReport::new(policy, cutoff);
+
+            if let Some(cutoff) = cutoff {
+                let row = conn
+                    .query_one(
+                        policy.target.preview_query(config.keep_unforwarded),
+                        &[&cutoff],
+                    )
+                    .await
+                    .map_err(DbError::from)?;
+                report.rows = row.try_get("rows").map_err(DbError::from)?;
+                report.oldest = row.try_get("oldest").map_err(DbError::from)?;
+            }
+
+            reports.push(report);
+        }
+        Ok(reports)
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to preview purge: {e}");
+            e
+        })
+}
+
+/// Deletes the rows older than their retention, in batches of `batch_size` rows
+///
+/// Each batch is its own short statement so locks are never held for long.
+/// Returns the number of rows deleted per table.
+pub async fn purge(
+    pool: &DbPool,
+    config: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<PurgeReport>, DbError> {
+    let mut reports = Vec::with_capacity(config.policies.len());
+
+    for policy in &config.policies {
+        let cutoff = policy.cutoff(now);
+        let mut report = PurgeReport::new(policy, cutoff);
+
+        if let Some(cutoff) = cutoff {
+            loop {
+                let conn = pool.get().await?;
+                let deleted = with_timeout(
+                    async {
+                        conn.execute(
+                            policy.target.delete_query(config.keep_unforwarded),
+                            &[&cutoff, &config.batch_size],
+                        )
+                        .await
+                        .map_err(DbError::from)
+                    },
+                    RANGE_QUERY_TIMEOUT,
+                )
+                .await
+                .map_err(|e| {
+                    log_error!("Failed to purge {}: {e}", policy.target.table());
+                    e
+                })?;
+
+                report.rows += deleted as i64;
+                if (deleted as i64) < config.batch_size {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Spawns the background task enforcing the retention policies
+///
+/// In dry-run mode, the task only logs what would be purged. Actual purges are recorded in the
+/// audit trail.
+pub fn spawn_retention_job(
+    pool: DbPool,
+    config: RetentionConfig,
+    audit: AuditLog,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+
+            let result = if config.dry_run {
+                preview_purge(&pool, &config, Utc::now()).await
+            } else {
+                purge(&pool, &config, Utc::now()).await
+            };
+
+            if let Ok(reports) = result {
+                for report in reports.iter().filter(|r| r.rows > 0) {
+                    log_info!(
+                        "Retention{}: {} rows purged from {} (older than {:?})",
+                        if config.dry_run { " (dry run)" } else { "" },
+                        report.rows,
+                        report.table,
+                        report.cutoff
+                    );
+                    if !config.dry_run {
+                        audit.record(AuditEvent::new(AuditAction::DataPurged).with_detail(
+                            format!(
+                                "{} rows of {} older than {:?}",
+                                report.rows, report.table, report.cutoff
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    #[test]
+    fn test_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let policy = RetentionPolicy {
+            target: RetentionTarget::Raw,
+            keep_days: Some(90),
+        };
+        assert_eq!(
+            policy.cutoff(now),
+            Some(Utc.with_ymd_and_hms(2024, 3, 3, 12, 0, 0).unwrap())
+        );
+
+        let forever = RetentionPolicy {
+            target: RetentionTarget::DayRollup,
+            keep_days: None,
+        };
+        assert_eq!(forever.cutoff(now), None);
+    }
+
+    #[test]
+    fn test_queries_target_their_table() {
+        for target in [
+            RetentionTarget::Raw,
+            RetentionTarget::MinuteRollup,
+            RetentionTarget::HourRollup,
+            RetentionTarget::DayRollup,
+            RetentionTarget::Audit,
+        ] {
+            for keep_unforwarded in [false, true] {
+                let from = format!("FROM {} WHERE", target.table());
+                assert!(target.preview_query(keep_unforwarded).contains(&from));
+                assert!(
+                    target
+                        .delete_query(keep_unforwarded)
+                        .starts_with(&format!("DELETE {from}"))
+                );
+            }
+        }
+        assert!(
+            RetentionTarget::Raw
+                .delete_query(true)
+                .contains("forward_watermark")
+        );
+    }
+}
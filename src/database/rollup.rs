@@ -0,0 +1,428 @@
+use crate::api::query::aggregate_query::GroupBy;
+use crate::config::constant::{ROLLUP_BATCH_SIZE, ROLLUP_INTERVAL_SECS, ROLLUP_SETTLE_SECS};
+use crate::core::dto::speed_aggregate::SpeedAggregate;
+use crate::core::time_bucket::TimeBucket;
+use crate::core::vehicle_class::VehicleClass;
+use crate::database::pool::DbPool;
+use crate::database::retention::{RetentionConfig, RetentionTarget};
+use crate::database::types::{DbError, FromPostgresRow};
+use crate::database::util::{RANGE_QUERY_TIMEOUT, ROLLUP_TIMEOUT, with_timeout};
+use crate::{log_error, log_info};
+use chrono::{DateTime, Duration, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// Folds the new valid readings of a batch into one rollup table, truncating buckets in UTC
+macro_rules! refresh_query {
+    ($field:literal) => {
+        concat!(
+            "INSERT INTO speed_rollup_",
+            $field,
+            " AS r (bucket_start, lane, sensor_name, vehicle_class, count, sum_speed, sum_sq_speed, min_speed, max_speed) \
+            SELECT date_trunc('",
+            $field,
+            "', created_at, 'UTC'), lane, coalesce(sensor_name, ''), coalesce(vehicle_class, ''), count(*), \
+                   sum(speed::float8), sum(speed::float8 * speed::float8), min(speed), max(speed) \
+            FROM speed WHERE id > $1::int8 AND id <= $2::int8 AND quality_flag IS NULL GROUP BY 1, 2, 3, 4 \
+            ON CONFLICT (bucket_start, lane, sensor_name, vehicle_class) DO UPDATE SET \
+                count = r.count + EXCLUDED.count, \
+                sum_speed = r.sum_speed + EXCLUDED.sum_speed, \
+                sum_sq_speed = r.sum_sq_speed + EXCLUDED.sum_sq_speed, \
+                min_speed = least(r.min_speed, EXCLUDED.min_speed), \
+                max_speed = greatest(r.max_speed, EXCLUDED.max_speed)"
+        )
+    };
+}
+
+/// Aggregates one rollup table, completed with the raw readings not folded in yet
+macro_rules! aggregate_query {
+    ($field:literal) => {
+        concat!(
+            "WITH parts AS (\
+                SELECT bucket_start AS at, lane, sensor_name, vehicle_class, count, sum_speed, min_speed, max_speed \
+                FROM speed_rollup_",
+            $field,
+            " WHERE bucket_start >= $2 AND bucket_start <= $3 AND ($7::text IS NULL OR vehicle_class = $7) \
+                UNION ALL \
+                SELECT created_at, lane, coalesce(sensor_name, ''), coalesce(vehicle_class, ''), 1, speed, speed, speed \
+                FROM speed \
+                WHERE id > coalesce((SELECT last_id FROM rollup_watermark WHERE name = 'speed'), 0) \
+                  AND created_at >= $2 AND created_at <= $3 AND ($7::text IS NULL OR vehicle_class = $7) AND quality_flag IS NULL\
+            ) \
+            SELECT date_trunc($1, at, $8) AS bucket_start, CASE WHEN $4 THEN lane END AS lane, \
+                   CASE WHEN $5 THEN nullif(sensor_name, '') END AS sensor_name, CASE WHEN $6 THEN nullif(vehicle_class, '') END AS vehicle_class, \
+                   sum(count)::int8 AS count, (sum(sum_speed) / sum(count)::float8)::float8 AS avg_speed, \
+                   min(min_speed) AS min_speed, max(max_speed) AS max_speed \
+            FROM parts GROUP BY 1, 2, 3, 4 ORDER BY 1, 2, 3, 4"
+        )
+    };
+}
+
+/// Granularity of the rollup tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupLevel {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl RollupLevel {
+    /// Returns the rollup levels able to serve a bucket, coarsest first
+    fn candidates(bucket: TimeBucket) -> &'static [RollupLevel] {
+        match bucket {
+            TimeBucket::Minute => &[Self::Minute],
+            TimeBucket::Hour => &[Self::Hour, Self::Minute],
+            TimeBucket::Day | TimeBucket::Week | TimeBucket::Month => {
+                &[Self::Day, Self::Hour, Self::Minute]
+            }
+        }
+    }
+
+    /// Returns the retention target of the rollup table
+    fn retention_target(self) -> RetentionTarget {
+        match self {
+            Self::Minute => RetentionTarget::MinuteRollup,
+            Self::Hour => RetentionTarget::HourRollup,
+            Self::Day => RetentionTarget::DayRollup,
+        }
+    }
+
+    /// Checks whether an instant falls on a bucket boundary of this level (buckets are in UTC)
+    fn is_boundary(self, at: DateTime<Utc>) -> bool {
+        let minute = at.second() == 0 && at.nanosecond() == 0;
+        match self {
+            Self::Minute => minute,
+            Self::Hour => minute && at.minute() == 0,
+            Self::Day => minute && at.minute() == 0 && at.hour() == 0,
+        }
+    }
+
+    /// Checks whether local buckets of `tz` are made of whole buckets of this level
+    fn fits_timezone(self, tz: Tz, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        let offset = |at: DateTime<Utc>| {
+            tz.offset_from_utc_datetime(&at.naive_utc())
+                .fix()
+                .local_minus_utc()
+        };
+        match self {
+            Self::Minute => true,
+            Self::Hour => offset(start) % 3600 == 0 && offset(end) % 3600 == 0,
+            Self::Day => tz == Tz::UTC,
+        }
+    }
+}
+
+/// Picks the coarsest rollup level that gives the same result as the raw readings
+///
+/// The range must start on a boundary of the level and either end on one (inclusive ends such as
+/// `23:59:59` count as boundaries) or reach the present. Levels whose rows before `start` were
+/// already purged by the retention job are skipped. Returns `None` if only raw readings fit.
+#[must_use]
+pub fn choose_rollup(
+    bucket: TimeBucket,
+    tz: Tz,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    retention: &RetentionConfig,
+    now: DateTime<Utc>,
+) -> Option<RollupLevel> {
+    RollupLevel::candidates(bucket)
+        .iter()
+        .copied()
+        .find(|level| {
+            let ends_on_boundary = end >= now
+                || level.is_boundary(end)
+                || level.is_boundary(end + Duration::seconds(1))
+                || level.is_boundary(end + Duration::microseconds(1));
+            let retained = retention
+                .cutoff(level.retention_target(), now)
+                .is_none_or(|cutoff| start >= cutoff);
+            level.fits_timezone(tz, start, end)
+                && level.is_boundary(start)
+                && ends_on_boundary
+                && retained
+        })
+}
+
+/// Settings of the background job maintaining the rollup tables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollupConfig {
+    pub interval: std::time::Duration, // Delay between two refreshes
+    pub batch_size: i64,               // Maximum readings folded per transaction
+    pub settle_secs: f64,              // Age before a reading is folded
+}
+
+impl RollupConfig {
+    /// Builds the configuration from the `ROLLUP_*` environment variables
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(*ROLLUP_INTERVAL_SECS),
+            batch_size: *ROLLUP_BATCH_SIZE,
+            settle_secs: *ROLLUP_SETTLE_SECS,
+        }
+    }
+}
+
+/// Folds the next batch of readings into the minute, hour and day rollups
+///
+/// Readings are folded by increasing id once stored for `settle_secs`, so rows still being
+/// inserted are never skipped, including readings forwarded late by edge servers. The rollups
+/// and the watermark are updated in one transaction.
+/// Returns the number of readings folded.
+pub async fn refresh_rollups(pool: &DbPool, config: &RollupConfig) -> Result<i64, DbError> {
+    const SELECT_WATERMARK: &str =
+        "SELECT last_id FROM rollup_watermark WHERE name = 'speed' FOR UPDATE";
+    const SELECT_BATCH: &str = "SELECT max(id)::int8 AS upper_id, count(*) AS rows FROM (SELECT id FROM speed WHERE id > $1::int8 AND received_at < now() - make_interval(secs => $3) ORDER BY id LIMIT $2) batch";
+    const UPDATE_WATERMARK: &str =
+        "UPDATE rollup_watermark SET last_id = $1, updated_at = now() WHERE name = 'speed'";
+
+    let mut conn = pool.get().await?;
+
+    let refresh_future = async {
+        let tx = conn.transaction().await.map_err(DbError::from)?;
+
+        let last_id: i64 = tx
+            .query_one(SELECT_WATERMARK, &[])
+            .await
+            .map_err(DbError::from)?
+            .try_get("last_id")
+            .map_err(DbError::from)?;
+
+        let batch = tx
+            .query_one(
+                SELECT_BATCH,
+                &[&last_id, &config.batch_size, &config.settle_secs],
+            )
+            .await
+            .map_err(DbError::from)?;
+        let rows: i64 = batch.try_get("rows").map_err(DbError::from)?;
+        let Some(upper_id) = batch
+            .try_get::<_, Option<i64>>("upper_id")
+            .map_err(DbError::from)?
+        else {
+            return Ok(0);
+        };
+
+        for query in [
+            refresh_query!("minute"),
+            refresh_query!("hour"),
+            refresh_query!("day"),
+        ] {
+            tx.execute(query, &[&last_id, &upper_id])
+                .await
+                .map_err(DbError::from)?;
+        }
+
+        tx.execute(UPDATE_WATERMARK, &[&upper_id])
+            .await
+            .map_err(DbError::from)?;
+        tx.commit().await.map_err(DbError::from)?;
+
+        Ok(rows)
+    };
+
+    with_timeout(refresh_future, ROLLUP_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to refresh rollups: {e}");
+            e
+        })
+}
+
+/// Fetches speed statistics per time bucket from a rollup table
+///
+/// Returns the same rows as `fetch_speed_aggregates` without flagged readings. Readings not
+/// folded into the rollups yet are read from the raw table, so results are never stale.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_rollup_aggregates(
+    pool: &DbPool,
+    level: RollupLevel,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    bucket: TimeBucket,
+    group_by: GroupBy,
+    vehicle_class: Option<VehicleClass>,
+    tz: Tz,
+) -> Result<Vec<SpeedAggregate>, DbError> {
+    let query = match level {
+        RollupLevel::Minute => aggregate_query!("minute"),
+        RollupLevel::Hour => aggregate_query!("hour"),
+        RollupLevel::Day => aggregate_query!("day"),
+    };
+
+    let conn = pool.get().await?;
+
+    let query_future = async {
+        let stmt = conn.prepare(query).await.map_err(DbError::from)?;
+        let rows = conn
+            .query(
+                &stmt,
+                &[
+                    &bucket.as_str(),
+                    &start_date,
+                    &end_date,
+                    &group_by.lane,
+                    &group_by.sensor,
+                    &group_by.class,
+                    &vehicle_class.map(|c| c.as_str()),
+                    &tz.name(),
+                ],
+            )
+            .await
+            .map_err(DbError::from)?;
+
+        rows.iter()
+            .map(SpeedAggregate::from_row)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    with_timeout(query_future, RANGE_QUERY_TIMEOUT)
+        .await
+        .map_err(|e| {
+            log_error!("Failed to fetch rollup aggregates: {e}");
+            e
+        })
+}
+
+/// Spawns the background task keeping the rollup tables up to date
+///
+/// Each tick folds batches until the backlog is caught up.
+pub fn spawn_rollup_job(pool: DbPool, config: RollupConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+            loop {
+                match refresh_rollups(&pool, &config).await {
+                    Ok(rows) if rows >= config.batch_size => {
+                        log_info!("Folded {rows} readings into rollups, continuing backlog");
+                    }
+                    Ok(_) | Err(_) => break,
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::retention::RetentionPolicy;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn now() -> DateTime<Utc> {
+        at("2024-03-13T10:30:12Z")
+    }
+
+    fn retention_keeping(minute: Option<i64>, hour: Option<i64>) -> RetentionConfig {
+        let policy = |target, keep_days| RetentionPolicy { target, keep_days };
+        RetentionConfig {
+            policies: vec![
+                policy(RetentionTarget::MinuteRollup, minute),
+                policy(RetentionTarget::HourRollup, hour),
+                policy(RetentionTarget::DayRollup, None),
+            ],
+            interval: std::time::Duration::from_secs(3600),
+            batch_size: 1000,
+            dry_run: false,
+            keep_unforwarded: false,
+        }
+    }
+
+    fn retention() -> RetentionConfig {
+        retention_keeping(None, None)
+    }
+
+    #[test]
+    fn test_choose_coarsest_rollup() {
+        let start = at("2024-03-01T00:00:00Z");
+        let end = at("2024-03-10T23:59:59Z");
+
+        assert_eq!(
+            choose_rollup(TimeBucket::Month, Tz::UTC, start, end, &retention(), now()),
+            Some(RollupLevel::Day)
+        );
+        assert_eq!(
+            choose_rollup(TimeBucket::Hour, Tz::UTC, start, end, &retention(), now()),
+            Some(RollupLevel::Hour)
+        );
+        assert_eq!(
+            choose_rollup(TimeBucket::Minute, Tz::UTC, start, end, &retention(), now()),
+            Some(RollupLevel::Minute)
+        );
+    }
+
+    #[test]
+    fn test_choose_rollup_for_unaligned_range() {
+        // Starts at 10:15, only the minute rollup keeps whole buckets
+        let start = at("2024-03-13T10:15:00Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Day, Tz::UTC, start, now(), &retention(), now()),
+            Some(RollupLevel::Minute)
+        );
+
+        // Starts mid-minute, only raw readings fit
+        let start = at("2024-03-13T10:15:12Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Hour, Tz::UTC, start, now(), &retention(), now()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_choose_rollup_in_timezone() {
+        // Local days don't start at UTC midnight, but are made of whole UTC hours
+        let paris = chrono_tz::Europe::Paris;
+        let start = at("2024-02-29T23:00:00Z");
+        let end = at("2024-03-10T22:59:59.999999Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Day, paris, start, end, &retention(), now()),
+            Some(RollupLevel::Hour)
+        );
+
+        // Half-hour offsets fall back to the minute rollup
+        let kolkata = chrono_tz::Asia::Kolkata;
+        let start = at("2024-02-29T18:30:00Z");
+        let end = at("2024-03-10T18:29:59Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Day, kolkata, start, end, &retention(), now()),
+            Some(RollupLevel::Minute)
+        );
+    }
+
+    #[test]
+    fn test_choose_rollup_skips_purged_levels() {
+        // The minute rollup only keeps 7 days, the hour rollup 30
+        let retention = retention_keeping(Some(7), Some(30));
+        let start = at("2024-03-01T00:00:00Z");
+        let end = at("2024-03-10T23:59:59Z");
+
+        assert_eq!(
+            choose_rollup(TimeBucket::Minute, Tz::UTC, start, end, &retention, now()),
+            None
+        );
+        assert_eq!(
+            choose_rollup(TimeBucket::Hour, Tz::UTC, start, end, &retention, now()),
+            Some(RollupLevel::Hour)
+        );
+
+        // Unaligned on hours, the minute rollup would be needed but is purged
+        let start = at("2024-03-01T00:15:00Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Day, Tz::UTC, start, end, &retention, now()),
+            None
+        );
+
+        // Recent ranges still use the minute rollup
+        let start = at("2024-03-12T00:15:00Z");
+        assert_eq!(
+            choose_rollup(TimeBucket::Day, Tz::UTC, start, now(), &retention, now()),
+            Some(RollupLevel::Minute)
+        );
+    }
+}
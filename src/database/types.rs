@@ -8,6 +8,12 @@ pub trait FromPostgresRow: Sized {
     fn from_row(row: &tokio_postgres::Row) -> Result<Self, DbError>;
 }
 
+/// Trait for mapping rusqlite rows to domain types
+#[cfg(feature = "sqlite")]
+pub trait FromSqliteRow: Sized {
+    fn from_sqlite_row(row: &rusqlite::Row<'_>) -> Result<Self, DbError>;
+}
+
 /// Unified database error type
 ///
 /// Provides better error categorization than raw tokio_postgres::Error
@@ -28,6 +34,10 @@ pub enum DbError {
 
     /// Connection pool errors (pool exhaustion, configuration issues, etc.)
     PoolError(String),
+
+    /// SQLite errors (open failures, query errors, type conversion failures, etc.)
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
 }
 
 impl fmt::Display for DbError {
@@ -38,6 +48,8 @@ impl fmt::Display for DbError {
             DbError::Timeout => write!(f, "Database operation timed out"),
             DbError::RowParsing(msg) => write!(f, "Row parsing error: {}", msg),
             DbError::PoolError(msg) => write!(f, "Connection pool error: {}", msg),
+            #[cfg(feature = "sqlite")]
+            DbError::Sqlite(e) => write!(f, "SQLite error: {}", e),
         }
     }
 }
@@ -63,6 +75,13 @@ impl From<bb8::RunError<tokio_postgres::Error>> for DbError {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Sqlite(e)
+    }
+}
+
 impl From<tokio::time::error::Elapsed> for DbError {
     fn from(_: tokio::time::error::Elapsed) -> Self {
         DbError::Timeout
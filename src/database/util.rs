@@ -20,6 +20,12 @@ pub const SIMPLE_SELECT_TIMEOUT: Duration = Duration::from_secs(2);
 /// but with proper indexes should still complete quickly.
 pub const RANGE_QUERY_TIMEOUT: Duration = Duration::from_secs(4);
 
+/// Timeout for the rollup refresh transaction
+///
+/// Folds up to a full batch of readings into three rollup tables,
+/// so it is allowed much longer than request-path queries.
+pub const ROLLUP_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Optimized timeout for authentication queries
 ///
 /// Auth queries are critical path and
@@ -1,6 +1,8 @@
+pub mod analytics;
 pub mod api;
 pub mod config;
 pub mod core;
 pub mod database;
 pub mod middleware;
+pub mod sync;
 pub mod telemetry;
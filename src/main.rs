@@ -1,20 +1,45 @@
-use axum::{
-    Router, middleware,
-    routing::{get, post},
+use speed_stream::analytics::congestion::{
+    CongestionConfig, CongestionMonitor, spawn_congestion_monitor,
 };
-use redis::Client;
-use speed_stream::api::handler::{
-    create_speed, get_last_n_speed, get_last_speed, get_speed_by_date_range, get_speed_pagination,
-    get_speed_today, health_check, root, speed_stream,
+use speed_stream::analytics::quality::{FaultDetector, FaultDetectorConfig};
+use speed_stream::api::payload::create_api_key_request::CreateApiKeyRequest;
+use speed_stream::api::router::create_router;
+#[cfg(feature = "sqlite")]
+use speed_stream::config::constant::SQLITE_PATH;
+use speed_stream::config::constant::{
+    API_KEY_PEPPER, AUDIT_AUTH_SUCCESS, AUDIT_QUEUE_CAPACITY, CACHE_BACKEND, CACHE_MEMORY_CAPACITY,
+    CACHE_RETRY_SECS, DATABASE_URL, HOST, PORT, REDIS_URL, RUN_MIGRATIONS, STORAGE_BACKEND,
 };
-use speed_stream::config::constant::{DATABASE_URL, HOST, PORT, REDIS_URL};
+use speed_stream::core::api_key::{generate_api_key, hash_api_key, key_prefix};
 use speed_stream::core::app_state::AppState;
-use speed_stream::middleware::auth::auth_middleware;
+use speed_stream::database::cache::Cache;
+use speed_stream::database::cache::fallback::FallbackCache;
+use speed_stream::database::cache::memory::MemoryCache;
+use speed_stream::database::cache::redis::RedisCache;
+use speed_stream::database::cache::revocation::spawn_revocation_listener;
+use speed_stream::database::migration::{MigrationError, check_migrations, run_migrations};
+use speed_stream::database::partition::{PartitionConfig, spawn_partition_manager};
+use speed_stream::database::pool::DbPool;
+use speed_stream::database::repository::SpeedRepository;
+use speed_stream::database::repository::postgres::PostgresSpeedRepository;
+#[cfg(feature = "sqlite")]
+use speed_stream::database::repository::sqlite::SqliteSpeedRepository;
+use speed_stream::database::retention::{RetentionConfig, spawn_retention_job};
+use speed_stream::database::rollup::{RollupConfig, spawn_rollup_job};
+use speed_stream::middleware::brute_force::{BruteForceConfig, BruteForceGuard};
+use speed_stream::middleware::client_ip::TrustedProxies;
+use speed_stream::middleware::jwt::{JwtConfig, JwtVerifier};
+use speed_stream::middleware::rate_limit::RateLimitConfig;
+use speed_stream::middleware::signature::SigningConfig;
+use speed_stream::sync::forwarder::{ForwarderConfig, spawn_forwarder};
+use speed_stream::telemetry::audit::{AuditLog, spawn_audit_writer};
 use speed_stream::telemetry::tracing::log_level::LogLevel;
 use speed_stream::telemetry::tracing::logger::Logger;
-use speed_stream::{log_error, log_info};
+use speed_stream::{log_error, log_info, log_warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,72 +47,214 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let dotenv_path = std::env::current_dir()?.join(".env");
     dotenvy::from_path(dotenv_path).ok();
 
+    // `SpeedStream hash-key` prints the hash to store for the API key read on stdin
+    if std::env::args().nth(1).as_deref() == Some("hash-key") {
+        let mut token = String::new();
+        std::io::stdin().read_line(&mut token)?;
+        println!("{}", hash_api_key(token.trim()));
+        return Ok(());
+    }
+
     // Create a logger that writes to "app.log" with minimum level of Info
     Logger::init("app.log", LogLevel::Trace)?;
 
     log_info!("Starting Sensor API Server...");
 
-    // Configure database connection pool (bb8 with tokio-postgres)
-    log_info!("Connecting to Postgres at: {}", DATABASE_URL.as_str());
-    let pool = speed_stream::database::pool::create_pool(DATABASE_URL.as_str())
-        .await
-        .map_err(|e| {
-            log_error!("Failed to connect to Postgres: {e}");
+    // `SpeedStream migrate` applies the pending schema migrations and exits
+    let migrate_only = std::env::args().nth(1).as_deref() == Some("migrate");
+
+    // `SpeedStream create-admin-key [name]` creates a key with every scope, prints it and exits
+    let admin_key_name =
+        (std::env::args().nth(1).as_deref() == Some("create-admin-key")).then(|| {
+            std::env::args()
+                .nth(2)
+                .unwrap_or_else(|| "bootstrap".to_string())
+        });
+
+    // Storage backend, and the Postgres pool used by the SQL only features
+    let (repository, pool): (Arc<dyn SpeedRepository>, Option<DbPool>) =
+        match STORAGE_BACKEND.as_str() {
+            "postgres" => {
+                let pool = open_postgres(migrate_only).await?;
+                (
+                    Arc::new(PostgresSpeedRepository::new(pool.clone())),
+                    Some(pool),
+                )
+            }
+            "sqlite" => (open_sqlite()?, None),
+            other => {
+                log_error!("Unknown storage backend {other}, expected postgres or sqlite");
+                return Err(format!("Unknown storage backend: {other}").into());
+            }
+        };
+
+    // API keys are stored hashed, including the ones inserted in plaintext by hand. Hashing
+    // them drops the plaintext, so it waits for the pepper: hashes without it would all be
+    // invalidated once it is set. Serving meanwhile would refuse those keys, so it doesn't start.
+    if API_KEY_PEPPER.is_empty() {
+        let plaintext = repository.count_plaintext_api_keys().await.map_err(|e| {
+            log_error!("Failed to count plaintext API keys: {e}");
+            e
+        })?;
+        if plaintext > 0 && !migrate_only {
+            log_error!(
+                "{plaintext} API keys are stored in plaintext and can only be hashed with API_KEY_PEPPER set, refusing to start"
+            );
+            return Err(format!(
+                "API_KEY_PEPPER must be set to hash the {plaintext} API keys stored in plaintext"
+            )
+            .into());
+        }
+        log_warn!("API_KEY_PEPPER is not set, API keys are hashed without a secret");
+        if plaintext > 0 {
+            log_warn!("{plaintext} API keys stored in plaintext are left untouched until it is");
+        }
+    } else {
+        let hashed = repository.hash_plaintext_api_keys().await.map_err(|e| {
+            log_error!("Failed to hash plaintext API keys: {e}");
             e
         })?;
+        if hashed > 0 {
+            log_info!("Hashed {hashed} API keys stored in plaintext");
+        }
+    }
 
-    log_info!("Connected to Postgres database (pool: 5-20 connections with bb8)");
+    if migrate_only {
+        return Ok(());
+    }
 
-    // Initialize Redis connection
-    log_info!("Connecting to Redis at: {}", REDIS_URL.as_str());
-    let redis_client = Client::open(REDIS_URL.as_str()).map_err(|e| {
-        log_error!("Failed to create Redis client: {e}");
-        e
-    })?;
-    let redis_manager = redis::aio::ConnectionManager::new(redis_client)
-        .await
-        .map_err(|e| {
-            log_error!("Failed to connect to Redis: {e}");
+    if let Some(name) = admin_key_name {
+        let request: CreateApiKeyRequest = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "scopes": ["speeds:write", "speeds:read", "stream:read", "admin"],
+        }))?;
+        request.validate()?;
+
+        let api_key = generate_api_key();
+        let info = repository
+            .create_api_key(request, &hash_api_key(&api_key), &key_prefix(&api_key))
+            .await
+            .map_err(|e| {
+                log_error!("Failed to create the admin API key: {e}");
+                e
+            })?;
+        log_info!("Created admin API key {} ({:?})", info.id, info.name);
+        println!("{api_key}");
+        return Ok(());
+    }
+
+    // Initialize the cache; Redis is connected lazily so an outage doesn't prevent starting
+    let memory_cache = MemoryCache::new(*CACHE_MEMORY_CAPACITY);
+    let cache: Arc<dyn Cache> = if CACHE_BACKEND.as_str() == "memory" {
+        log_info!("Using the in-process cache");
+        Arc::new(memory_cache)
+    } else {
+        log_info!("Using the Redis cache at: {}", REDIS_URL.as_str());
+        let redis_cache = RedisCache::new(REDIS_URL.as_str()).map_err(|e| {
+            log_error!("Failed to create Redis client: {e}");
             e
         })?;
+        let cache = FallbackCache::new(
+            redis_cache,
+            memory_cache,
+            Duration::from_secs(*CACHE_RETRY_SECS),
+        );
+        if cache.ping().await.is_err() {
+            log_warn!("Redis unreachable at startup, serving from the in-process cache");
+        }
+        let cache: Arc<dyn Cache> = Arc::new(cache);
 
-    log_info!("Connected to Redis cache");
+        // Tokens revoked by other instances are evicted from the in-process cache too
+        let subscriber = RedisCache::new(REDIS_URL.as_str())?;
+        spawn_revocation_listener(
+            subscriber,
+            cache.clone(),
+            Duration::from_secs(*CACHE_RETRY_SECS),
+        );
+        cache
+    };
 
     // Create broadcast channel for real-time speed notifications
     // Channel capacity of 1000 means it can hold up to 1000 messages before dropping oldest
     // This prevents message loss during traffic spikes while maintaining reasonable memory usage (~100KB buffer)
     let (broadcast_tx, _) = tokio::sync::broadcast::channel(1000);
 
-    let app_state = AppState::new(pool, redis_manager, broadcast_tx);
-
-    // Protected routes that require Bearer token authentication
-    let protected_routes = Router::new()
-        // RESTful endpoints for speed measurements
-        .route("/api/speeds", post(create_speed))
-        .route("/api/speeds", get(get_last_n_speed))
-        .route("/api/speeds/latest", get(get_last_speed))
-        .route("/api/speeds/today", get(get_speed_today))
-        .route("/api/speeds/paginated", get(get_speed_pagination))
-        .route("/api/speeds/range", get(get_speed_by_date_range))
-        // Real-time SSE endpoint for speed notifications
-        .route("/api/speeds/stream", get(speed_stream))
-        .route_layer(middleware::from_fn_with_state(
-            app_state.clone(),
-            auth_middleware,
-        ));
-
-    // Public routes that don't require authentication
-    let public_routes = Router::new()
-        .route("/", get(root))
-        .route("/health", get(health_check));
-
-    // Combine all routes
-    let app = Router::new()
-        .merge(public_routes)
-        .merge(protected_routes)
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
-        .with_state(app_state);
+    // Rolling-window congestion detection fed by the real-time broadcast channel
+    let congestion = Arc::new(CongestionMonitor::new(CongestionConfig::from_env(), 100));
+    spawn_congestion_monitor(congestion.clone(), broadcast_tx.subscribe());
+
+    // Sensor fault detectors run on every incoming reading
+    let fault_detector = Arc::new(FaultDetector::new(FaultDetectorConfig::from_env()));
+
+    // Audit trail of authentications, key management and deletions, written in the background
+    let (audit, audit_receiver) = AuditLog::new(*AUDIT_QUEUE_CAPACITY, *AUDIT_AUTH_SUCCESS);
+    spawn_audit_writer(repository.clone(), audit_receiver);
+
+    if let Some(pool) = &pool {
+        // Monthly partitions of the speed table, created ahead and detached past retention
+        spawn_partition_manager(pool.clone(), PartitionConfig::from_env(), audit.clone());
+
+        // Minute, hour and day rollups used by the aggregate endpoint
+        spawn_rollup_job(pool.clone(), RollupConfig::from_env());
+
+        // Scheduled purge of readings and rollups older than their retention
+        spawn_retention_job(pool.clone(), RetentionConfig::from_env(), audit.clone());
+    }
+
+    // Edge mode: readings stored locally are forwarded to the central server
+    if let Some(config) = ForwarderConfig::from_env() {
+        log_info!("Forwarding readings to {} as {}", config.url, config.origin);
+        spawn_forwarder(repository.clone(), config);
+    }
+
+    let mut app_state = AppState::new(
+        repository,
+        pool,
+        cache,
+        broadcast_tx,
+        congestion,
+        fault_detector,
+    )
+    .with_audit(audit);
+
+    // JWT bearer tokens of an identity provider are accepted besides API keys
+    if let Some(config) = JwtConfig::from_env() {
+        let verifier = JwtVerifier::new(config).map_err(|e| {
+            log_error!("{e}");
+            e
+        })?;
+        match verifier.refresh().await {
+            Ok(keys) => log_info!("Accepting JWTs signed by {keys} keys"),
+            Err(e) => log_warn!("{e}, loading it again on the first JWT"),
+        }
+        app_state = app_state.with_jwt(Arc::new(verifier));
+    }
+
+    // Sensors on plain HTTP sign their readings rather than sending their API key
+    if let Some(config) = SigningConfig::from_env() {
+        log_info!(
+            "Accepting signed readings within {} s of the server clock",
+            config.window.as_secs()
+        );
+        app_state = app_state.with_signing(Arc::new(config));
+    }
+
+    // Token buckets per client IP and per key, shared by the instances through Redis
+    let rate_limit = RateLimitConfig::from_env();
+    let brute_force = BruteForceConfig::from_env(&rate_limit);
+    app_state = app_state.with_rate_limit(Arc::new(rate_limit));
+
+    // Client IPs told by the reverse proxies, rather than the address of the proxy
+    let trusted_proxies = TrustedProxies::from_env().map_err(|e| {
+        log_error!("{e}");
+        e
+    })?;
+    app_state = app_state.with_trusted_proxies(Arc::new(trusted_proxies));
+
+    // Lockouts of the client IPs failing to authenticate, and the breaker of the token lookups
+    app_state = app_state.with_brute_force(Arc::new(BruteForceGuard::new(brute_force)));
+
+    let app = create_router(app_state);
 
     // Bind to address and serve the application
     let addr = format!("{}:{}", *HOST, *PORT);
@@ -99,10 +266,76 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     log_info!("Listening on http://{}", listener.local_addr()?);
 
-    axum::serve(listener, app).await.map_err(|e| {
+    // The peer address is needed to rate limit each client IP
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .map_err(|e| {
         log_error!("Server error: {e}");
         e
     })?;
 
     Ok(())
 }
+
+/// Connects to Postgres and brings its schema up to date, or checks it is
+///
+/// With `migrate_only`, pending migrations are applied whatever `RUN_MIGRATIONS` says.
+async fn open_postgres(migrate_only: bool) -> Result<DbPool, Box<dyn std::error::Error>> {
+    // Configure database connection pool (bb8 with tokio-postgres)
+    log_info!("Connecting to Postgres at: {}", DATABASE_URL.as_str());
+    let pool = speed_stream::database::pool::create_pool(DATABASE_URL.as_str())
+        .await
+        .map_err(|e| {
+            log_error!("Failed to connect to Postgres: {e}");
+            e
+        })?;
+
+    log_info!("Connected to Postgres database (pool: 5-20 connections with bb8)");
+
+    if migrate_only {
+        let applied = run_migrations(&pool).await.map_err(|e| {
+            log_error!("{e}");
+            e
+        })?;
+        log_info!("Schema up to date ({} migrations applied)", applied.len());
+        return Ok(pool);
+    }
+
+    // Refuse to start on a schema that is unknown to this build, or not migrated yet
+    if *RUN_MIGRATIONS {
+        run_migrations(&pool).await
+    } else {
+        check_migrations(&pool)
+            .await
+            .and_then(|pending| match pending.first() {
+                Some(&version) => Err(MigrationError::Pending(version)),
+                None => Ok(pending),
+            })
+    }
+    .map_err(|e| {
+        log_error!("{e}");
+        e
+    })?;
+
+    Ok(pool)
+}
+
+/// Opens the SQLite database and applies its pending migrations
+#[cfg(feature = "sqlite")]
+fn open_sqlite() -> Result<Arc<dyn SpeedRepository>, Box<dyn std::error::Error>> {
+    log_info!("Opening SQLite database at: {}", SQLITE_PATH.as_str());
+    let repository = SqliteSpeedRepository::open(SQLITE_PATH.as_str()).map_err(|e| {
+        log_error!("Failed to open SQLite database: {e}");
+        e
+    })?;
+    Ok(Arc::new(repository))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite() -> Result<Arc<dyn SpeedRepository>, Box<dyn std::error::Error>> {
+    log_error!("STORAGE_BACKEND=sqlite but this build lacks the `sqlite` feature");
+    Err("SQLite support requires building with `--features sqlite`".into())
+}
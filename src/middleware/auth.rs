@@ -1,15 +1,20 @@
+use crate::core::api_key::{AuthenticatedKey, hash_api_key, key_prefix};
+use crate::core::api_key_scope::ApiKeyScope;
 use crate::core::app_state::AppState;
-use crate::database::auth::validate_token;
+use crate::core::dto::audit_event::AuditEvent;
 use crate::database::cache::{
-    cache_invalid_token, cache_valid_token, is_token_cached, is_token_cached_invalid,
+    cache_invalid_token, cache_valid_token, get_cached_token, is_token_cached_invalid,
 };
 use crate::log_error;
+use crate::middleware::jwt::{JwtError, is_jwt};
+use crate::telemetry::audit::AuditContext;
 use axum::{
     extract::{Request, State},
     http::{HeaderMap, StatusCode},
     middleware::Next,
     response::Response,
 };
+use chrono::Utc;
 
 /// Extracts Bearer token from Authorization header value
 ///
@@ -30,82 +35,147 @@ pub fn extract_bearer_token(auth_header: &str) -> Option<&str> {
 
 /// Middleware to validate Bearer token authentication
 ///
-/// This middleware:
+/// This middleware lets requests already authenticated by a signature through, otherwise:
 /// 1. Extracts the Bearer token from the Authorization header
-/// 2. Checks if the token is cached in Redis (fast path)
-/// 3. If not cached, validates against the database
-/// 4. If valid, caches the token for future requests
-/// 5. Returns 401 Unauthorized if token is missing or invalid
+///    - when JWTs are accepted and the token is one, verifies it against the JSON Web Key Set
+///      instead, without looking it up nor caching it
+/// 2. Hashes it, only the hash is looked up and cached
+/// 3. Checks if the hash is cached in Redis (fast path), when caching is enabled
+/// 4. If not cached, validates against the storage backend, which refuses expired keys
+///    - while the circuit breaker of `BruteForceGuard` is open and its lookups are all taken,
+///      answers 503 Service Unavailable instead
+/// 5. If valid, caches the hash for future requests, until the key expires at most
+/// 6. Attaches the `AuthenticatedKey` to the request for the handlers
+/// 7. Returns 401 Unauthorized if token is missing or invalid
+///
+/// Every outcome is recorded in the audit trail, with the client IP and the route.
 pub async fn auth_middleware(
-    State(mut state): State<AppState>,
+    State(state): State<AppState>,
     headers: HeaderMap,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
+    // Already authenticated by `signature_middleware`
+    if request.extensions().get::<AuthenticatedKey>().is_some() {
+        return Ok(next.run(request).await);
+    }
+
+    let context = AuditContext::of(&request);
+
     // Extract Authorization header
-    let auth_header = headers
-        .get("authorization")
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| {
-            log_error!("Missing Authorization header");
-            StatusCode::UNAUTHORIZED
-        })?;
+    let Some(auth_header) = headers.get("authorization").and_then(|v| v.to_str().ok()) else {
+        return Err(refuse(
+            &state,
+            context.failure("Missing Authorization header"),
+        ));
+    };
 
     // Extract the Bearer token
-    let token = extract_bearer_token(auth_header).ok_or_else(|| {
-        log_error!("Invalid Authorization header format - must be 'Bearer <token>'");
-        StatusCode::UNAUTHORIZED
-    })?;
+    let Some(token) = extract_bearer_token(auth_header) else {
+        return Err(refuse(
+            &state,
+            context.failure("Invalid Authorization header format - must be 'Bearer <token>'"),
+        ));
+    };
+
+    // JWTs are self-contained, checked with the public keys of their issuer
+    if let Some(jwt) = &state.jwt
+        && is_jwt(token)
+    {
+        return match jwt.verify(token).await {
+            Ok(api_key) => {
+                state.record_audit(context.success(&api_key));
+                request.extensions_mut().insert(api_key);
+                Ok(next.run(request).await)
+            }
+            Err(e @ JwtError::Jwks(_)) => {
+                log_error!("{e}");
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            Err(e) => Err(refuse(&state, context.failure(e.to_string()))),
+        };
+    }
+
+    let key_hash = hash_api_key(token);
 
     // First, check if token is cached as invalid (fastest rejection path)
-    match is_token_cached_invalid(&mut state.redis, token).await {
+    match is_token_cached_invalid(state.cache.as_ref(), &key_hash).await {
         Ok(true) => {
-            log_error!("Token found in invalid cache");
-            return Err(StatusCode::UNAUTHORIZED);
+            return Err(refuse(
+                &state,
+                presenting(context.failure("Token found in invalid cache"), token),
+            ));
         }
         Ok(false) => {
             // Token not in invalid cache, continue to valid cache check
         }
         Err(e) => {
-            log_error!("Redis error while checking invalid token cache: {e}");
-            // Continue to valid token cache check even if Redis fails
+            log_error!("Cache error while checking invalid token cache: {e}");
+            // Continue to valid token cache check even if the cache fails
         }
     }
 
     // Check if token is cached as valid (fast path)
-    match is_token_cached(&mut state.redis, token).await {
-        Ok(true) => {
+    match get_cached_token(state.cache.as_ref(), &key_hash).await {
+        Ok(Some(api_key)) if api_key.is_expired(Utc::now()) => {
+            // Entries are cached until the key expires at most, the key just expired
+            return Err(refuse(
+                &state,
+                context
+                    .failure(format!("{api_key} has expired"))
+                    .by(&api_key),
+            ));
+        }
+        Ok(Some(api_key)) => {
             // Token is cached and valid, proceed with request
+            state.record_audit(context.success(&api_key));
+            request.extensions_mut().insert(api_key);
             return Ok(next.run(request).await);
         }
-        Ok(false) => {
+        Ok(None) => {
             // Token not in cache, need to validate against database
         }
         Err(e) => {
-            log_error!("Redis error while checking token cache: {e}");
-            // Continue to database validation even if Redis fails
+            log_error!("Cache error while checking token cache: {e}");
+            // Continue to database validation even if the cache fails
         }
     }
 
-    // Validate token against database
-    match validate_token(&state.db, token).await {
-        Ok(true) => {
+    // Only a few tokens are looked up at once while too many unknown ones are tried
+    let _lookup = match &state.brute_force {
+        Some(guard) => match guard.admit_lookup() {
+            Ok(permit) => permit,
+            Err(retry_after) => return Ok(guard.refuse_lookup(retry_after)),
+        },
+        None => None,
+    };
+
+    // Validate token against the storage backend
+    match state.repository.validate_token(&key_hash).await {
+        Ok(Some(api_key)) => {
             // Token is valid, cache it for future requests
-            if let Err(e) = cache_valid_token(&mut state.redis, token).await {
+            if let Err(e) = cache_valid_token(state.cache.as_ref(), &key_hash, &api_key).await {
                 log_error!("Failed to cache valid token: {e}");
                 // Continue anyway - this is just an optimization
             }
 
             // Proceed with the request
+            state.record_audit(context.success(&api_key));
+            request.extensions_mut().insert(api_key);
             Ok(next.run(request).await)
         }
-        Ok(false) => {
+        Ok(None) => {
             // Token is invalid, cache it to prevent repeated DB queries
-            if let Err(e) = cache_invalid_token(&mut state.redis, token).await {
+            if let Some(guard) = &state.brute_force {
+                guard.record_unknown();
+            }
+            if let Err(e) = cache_invalid_token(state.cache.as_ref(), &key_hash).await {
                 log_error!("Failed to cache invalid token: {e}");
             }
-            log_error!("Invalid or inactive token");
-            Err(StatusCode::UNAUTHORIZED)
+            Err(refuse(
+                &state,
+                presenting(context.failure("Invalid or inactive token"), token),
+            ))
         }
         Err(e) => {
             log_error!("Database error while validating token: {e}");
@@ -114,6 +184,40 @@ pub async fn auth_middleware(
     }
 }
 
+/// Logs the reason of a refused request and records it in the audit trail
+pub(crate) fn refuse(state: &AppState, event: AuditEvent) -> StatusCode {
+    log_error!("{}", event.detail.as_deref().unwrap_or("Request refused"));
+    state.record_audit(event);
+    StatusCode::UNAUTHORIZED
+}
+
+/// Tells which token was refused by its first characters, as listed to administrators
+fn presenting(mut event: AuditEvent, token: &str) -> AuditEvent {
+    event.key_prefix = Some(key_prefix(token));
+    event
+}
+
+/// Middleware restricting routes to API keys granted the scope given as state
+///
+/// Must run after `auth_middleware`, which attaches the authenticated key to the request.
+/// Returns 403 Forbidden for keys lacking the scope, recorded in the audit trail.
+pub async fn require_scope(
+    State((state, scope)): State<(AppState, ApiKeyScope)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    match request.extensions().get::<AuthenticatedKey>() {
+        Some(api_key) if api_key.has_scope(scope) => Ok(next.run(request).await),
+        Some(api_key) => {
+            let reason = format!("{api_key} lacks the {} scope", scope.as_str());
+            log_error!("{reason}");
+            state.record_audit(AuditContext::of(&request).failure(reason));
+            Err(StatusCode::FORBIDDEN)
+        }
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
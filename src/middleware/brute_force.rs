@@ -0,0 +1,555 @@
+use crate::config::constant::{
+    AUTH_BREAKER_COOLDOWN_SECS, AUTH_BREAKER_FAILURES, AUTH_BREAKER_LOOKUPS,
+    AUTH_BREAKER_WINDOW_SECS, AUTH_FAILURE_WINDOW_SECS, AUTH_LOCKOUT_SECS, AUTH_MAX_FAILURES,
+    AUTH_MAX_LOCKOUT_SECS,
+};
+use crate::core::app_state::AppState;
+use crate::database::cache::{
+    AUTH_FAILURES_PREFIX, Cache, CacheError, LOCKOUT_COUNT_PREFIX, LOCKOUT_PREFIX,
+};
+use crate::middleware::client_ip::client_key;
+use crate::middleware::rate_limit::RateLimitConfig;
+use crate::telemetry::audit::AuditContext;
+use crate::{log_error, log_warn};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+const LOCKOUT_MEMORY: Duration = Duration::from_secs(86400); // Lockouts of an IP are remembered for a day
+const DEFAULT_BREAKER_FAILURES: u32 = 200; // Without a per-IP rate limit to scale with
+
+/// Limits of the failed authentications, per client IP and overall
+///
+/// A threshold of 0 disables its protection.
+#[derive(Debug, Clone)]
+pub struct BruteForceConfig {
+    pub window: Duration,           // Window over which the failures of an IP count
+    pub max_failures: u32,          // Failures of an IP per window locking it out
+    pub lockout: Duration,          // First lockout of an IP, doubled on each next one
+    pub max_lockout: Duration,      // Longest lockout of an IP
+    pub breaker_failures: u32,      // Unknown tokens per window opening the breaker
+    pub breaker_window: Duration,   // Window over which the unknown tokens count
+    pub breaker_lookups: u32,       // Lookups running at once while the breaker is open
+    pub breaker_cooldown: Duration, // Time the token lookups stay limited
+}
+
+impl BruteForceConfig {
+    /// Builds the configuration from the `AUTH_*` environment variables
+    ///
+    /// Without `AUTH_BREAKER_FAILURES`, the breaker threshold scales with the per-IP rate limit
+    /// of `rate_limit`, see `default_breaker_failures`.
+    #[must_use]
+    pub fn from_env(rate_limit: &RateLimitConfig) -> Self {
+        let breaker_window = Duration::from_secs(*AUTH_BREAKER_WINDOW_SECS);
+        Self {
+            window: Duration::from_secs(*AUTH_FAILURE_WINDOW_SECS),
+            max_failures: *AUTH_MAX_FAILURES,
+            lockout: Duration::from_secs(*AUTH_LOCKOUT_SECS),
+            max_lockout: Duration::from_secs(*AUTH_MAX_LOCKOUT_SECS),
+            breaker_failures: AUTH_BREAKER_FAILURES
+                .unwrap_or_else(|| default_breaker_failures(rate_limit, breaker_window)),
+            breaker_window,
+            breaker_lookups: *AUTH_BREAKER_LOOKUPS,
+            breaker_cooldown: Duration::from_secs(*AUTH_BREAKER_COOLDOWN_SECS),
+        }
+    }
+
+    /// Returns the duration of the `count`th lockout of an IP within a day
+    #[must_use]
+    pub fn lockout_duration(&self, count: u64) -> Duration {
+        let doublings = count.saturating_sub(1).min(31) as u32;
+        self.lockout
+            .saturating_mul(2u32.pow(doublings))
+            .min(self.max_lockout)
+    }
+}
+
+/// Returns the requests a single client IP may send within `breaker_window`
+///
+/// The breaker then only opens once the unknown tokens are more than one client at its limit
+/// can send, i.e. they come from many addresses.
+fn default_breaker_failures(rate_limit: &RateLimitConfig, breaker_window: Duration) -> u32 {
+    let Some(bucket) = rate_limit.ip_bucket() else {
+        return DEFAULT_BREAKER_FAILURES;
+    };
+    let requests = u128::from(bucket.capacity) * breaker_window.as_millis();
+    let failures = requests.div_ceil(bucket.period.as_millis());
+    u32::try_from(failures).unwrap_or(u32::MAX).max(1)
+}
+
+/// Requests refused by the brute-force protection since the instance started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthMetrics {
+    pub rejected: u64,              // Requests refused for their credentials, with 401
+    pub rejected_locked_out: u64,   // Refused requests of locked out client IPs, answered 429
+    pub rejected_breaker_open: u64, // Requests refused with 503 while the breaker is open and busy
+    pub lockouts: u64,              // Client IPs locked out
+    pub breaker_trips: u64,         // Times the circuit breaker opened
+    pub breaker_open: bool,         // Token lookups are currently limited
+    pub breaker_failures: u32,      // Unknown tokens looked up in the current breaker window
+}
+
+#[derive(Default)]
+struct Counters {
+    rejected: AtomicU64,
+    rejected_locked_out: AtomicU64,
+    rejected_breaker_open: AtomicU64,
+    lockouts: AtomicU64,
+    breaker_trips: AtomicU64,
+}
+
+struct Breaker {
+    window_start: Instant,
+    failures: u32,               // Unknown tokens looked up since `window_start`
+    open_until: Option<Instant>, // Lookups are limited until then
+}
+
+/// Protection of the token validation against brute force
+///
+/// Each client IP failing to authenticate too often is locked out, for longer each time. The
+/// failures and lockouts are kept in the cache, so they are shared by the instances.
+///
+/// Locking out IPs doesn't stop an attacker spreading random tokens over many addresses, each
+/// of them still costing a lookup in the storage. The circuit breaker counts those lookups
+/// finding nothing: past the threshold, only `breaker_lookups` tokens and signing keys missing
+/// from the cache are looked up at once until the cooldown ends, the others are refused without
+/// a lookup. Valid keys keep authenticating through those lookups, and keys cached as valid
+/// skip them. Each instance counts the lookups it makes.
+pub struct BruteForceGuard {
+    config: BruteForceConfig,
+    breaker: Mutex<Breaker>,
+    lookups: Semaphore, // Lookups allowed at once while the breaker is open
+    counters: Counters,
+}
+
+impl BruteForceGuard {
+    /// Creates the guard, with its circuit breaker closed
+    #[must_use]
+    pub fn new(config: BruteForceConfig) -> Self {
+        Self {
+            lookups: Semaphore::new(config.breaker_lookups as usize),
+            config,
+            breaker: Mutex::new(Breaker {
+                window_start: Instant::now(),
+                failures: 0,
+                open_until: None,
+            }),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Admits the lookup of a token or key id missing from the cache
+    ///
+    /// While the breaker is open, returns the permit to hold during the lookup, or how long the
+    /// lookups stay limited when `breaker_lookups` of them are already running.
+    pub fn admit_lookup(&self) -> Result<Option<SemaphorePermit<'_>>, Duration> {
+        let Some(retry_after) = self.breaker_open() else {
+            return Ok(None);
+        };
+        self.lookups
+            .try_acquire()
+            .map(Some)
+            .map_err(|_| retry_after)
+    }
+
+    /// Returns how long the token lookups stay limited, `None` when the breaker is closed
+    #[must_use]
+    pub fn breaker_open(&self) -> Option<Duration> {
+        self.breaker_open_at(Instant::now())
+    }
+
+    fn breaker_open_at(&self, now: Instant) -> Option<Duration> {
+        let breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker
+            .open_until
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Counts a token or key id looked up in the storage and not found, opening the breaker
+    /// past the threshold
+    pub fn record_unknown(&self) {
+        self.record_unknown_at(Instant::now());
+    }
+
+    fn record_unknown_at(&self, now: Instant) {
+        if self.config.breaker_failures == 0 {
+            return;
+        }
+
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(breaker.window_start) >= self.config.breaker_window {
+            breaker.window_start = now;
+            breaker.failures = 0;
+        }
+        breaker.failures += 1;
+        if breaker.failures < self.config.breaker_failures {
+            return;
+        }
+
+        breaker.window_start = now;
+        breaker.failures = 0;
+        breaker.open_until = Some(now + self.config.breaker_cooldown);
+        self.counters.breaker_trips.fetch_add(1, Ordering::Relaxed);
+        log_warn!(
+            "{} unknown tokens within {} s, token lookups limited to {} at once for {} s",
+            self.config.breaker_failures,
+            self.config.breaker_window.as_secs(),
+            self.config.breaker_lookups,
+            self.config.breaker_cooldown.as_secs()
+        );
+    }
+
+    /// Answers 503 Service Unavailable to a request whose token can't be looked up while the
+    /// breaker is open and its lookups are all taken
+    #[must_use]
+    pub fn refuse_lookup(&self, retry_after: Duration) -> Response {
+        self.counters
+            .rejected_breaker_open
+            .fetch_add(1, Ordering::Relaxed);
+        retry_later(StatusCode::SERVICE_UNAVAILABLE, retry_after)
+    }
+
+    /// Returns the metrics of the requests refused so far
+    #[must_use]
+    pub fn metrics(&self) -> AuthMetrics {
+        let breaker_open = self.breaker_open().is_some();
+        let breaker_failures = self
+            .breaker
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .failures;
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        AuthMetrics {
+            rejected: count(&self.counters.rejected),
+            rejected_locked_out: count(&self.counters.rejected_locked_out),
+            rejected_breaker_open: count(&self.counters.rejected_breaker_open),
+            lockouts: count(&self.counters.lockouts),
+            breaker_trips: count(&self.counters.breaker_trips),
+            breaker_open,
+            breaker_failures,
+        }
+    }
+
+    /// Returns how long `ip` stays locked out, `None` if it isn't
+    ///
+    /// The addresses of an IPv6 /64 are locked out together. An unavailable cache locks out
+    /// nobody.
+    async fn lockout_remaining(&self, cache: &dyn Cache, ip: IpAddr) -> Option<Duration> {
+        if self.config.max_failures == 0 {
+            return None;
+        }
+
+        let client = client_key(ip);
+        match cache.get(&format!("{LOCKOUT_PREFIX}{client}")).await {
+            Ok(Some(until)) => {
+                let millis = until.parse::<i64>().ok()? - Utc::now().timestamp_millis();
+                (millis > 0).then(|| Duration::from_millis(millis as u64))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log_error!("Cache error while checking the lockout of {client}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Counts a failed authentication of `ip`, returning the lockout it starts if any
+    ///
+    /// The lockout starts on the failure reaching the threshold only, so concurrent failures
+    /// lock out once. The count then starts over.
+    async fn record_failure(
+        &self,
+        cache: &dyn Cache,
+        ip: IpAddr,
+    ) -> Result<Option<Duration>, CacheError> {
+        if self.config.max_failures == 0 {
+            return Ok(None);
+        }
+
+        let client = client_key(ip);
+        let failures_key = format!("{AUTH_FAILURES_PREFIX}{client}");
+        let failures = cache.incr(&failures_key, self.config.window).await?;
+        if failures != u64::from(self.config.max_failures) {
+            return Ok(None);
+        }
+
+        let count = cache
+            .incr(&format!("{LOCKOUT_COUNT_PREFIX}{client}"), LOCKOUT_MEMORY)
+            .await?;
+        let lockout = self.config.lockout_duration(count);
+        let until = Utc::now().timestamp_millis() + lockout.as_millis() as i64;
+        cache
+            .set_ex(
+                &format!("{LOCKOUT_PREFIX}{client}"),
+                &until.to_string(),
+                lockout,
+            )
+            .await?;
+        cache.del(&failures_key).await?;
+        self.counters.lockouts.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(lockout))
+    }
+}
+
+/// Answers `status` with the `Retry-After` header, in whole seconds
+fn retry_later(status: StatusCode, retry_after: Duration) -> Response {
+    let mut response = status.into_response();
+    let seconds = retry_after.as_millis().div_ceil(1000).max(1);
+    if let Ok(value) = HeaderValue::from_str(&seconds.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+/// Middleware locking out the client IPs failing to authenticate too often
+///
+/// Must run before `signature_middleware` and `auth_middleware`: each 401 Unauthorized they
+/// answer counts as a failure of the client IP, as resolved by `resolve_client_ip`. Once locked
+/// out, the requests of an IP refused for their credentials get 429 Too Many Requests instead,
+/// until the lockout ends; valid credentials are still accepted, so the clients sharing the
+/// address of a misconfigured one keep working. The lockout is recorded in the audit trail.
+/// Requests served without the peer address are only counted in the metrics.
+pub async fn guard_authentication(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(guard) = &state.brute_force else {
+        return next.run(request).await;
+    };
+
+    let context = AuditContext::of(&request);
+    let locked_out = match context.ip {
+        Some(ip) => guard.lockout_remaining(state.cache.as_ref(), ip).await,
+        None => None,
+    };
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return response;
+    }
+
+    if let Some(retry_after) = locked_out {
+        guard
+            .counters
+            .rejected_locked_out
+            .fetch_add(1, Ordering::Relaxed);
+        return retry_later(StatusCode::TOO_MANY_REQUESTS, retry_after);
+    }
+    guard.counters.rejected.fetch_add(1, Ordering::Relaxed);
+    if let Some(ip) = context.ip {
+        match guard.record_failure(state.cache.as_ref(), ip).await {
+            Ok(Some(lockout)) => {
+                let reason = format!(
+                    "{} locked out for {} s after {} failed authentications",
+                    client_key(ip),
+                    lockout.as_secs(),
+                    guard.config.max_failures
+                );
+                log_warn!("{reason}");
+                state.record_audit(context.failure(reason));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log_error!(
+                    "Cache error while counting the failures of {}: {e}",
+                    client_key(ip)
+                );
+            }
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::cache::generate_invalid_token_cache_key;
+    use crate::database::cache::memory::MemoryCache;
+
+    fn config() -> BruteForceConfig {
+        BruteForceConfig {
+            window: Duration::from_secs(300),
+            max_failures: 3,
+            lockout: Duration::from_secs(60),
+            max_lockout: Duration::from_secs(600),
+            breaker_failures: 2,
+            breaker_window: Duration::from_secs(60),
+            breaker_lookups: 1,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_lockout_doubles_up_to_max() {
+        let config = config();
+        let durations: Vec<u64> = (1..=6)
+            .map(|count| config.lockout_duration(count).as_secs())
+            .collect();
+        assert_eq!(durations, [60, 120, 240, 480, 600, 600]);
+        assert_eq!(config.lockout_duration(u64::MAX), config.max_lockout);
+    }
+
+    #[tokio::test]
+    async fn test_ip_locked_out_after_max_failures() {
+        let guard = BruteForceGuard::new(config());
+        let cache = MemoryCache::new(100);
+        let ip = IpAddr::from([192, 0, 2, 3]);
+
+        for _ in 0..2 {
+            assert_eq!(guard.record_failure(&cache, ip).await.unwrap(), None);
+        }
+        assert_eq!(guard.lockout_remaining(&cache, ip).await, None);
+        assert_eq!(
+            guard.record_failure(&cache, ip).await.unwrap(),
+            Some(Duration::from_secs(60))
+        );
+        let remaining = guard.lockout_remaining(&cache, ip).await.unwrap();
+        assert!(remaining > Duration::from_secs(59));
+
+        // Other IPs are not affected, the next lockout of the IP lasts twice as long
+        let other = IpAddr::from([192, 0, 2, 4]);
+        assert_eq!(guard.lockout_remaining(&cache, other).await, None);
+        for _ in 0..2 {
+            guard.record_failure(&cache, ip).await.unwrap();
+        }
+        assert_eq!(
+            guard.record_failure(&cache, ip).await.unwrap(),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(guard.metrics().lockouts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_lockout_survives_cache_pressure() {
+        let guard = BruteForceGuard::new(config());
+        let cache = MemoryCache::new(2);
+        let ip = IpAddr::from([192, 0, 2, 3]);
+        for _ in 0..3 {
+            guard.record_failure(&cache, ip).await.unwrap();
+        }
+
+        // Cached invalid tokens sent by the locked out client can't evict its lockout
+        for i in 0..10 {
+            let key = generate_invalid_token_cache_key(&i.to_string());
+            cache
+                .set_ex(&key, "1", Duration::from_secs(60))
+                .await
+                .unwrap();
+        }
+        assert!(guard.lockout_remaining(&cache, ip).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_locked_out_by_64() {
+        let guard = BruteForceGuard::new(config());
+        let cache = MemoryCache::new(100);
+
+        for host in 1..=3u16 {
+            let ip = IpAddr::from([0x2001, 0xdb8, 0, 1, 0, 0, 0, host]);
+            guard.record_failure(&cache, ip).await.unwrap();
+        }
+        let neighbour = IpAddr::from([0x2001, 0xdb8, 0, 1, 0xffff, 0, 0, 9]);
+        assert!(guard.lockout_remaining(&cache, neighbour).await.is_some());
+        let other = IpAddr::from([0x2001, 0xdb8, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(guard.lockout_remaining(&cache, other).await, None);
+    }
+
+    #[test]
+    fn test_breaker_opens_then_closes() {
+        let guard = BruteForceGuard::new(config());
+        let start = Instant::now();
+
+        guard.record_unknown_at(start);
+        // The window ended, the first failure is forgotten
+        guard.record_unknown_at(start + Duration::from_secs(61));
+        assert_eq!(guard.breaker_open_at(start + Duration::from_secs(61)), None);
+
+        guard.record_unknown_at(start + Duration::from_secs(62));
+        assert_eq!(
+            guard.breaker_open_at(start + Duration::from_secs(62)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(guard.breaker_open_at(start + Duration::from_secs(92)), None);
+        assert_eq!(guard.metrics().breaker_trips, 1);
+    }
+
+    #[test]
+    fn test_open_breaker_admits_a_few_lookups() {
+        let guard = BruteForceGuard::new(config());
+        assert!(matches!(guard.admit_lookup(), Ok(None)));
+
+        guard.record_unknown();
+        guard.record_unknown();
+        let permit = guard.admit_lookup().unwrap();
+        assert!(permit.is_some());
+        // The only lookup allowed at once is running
+        assert!(guard.admit_lookup().unwrap_err() > Duration::from_secs(29));
+        drop(permit);
+        assert!(guard.admit_lookup().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_breaker_threshold_scales_with_ip_rate_limit() {
+        let rate_limit = RateLimitConfig {
+            window: Duration::from_secs(60),
+            per_ip: 1200,
+            speeds_write: 0,
+            speeds_read: 0,
+            stream_read: 0,
+            admin: 0,
+        };
+        let window = Duration::from_secs(60);
+        assert_eq!(default_breaker_failures(&rate_limit, window), 1200);
+        assert_eq!(
+            default_breaker_failures(&rate_limit, Duration::from_secs(30)),
+            600
+        );
+        let hourly = RateLimitConfig {
+            window: Duration::from_secs(3600),
+            per_ip: 100,
+            ..rate_limit.clone()
+        };
+        assert_eq!(default_breaker_failures(&hourly, window), 2);
+
+        // Without a per-IP limit, the fixed default applies
+        let unlimited = RateLimitConfig {
+            per_ip: 0,
+            ..rate_limit
+        };
+        assert_eq!(
+            default_breaker_failures(&unlimited, window),
+            DEFAULT_BREAKER_FAILURES
+        );
+    }
+
+    #[test]
+    fn test_disabled_breaker_never_opens() {
+        let guard = BruteForceGuard::new(BruteForceConfig {
+            breaker_failures: 0,
+            ..config()
+        });
+        for _ in 0..10 {
+            guard.record_unknown();
+        }
+        assert_eq!(guard.breaker_open(), None);
+    }
+
+    #[test]
+    fn test_retry_later_rounds_up() {
+        let response = retry_later(StatusCode::TOO_MANY_REQUESTS, Duration::from_millis(1500));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
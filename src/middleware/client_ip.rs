@@ -0,0 +1,234 @@
+use crate::config::constant::TRUSTED_PROXIES;
+use crate::core::app_state::AppState;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Extensions, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use std::net::{IpAddr, SocketAddr};
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Address of the client of a request, set as a request extension by `resolve_client_ip`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Networks of the reverse proxies, whose `X-Forwarded-For` header tells the client address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>, // Address and prefix length of each network
+}
+
+impl TrustedProxies {
+    /// Builds the list from the `TRUSTED_PROXIES` environment variable
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&TRUSTED_PROXIES)
+    }
+
+    /// Parses addresses and networks in CIDR notation, e.g. `10.0.0.1` or `fd00::/8`
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, String> {
+        let networks = entries
+            .iter()
+            .map(|entry| {
+                let entry = entry.as_ref();
+                let invalid = || format!("Invalid trusted proxy '{entry}'");
+                let (address, prefix) = match entry.split_once('/') {
+                    Some((address, prefix)) => {
+                        (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?))
+                    }
+                    None => (entry, None),
+                };
+                let address = address.parse::<IpAddr>().map_err(|_| invalid())?;
+                let address = address.to_canonical();
+                let bits = if address.is_ipv4() { 32 } else { 128 };
+                match prefix {
+                    Some(prefix) if prefix > bits => Err(invalid()),
+                    prefix => Ok((address, prefix.unwrap_or(bits))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { networks })
+    }
+
+    /// Checks whether `ip` belongs to one of the networks
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks
+            .iter()
+            .any(|&(network, prefix)| match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    same_prefix(network.to_bits().into(), ip.to_bits().into(), prefix, 32)
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    same_prefix(network.to_bits(), ip.to_bits(), prefix, 128)
+                }
+                _ => false,
+            })
+    }
+
+    /// Returns the address of the client, as told by the trusted proxies
+    ///
+    /// `X-Forwarded-For` is only read when the peer is a trusted proxy, as any client can send
+    /// it. Each proxy appends the address it got the request from, so the hops are read from the
+    /// right and the first one not trusted is the client; a malformed hop stops there.
+    #[must_use]
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip.to_canonical();
+            if !self.contains(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Checks whether the first `prefix` of `bits` bits of two addresses are equal
+fn same_prefix(network: u128, ip: u128, prefix: u8, bits: u32) -> bool {
+    let shift = bits - u32::from(prefix);
+    network.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+}
+
+/// Returns the key the limits and lockouts of a client are counted under
+///
+/// An IPv6 client usually gets a whole /64 and can pick any address in it, so the addresses
+/// of a /64 share a key.
+#[must_use]
+pub fn client_key(ip: IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let network = std::net::Ipv6Addr::from_bits(ip.to_bits() & !u128::from(u64::MAX));
+            format!("{network}/64")
+        }
+    }
+}
+
+/// Returns the client IP attached to a request, or its peer address if none was
+#[must_use]
+pub fn client_ip_of(extensions: &Extensions) -> Option<IpAddr> {
+    match extensions.get::<ClientIp>() {
+        Some(ClientIp(ip)) => Some(*ip),
+        None => extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical()),
+    }
+}
+
+/// Middleware attaching the address of the client to the request, as `ClientIp`
+///
+/// Must run before the other middlewares, which read it. Requests served without the peer
+/// address, e.g. in tests, get none.
+pub async fn resolve_client_ip(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let ip = match &state.trusted_proxies {
+            Some(proxies) => proxies.client_ip(addr.ip(), request.headers()),
+            None => addr.ip().to_canonical(),
+        };
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::parse(&["10.0.0.0/8", "192.0.2.1", "fd00::/8"]).unwrap()
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED_FOR_HEADER, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        let proxies = proxies();
+        assert!(proxies.contains("10.1.2.3".parse().unwrap()));
+        assert!(proxies.contains("192.0.2.1".parse().unwrap()));
+        assert!(!proxies.contains("192.0.2.2".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("2001:db8::1".parse().unwrap()));
+        // IPv4 clients of a dual-stack socket
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        assert!(
+            TrustedProxies::parse(&["0.0.0.0/0"])
+                .unwrap()
+                .contains("203.0.113.9".parse().unwrap())
+        );
+        for invalid in ["10.0.0.0/33", "fd00::/129", "proxy", "10.0.0.0/x"] {
+            assert!(TrustedProxies::parse(&[invalid]).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_client_ip_from_trusted_proxies() {
+        let proxies = proxies();
+        let client: IpAddr = "203.0.113.9".parse().unwrap();
+
+        // The header of untrusted peers is ignored
+        let headers = forwarded_for(&["198.51.100.1"]);
+        assert_eq!(proxies.client_ip(client, &headers), client);
+
+        // The first untrusted hop from the right is the client, whatever it claims before
+        let headers = forwarded_for(&["198.51.100.1, 203.0.113.9", "10.0.0.2"]);
+        assert_eq!(
+            proxies.client_ip("10.0.0.1".parse().unwrap(), &headers),
+            client
+        );
+
+        // Without the header, or with a malformed hop, the last trusted address is kept
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(proxies.client_ip(proxy, &HeaderMap::new()), proxy);
+        let headers = forwarded_for(&["203.0.113.9, unknown"]);
+        assert_eq!(proxies.client_ip(proxy, &headers), proxy);
+    }
+
+    #[test]
+    fn test_client_key_groups_ipv6_by_64() {
+        assert_eq!(client_key("203.0.113.9".parse().unwrap()), "203.0.113.9");
+        assert_eq!(
+            client_key("::ffff:203.0.113.9".parse().unwrap()),
+            "203.0.113.9"
+        );
+        assert_eq!(
+            client_key("2001:db8:1:2:aaaa::1".parse().unwrap()),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(
+            client_key("2001:db8:1:2:bbbb::2".parse().unwrap()),
+            client_key("2001:db8:1:2:aaaa::1".parse().unwrap())
+        );
+        assert_ne!(
+            client_key("2001:db8:1:3::1".parse().unwrap()),
+            client_key("2001:db8:1:2::1".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,550 @@
+use crate::config::constant::{
+    JWT_ADMIN_SCOPE, JWT_AUDIENCE, JWT_ISSUER, JWT_JWKS, JWT_JWKS_REFRESH_SECS, JWT_LEEWAY_SECS,
+};
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::log_warn;
+use chrono::DateTime;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use serde::Deserialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Algorithms accepted for the signature of the tokens, the others are refused
+const ALLOWED_ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::ES256];
+
+/// Minimum delay between two loads of the key set triggered by an unknown key id
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Timeout of a request fetching the key set
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the JSON Web Key Set is loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwksSource {
+    File(PathBuf), // Local file, e.g. mounted from a secret
+    Url(String),   // Endpoint of the identity provider
+}
+
+impl JwksSource {
+    /// Reads an http(s) URL as a `Url`, anything else as a file path
+    #[must_use]
+    pub fn parse(value: &str) -> Self {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            Self::Url(value.to_string())
+        } else {
+            Self::File(PathBuf::from(value))
+        }
+    }
+}
+
+/// Settings of the verification of JWT bearer tokens
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub jwks: JwksSource,            // Public keys the tokens are signed with
+    pub issuer: String,              // Expected `iss` claim
+    pub audience: String,            // Expected `aud` claim
+    pub leeway: Duration,            // Clock skew tolerated on `exp` and `nbf`
+    pub jwks_refresh: Duration,      // Delay after which the key set is loaded again
+    pub admin_scope: Option<String>, // Token scope granting `admin`, None to never grant it
+}
+
+impl JwtConfig {
+    /// Builds the configuration from the `JWT_*` environment variables
+    ///
+    /// Returns `None` when `JWT_JWKS` is not set, i.e. only API keys are accepted.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let jwks = JWT_JWKS.trim();
+        if jwks.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            jwks: JwksSource::parse(jwks),
+            issuer: JWT_ISSUER.clone(),
+            audience: JWT_AUDIENCE.clone(),
+            leeway: Duration::from_secs(*JWT_LEEWAY_SECS),
+            jwks_refresh: Duration::from_secs(*JWT_JWKS_REFRESH_SECS),
+            admin_scope: Some(JWT_ADMIN_SCOPE.trim().to_string()).filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+/// Errors raised while verifying a JWT
+#[derive(Debug)]
+pub enum JwtError {
+    /// The configuration lacks a setting
+    Config(&'static str),
+
+    /// The key set could not be loaded
+    Jwks(String),
+
+    /// The token header could not be read
+    Malformed(jsonwebtoken::errors::Error),
+
+    /// The token is signed with an algorithm other than RS256 and ES256
+    UnsupportedAlgorithm(Algorithm),
+
+    /// No key of the key set matches the key id of the token
+    UnknownKey(Option<String>),
+
+    /// The signature, expiry, issuer or audience of the token is wrong
+    Invalid(jsonwebtoken::errors::Error),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::Config(e) => write!(f, "Invalid JWT configuration: {}", e),
+            JwtError::Jwks(e) => write!(f, "JSON Web Key Set unavailable: {}", e),
+            JwtError::Malformed(e) => write!(f, "Malformed JWT: {}", e),
+            JwtError::UnsupportedAlgorithm(alg) => {
+                write!(f, "JWT signed with the unsupported algorithm {:?}", alg)
+            }
+            JwtError::UnknownKey(kid) => write!(f, "JWT signed with the unknown key {:?}", kid),
+            JwtError::Invalid(e) => write!(f, "Invalid JWT: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+/// Claims read from the tokens, the registered ones are checked by `jsonwebtoken`
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: Option<String>,      // Subject, e.g. the client id
+    exp: i64,                 // Expiry, seconds since the epoch
+    scope: Option<String>,    // Space separated scopes, RFC 8693
+    scp: Option<Vec<String>>, // Array of scopes, as issued by some providers
+}
+
+impl Claims {
+    /// Maps the scopes of the token to the API key scopes, ignoring the unknown ones
+    ///
+    /// Identity providers add their own scopes, such as `openid`, which grant nothing here.
+    /// Since any client of the provider may be issued a scope named `admin`, administration is
+    /// only granted by `admin_scope`.
+    fn scopes(&self, admin_scope: Option<&str>) -> Vec<ApiKeyScope> {
+        let mut scopes = Vec::new();
+        let names = self
+            .scope
+            .iter()
+            .flat_map(|scope| scope.split_whitespace())
+            .chain(self.scp.iter().flatten().map(String::as_str));
+        let granted = names.filter_map(|name| {
+            if Some(name) == admin_scope {
+                return Some(ApiKeyScope::Admin);
+            }
+            ApiKeyScope::try_from(name)
+                .ok()
+                .filter(|scope| *scope != ApiKeyScope::Admin)
+        });
+        for scope in granted {
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        scopes
+    }
+}
+
+/// Key set in use, along with when it was loaded
+#[derive(Clone)]
+struct LoadedJwks {
+    set: Arc<JwkSet>,
+    loaded_at: Instant,
+}
+
+/// Verifies JWT bearer tokens against a JSON Web Key Set
+///
+/// The key set is loaded on first use and kept for `jwks_refresh`. A token signed with an
+/// unknown key id loads it again, at most every `MIN_RELOAD_INTERVAL`, so keys rotated by the
+/// identity provider are picked up without waiting for the refresh.
+pub struct JwtVerifier {
+    config: JwtConfig,
+    client: reqwest::Client,
+    jwks: RwLock<Option<LoadedJwks>>,
+}
+
+impl JwtVerifier {
+    /// Creates a verifier, refusing a configuration without issuer or audience
+    pub fn new(config: JwtConfig) -> Result<Self, JwtError> {
+        if config.issuer.is_empty() {
+            return Err(JwtError::Config("JWT_ISSUER must be set"));
+        }
+        if config.audience.is_empty() {
+            return Err(JwtError::Config("JWT_AUDIENCE must be set"));
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| JwtError::Jwks(e.to_string()))?;
+        Ok(Self {
+            config,
+            client,
+            jwks: RwLock::new(None),
+        })
+    }
+
+    /// Loads the key set now rather than on the first token, returning its number of keys
+    pub async fn refresh(&self) -> Result<usize, JwtError> {
+        Ok(self.jwks(Duration::ZERO).await?.set.keys.len())
+    }
+
+    /// Verifies a token, returning it as an authenticated key granted the scopes of its claims
+    pub async fn verify(&self, token: &str) -> Result<AuthenticatedKey, JwtError> {
+        let header = decode_header(token).map_err(JwtError::Malformed)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(JwtError::UnsupportedAlgorithm(header.alg));
+        }
+
+        let jwk = self.find_key(header.kid.as_deref()).await?;
+        if let Some(alg) = jwk.common.key_algorithm
+            && alg.to_string().parse::<Algorithm>().ok() != Some(header.alg)
+        {
+            // The key is meant for another algorithm
+            return Err(JwtError::UnsupportedAlgorithm(header.alg));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(JwtError::Invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.validate_nbf = true;
+        validation.leeway = self.config.leeway.as_secs();
+
+        let claims = decode::<Claims>(token, &key, &validation)
+            .map_err(JwtError::Invalid)?
+            .claims;
+        Ok(AuthenticatedKey {
+            id: None,
+            scopes: claims.scopes(self.config.admin_scope.as_deref()),
+            expires_at: DateTime::from_timestamp(claims.exp, 0),
+            subject: claims.sub,
+            sensor_name: None,
+        })
+    }
+
+    /// Returns the key matching `kid`, loading the key set again when none does
+    async fn find_key(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
+        let jwks = self.jwks(self.config.jwks_refresh).await?;
+        if let Some(jwk) = select_key(&jwks.set, kid) {
+            return Ok(jwk.clone());
+        }
+
+        // The identity provider may have rotated its keys since the last load
+        let jwks = self.jwks(MIN_RELOAD_INTERVAL).await?;
+        select_key(&jwks.set, kid)
+            .cloned()
+            .ok_or_else(|| JwtError::UnknownKey(kid.map(str::to_string)))
+    }
+
+    /// Returns the key set, loading it when it is older than `max_age`
+    ///
+    /// When the key set can't be loaded again, the one already loaded is kept until the next
+    /// refresh, or the next unknown key id.
+    async fn jwks(&self, max_age: Duration) -> Result<LoadedJwks, JwtError> {
+        if let Some(jwks) = self.jwks.read().await.as_ref()
+            && jwks.loaded_at.elapsed() < max_age
+        {
+            return Ok(jwks.clone());
+        }
+
+        let mut current = self.jwks.write().await;
+        if let Some(jwks) = current.as_ref()
+            && jwks.loaded_at.elapsed() < max_age
+        {
+            // Loaded by another request in the meantime
+            return Ok(jwks.clone());
+        }
+        match self.load().await {
+            Ok(keys) => {
+                let jwks = LoadedJwks {
+                    set: Arc::new(keys),
+                    loaded_at: Instant::now(),
+                };
+                *current = Some(jwks.clone());
+                Ok(jwks)
+            }
+            Err(e) => match current.as_mut() {
+                Some(jwks) => {
+                    log_warn!("Keeping the previous JSON Web Key Set: {e}");
+                    jwks.loaded_at = Instant::now();
+                    Ok(jwks.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Reads the key set from its file or URL
+    async fn load(&self) -> Result<JwkSet, JwtError> {
+        match &self.config.jwks {
+            JwksSource::File(path) => {
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| JwtError::Jwks(format!("{}: {e}", path.display())))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| JwtError::Jwks(format!("{}: {e}", path.display())))
+            }
+            JwksSource::Url(url) => self
+                .client
+                .get(url)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| JwtError::Jwks(e.to_string()))?
+                .json()
+                .await
+                .map_err(|e| JwtError::Jwks(format!("{url}: {e}"))),
+        }
+    }
+}
+
+/// Selects the key of `kid`, or the only key of the set for a token without key id
+fn select_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
+
+/// Tells JWTs apart from API keys, which never contain a dot once generated by the server
+#[must_use]
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jwt");
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            jwks: JwksSource::File(PathBuf::from(FIXTURES).join("jwks.json")),
+            issuer: "https://idp.example.com".to_string(),
+            audience: "speedstream".to_string(),
+            leeway: Duration::from_secs(60),
+            jwks_refresh: Duration::from_secs(3600),
+            admin_scope: None,
+        }
+    }
+
+    fn sign(alg: Algorithm, kid: Option<&str>, pem: &str, claims: &serde_json::Value) -> String {
+        let pem = std::fs::read(PathBuf::from(FIXTURES).join(pem)).unwrap();
+        let key = match alg {
+            Algorithm::ES256 => EncodingKey::from_ec_pem(&pem).unwrap(),
+            _ => EncodingKey::from_rsa_pem(&pem).unwrap(),
+        };
+        let mut header = Header::new(alg);
+        header.kid = kid.map(str::to_string);
+        encode(&header, claims, &key).unwrap()
+    }
+
+    fn claims(exp_offset: i64) -> serde_json::Value {
+        json!({
+            "iss": "https://idp.example.com",
+            "aud": "speedstream",
+            "sub": "sensor-42",
+            "exp": chrono::Utc::now().timestamp() + exp_offset,
+            "scope": "openid speeds:write speeds:read",
+        })
+    }
+
+    #[test]
+    fn test_is_jwt() {
+        assert!(is_jwt("eyJhbGciOiJSUzI1NiJ9.eyJzdWIiOiJhIn0.c2ln"));
+        assert!(!is_jwt("abc123def456"));
+        assert!(!is_jwt("abc-123_DEF.456~xyz"));
+    }
+
+    #[test]
+    fn test_jwks_source_parse() {
+        assert_eq!(
+            JwksSource::parse("https://idp.example.com/.well-known/jwks.json"),
+            JwksSource::Url("https://idp.example.com/.well-known/jwks.json".to_string())
+        );
+        assert_eq!(
+            JwksSource::parse("/etc/speedstream/jwks.json"),
+            JwksSource::File(PathBuf::from("/etc/speedstream/jwks.json"))
+        );
+    }
+
+    #[test]
+    fn test_claims_scopes() {
+        let claims: Claims = serde_json::from_value(json!({
+            "exp": 0,
+            "scope": "openid speeds:read admin",
+            "scp": ["speeds:read", "stream:read"],
+        }))
+        .unwrap();
+        assert_eq!(
+            claims.scopes(None),
+            vec![ApiKeyScope::SpeedsRead, ApiKeyScope::StreamRead]
+        );
+        assert_eq!(
+            claims.scopes(Some("admin")),
+            vec![
+                ApiKeyScope::SpeedsRead,
+                ApiKeyScope::Admin,
+                ApiKeyScope::StreamRead
+            ]
+        );
+
+        // Only the configured scope grants administration
+        let claims: Claims = serde_json::from_value(json!({
+            "exp": 0,
+            "scope": "admin speedstream:admin",
+        }))
+        .unwrap();
+        assert_eq!(
+            claims.scopes(Some("speedstream:admin")),
+            vec![ApiKeyScope::Admin]
+        );
+    }
+
+    #[test]
+    fn test_new_requires_issuer_and_audience() {
+        let mut config = config();
+        config.audience.clear();
+        assert!(matches!(
+            JwtVerifier::new(config),
+            Err(JwtError::Config("JWT_AUDIENCE must be set"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_rs256_and_es256() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+        assert_eq!(verifier.refresh().await.unwrap(), 2);
+
+        for (alg, kid, pem) in [
+            (Algorithm::RS256, "rsa-1", "rsa_private.pem"),
+            (Algorithm::ES256, "ec-1", "ec_private.pem"),
+        ] {
+            let token = sign(alg, Some(kid), pem, &claims(300));
+            let key = verifier.verify(&token).await.unwrap();
+            assert_eq!(key.id, None);
+            assert_eq!(key.subject.as_deref(), Some("sensor-42"));
+            assert_eq!(
+                key.scopes,
+                vec![ApiKeyScope::SpeedsWrite, ApiKeyScope::SpeedsRead]
+            );
+            assert!(key.expires_at.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_checks_expiry_with_leeway() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+
+        // Expired 30 seconds ago, within the leeway
+        let token = sign(
+            Algorithm::RS256,
+            Some("rsa-1"),
+            "rsa_private.pem",
+            &claims(-30),
+        );
+        assert!(verifier.verify(&token).await.is_ok());
+
+        let token = sign(
+            Algorithm::RS256,
+            Some("rsa-1"),
+            "rsa_private.pem",
+            &claims(-120),
+        );
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::Invalid(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_checks_issuer_and_audience() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+        for (claim, value) in [("iss", "https://evil.example.com"), ("aud", "other-api")] {
+            let mut claims = claims(300);
+            claims[claim] = json!(value);
+            let token = sign(Algorithm::ES256, Some("ec-1"), "ec_private.pem", &claims);
+            assert!(
+                matches!(verifier.verify(&token).await, Err(JwtError::Invalid(_))),
+                "{claim}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_refuses_unknown_keys() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+
+        // Unknown key id, and a key id of the set with a key that isn't its own
+        let token = sign(
+            Algorithm::RS256,
+            Some("rsa-2"),
+            "unknown_private.pem",
+            &claims(300),
+        );
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::UnknownKey(Some(_)))
+        ));
+        let token = sign(
+            Algorithm::RS256,
+            Some("rsa-1"),
+            "unknown_private.pem",
+            &claims(300),
+        );
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::Invalid(_))
+        ));
+
+        // Without key id, the key can't be chosen among the two of the set
+        let token = sign(Algorithm::RS256, None, "rsa_private.pem", &claims(300));
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::UnknownKey(None))
+        ));
+
+        // Shared secrets are refused whatever the key set
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &claims(300),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::UnsupportedAlgorithm(Algorithm::HS256))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_jwks() {
+        let mut config = config();
+        config.jwks = JwksSource::File(PathBuf::from(FIXTURES).join("missing.json"));
+        let verifier = JwtVerifier::new(config).unwrap();
+        let token = sign(
+            Algorithm::RS256,
+            Some("rsa-1"),
+            "rsa_private.pem",
+            &claims(300),
+        );
+        assert!(matches!(
+            verifier.verify(&token).await,
+            Err(JwtError::Jwks(_))
+        ));
+    }
+}
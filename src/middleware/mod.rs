@@ -1 +1,6 @@
 pub mod auth;
+pub mod brute_force;
+pub mod client_ip;
+pub mod jwt;
+pub mod rate_limit;
+pub mod signature;
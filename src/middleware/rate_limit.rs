@@ -0,0 +1,257 @@
+use crate::config::constant::{
+    RATE_LIMIT_ADMIN, RATE_LIMIT_PER_IP, RATE_LIMIT_SPEEDS_READ, RATE_LIMIT_SPEEDS_WRITE,
+    RATE_LIMIT_STREAM_READ, RATE_LIMIT_WINDOW_SECS,
+};
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::api_key_scope::ApiKeyScope;
+use crate::core::app_state::AppState;
+use crate::database::cache::Cache;
+use crate::database::cache::rate_limit::{BucketState, RATE_LIMIT_PREFIX, TokenBucket};
+use crate::middleware::client_ip::{client_ip_of, client_key};
+use crate::{log_error, log_warn};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+
+/// Limits of the requests, as token buckets refilled over `window`
+///
+/// A limit of 0 disables it.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub window: Duration,  // Time for an exhausted limit to be fully restored
+    pub per_ip: u32,       // Requests of a client IP per window, before authentication
+    pub speeds_write: u32, // Requests of a key per window on the `speeds:write` routes
+    pub speeds_read: u32,  // Requests of a key per window on the `speeds:read` routes
+    pub stream_read: u32,  // Requests of a key per window on the `stream:read` routes
+    pub admin: u32,        // Requests of a key per window on the `admin` routes
+}
+
+impl RateLimitConfig {
+    /// Builds the configuration from the `RATE_LIMIT_*` environment variables
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            window: Duration::from_secs(*RATE_LIMIT_WINDOW_SECS),
+            per_ip: *RATE_LIMIT_PER_IP,
+            speeds_write: *RATE_LIMIT_SPEEDS_WRITE,
+            speeds_read: *RATE_LIMIT_SPEEDS_READ,
+            stream_read: *RATE_LIMIT_STREAM_READ,
+            admin: *RATE_LIMIT_ADMIN,
+        }
+    }
+
+    /// Returns the bucket of each key on the routes requiring `scope`, `None` if unlimited
+    #[must_use]
+    pub fn key_bucket(&self, scope: ApiKeyScope) -> Option<TokenBucket> {
+        let capacity = match scope {
+            ApiKeyScope::SpeedsWrite => self.speeds_write,
+            ApiKeyScope::SpeedsRead => self.speeds_read,
+            ApiKeyScope::StreamRead => self.stream_read,
+            ApiKeyScope::Admin => self.admin,
+        };
+        self.bucket(capacity)
+    }
+
+    /// Returns the bucket of each client IP, `None` if unlimited
+    #[must_use]
+    pub fn ip_bucket(&self) -> Option<TokenBucket> {
+        self.bucket(self.per_ip)
+    }
+
+    fn bucket(&self, capacity: u32) -> Option<TokenBucket> {
+        (capacity > 0 && !self.window.is_zero()).then_some(TokenBucket {
+            capacity,
+            period: self.window,
+        })
+    }
+}
+
+/// Identifies the bucket of a key, shared by every instance
+fn key_bucket_key(api_key: &AuthenticatedKey, scope: ApiKeyScope) -> String {
+    match (api_key.id, &api_key.subject) {
+        (Some(id), _) => format!("{RATE_LIMIT_PREFIX}key:{id}:{}", scope.as_str()),
+        (None, subject) => format!(
+            "{RATE_LIMIT_PREFIX}jwt:{}:{}",
+            subject.as_deref().unwrap_or_default(),
+            scope.as_str()
+        ),
+    }
+}
+
+/// Takes a token from a bucket, letting the request through if the cache fails
+///
+/// Limiting is a protection, an unavailable cache must not stop the ingestion.
+async fn take_token(cache: &dyn Cache, key: &str, bucket: TokenBucket) -> Option<BucketState> {
+    match cache.take_token(key, bucket).await {
+        Ok(state) => Some(state),
+        Err(e) => {
+            log_error!("Cache error while rate limiting: {e}");
+            None
+        }
+    }
+}
+
+/// Sets the `RateLimit-*` headers describing a bucket
+fn set_headers(headers: &mut HeaderMap, state: &BucketState) {
+    let seconds = |duration: Duration| duration.as_millis().div_ceil(1000).to_string();
+    let mut set = |name: &'static str, value: String| {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    };
+    set("ratelimit-limit", state.limit.to_string());
+    set("ratelimit-remaining", state.remaining.to_string());
+    set("ratelimit-reset", seconds(state.reset));
+    if !state.allowed {
+        set(header::RETRY_AFTER.as_str(), seconds(state.retry_after));
+    }
+}
+
+/// Answers 429 Too Many Requests, with the headers telling when to retry
+fn too_many_requests(state: &BucketState) -> Response {
+    let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+    set_headers(response.headers_mut(), state);
+    response
+}
+
+/// Middleware limiting the requests of each client IP, before authentication
+///
+/// Stops a client flooding the server, even with invalid tokens. Requests served without the
+/// peer address, e.g. in tests, are not limited. Behind the `TRUSTED_PROXIES`, the client is
+/// read from `X-Forwarded-For`, see `resolve_client_ip`. The addresses of an IPv6 /64 share
+/// their bucket.
+pub async fn limit_ip_rate(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (Some(config), Some(ip)) = (&state.rate_limit, client_ip_of(request.extensions())) else {
+        return next.run(request).await;
+    };
+    let Some(bucket) = config.ip_bucket() else {
+        return next.run(request).await;
+    };
+
+    let client = client_key(ip);
+    let key = format!("{RATE_LIMIT_PREFIX}ip:{client}");
+    match take_token(state.cache.as_ref(), &key, bucket).await {
+        Some(bucket_state) if !bucket_state.allowed => {
+            log_warn!("Rate limit of {client} reached");
+            too_many_requests(&bucket_state)
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Middleware limiting the requests of each API key or JWT on the routes requiring a scope
+///
+/// Must run after `auth_middleware`, and after `require_scope` so requests refused for their
+/// scope don't count. The `RateLimit-*` headers of the key are set on every response.
+pub async fn limit_key_rate(
+    State((state, scope)): State<(AppState, ApiKeyScope)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (Some(config), Some(api_key)) = (
+        &state.rate_limit,
+        request.extensions().get::<AuthenticatedKey>(),
+    ) else {
+        return next.run(request).await;
+    };
+    let Some(bucket) = config.key_bucket(scope) else {
+        return next.run(request).await;
+    };
+
+    let key = key_bucket_key(api_key, scope);
+    match take_token(state.cache.as_ref(), &key, bucket).await {
+        Some(bucket_state) if !bucket_state.allowed => {
+            log_warn!("Rate limit of {api_key} on {} reached", scope.as_str());
+            too_many_requests(&bucket_state)
+        }
+        Some(bucket_state) => {
+            let mut response = next.run(request).await;
+            set_headers(response.headers_mut(), &bucket_state);
+            response
+        }
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            window: Duration::from_secs(60),
+            per_ip: 0,
+            speeds_write: 120,
+            speeds_read: 600,
+            stream_read: 30,
+            admin: 60,
+        }
+    }
+
+    #[test]
+    fn test_buckets() {
+        let config = config();
+        assert_eq!(config.ip_bucket(), None);
+        assert_eq!(
+            config.key_bucket(ApiKeyScope::StreamRead),
+            Some(TokenBucket {
+                capacity: 30,
+                period: Duration::from_secs(60),
+            })
+        );
+
+        let disabled = RateLimitConfig {
+            window: Duration::ZERO,
+            ..config
+        };
+        assert_eq!(disabled.key_bucket(ApiKeyScope::SpeedsWrite), None);
+    }
+
+    #[test]
+    fn test_key_bucket_key() {
+        let mut api_key = AuthenticatedKey {
+            id: Some(7),
+            subject: None,
+            scopes: vec![],
+            sensor_name: None,
+            expires_at: None,
+        };
+        assert_eq!(
+            key_bucket_key(&api_key, ApiKeyScope::SpeedsWrite),
+            "speedstream:ratelimit:key:7:speeds:write"
+        );
+        api_key.id = None;
+        api_key.subject = Some("sensor-42".to_string());
+        assert_eq!(
+            key_bucket_key(&api_key, ApiKeyScope::SpeedsRead),
+            "speedstream:ratelimit:jwt:sensor-42:speeds:read"
+        );
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut headers = HeaderMap::new();
+        set_headers(
+            &mut headers,
+            &BucketState {
+                allowed: false,
+                limit: 120,
+                remaining: 0,
+                reset: Duration::from_millis(59_500),
+                retry_after: Duration::from_millis(400),
+            },
+        );
+        assert_eq!(headers["ratelimit-limit"], "120");
+        assert_eq!(headers["ratelimit-remaining"], "0");
+        assert_eq!(headers["ratelimit-reset"], "60");
+        assert_eq!(headers[header::RETRY_AFTER], "1");
+    }
+}
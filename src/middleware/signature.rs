@@ -0,0 +1,397 @@
+use crate::config::constant::{SIGNATURE_WINDOW_SECS, SIGNING_SECRET};
+use crate::core::api_key::hash_with_pepper;
+use crate::core::app_state::AppState;
+use crate::database::cache::{cache_signing_key, get_cached_signing_key};
+use crate::middleware::auth::refuse;
+use crate::telemetry::audit::AuditContext;
+use crate::{log_error, log_warn};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Id of the API key signing the request
+pub const KEY_ID_HEADER: &str = "x-key-id";
+
+/// Time of the signature, in seconds since the epoch
+pub const TIMESTAMP_HEADER: &str = "x-timestamp";
+
+/// Random value used once per key within the timestamp window
+pub const NONCE_HEADER: &str = "x-nonce";
+
+/// Hexadecimal HMAC-SHA256 of the timestamp, nonce and body
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+const NONCE_CACHE_PREFIX: &str = "speedstream:nonce:";
+const MIN_NONCE_LENGTH: usize = 16;
+const MAX_NONCE_LENGTH: usize = 64;
+const MAX_SIGNED_BODY_BYTES: usize = 64 * 1024; // Readings are a few hundred bytes
+
+/// Settings of the requests signed with HMAC-SHA256 instead of carrying a Bearer token
+#[derive(Debug, Clone)]
+pub struct SigningConfig {
+    pub master_secret: String, // Secret the signing secret of each key is derived from
+    pub window: Duration,      // Maximum clock difference with the devices
+}
+
+impl SigningConfig {
+    /// Builds the configuration from the `SIGNING_SECRET` and `SIGNATURE_WINDOW_SECS`
+    /// environment variables
+    ///
+    /// Returns `None` when `SIGNING_SECRET` is not set, i.e. signed requests are refused.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        if SIGNING_SECRET.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            master_secret: SIGNING_SECRET.clone(),
+            window: Duration::from_secs(*SIGNATURE_WINDOW_SECS),
+        })
+    }
+
+    /// Returns the signing secret of the API key with this hash
+    ///
+    /// Derived rather than stored, so a leaked `api_keys` table can't be used to sign requests
+    /// without the master secret. Rotating the key changes its signing secret too.
+    #[must_use]
+    pub fn signing_secret(&self, key_hash: &str) -> String {
+        hash_with_pepper(self.master_secret.as_bytes(), key_hash)
+    }
+}
+
+/// Signs a request body, as devices do with the signing secret of their key
+///
+/// The HMAC-SHA256 covers `<timestamp>\n<nonce>\n<body>`, keyed with the signing secret as
+/// sent to the device, i.e. its 64 hexadecimal characters.
+#[must_use]
+pub fn sign_request(secret: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    hex::encode(
+        signature_mac(secret, timestamp, nonce, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Checks a hexadecimal signature in constant time
+fn verify_signature(
+    secret: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    signature_mac(secret, timestamp, nonce, body)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn signature_mac(secret: &str, timestamp: i64, nonce: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}\n{nonce}\n").as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// Signature headers of a request
+#[derive(Debug, PartialEq, Eq)]
+struct SignedHeaders {
+    key_id: i32,
+    timestamp: i64,
+    nonce: String,
+    signature: String,
+}
+
+impl SignedHeaders {
+    /// Reads the signature headers, `None` when the request is not signed
+    fn parse(headers: &HeaderMap) -> Result<Option<Self>, &'static str> {
+        let Some(signature) = headers.get(SIGNATURE_HEADER) else {
+            return Ok(None);
+        };
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let key_id = header(KEY_ID_HEADER)
+            .and_then(|v| v.parse().ok())
+            .ok_or("X-Key-Id must be the id of an API key")?;
+        let timestamp = header(TIMESTAMP_HEADER)
+            .and_then(|v| v.parse().ok())
+            .ok_or("X-Timestamp must be a number of seconds since the epoch")?;
+        let nonce = header(NONCE_HEADER)
+            .filter(|v| (MIN_NONCE_LENGTH..=MAX_NONCE_LENGTH).contains(&v.len()))
+            .filter(|v| {
+                v.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+            .ok_or("X-Nonce must be 16 to 64 letters, digits, '-' or '_'")?;
+        let signature = signature
+            .to_str()
+            .map_err(|_| "X-Signature must be hexadecimal")?;
+
+        Ok(Some(Self {
+            key_id,
+            timestamp,
+            nonce: nonce.to_string(),
+            signature: signature.to_string(),
+        }))
+    }
+}
+
+/// Middleware authenticating requests signed with the signing secret of an API key
+///
+/// Devices on plain HTTP can't send a Bearer token without exposing it. They can sign the
+/// request instead, with the `X-Key-Id`, `X-Timestamp`, `X-Nonce` and `X-Signature` headers:
+/// 1. The timestamp must be within `window` of the server clock
+/// 2. The key must be active and not expired, it is cached by id once looked up
+/// 3. The signature must match the body, timestamp and nonce
+/// 4. The nonce must not have been used by the key within the window, which stops replays
+///
+/// The key is then attached to the request like `auth_middleware` does, which lets it through.
+/// Requests without `X-Signature` are left to `auth_middleware`. Every outcome is recorded in
+/// the audit trail, like `auth_middleware` does.
+pub async fn signature_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let context = AuditContext::of(&request);
+    let headers = match SignedHeaders::parse(request.headers()) {
+        Ok(Some(headers)) => headers,
+        Ok(None) => return Ok(next.run(request).await),
+        Err(reason) => {
+            return Err(refuse(
+                &state,
+                context.failure(format!("Invalid signed request: {reason}")),
+            ));
+        }
+    };
+    // Failures are attributed to the key the request claims to be signed by
+    let failure = |reason: String| {
+        let mut event = context.failure(reason);
+        event.key_id = Some(headers.key_id);
+        event
+    };
+    let Some(config) = &state.signing else {
+        return Err(refuse(
+            &state,
+            failure("Signed request refused, SIGNING_SECRET is not set".to_string()),
+        ));
+    };
+
+    let skew = Utc::now().timestamp().abs_diff(headers.timestamp);
+    if skew > config.window.as_secs() {
+        return Err(refuse(
+            &state,
+            failure(format!(
+                "Signed request of API key {} is {skew} s off the server clock",
+                headers.key_id
+            )),
+        ));
+    }
+
+    // Keys are cached by id, and evicted from the cache when rotated, deactivated or deleted
+    let cached = match get_cached_signing_key(state.cache.as_ref(), headers.key_id).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            log_error!("Cache error while checking signing key cache: {e}");
+            None
+        }
+    };
+    let (api_key, key_hash) = match cached {
+        Some((api_key, _)) if api_key.is_expired(Utc::now()) => {
+            // Entries are cached until the key expires at most, the key just expired
+            return Err(refuse(&state, failure(format!("{api_key} has expired"))));
+        }
+        Some(key) => key,
+        None => {
+            // Only a few keys are looked up at once while too many unknown ones are tried
+            let _lookup = match &state.brute_force {
+                Some(guard) => match guard.admit_lookup() {
+                    Ok(permit) => permit,
+                    Err(retry_after) => return Ok(guard.refuse_lookup(retry_after)),
+                },
+                None => None,
+            };
+
+            match state.repository.validate_key_id(headers.key_id).await {
+                Ok(Some((api_key, key_hash))) => {
+                    if let Err(e) =
+                        cache_signing_key(state.cache.as_ref(), &api_key, &key_hash).await
+                    {
+                        log_error!("Failed to cache signing key: {e}");
+                    }
+                    (api_key, key_hash)
+                }
+                Ok(None) => {
+                    if let Some(guard) = &state.brute_force {
+                        guard.record_unknown();
+                    }
+                    return Err(refuse(
+                        &state,
+                        failure(format!(
+                            "Signed request of unknown or inactive API key {}",
+                            headers.key_id
+                        )),
+                    ));
+                }
+                Err(e) => {
+                    log_error!(
+                        "Database error while validating API key {}: {e}",
+                        headers.key_id
+                    );
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            }
+        }
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_SIGNED_BODY_BYTES)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    if !verify_signature(
+        &config.signing_secret(&key_hash),
+        headers.timestamp,
+        &headers.nonce,
+        &body,
+        &headers.signature,
+    ) {
+        return Err(refuse(
+            &state,
+            failure(format!("Invalid signature for {api_key}")),
+        ));
+    }
+
+    // Nonces are kept for twice the window, the timestamp may be ahead of the server clock
+    let nonce_key = format!("{NONCE_CACHE_PREFIX}{}:{}", headers.key_id, headers.nonce);
+    match state.cache.set_nx(&nonce_key, "1", config.window * 2).await {
+        Ok(true) => {}
+        Ok(false) => {
+            log_warn!("Replayed signed request of {api_key}");
+            state.record_audit(failure(format!("Replayed signed request of {api_key}")));
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Err(e) => {
+            // Without the nonce cache, replays can't be detected
+            log_error!("Cache error while recording a nonce: {e}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    state.record_audit(context.success(&api_key).with_detail("Signed request"));
+    let mut request = Request::from_parts(parts, Body::from(body));
+    request.extensions_mut().insert(api_key);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let body = br#"{"speed":42.0,"lane":0}"#;
+        let signature = sign_request("secret", 1_700_000_000, "n0nce-0123456789", body);
+        assert_eq!(signature.len(), 64);
+        assert!(verify_signature(
+            "secret",
+            1_700_000_000,
+            "n0nce-0123456789",
+            body,
+            &signature
+        ));
+
+        // Any change of the secret, timestamp, nonce or body breaks the signature
+        for (secret, timestamp, nonce, body) in [
+            ("other", 1_700_000_000, "n0nce-0123456789", &body[..]),
+            ("secret", 1_700_000_001, "n0nce-0123456789", &body[..]),
+            ("secret", 1_700_000_000, "n0nce-0123456780", &body[..]),
+            (
+                "secret",
+                1_700_000_000,
+                "n0nce-0123456789",
+                br#"{"speed":4.0,"lane":0}"#,
+            ),
+        ] {
+            assert!(!verify_signature(
+                secret, timestamp, nonce, body, &signature
+            ));
+        }
+        assert!(!verify_signature(
+            "secret",
+            1_700_000_000,
+            "n0nce-0123456789",
+            body,
+            "zz"
+        ));
+    }
+
+    #[test]
+    fn test_signing_secret_depends_on_master_and_key() {
+        let config = SigningConfig {
+            master_secret: "master".to_string(),
+            window: Duration::from_secs(300),
+        };
+        let secret = config.signing_secret("hash-a");
+        assert_eq!(secret.len(), 64);
+        assert_ne!(secret, config.signing_secret("hash-b"));
+        let other = SigningConfig {
+            master_secret: "other".to_string(),
+            ..config
+        };
+        assert_ne!(secret, other.signing_secret("hash-a"));
+    }
+
+    #[test]
+    fn test_parse_signed_headers() {
+        assert_eq!(SignedHeaders::parse(&HeaderMap::new()), Ok(None));
+
+        let valid = [
+            (KEY_ID_HEADER, "7"),
+            (TIMESTAMP_HEADER, "1700000000"),
+            (NONCE_HEADER, "n0nce-0123456789"),
+            (SIGNATURE_HEADER, "abcd"),
+        ];
+        assert_eq!(
+            SignedHeaders::parse(&headers(&valid)),
+            Ok(Some(SignedHeaders {
+                key_id: 7,
+                timestamp: 1_700_000_000,
+                nonce: "n0nce-0123456789".to_string(),
+                signature: "abcd".to_string(),
+            }))
+        );
+
+        for (name, value) in [
+            (KEY_ID_HEADER, "abc"),
+            (TIMESTAMP_HEADER, "yesterday"),
+            (NONCE_HEADER, "short"),
+            (NONCE_HEADER, "not a nonce, has spaces"),
+        ] {
+            let mut pairs = valid.to_vec();
+            pairs.retain(|(n, _)| *n != name);
+            pairs.push((name, value));
+            assert!(SignedHeaders::parse(&headers(&pairs)).is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::api::payload::speed_batch_request::{
+    MAX_BATCH_SIZE, SpeedBatchRequest, SpeedBatchResponse,
+};
+use crate::config::constant::{
+    FORWARD_API_KEY, FORWARD_BATCH_SIZE, FORWARD_INTERVAL_SECS, FORWARD_MAX_BACKOFF_SECS,
+    FORWARD_ORIGIN, FORWARD_SETTLE_SECS, FORWARD_URL,
+};
+use crate::database::repository::SpeedRepository;
+use crate::database::types::DbError;
+use crate::{log_error, log_info, log_warn};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Timeout of a request to the central server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Settings of the forwarding of local readings to a central server
+#[derive(Debug, Clone)]
+pub struct ForwarderConfig {
+    pub url: String,           // Base URL of the central server
+    pub api_key: String,       // Bearer token accepted by the central server
+    pub origin: String,        // Name of this edge server on the central server
+    pub batch_size: u32,       // Maximum readings per request
+    pub interval: Duration,    // Delay between two rounds once caught up
+    pub max_backoff: Duration, // Maximum delay between two failed attempts
+    pub settle_secs: f64,      // Age before a local reading is forwarded
+}
+
+impl ForwarderConfig {
+    /// Builds the configuration from the `FORWARD_*` environment variables
+    ///
+    /// Returns `None` when `FORWARD_URL` is not set, i.e. this server is not an edge.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let url = FORWARD_URL.trim_end_matches('/');
+        if url.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            url: url.to_string(),
+            api_key: FORWARD_API_KEY.clone(),
+            origin: FORWARD_ORIGIN.clone(),
+            batch_size: (*FORWARD_BATCH_SIZE).min(MAX_BATCH_SIZE as u32), // Larger batches are refused
+            interval: Duration::from_secs(*FORWARD_INTERVAL_SECS),
+            max_backoff: Duration::from_secs(*FORWARD_MAX_BACKOFF_SECS),
+            settle_secs: *FORWARD_SETTLE_SECS,
+        })
+    }
+}
+
+/// Errors raised while forwarding a batch
+#[derive(Debug)]
+pub enum ForwardError {
+    /// The local readings or the high-water mark could not be read or stored
+    Storage(DbError),
+
+    /// The central server could not be reached
+    Http(reqwest::Error),
+
+    /// The central server refused the batch for now, it is sent again
+    Rejected(reqwest::StatusCode),
+}
+
+/// Checks whether the central server refused the readings of a batch themselves
+///
+/// Only a malformed or invalid batch is refused whatever the delay. Other client errors, e.g.
+/// a wrong key or URL, come from the configuration of either server and are retried until it
+/// is fixed.
+#[must_use]
+pub fn is_permanent_rejection(status: reqwest::StatusCode) -> bool {
+    use reqwest::StatusCode;
+
+    matches!(
+        status,
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY
+    )
+}
+
+impl fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForwardError::Storage(e) => write!(f, "Local storage error: {}", e),
+            ForwardError::Http(e) => write!(f, "Central server unreachable: {}", e),
+            ForwardError::Rejected(status) => {
+                write!(f, "Central server rejected the batch with {}", status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ForwardError {}
+
+impl From<DbError> for ForwardError {
+    fn from(e: DbError) -> Self {
+        ForwardError::Storage(e)
+    }
+}
+
+impl From<reqwest::Error> for ForwardError {
+    fn from(e: reqwest::Error) -> Self {
+        ForwardError::Http(e)
+    }
+}
+
+/// Forwards the next batch of local readings and returns how many were sent
+///
+/// The high-water mark only moves once the central server acknowledged the batch, so a batch
+/// is sent again after a failure or a restart. The central server ignores the duplicates.
+/// A batch too large is sent again in halves. A batch refused as invalid is copied into the
+/// quarantine and skipped, so it doesn't block the following ones.
+pub async fn forward_once(
+    client: &reqwest::Client,
+    repository: &dyn SpeedRepository,
+    config: &ForwarderConfig,
+) -> Result<usize, ForwardError> {
+    use reqwest::StatusCode;
+
+    let last_id = repository.fetch_forward_watermark().await?;
+    let mut readings = repository
+        .fetch_unforwarded_speed_data(last_id, config.batch_size, config.settle_secs)
+        .await?;
+
+    let (count, upper_id, response) = loop {
+        let (Some(first_id), Some(upper_id)) = (
+            readings.iter().map(|r| r.id).min(),
+            readings.iter().map(|r| i64::from(r.id)).max(),
+        ) else {
+            return Ok(0);
+        };
+
+        let count = readings.len();
+        let response = client
+            .post(format!("{}/api/speeds/batch", config.url))
+            .bearer_auth(&config.api_key)
+            .timeout(REQUEST_TIMEOUT)
+            .json(&SpeedBatchRequest::new(
+                config.origin.as_str(),
+                readings.clone(),
+            ))
+            .send()
+            .await?;
+        let status = response.status();
+        if status == StatusCode::PAYLOAD_TOO_LARGE && count > 1 {
+            log_warn!(
+                "Central server refused {count} readings as too large, sending {} at a time; FORWARD_BATCH_SIZE should be lowered",
+                count / 2
+            );
+            readings.truncate(count / 2);
+            continue;
+        }
+        if is_permanent_rejection(status) || status == StatusCode::PAYLOAD_TOO_LARGE {
+            let reason = response.text().await.unwrap_or_default();
+            log_error!(
+                "Central server refused readings {first_id} to {upper_id} with {status}, quarantining them: {reason}"
+            );
+            repository
+                .quarantine_speed_data(&readings, &format!("{status}: {reason}"))
+                .await?;
+            repository.store_forward_watermark(upper_id).await?;
+            return Ok(count);
+        }
+        if !status.is_success() {
+            return Err(ForwardError::Rejected(status));
+        }
+        break (count, upper_id, response);
+    };
+    let result: SpeedBatchResponse = response.json().await?;
+
+    repository.store_forward_watermark(upper_id).await?;
+    if result.inserted < count as u64 {
+        log_info!(
+            "Forwarded {count} readings up to id {upper_id}, {} already received",
+            count as u64 - result.inserted
+        );
+    }
+    Ok(count)
+}
+
+/// Delay before the next attempt after `failures` consecutive failures
+///
+/// Doubles from `base` on each failure, up to `max`.
+#[must_use]
+pub fn backoff_delay(failures: u32, base: Duration, max: Duration) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(max)
+}
+
+/// Spawns the background task forwarding local readings to the central server
+///
+/// Backlogs are sent batch after batch, then the task polls every `interval`. While the
+/// central server is unreachable, attempts are spaced with an exponential backoff.
+pub fn spawn_forwarder(
+    repository: Arc<dyn SpeedRepository>,
+    config: ForwarderConfig,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut failures: u32 = 0;
+        loop {
+            let delay = match forward_once(&client, repository.as_ref(), &config).await {
+                Ok(sent) => {
+                    if failures > 0 {
+                        log_info!("Forwarding to {} resumed", config.url);
+                        failures = 0;
+                    }
+                    if sent >= config.batch_size as usize {
+                        continue;
+                    }
+                    config.interval
+                }
+                Err(e) => {
+                    failures = failures.saturating_add(1);
+                    let delay = backoff_delay(failures, config.interval, config.max_backoff);
+                    log_warn!(
+                        "Forwarding failed ({failures} in a row), retrying in {}s: {e}",
+                        delay.as_secs()
+                    );
+                    delay
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_permanent_rejection() {
+        use reqwest::StatusCode;
+
+        for status in [StatusCode::BAD_REQUEST, StatusCode::UNPROCESSABLE_ENTITY] {
+            assert!(is_permanent_rejection(status), "{status}");
+        }
+        for status in [
+            StatusCode::NOT_FOUND,
+            StatusCode::METHOD_NOT_ALLOWED,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(!is_permanent_rejection(status), "{status}");
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let base = Duration::from_secs(5);
+        let max = Duration::from_secs(300);
+
+        let delays: Vec<u64> = (1..=8)
+            .map(|failures| backoff_delay(failures, base, max).as_secs())
+            .collect();
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160, 300, 300]);
+
+        // Doesn't overflow after a long outage
+        assert_eq!(backoff_delay(u32::MAX, base, max), max);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::core::api_key::AuthenticatedKey;
+use crate::core::audit_action::AuditAction;
+use crate::core::dto::audit_event::AuditEvent;
+use crate::database::repository::SpeedRepository;
+use crate::middleware::client_ip::client_ip_of;
+use crate::{log_error, log_warn};
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{Extensions, Method, Uri};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Maximum number of events written per statement
+const AUDIT_BATCH_SIZE: usize = 500;
+
+/// Sends the audit events to the background writer
+///
+/// Events are queued rather than written by the request recording them, so the audit trail
+/// never slows down nor fails a request. When the storage falls behind and the queue is full,
+/// events are dropped and logged.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: mpsc::Sender<AuditEvent>,
+    auth_successes: bool, // Record every authenticated request, not only the failures
+}
+
+impl AuditLog {
+    /// Creates the queue holding at most `capacity` events, along with its receiving end for
+    /// `spawn_audit_writer`
+    #[must_use]
+    pub fn new(capacity: usize, auth_successes: bool) -> (Self, mpsc::Receiver<AuditEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (
+            Self {
+                sender,
+                auth_successes,
+            },
+            receiver,
+        )
+    }
+
+    /// Queues an event for the writer
+    pub fn record(&self, event: AuditEvent) {
+        if event.action == AuditAction::AuthSuccess && !self.auth_successes {
+            return;
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                log_warn!(
+                    "Audit queue full, dropped a {} event",
+                    event.action.as_str()
+                );
+            }
+            Err(mpsc::error::TrySendError::Closed(event)) => {
+                log_error!(
+                    "Audit writer stopped, dropped a {} event",
+                    event.action.as_str()
+                );
+            }
+        }
+    }
+}
+
+/// Spawns the background task storing the queued audit events, in batches
+///
+/// The task ends once every `AuditLog` is dropped and the queue is drained.
+pub fn spawn_audit_writer(
+    repository: Arc<dyn SpeedRepository>,
+    mut receiver: mpsc::Receiver<AuditEvent>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut batch = Vec::with_capacity(AUDIT_BATCH_SIZE);
+        while receiver.recv_many(&mut batch, AUDIT_BATCH_SIZE).await > 0 {
+            let count = batch.len();
+            if let Err(e) = repository
+                .insert_audit_events(std::mem::take(&mut batch))
+                .await
+            {
+                log_error!("Lost {count} audit events: {e}");
+            }
+        }
+    })
+}
+
+/// Client, route and caller of a request, recorded with the audit events it causes
+///
+/// Extracted by the handlers, or read from the request by the middlewares. The client address
+/// is the one resolved by `resolve_client_ip`, only known when the server is run with
+/// `into_make_service_with_connect_info`.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub ip: Option<IpAddr>,
+    pub method: Method,
+    pub route: String,
+    pub caller: Option<AuthenticatedKey>, // Set once the request is authenticated
+}
+
+impl AuditContext {
+    /// Reads the context of a request going through a middleware
+    #[must_use]
+    pub fn of(request: &Request) -> Self {
+        Self::new(request.extensions(), request.method(), request.uri())
+    }
+
+    fn new(extensions: &Extensions, method: &Method, uri: &Uri) -> Self {
+        Self {
+            ip: client_ip_of(extensions),
+            method: method.clone(),
+            route: uri.path().to_string(),
+            caller: extensions.get::<AuthenticatedKey>().cloned(),
+        }
+    }
+
+    /// Creates an event of this request, attributed to its caller if authenticated
+    pub fn event(&self, action: AuditAction) -> AuditEvent {
+        let mut event = AuditEvent::new(action);
+        if let Some(caller) = &self.caller {
+            event = event.by(caller);
+        }
+        event.ip = self.ip.map(|ip| ip.to_string());
+        event.method = Some(self.method.to_string());
+        event.route = Some(self.route.clone());
+        event
+    }
+
+    /// Creates the event of the request authenticated by `api_key`
+    pub fn success(&self, api_key: &AuthenticatedKey) -> AuditEvent {
+        self.event(AuditAction::AuthSuccess).by(api_key)
+    }
+
+    /// Creates the event of the request refused for `reason`
+    pub fn failure(&self, reason: impl Into<String>) -> AuditEvent {
+        self.event(AuditAction::AuthFailure).with_detail(reason)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::new(&parts.extensions, &parts.method, &parts.uri))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api_key_scope::ApiKeyScope;
+    use crate::database::repository::memory::InMemorySpeedRepository;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use chrono::{Duration, Utc};
+    use std::net::SocketAddr;
+
+    fn api_key() -> AuthenticatedKey {
+        AuthenticatedKey {
+            id: Some(3),
+            subject: None,
+            scopes: vec![ApiKeyScope::Admin],
+            sensor_name: None,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_context_events() {
+        let mut request = Request::post("/api/admin/keys/7/rotate")
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 7], 40000))));
+
+        let context = AuditContext::of(&request);
+        let failure = context.failure("Missing Authorization header");
+        assert_eq!(failure.action, AuditAction::AuthFailure);
+        assert_eq!(failure.key_id, None);
+        assert_eq!(failure.ip.as_deref(), Some("192.0.2.7"));
+        assert_eq!(failure.method.as_deref(), Some("POST"));
+        assert_eq!(failure.route.as_deref(), Some("/api/admin/keys/7/rotate"));
+        assert_eq!(context.success(&api_key()).key_id, Some(3));
+
+        // Handlers see the caller attached by the auth middleware
+        request.extensions_mut().insert(api_key());
+        let event = AuditContext::of(&request)
+            .event(AuditAction::KeyRotated)
+            .on_key(7);
+        assert_eq!((event.key_id, event.target_key_id), (Some(3), Some(7)));
+    }
+
+    #[tokio::test]
+    async fn test_writer_stores_queued_events() {
+        let repository = Arc::new(InMemorySpeedRepository::new());
+        let (audit, receiver) = AuditLog::new(16, false);
+        let writer = spawn_audit_writer(repository.clone(), receiver);
+
+        let start = Utc::now();
+        audit.record(AuditEvent::new(AuditAction::AuthSuccess)); // Not recorded
+        audit.record(AuditEvent::new(AuditAction::AuthFailure));
+        audit.record(AuditEvent::new(AuditAction::KeyCreated).on_key(2));
+        drop(audit);
+        writer.await.unwrap();
+
+        let events = repository
+            .fetch_audit_events(start, Utc::now() + Duration::seconds(1), None, None, 10)
+            .await
+            .unwrap();
+        let actions: Vec<_> = events.iter().map(|e| e.action).collect();
+        assert_eq!(actions, [AuditAction::KeyCreated, AuditAction::AuthFailure]);
+    }
+}
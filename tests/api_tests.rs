@@ -0,0 +1,991 @@
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode, header};
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use speed_stream::analytics::congestion::{CongestionConfig, CongestionMonitor};
+use speed_stream::analytics::quality::{FaultDetector, FaultDetectorConfig};
+use speed_stream::api::router::create_router;
+use speed_stream::core::api_key_scope::ApiKeyScope;
+use speed_stream::core::app_state::AppState;
+use speed_stream::database::cache::memory::MemoryCache;
+use speed_stream::database::repository::memory::InMemorySpeedRepository;
+use speed_stream::middleware::brute_force::{BruteForceConfig, BruteForceGuard};
+use speed_stream::middleware::client_ip::TrustedProxies;
+use speed_stream::middleware::jwt::{JwksSource, JwtConfig, JwtVerifier};
+use speed_stream::middleware::rate_limit::RateLimitConfig;
+use speed_stream::middleware::signature::{SigningConfig, sign_request};
+use speed_stream::telemetry::audit::{AuditLog, spawn_audit_writer};
+use speed_stream::telemetry::tracing::log_level::LogLevel;
+use speed_stream::telemetry::tracing::logger::Logger;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+// Integration tests of the HTTP API backed by the in-memory repository
+// These tests run without Postgres nor Redis
+
+const API_KEY: &str = "test-key";
+const ADMIN_KEY: &str = "admin-key";
+const SENSOR_KEY: &str = "sensor-key";
+const READER_KEY: &str = "reader-key";
+const BOUND_KEY: &str = "bound-key";
+
+fn app() -> Router {
+    create_router(state())
+}
+
+fn state() -> AppState {
+    // Handlers log their errors, the logger may already be set by another test
+    let _ = Logger::init(
+        std::env::temp_dir().join("speedstream-api-tests.log"),
+        LogLevel::Error,
+    );
+
+    let repository = InMemorySpeedRepository::new()
+        .with_api_key(API_KEY, true)
+        .with_api_key("revoked-key", false)
+        .with_admin_key(ADMIN_KEY)
+        .with_scoped_key(SENSOR_KEY, &[ApiKeyScope::SpeedsWrite])
+        .with_scoped_key(READER_KEY, &[ApiKeyScope::SpeedsRead])
+        .with_sensor_key(BOUND_KEY, "RSU-7");
+    let (broadcast_tx, _) = tokio::sync::broadcast::channel(16);
+    AppState::new(
+        Arc::new(repository),
+        None,
+        Arc::new(MemoryCache::new(100)),
+        broadcast_tx,
+        Arc::new(CongestionMonitor::new(CongestionConfig::from_env(), 16)),
+        Arc::new(FaultDetector::new(FaultDetectorConfig::from_env())),
+    )
+}
+
+/// Signs a JWT with the RS256 key of the fixture key set
+fn jwt(claims: serde_json::Value) -> String {
+    let pem = std::fs::read("tests/fixtures/jwt/rsa_private.pem").unwrap();
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("rsa-1".to_string());
+    encode(&header, &claims, &EncodingKey::from_rsa_pem(&pem).unwrap()).unwrap()
+}
+
+fn request(method: &str, uri: &str, token: Option<&str>, body: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    match body {
+        Some(body) => builder
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => builder.body(Body::empty()).unwrap(),
+    }
+}
+
+async fn json(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let value = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+#[tokio::test]
+async fn test_health_check() {
+    let app = app();
+    let (status, body) = json(&app, request("GET", "/health", None, None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["storage"], "up");
+    assert_eq!(body["cache"]["backend"], "memory");
+    assert_eq!(body["cache"]["degraded"], false);
+}
+
+#[tokio::test]
+async fn test_requires_valid_token() {
+    let app = app();
+    for token in [None, Some("revoked-key"), Some("unknown-key")] {
+        let (status, _) = json(&app, request("GET", "/api/speeds", token, None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "token {token:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_create_and_read_speeds() {
+    let app = app();
+
+    let (status, _) = json(
+        &app,
+        request("GET", "/api/speeds/latest", Some(API_KEY), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for body in [
+        r#"{"sensor_name":"A","speed":50.0,"lane":0}"#,
+        r#"{"sensor_name":"A","speed":100.0,"lane":1,"vehicle_class":"truck"}"#,
+    ] {
+        let (status, _) = json(
+            &app,
+            request("POST", "/api/speeds", Some(API_KEY), Some(body)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, body) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert_eq!(body[0]["speed"], 100.0);
+
+    let (status, body) = json(
+        &app,
+        request("GET", "/api/speeds/latest?units=mph", Some(API_KEY), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((body["speed"].as_f64().unwrap() - 62.137).abs() < 0.01);
+
+    let (_, body) = json(
+        &app,
+        request(
+            "GET",
+            "/api/speeds?vehicle_class=truck",
+            Some(API_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+
+    let (status, body) = json(
+        &app,
+        request(
+            "GET",
+            "/api/speeds/range?start_date=-1h",
+            Some(API_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_rejects_invalid_lane() {
+    let app = app();
+    let body = r#"{"speed":50.0,"lane":7}"#;
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/speeds", Some(API_KEY), Some(body)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_rejects_invalid_vehicle() {
+    let app = app();
+    for body in [
+        r#"{"speed":50.0,"lane":0,"vehicle_length":0}"#,
+        r#"{"speed":50.0,"lane":0,"vehicle_length":-4.2}"#,
+        r#"{"speed":50.0,"lane":0,"vehicle_length":1e39}"#,
+        r#"{"speed":50.0,"lane":0,"vehicle_class":"spaceship"}"#,
+    ] {
+        let (status, _) = json(
+            &app,
+            request("POST", "/api/speeds", Some(API_KEY), Some(body)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    let (status, body) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_aggregates_without_postgres() {
+    let app = app();
+    for speed in [40.0, 60.0] {
+        let body = format!(r#"{{"speed":{speed},"lane":0}}"#);
+        let (status, _) = json(
+            &app,
+            request("POST", "/api/speeds", Some(API_KEY), Some(&body)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, body) = json(
+        &app,
+        request(
+            "GET",
+            "/api/speeds/aggregate?start_date=-1h&bucket=day",
+            Some(API_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["count"], 2);
+    assert_eq!(body[0]["avg_speed"], 50.0);
+}
+
+#[tokio::test]
+async fn test_sql_analytics_need_postgres() {
+    let app = app();
+    let (status, _) = json(
+        &app,
+        request(
+            "GET",
+            "/api/analytics/flow?start_date=-1h",
+            Some(API_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+}
+
+#[tokio::test]
+async fn test_forwarded_batch_is_idempotent() {
+    let app = app();
+    let body = r#"{"origin":"site-a","readings":[
+        {"id":7,"sensor_name":"edge-1","speed":42.0,"lane":1,"created_at":"2024-03-01T08:00:00Z"},
+        {"id":8,"sensor_name":"edge-1","speed":55.0,"lane":0,"created_at":"2024-03-01T08:00:05Z","quality_flag":"stuck_value"}
+    ]}"#;
+
+    for inserted in [2, 0] {
+        let (status, result) = json(
+            &app,
+            request("POST", "/api/speeds/batch", Some(API_KEY), Some(body)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["received"], 2);
+        assert_eq!(result["inserted"], inserted);
+    }
+
+    let (status, latest) = json(
+        &app,
+        request("GET", "/api/speeds/latest", Some(API_KEY), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(latest["sensor_name"], "edge-1");
+    assert_eq!(latest["created_at"], "2024-03-01T08:00:05Z");
+    assert_eq!(latest["quality_flag"], "stuck_value");
+
+    let (status, _) = json(
+        &app,
+        request(
+            "POST",
+            "/api/speeds/batch",
+            Some(API_KEY),
+            Some(r#"{"origin":"","readings":[]}"#),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_manage_api_keys() {
+    let app = app();
+
+    let (status, _) = json(&app, request("GET", "/api/admin/keys", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let expired = r#"{"name":"Site A","expires_at":"2020-01-01T00:00:00Z"}"#;
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/admin/keys", Some(ADMIN_KEY), Some(expired)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let body = r#"{"name":"Site A","owner":"ops"}"#;
+    let (status, created) = json(
+        &app,
+        request("POST", "/api/admin/keys", Some(ADMIN_KEY), Some(body)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        created["scopes"],
+        serde_json::json!(["speeds:write", "speeds:read", "stream:read"])
+    );
+    let id = created["id"].as_i64().unwrap();
+    let key = created["api_key"].as_str().unwrap().to_string();
+    assert_eq!(created["key_prefix"].as_str().unwrap(), &key[..8]);
+
+    // The secret is only returned on creation
+    let (_, keys) = json(
+        &app,
+        request("GET", "/api/admin/keys", Some(ADMIN_KEY), None),
+    )
+    .await;
+    let listed = keys
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|k| k["id"] == id)
+        .unwrap();
+    assert_eq!(listed["name"], "Site A");
+    assert!(listed.get("api_key").is_none());
+
+    let (status, _) = json(&app, request("GET", "/api/speeds", Some(&key), None)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The previous secret is refused right after a rotation, although it was cached
+    let (status, rotated) = json(
+        &app,
+        request(
+            "POST",
+            &format!("/api/admin/keys/{id}/rotate"),
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let rotated_key = rotated["api_key"].as_str().unwrap().to_string();
+    let (status, _) = json(&app, request("GET", "/api/speeds", Some(&key), None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = json(
+        &app,
+        request("GET", "/api/speeds", Some(&rotated_key), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let deactivate = format!("/api/admin/keys/{id}/deactivate");
+    let (status, _) = json(&app, request("POST", &deactivate, Some(ADMIN_KEY), None)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = json(
+        &app,
+        request("GET", "/api/speeds", Some(&rotated_key), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let uri = format!("/api/admin/keys/{id}");
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let (status, _) = json(&app, request("DELETE", &uri, Some(ADMIN_KEY), None)).await;
+        assert_eq!(status, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_admin_cannot_remove_own_key() {
+    let app = app();
+    let (_, keys) = json(
+        &app,
+        request("GET", "/api/admin/keys", Some(ADMIN_KEY), None),
+    )
+    .await;
+    let own = keys
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|k| k["scopes"].as_array().unwrap().contains(&"admin".into()))
+        .unwrap();
+
+    let uri = format!("/api/admin/keys/{}", own["id"]);
+    let (status, _) = json(&app, request("DELETE", &uri, Some(ADMIN_KEY), None)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_routes_require_scope() {
+    let app = app();
+    let reading = r#"{"speed":42.0,"lane":0}"#;
+
+    // An ingest-only key can send readings but not read them back
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/speeds", Some(SENSOR_KEY), Some(reading)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    for uri in [
+        "/api/speeds/latest",
+        "/api/speeds/stream",
+        "/api/admin/keys",
+    ] {
+        let (status, _) = json(&app, request("GET", uri, Some(SENSOR_KEY), None)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    // A read-only key is the other way around, and doesn't follow the streams
+    let (status, _) = json(
+        &app,
+        request("GET", "/api/speeds/latest", Some(READER_KEY), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/speeds", Some(READER_KEY), Some(reading)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = json(
+        &app,
+        request(
+            "GET",
+            "/api/analytics/congestion/stream",
+            Some(READER_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_sensor_key_sets_sensor_name() {
+    let app = app();
+
+    // Readings of a bound key get its sensor, whether they name it or not
+    for body in [
+        r#"{"speed":42.0,"lane":0}"#,
+        r#"{"sensor_name":"RSU-7","speed":43.0,"lane":0}"#,
+    ] {
+        let (status, _) = json(
+            &app,
+            request("POST", "/api/speeds", Some(BOUND_KEY), Some(body)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let (status, _) = json(
+        &app,
+        request(
+            "POST",
+            "/api/speeds",
+            Some(BOUND_KEY),
+            Some(r#"{"sensor_name":"RSU-8","speed":44.0,"lane":0}"#),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, body) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    let sensors: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["sensor_name"].as_str())
+        .collect();
+    assert_eq!(sensors, [Some("RSU-7"), Some("RSU-7")]);
+}
+
+#[tokio::test]
+async fn test_jwt_bearer_tokens() {
+    let verifier = JwtVerifier::new(JwtConfig {
+        jwks: JwksSource::parse("tests/fixtures/jwt/jwks.json"),
+        issuer: "https://idp.example.com".to_string(),
+        audience: "speedstream".to_string(),
+        leeway: Duration::from_secs(60),
+        jwks_refresh: Duration::from_secs(3600),
+        admin_scope: Some("speedstream:admin".to_string()),
+    })
+    .unwrap();
+    let app = create_router(state().with_jwt(Arc::new(verifier)));
+    let claims = |audience: &str, scope: &str| {
+        serde_json::json!({
+            "iss": "https://idp.example.com",
+            "aud": audience,
+            "sub": "sensor-42",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "scope": scope,
+        })
+    };
+    let reading = r#"{"speed":42.0,"lane":0}"#;
+
+    // The scopes of the token decide the routes, as for API keys
+    let token = jwt(claims("speedstream", "openid speeds:write"));
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/speeds", Some(&token), Some(reading)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = json(&app, request("GET", "/api/speeds", Some(&token), None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Administration is only granted by the configured scope
+    let token = jwt(claims("speedstream", "admin"));
+    let (status, _) = json(&app, request("GET", "/api/admin/keys", Some(&token), None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let token = jwt(claims("speedstream", "speedstream:admin"));
+    let (status, _) = json(&app, request("GET", "/api/admin/keys", Some(&token), None)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let token = jwt(claims("other-api", "speeds:write"));
+    let (status, _) = json(
+        &app,
+        request("POST", "/api/speeds", Some(&token), Some(reading)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // API keys are still accepted
+    let (status, body) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+/// Builds a reading signed with `secret` for the key `key_id`
+fn signed_request(key_id: &str, secret: &str, timestamp: i64, nonce: &str) -> Request<Body> {
+    let body = r#"{"speed":42.0,"lane":0}"#;
+    Request::builder()
+        .method("POST")
+        .uri("/api/speeds")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("X-Key-Id", key_id)
+        .header("X-Timestamp", timestamp.to_string())
+        .header("X-Nonce", nonce)
+        .header(
+            "X-Signature",
+            sign_request(secret, timestamp, nonce, body.as_bytes()),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_signed_readings() {
+    let signing = SigningConfig {
+        master_secret: "master".to_string(),
+        window: Duration::from_secs(300),
+    };
+    let app = create_router(state().with_signing(Arc::new(signing)));
+    let now = chrono::Utc::now().timestamp();
+
+    // The signing secret is returned along with the key
+    let (status, created) = json(
+        &app,
+        request(
+            "POST",
+            "/api/admin/keys",
+            Some(ADMIN_KEY),
+            Some(r#"{"name":"Arduino","scopes":["speeds:write"]}"#),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let key_id = created["id"].to_string();
+    let secret = created["signing_secret"].as_str().unwrap();
+
+    let (status, _) = json(
+        &app,
+        signed_request(&key_id, secret, now, "nonce-0000000001"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Replayed nonce, stale timestamp, other secret and unknown key
+    for (key_id, secret, timestamp, nonce) in [
+        (key_id.as_str(), secret, now, "nonce-0000000001"),
+        (key_id.as_str(), secret, now - 600, "nonce-0000000002"),
+        (key_id.as_str(), "not-the-secret", now, "nonce-0000000003"),
+        ("999", secret, now, "nonce-0000000004"),
+    ] {
+        let (status, _) = json(&app, signed_request(key_id, secret, timestamp, nonce)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{nonce}");
+    }
+
+    // Signed requests need the scope too
+    let (_, created) = json(
+        &app,
+        request(
+            "POST",
+            "/api/admin/keys",
+            Some(ADMIN_KEY),
+            Some(r#"{"name":"Dashboard","scopes":["speeds:read"]}"#),
+        ),
+    )
+    .await;
+    let (status, _) = json(
+        &app,
+        signed_request(
+            &created["id"].to_string(),
+            created["signing_secret"].as_str().unwrap(),
+            now,
+            "nonce-0000000005",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_signing_keys_are_evicted_and_secrets_retrievable() {
+    let signing = SigningConfig {
+        master_secret: "master".to_string(),
+        window: Duration::from_secs(300),
+    };
+    let app = create_router(state().with_signing(Arc::new(signing)));
+    let now = chrono::Utc::now().timestamp();
+
+    let (_, created) = json(
+        &app,
+        request(
+            "POST",
+            "/api/admin/keys",
+            Some(ADMIN_KEY),
+            Some(r#"{"name":"Arduino","scopes":["speeds:write"]}"#),
+        ),
+    )
+    .await;
+    let id = created["id"].to_string();
+    let secret = created["signing_secret"].as_str().unwrap();
+    let (status, _) = json(&app, signed_request(&id, secret, now, "nonce-0000000001")).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The key cached by the first request is evicted on rotation
+    let (status, rotated) = json(
+        &app,
+        request(
+            "POST",
+            &format!("/api/admin/keys/{id}/rotate"),
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = json(&app, signed_request(&id, secret, now, "nonce-0000000002")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let rotated_secret = rotated["signing_secret"].as_str().unwrap();
+    let (status, _) = json(
+        &app,
+        signed_request(&id, rotated_secret, now, "nonce-0000000003"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Admins can retrieve the signing secret, e.g. of keys created before signing was enabled
+    let (status, body) = json(
+        &app,
+        request(
+            "GET",
+            &format!("/api/admin/keys/{id}/signing-secret"),
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["signing_secret"], rotated_secret);
+    let (status, _) = json(
+        &app,
+        request(
+            "GET",
+            "/api/admin/keys/999/signing-secret",
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Deactivated keys are evicted too
+    let (status, _) = json(
+        &app,
+        request(
+            "POST",
+            &format!("/api/admin/keys/{id}/deactivate"),
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = json(
+        &app,
+        signed_request(&id, rotated_secret, now, "nonce-0000000004"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Without SIGNING_SECRET, keys have no signing secret
+    let (status, _) = json(
+        &create_router(state()),
+        request(
+            "GET",
+            "/api/admin/keys/1/signing-secret",
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_rate_limits() {
+    let rate_limit = RateLimitConfig {
+        window: Duration::from_secs(3600),
+        per_ip: 4,
+        speeds_write: 2,
+        speeds_read: 0,
+        stream_read: 0,
+        admin: 0,
+    };
+    let app = create_router(state().with_rate_limit(Arc::new(rate_limit)));
+    let reading = r#"{"speed":42.0,"lane":0}"#;
+
+    // Each key has its own bucket per scope
+    for token in [API_KEY, SENSOR_KEY] {
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(request("POST", "/api/speeds", Some(token), Some(reading)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(response.headers()["ratelimit-limit"], "2");
+        }
+    }
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/api/speeds", Some(API_KEY), Some(reading)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["ratelimit-remaining"], "0");
+    assert_eq!(response.headers()["retry-after"], "1800");
+    let (status, _) = json(&app, request("GET", "/api/speeds", Some(API_KEY), None)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Client IPs are limited before authentication, whatever the token
+    let from_ip = |token| {
+        let mut request = request("GET", "/api/speeds", Some(token), None);
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 1], 40000))));
+        request
+    };
+    for token in ["unknown-key", "unknown-key", API_KEY, READER_KEY] {
+        let (status, _) = json(&app, from_ip(token)).await;
+        assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+    let (status, _) = json(&app, from_ip(API_KEY)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_ip_rate_limit_behind_trusted_proxies() {
+    let rate_limit = RateLimitConfig {
+        window: Duration::from_secs(3600),
+        per_ip: 2,
+        speeds_write: 0,
+        speeds_read: 0,
+        stream_read: 0,
+        admin: 0,
+    };
+    let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+    let app = create_router(
+        state()
+            .with_rate_limit(Arc::new(rate_limit))
+            .with_trusted_proxies(Arc::new(proxies)),
+    );
+    let from = |peer: SocketAddr, forwarded_for: Option<&str>| {
+        let mut request = request("GET", "/api/speeds", Some(API_KEY), None);
+        if let Some(forwarded_for) = forwarded_for {
+            request
+                .headers_mut()
+                .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        }
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    };
+    let proxy = SocketAddr::from(([10, 0, 0, 1], 40000));
+
+    // Each client behind the proxy has its own bucket
+    for client in ["192.0.2.1", "192.0.2.1", "192.0.2.2", "192.0.2.2"] {
+        let (status, _) = json(&app, from(proxy, Some(client))).await;
+        assert_eq!(status, StatusCode::OK, "{client}");
+    }
+    let (status, _) = json(&app, from(proxy, Some("192.0.2.1"))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Untrusted peers can't pick their address
+    let peer = SocketAddr::from(([192, 0, 2, 3], 40000));
+    for forwarded_for in ["198.51.100.1", "198.51.100.2"] {
+        let (status, _) = json(&app, from(peer, Some(forwarded_for))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = json(&app, from(peer, Some("198.51.100.3"))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Nor spread their requests over the addresses of their IPv6 /64
+    for address in ["2001:db8::1", "2001:db8::2", "2001:db8::3"] {
+        let peer = SocketAddr::new(address.parse().unwrap(), 40000);
+        let (status, _) = json(&app, from(peer, None)).await;
+        let expected = if address == "2001:db8::3" {
+            StatusCode::TOO_MANY_REQUESTS
+        } else {
+            StatusCode::OK
+        };
+        assert_eq!(status, expected, "{address}");
+    }
+}
+
+#[tokio::test]
+async fn test_audit_trail() {
+    let state = state();
+    let (audit, receiver) = AuditLog::new(100, true);
+    spawn_audit_writer(state.repository.clone(), receiver);
+    let app = create_router(state.with_audit(audit));
+
+    let mut refused = request("GET", "/api/speeds", Some("unknown-key"), None);
+    refused
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 9], 40000))));
+    let (status, _) = json(&app, refused).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = json(
+        &app,
+        request("GET", "/api/admin/audit", Some(READER_KEY), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, created) = json(
+        &app,
+        request(
+            "POST",
+            "/api/admin/keys",
+            Some(ADMIN_KEY),
+            Some(r#"{"name":"Site B"}"#),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = created["id"].as_i64().unwrap();
+    let uri = format!("/api/admin/keys/{id}");
+    let (status, _) = json(&app, request("DELETE", &uri, Some(ADMIN_KEY), None)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Events are written in the background
+    let audit = |query: String| {
+        let app = app.clone();
+        async move {
+            for _ in 0..50 {
+                let uri = format!("/api/admin/audit?{query}");
+                let (status, body) = json(&app, request("GET", &uri, Some(ADMIN_KEY), None)).await;
+                assert_eq!(status, StatusCode::OK);
+                if body.as_array().unwrap().len() >= 2 {
+                    return body;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("audit events of {query} not written");
+        }
+    };
+
+    let failures = audit("action=auth_failure".to_string()).await;
+    assert_eq!(failures[0]["route"], "/api/admin/audit");
+    assert_eq!(failures[0]["key_id"], 5);
+    assert_eq!(failures[1]["detail"], "Invalid or inactive token");
+    assert_eq!(failures[1]["key_prefix"], "unknown-");
+    assert_eq!(failures[1]["ip"], "192.0.2.9");
+    assert_eq!(failures[1]["method"], "GET");
+
+    let actions = audit(format!("key_id={id}&start_date=today")).await;
+    assert_eq!(actions[0]["action"], "key_deleted");
+    assert_eq!(actions[1]["action"], "key_created");
+    assert_eq!(actions[1]["key_id"], 3);
+    assert_eq!(actions[1]["target_key_id"], id);
+
+    let successes = audit("action=auth_success&limit=2".to_string()).await;
+    assert_eq!(successes.as_array().unwrap().len(), 2);
+
+    let (status, _) = json(
+        &app,
+        request(
+            "GET",
+            "/api/admin/audit?action=login",
+            Some(ADMIN_KEY),
+            None,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_brute_force_protection() {
+    let brute_force = BruteForceConfig {
+        window: Duration::from_secs(300),
+        max_failures: 3,
+        lockout: Duration::from_secs(60),
+        max_lockout: Duration::from_secs(3600),
+        breaker_failures: 2,
+        breaker_window: Duration::from_secs(60),
+        breaker_lookups: 1,
+        breaker_cooldown: Duration::from_secs(30),
+    };
+    let app = create_router(state().with_brute_force(Arc::new(BruteForceGuard::new(brute_force))));
+    let from_ip = |token, ip: u8| {
+        let mut request = request("GET", "/api/speeds", Some(token), None);
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, ip], 40000))));
+        request
+    };
+    let metrics = || request("GET", "/api/admin/auth/metrics", Some(ADMIN_KEY), None);
+    let (status, body) = json(&app, metrics()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["rejected"], 0);
+
+    // The third failure locks the IP out: its refused requests get 429, valid keys still pass
+    for _ in 0..3 {
+        let (status, _) = json(&app, from_ip("unknown-key", 1)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let response = app.clone().oneshot(from_ip("other-key", 1)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "60");
+    for ip in [1, 2] {
+        let (status, _) = json(&app, from_ip(API_KEY, ip)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // The breaker opened on the second unknown token, keys are still looked up one at a time
+    let (_, body) = json(&app, metrics()).await;
+    assert_eq!(body["breaker_open"], true);
+    for (token, expected) in [
+        ("random-token-1", StatusCode::UNAUTHORIZED),
+        (READER_KEY, StatusCode::OK),
+        (API_KEY, StatusCode::OK),
+    ] {
+        let (status, _) = json(&app, from_ip(token, 3)).await;
+        assert_eq!(status, expected, "{token}");
+    }
+
+    let (status, body) = json(&app, metrics()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["rejected"], 4);
+    assert_eq!(body["rejected_locked_out"], 1);
+    assert_eq!(body["rejected_breaker_open"], 0);
+    assert_eq!(body["lockouts"], 1);
+    assert_eq!(body["breaker_trips"], 1);
+    assert_eq!(body["breaker_open"], true);
+
+    // Without the protection, there are no metrics
+    let (status, _) = json(&create_router(state()), metrics()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
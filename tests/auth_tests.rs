@@ -1,3 +1,4 @@
+use speed_stream::core::api_key::hash_api_key;
 use speed_stream::database::cache::generate_token_cache_key;
 use speed_stream::middleware::auth::extract_bearer_token;
 
@@ -16,9 +17,10 @@ fn test_token_extraction_flow() {
     let token = token.unwrap();
     assert_eq!(token, "mySecretToken123");
 
-    // Generate cache key for this token
-    let cache_key = generate_token_cache_key(token);
-    assert_eq!(cache_key, "speedstream:token:mySecretToken123");
+    // Generate cache key for this token, the token itself never reaches the cache
+    let cache_key = generate_token_cache_key(&hash_api_key(token));
+    assert!(cache_key.starts_with("speedstream:token:"));
+    assert!(!cache_key.contains(token));
 }
 
 #[test]
@@ -43,11 +45,11 @@ fn test_cache_key_consistency() {
     // Test that the same token always generates the same cache key
     let token = "consistent_token_123";
 
-    let key1 = generate_token_cache_key(token);
-    let key2 = generate_token_cache_key(token);
+    let key1 = generate_token_cache_key(&hash_api_key(token));
+    let key2 = generate_token_cache_key(&hash_api_key(token));
 
     assert_eq!(key1, key2);
-    assert_eq!(key1, "speedstream:token:consistent_token_123");
+    assert_eq!(key1, format!("speedstream:token:{}", hash_api_key(token)));
 }
 
 #[test]
@@ -56,8 +58,8 @@ fn test_different_tokens_different_keys() {
     let token1 = "token_abc";
     let token2 = "token_xyz";
 
-    let key1 = generate_token_cache_key(token1);
-    let key2 = generate_token_cache_key(token2);
+    let key1 = generate_token_cache_key(&hash_api_key(token1));
+    let key2 = generate_token_cache_key(&hash_api_key(token2));
 
     assert_ne!(key1, key2);
 }
@@ -80,9 +82,9 @@ fn test_complete_auth_header_processing() {
         assert_eq!(token, expected_token);
 
         // Verify cache key generation
-        let cache_key = generate_token_cache_key(token);
+        let cache_key = generate_token_cache_key(&hash_api_key(token));
         assert!(cache_key.starts_with("speedstream:token:"));
-        assert!(cache_key.ends_with(expected_token));
+        assert!(!cache_key.ends_with(expected_token));
     }
 }
 
@@ -105,19 +107,13 @@ fn test_token_length_variations() {
         Some(long_token.as_str())
     );
 
-    // All should generate valid cache keys
-    assert_eq!(
-        generate_token_cache_key(short_token),
-        format!("speedstream:token:{}", short_token)
-    );
-    assert_eq!(
-        generate_token_cache_key(medium_token),
-        format!("speedstream:token:{}", medium_token)
-    );
-    assert_eq!(
-        generate_token_cache_key(&long_token),
-        format!("speedstream:token:{}", long_token)
-    );
+    // All should generate cache keys of the same length, from their hash
+    for token in [short_token, medium_token, &long_token] {
+        assert_eq!(
+            generate_token_cache_key(&hash_api_key(token)).len(),
+            "speedstream:token:".len() + 64
+        );
+    }
 }
 
 #[test]
@@ -0,0 +1,223 @@
+use axum::response::IntoResponse;
+use speed_stream::analytics::congestion::{CongestionConfig, CongestionMonitor};
+use speed_stream::analytics::quality::{FaultDetector, FaultDetectorConfig};
+use speed_stream::api::payload::create_speed_request::CreateSpeedDataRequest;
+use speed_stream::api::payload::speed_batch_request::{SpeedBatchRequest, SpeedBatchResponse};
+use speed_stream::api::router::create_router;
+use speed_stream::core::app_state::AppState;
+use speed_stream::database::cache::memory::MemoryCache;
+use speed_stream::database::repository::SpeedRepository;
+use speed_stream::database::repository::memory::InMemorySpeedRepository;
+use speed_stream::sync::forwarder::{ForwardError, ForwarderConfig, forward_once};
+use speed_stream::telemetry::tracing::log_level::LogLevel;
+use speed_stream::telemetry::tracing::logger::Logger;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+// Forwarding between an edge and a central server, both backed by the in-memory repository
+
+const API_KEY: &str = "central-key";
+
+/// Serves a central server on a random local port and returns its URL
+async fn central(repository: Arc<InMemorySpeedRepository>) -> String {
+    let _ = Logger::init(
+        std::env::temp_dir().join("speedstream-forward-tests.log"),
+        LogLevel::Error,
+    );
+
+    let (broadcast_tx, _) = tokio::sync::broadcast::channel(16);
+    let state = AppState::new(
+        repository,
+        None,
+        Arc::new(MemoryCache::new(100)),
+        broadcast_tx,
+        Arc::new(CongestionMonitor::new(CongestionConfig::from_env(), 16)),
+        Arc::new(FaultDetector::new(FaultDetectorConfig::from_env())),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+    url
+}
+
+/// Serves a central server answering every batch with `status`
+async fn answering(status: axum::http::StatusCode) -> String {
+    let router = axum::Router::new().route(
+        "/api/speeds/batch",
+        axum::routing::post(move || async move { status }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// Serves a central server refusing batches of more than `max` readings as too large
+async fn limited(max: usize) -> String {
+    let router = axum::Router::new().route(
+        "/api/speeds/batch",
+        axum::routing::post(
+            move |axum::Json(batch): axum::Json<SpeedBatchRequest>| async move {
+                let received = batch.readings.len();
+                if received > max {
+                    return axum::http::StatusCode::PAYLOAD_TOO_LARGE.into_response();
+                }
+                axum::Json(SpeedBatchResponse {
+                    received,
+                    inserted: received as u64,
+                })
+                .into_response()
+            },
+        ),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+fn config(url: &str, api_key: &str) -> ForwarderConfig {
+    ForwarderConfig {
+        url: url.to_string(),
+        api_key: api_key.to_string(),
+        origin: "site-a".to_string(),
+        batch_size: 2,
+        interval: std::time::Duration::from_secs(1),
+        max_backoff: std::time::Duration::from_secs(10),
+        settle_secs: 0.0,
+    }
+}
+
+async fn edge_with_readings(count: usize) -> InMemorySpeedRepository {
+    let edge = InMemorySpeedRepository::new();
+    for speed in (0..count).map(|i| 40.0 + i as f32) {
+        let request: CreateSpeedDataRequest = serde_json::from_value(
+            serde_json::json!({"sensor_name": "edge-1", "speed": speed, "lane": 0}),
+        )
+        .unwrap();
+        let _ = edge.insert_speed_data(request, None).await.unwrap();
+    }
+    edge
+}
+
+#[tokio::test]
+async fn test_forwards_backlog_in_batches() {
+    let central_repository = Arc::new(InMemorySpeedRepository::new().with_api_key(API_KEY, true));
+    let url = central(central_repository.clone()).await;
+    let edge = edge_with_readings(3).await;
+    let client = reqwest::Client::new();
+    let config = config(&url, API_KEY);
+
+    let mut sent = Vec::new();
+    for _ in 0..3 {
+        sent.push(forward_once(&client, &edge, &config).await.unwrap());
+    }
+    assert_eq!(sent, [2, 1, 0]);
+    assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 3);
+
+    let local = edge.fetch_last_n_speed_data(10, None).await.unwrap();
+    let forwarded = central_repository
+        .fetch_last_n_speed_data(10, None)
+        .await
+        .unwrap();
+    assert_eq!(forwarded.len(), 3);
+    for (local, forwarded) in local.iter().zip(&forwarded) {
+        assert_eq!(forwarded.sensor_name, local.sensor_name);
+        assert_eq!(forwarded.created_at, local.created_at);
+        assert_eq!(forwarded.speed, local.speed);
+    }
+
+    // A lost acknowledgement makes the edge send the batch again, without duplicates
+    edge.store_forward_watermark(0).await.unwrap();
+    assert_eq!(forward_once(&client, &edge, &config).await.unwrap(), 2);
+    assert_eq!(
+        central_repository
+            .fetch_last_n_speed_data(10, None)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn test_keeps_watermark_when_rejected() {
+    let central_repository = Arc::new(InMemorySpeedRepository::new().with_api_key(API_KEY, true));
+    let url = central(central_repository).await;
+    let edge = edge_with_readings(1).await;
+    let client = reqwest::Client::new();
+
+    let result = forward_once(&client, &edge, &config(&url, "wrong-key")).await;
+    assert!(matches!(result, Err(ForwardError::Rejected(status)) if status == 401));
+    assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 0);
+
+    // Nothing listens on port 1
+    let result = forward_once(&client, &edge, &config("http://127.0.0.1:1", API_KEY)).await;
+    assert!(matches!(result, Err(ForwardError::Http(_))));
+    assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_quarantines_batches_refused_for_good() {
+    use axum::http::StatusCode;
+
+    let edge = edge_with_readings(3).await;
+    let client = reqwest::Client::new();
+
+    // Rate limiting and errors of the configuration, e.g. a wrong URL, are retried
+    for status in [
+        StatusCode::TOO_MANY_REQUESTS,
+        StatusCode::NOT_FOUND,
+        StatusCode::METHOD_NOT_ALLOWED,
+    ] {
+        let url = answering(status).await;
+        let result = forward_once(&client, &edge, &config(&url, API_KEY)).await;
+        assert!(matches!(result, Err(ForwardError::Rejected(s)) if s == status));
+        assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 0);
+    }
+    assert!(edge.quarantined().is_empty());
+
+    // An invalid batch is quarantined and skipped, and the next one is sent
+    let url = answering(StatusCode::UNPROCESSABLE_ENTITY).await;
+    let result = forward_once(&client, &edge, &config(&url, API_KEY)).await;
+    assert_eq!(result.unwrap(), 2);
+    assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 2);
+    let quarantined = edge.quarantined();
+    let ids: Vec<_> = quarantined.iter().map(|(r, _)| r.id).collect();
+    assert_eq!(ids, [1, 2]);
+    assert!(quarantined[0].1.starts_with("422"));
+}
+
+#[tokio::test]
+async fn test_splits_batches_too_large() {
+    let edge = edge_with_readings(3).await;
+    let client = reqwest::Client::new();
+    let url = limited(1).await;
+    let three_at_a_time = ForwarderConfig {
+        batch_size: 3,
+        ..config(&url, API_KEY)
+    };
+
+    let mut sent = Vec::new();
+    for _ in 0..4 {
+        sent.push(
+            forward_once(&client, &edge, &three_at_a_time)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(sent, [1, 1, 1, 0]);
+    assert_eq!(edge.fetch_forward_watermark().await.unwrap(), 3);
+    assert!(edge.quarantined().is_empty());
+
+    // A single reading still too large is quarantined
+    let edge = edge_with_readings(1).await;
+    let url = limited(0).await;
+    assert_eq!(
+        forward_once(&client, &edge, &config(&url, API_KEY))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(edge.quarantined().len(), 1);
+}